@@ -0,0 +1,259 @@
+use super::{Event, Modifiers, MouseButton};
+use nalgebra::Vector2;
+use std::time::Instant;
+use winit::event::{
+    ElementState, KeyboardInput, ModifiersState, MouseScrollDelta, VirtualKeyCode, WindowEvent,
+};
+/// Pixels scrolled by one notch of the wheel, browsers report wheel events in pixels
+const LINE_HEIGHT: f32 = 100.0;
+/// Translates window events into game events. Events are collected as they arrive
+/// and handed to the game once per frame
+pub struct InputState {
+    events: Vec<Event>,
+    /// last position of the cursor in pixels from the top left of the window
+    cursor: Option<Vector2<f32>>,
+    buttons: Vec<MouseButton>,
+    modifiers: Modifiers,
+    last_mouse_move: Instant,
+    last_scroll: Instant,
+    last_frame: Instant,
+}
+impl Default for InputState {
+    fn default() -> Self {
+        let now = Instant::now();
+        Self {
+            events: vec![],
+            cursor: None,
+            buttons: vec![],
+            modifiers: Modifiers::default(),
+            last_mouse_move: now,
+            last_scroll: now,
+            last_frame: now,
+        }
+    }
+}
+impl InputState {
+    /// Starts with the scale factor of the window the events come from
+    pub fn new(scale_factor: f64) -> Self {
+        let mut input = Self::default();
+        input.events.push(Event::ScaleFactorChange {
+            scale_factor: scale_factor as f32,
+        });
+        input
+    }
+    pub fn handle(&mut self, event: &WindowEvent) {
+        match event {
+            // a minimized window is resized to nothing, there is no swapchain that small
+            WindowEvent::Resized(size) if size.width == 0 || size.height == 0 => (),
+            WindowEvent::Resized(size) => self.events.push(Event::ScreenSizeChange {
+                new_size: Vector2::new(size.width, size.height),
+            }),
+            WindowEvent::CursorMoved { position, .. } => {
+                let position = Vector2::new(position.x as f32, position.y as f32);
+                let delta = position - self.cursor.unwrap_or(position);
+                self.cursor = Some(position);
+                self.events.push(Event::MouseMove {
+                    delta_x: delta.x,
+                    delta_y: delta.y,
+                    x: position.x,
+                    y: position.y,
+                    delta_time_ms: elapsed_ms(&mut self.last_mouse_move),
+                    buttons_pressed: self.buttons.clone(),
+                    modifiers: self.modifiers,
+                });
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                if let Some(button) = mouse_button(*button) {
+                    let cursor = self.cursor.unwrap_or_else(|| Vector2::new(0.0, 0.0));
+                    match state {
+                        ElementState::Pressed => {
+                            if !self.buttons.contains(&button) {
+                                self.buttons.push(button.clone());
+                            }
+                            self.events.push(Event::MouseDown {
+                                x: cursor.x,
+                                y: cursor.y,
+                                button,
+                                modifiers: self.modifiers,
+                            });
+                        }
+                        ElementState::Released => {
+                            self.buttons.retain(|pressed| *pressed != button);
+                            self.events.push(Event::MouseUp {
+                                x: cursor.x,
+                                y: cursor.y,
+                                modifiers: self.modifiers,
+                            });
+                        }
+                    }
+                }
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                // browsers report scrolling down as positive
+                let delta_y = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => -y * LINE_HEIGHT,
+                    MouseScrollDelta::PixelDelta(position) => -position.y as f32,
+                };
+                self.events.push(Event::Scroll {
+                    delta_y,
+                    delta_time_ms: elapsed_ms(&mut self.last_scroll),
+                    modifiers: self.modifiers,
+                });
+            }
+            // ctrl and alt turn letters into control characters or nothing at all,
+            // those presses are read from the key codes below instead
+            WindowEvent::ReceivedCharacter(character)
+                if !character.is_control() && !self.modifiers.ctrl && !self.modifiers.alt =>
+            {
+                self.events.push(Event::KeyPress {
+                    key: character.to_string(),
+                    modifiers: self.modifiers,
+                })
+            }
+            // keys that do not type a character are sent by their browser name
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(keycode),
+                        ..
+                    },
+                ..
+            } => {
+                let key = key_name(*keycode).map(str::to_string).or_else(|| {
+                    if self.modifiers.ctrl || self.modifiers.alt {
+                        key_character(*keycode).map(|character| character.to_string())
+                    } else {
+                        None
+                    }
+                });
+                if let Some(key) = key {
+                    self.events.push(Event::KeyPress {
+                        key,
+                        modifiers: self.modifiers,
+                    })
+                }
+            }
+            WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                self.events.push(Event::ScaleFactorChange {
+                    scale_factor: *scale_factor as f32,
+                })
+            }
+            WindowEvent::ModifiersChanged(state) => self.modifiers = modifiers(*state),
+            // buttons released outside of the window are never reported
+            WindowEvent::Focused(false) => self.buttons.clear(),
+            _ => (),
+        }
+    }
+    /// Events received since the last frame, followed by the time since then
+    pub fn take_events(&mut self) -> Vec<Event> {
+        let delta_time_ms = elapsed_ms(&mut self.last_frame);
+        self.events.push(Event::FrameTime { delta_time_ms });
+        self.events.drain(..).collect()
+    }
+}
+/// Numbers winit passes on from the platform for the back and forward buttons, x11
+/// numbers them 8 and 9 and wayland uses the evdev codes
+#[cfg(target_os = "windows")]
+const BACK_FORWARD: [(u16, u16); 1] = [(1, 2)];
+#[cfg(target_os = "macos")]
+const BACK_FORWARD: [(u16, u16); 1] = [(3, 4)];
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+const BACK_FORWARD: [(u16, u16); 2] = [(8, 9), (0x113, 0x114)];
+fn mouse_button(button: winit::event::MouseButton) -> Option<MouseButton> {
+    match button {
+        winit::event::MouseButton::Left => Some(MouseButton::LeftClick),
+        winit::event::MouseButton::Right => Some(MouseButton::RightClick),
+        winit::event::MouseButton::Middle => Some(MouseButton::MiddleClick),
+        winit::event::MouseButton::Other(number) => {
+            BACK_FORWARD.iter().find_map(|(back, forward)| {
+                if number == *back {
+                    Some(MouseButton::Back)
+                } else if number == *forward {
+                    Some(MouseButton::Forward)
+                } else {
+                    None
+                }
+            })
+        }
+    }
+}
+fn key_name(keycode: VirtualKeyCode) -> Option<&'static str> {
+    Some(match keycode {
+        VirtualKeyCode::Down => "ArrowDown",
+        VirtualKeyCode::Left => "ArrowLeft",
+        VirtualKeyCode::Right => "ArrowRight",
+        VirtualKeyCode::Up => "ArrowUp",
+        VirtualKeyCode::Back => "Backspace",
+        VirtualKeyCode::Delete => "Delete",
+        VirtualKeyCode::End => "End",
+        VirtualKeyCode::Return => "Enter",
+        VirtualKeyCode::Escape => "Escape",
+        VirtualKeyCode::Home => "Home",
+        VirtualKeyCode::Insert => "Insert",
+        VirtualKeyCode::PageDown => "PageDown",
+        VirtualKeyCode::PageUp => "PageUp",
+        VirtualKeyCode::Tab => "Tab",
+        _ => return None,
+    })
+}
+/// Keys that type a character, used while ctrl or alt is held
+const CHARACTER_KEYS: [(VirtualKeyCode, char); 37] = [
+    (VirtualKeyCode::A, 'a'),
+    (VirtualKeyCode::B, 'b'),
+    (VirtualKeyCode::C, 'c'),
+    (VirtualKeyCode::D, 'd'),
+    (VirtualKeyCode::E, 'e'),
+    (VirtualKeyCode::F, 'f'),
+    (VirtualKeyCode::G, 'g'),
+    (VirtualKeyCode::H, 'h'),
+    (VirtualKeyCode::I, 'i'),
+    (VirtualKeyCode::J, 'j'),
+    (VirtualKeyCode::K, 'k'),
+    (VirtualKeyCode::L, 'l'),
+    (VirtualKeyCode::M, 'm'),
+    (VirtualKeyCode::N, 'n'),
+    (VirtualKeyCode::O, 'o'),
+    (VirtualKeyCode::P, 'p'),
+    (VirtualKeyCode::Q, 'q'),
+    (VirtualKeyCode::R, 'r'),
+    (VirtualKeyCode::S, 's'),
+    (VirtualKeyCode::T, 't'),
+    (VirtualKeyCode::U, 'u'),
+    (VirtualKeyCode::V, 'v'),
+    (VirtualKeyCode::W, 'w'),
+    (VirtualKeyCode::X, 'x'),
+    (VirtualKeyCode::Y, 'y'),
+    (VirtualKeyCode::Z, 'z'),
+    (VirtualKeyCode::Key0, '0'),
+    (VirtualKeyCode::Key1, '1'),
+    (VirtualKeyCode::Key2, '2'),
+    (VirtualKeyCode::Key3, '3'),
+    (VirtualKeyCode::Key4, '4'),
+    (VirtualKeyCode::Key5, '5'),
+    (VirtualKeyCode::Key6, '6'),
+    (VirtualKeyCode::Key7, '7'),
+    (VirtualKeyCode::Key8, '8'),
+    (VirtualKeyCode::Key9, '9'),
+    (VirtualKeyCode::Space, ' '),
+];
+fn key_character(keycode: VirtualKeyCode) -> Option<char> {
+    CHARACTER_KEYS
+        .iter()
+        .find(|(key, _)| *key == keycode)
+        .map(|(_, character)| *character)
+}
+fn modifiers(state: ModifiersState) -> Modifiers {
+    Modifiers {
+        shift: state.shift(),
+        ctrl: state.ctrl(),
+        alt: state.alt(),
+    }
+}
+/// Milliseconds since `last` and resets it to now
+fn elapsed_ms(last: &mut Instant) -> f32 {
+    let now = Instant::now();
+    let elapsed = now.duration_since(*last).as_secs_f32() * 1000.0;
+    *last = now;
+    elapsed
+}
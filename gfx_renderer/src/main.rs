@@ -1,4 +1,6 @@
 use gfx_hal::{prelude::*, window};
+use input::InputState;
+use log::{error, info};
 use nalgebra::Vector2;
 #[cfg(not(any(
     feature = "vulkan",
@@ -7,31 +9,49 @@ use nalgebra::Vector2;
     feature = "metal",
     feature = "gl",
 )))]
-use ski_tycoon_v2::{Game, Window as GameWindow};
+use ski_tycoon_v2::{
+    prelude::{Event, Modifiers, MouseButton, Recording},
+    Game, Window as GameWindow,
+};
 #[cfg(feature = "dx11")]
 extern crate gfx_backend_dx11 as back;
 #[cfg(feature = "dx11")]
-use ski_tycoon_dx11::{Game, Window as GameWindow};
+use ski_tycoon_dx11::{
+    prelude::{Event, Modifiers, MouseButton, Recording},
+    Game, Window as GameWindow,
+};
 
 #[cfg(feature = "dx12")]
 extern crate gfx_backend_dx12 as back;
 #[cfg(feature = "dx12")]
-use ski_tycoon_dx12::{Game, Window as GameWindow};
+use ski_tycoon_dx12::{
+    prelude::{Event, Modifiers, MouseButton, Recording},
+    Game, Window as GameWindow,
+};
 
 #[cfg(feature = "gl")]
 extern crate gfx_backend_gl as back;
 
 #[cfg(feature = "gl")]
-use ski_tycoon_gl::{Game, Window as GameWindow};
+use ski_tycoon_gl::{
+    prelude::{Event, Modifiers, MouseButton, Recording},
+    Game, Window as GameWindow,
+};
 #[cfg(feature = "metal")]
 extern crate gfx_backend_metal as back;
 #[cfg(feature = "metal")]
-use ski_tycoon_metal::{Game, Window as GameWindow};
+use ski_tycoon_metal::{
+    prelude::{Event, Modifiers, MouseButton, Recording},
+    Game, Window as GameWindow,
+};
 
 #[cfg(feature = "vulkan")]
 extern crate gfx_backend_vulkan as back;
 #[cfg(feature = "vulkan")]
-use ski_tycoon_vulkan::{Game, Window as GameWindow};
+use ski_tycoon_vulkan::{
+    prelude::{Event, Modifiers, MouseButton, Recording},
+    Game, Window as GameWindow,
+};
 #[cfg(not(any(
     feature = "vulkan",
     feature = "dx11",
@@ -40,14 +60,28 @@ use ski_tycoon_vulkan::{Game, Window as GameWindow};
     feature = "gl",
 )))]
 extern crate gfx_backend_empty as back;
+mod input;
 
 const DIMS: window::Extent2D = window::Extent2D {
     width: 1024,
     height: 768,
 };
-const DEFAULT_SIZE: &'static [u32] = &[1024, 1024];
+/// usage: gfx_renderer [--record <file>] [--replay <file>]
+/// A recording is written to its file when the window is closed
 fn main() {
+    // messages are shown with RUST_LOG, for example RUST_LOG=info
+    env_logger::init();
     println!("Hello, world!");
+    let args: Vec<String> = std::env::args().collect();
+    let flag = |name: &str| {
+        args.iter().position(|arg| arg == name).map(|i| {
+            args.get(i + 1)
+                .expect("flag must be followed by a file")
+                .clone()
+        })
+    };
+    let record_path = flag("--record");
+    let replay_path = flag("--replay");
     let event_loop = winit::event_loop::EventLoop::new();
 
     let wb = winit::window::WindowBuilder::new()
@@ -77,7 +111,7 @@ fn main() {
     };
 
     let mut game = Game::new(
-        Vector2::new(DEFAULT_SIZE[0], DEFAULT_SIZE[1]),
+        Vector2::new(DIMS.width, DIMS.height),
         GameWindow {
             instance,
             adapter,
@@ -86,17 +120,48 @@ fn main() {
         },
     )
     .expect("failed to create game");
+    // bindings.cfg in the working directory replaces the default bindings
+    if let Ok(config) = std::fs::read_to_string("bindings.cfg") {
+        if let Err(e) = game.load_bindings(&config) {
+            error!("invalid bindings.cfg: {:?}", e);
+        }
+    }
+    if let Some(path) = replay_path {
+        let text = std::fs::read_to_string(&path)
+            .unwrap_or_else(|e| panic!("failed to read {}: {}", path, e));
+        let recording = Recording::from_text(&text)
+            .unwrap_or_else(|e| panic!("invalid recording {}: {:?}", path, e));
+        game.replay(recording);
+    }
+    if record_path.is_some() {
+        game.start_recording();
+    }
 
+    let mut input = InputState::new(window.scale_factor());
     event_loop.run(move |event, _, control_flow| {
-        *control_flow = winit::event_loop::ControlFlow::Wait;
+        *control_flow = winit::event_loop::ControlFlow::Poll;
         match event {
             winit::event::Event::WindowEvent {
                 event: winit::event::WindowEvent::CloseRequested,
                 window_id,
-            } if window_id == window.id() => *control_flow = winit::event_loop::ControlFlow::Exit,
+            } if window_id == window.id() => {
+                if let (Some(path), Some(recording)) = (record_path.as_ref(), game.stop_recording())
+                {
+                    match std::fs::write(path, recording.to_text()) {
+                        Ok(()) => info!("saved recording to {}", path),
+                        Err(e) => error!("failed to save recording: {}", e),
+                    }
+                }
+                *control_flow = winit::event_loop::ControlFlow::Exit
+            }
+            winit::event::Event::WindowEvent { event, window_id } if window_id == window.id() => {
+                input.handle(&event)
+            }
+            winit::event::Event::MainEventsCleared => window.request_redraw(),
+            winit::event::Event::RedrawRequested(window_id) if window_id == window.id() => {
+                game.run_frame(input.take_events());
+            }
             _ => (),
         }
-        // todo accumulate events
-        game.run_frame(vec![]);
     });
 }
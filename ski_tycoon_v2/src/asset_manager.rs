@@ -28,6 +28,13 @@ impl<T> AssetManager<T> {
     pub fn get(&self, key: &str) -> Option<&T> {
         self.data.get(key)
     }
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut T> {
+        self.data.get_mut(key)
+    }
+    /// Removes data at key, returning it so that it can be cleaned up
+    pub fn remove(&mut self, key: &str) -> Option<T> {
+        self.data.remove(key)
+    }
     pub fn contains(&self, key: &str) -> bool {
         self.data.contains_key(key)
     }
@@ -48,5 +55,10 @@ mod test {
         assert_eq!(manager.overwrite("zero", 1), &1);
         assert_eq!(manager.overwrite("zero", 0), &0);
         assert_eq!(manager.overwrite("three", 3), &3);
+        *manager.get_mut("three").unwrap() = 4;
+        assert_eq!(manager.get("three"), Some(&4));
+        assert_eq!(manager.remove("three"), Some(4));
+        assert_eq!(manager.remove("three"), None);
+        assert_eq!(manager.contains("three"), false);
     }
 }
@@ -0,0 +1,47 @@
+//! Runs the game without a window or gpu using the software renderer.
+//! usage: headless [frames] [width] [height] [output directory] [recording] [camera paths]
+//! When an output directory is given the world view of every frame is saved to it as a png.
+//! A recording saved by the windowed game is played back over the frames, use `-` as the
+//! output directory to replay without saving frames. The keyframes of a camera paths
+//! file play from the first frame, use `-` for the recording to play them without one
+use nalgebra::Vector2;
+use ski_tycoon_v2::{prelude::Recording, Game};
+use std::path::PathBuf;
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let arg = |i: usize, default: u32| {
+        args.get(i)
+            .map(|a| a.parse().expect("arguments must be positive integers"))
+            .unwrap_or(default)
+    };
+    let frames = arg(1, 10);
+    let screen_size = Vector2::new(arg(2, 800), arg(3, 800));
+    let output = args.get(4).filter(|a| a.as_str() != "-").map(PathBuf::from);
+    if let Some(output) = output.as_ref() {
+        std::fs::create_dir_all(output).expect("failed to create output directory");
+    }
+    let mut game = Game::new(screen_size, screen_size).expect("failed to create game");
+    if let Some(path) = args.get(5).filter(|a| a.as_str() != "-") {
+        let text = std::fs::read_to_string(path)
+            .unwrap_or_else(|e| panic!("failed to read {}: {}", path, e));
+        let recording = Recording::from_text(&text)
+            .unwrap_or_else(|e| panic!("invalid recording {}: {:?}", path, e));
+        game.replay(recording);
+    }
+    if let Some(path) = args.get(6) {
+        let text = std::fs::read_to_string(path)
+            .unwrap_or_else(|e| panic!("failed to read {}: {}", path, e));
+        game.load_camera_paths(&text)
+            .unwrap_or_else(|e| panic!("invalid camera paths {}: {:?}", path, e));
+        game.play_camera_path();
+    }
+    for frame in 0..frames {
+        game.run_frame(vec![]);
+        if let Some(output) = output.as_ref() {
+            let path = output.join(format!("frame_{:05}.png", frame));
+            game.screenshot()
+                .save_png(&path)
+                .unwrap_or_else(|e| panic!("failed to save {}: {}", path.display(), e));
+        }
+    }
+}
@@ -1,35 +1,118 @@
-use super::prelude::Terrain;
+use super::prelude::{Terrain, Tracer};
+use egui::CtxRef;
 use legion::*;
-use nalgebra::{Matrix4, Point, Vector2, Vector3};
+use nalgebra::{Matrix4, Point, Vector2, Vector3, Vector4};
+mod path;
+pub use path::{Bookmark, CameraPath, CameraPathError, CameraPaths, Keyframe};
+/// How the camera moves and projects the world
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CameraMode {
+    /// rotates about an origin on the terrain
+    Orbit,
+    /// moves along the view direction and looks around from the eye
+    FreeFly,
+    /// looks straight down with an orthographic projection
+    TopDown,
+    /// orbits an entity with a `Tracer`, goes back to orbiting when the entity is removed
+    Follow(Entity),
+}
+impl CameraMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Orbit => "Orbit",
+            Self::FreeFly => "Free Fly",
+            Self::TopDown => "Top Down",
+            Self::Follow(_) => "Follow",
+        }
+    }
+    /// Mode after this one when cycling through the modes, following is skipped
+    pub fn next(&self) -> Self {
+        match self {
+            Self::Orbit => Self::FreeFly,
+            Self::FreeFly => Self::TopDown,
+            Self::TopDown | Self::Follow(_) => Self::Orbit,
+        }
+    }
+}
 pub struct DeltaCamera {
+    /// camera that is drawn, follows `target` with a spring
     previous: Camera,
+    /// where the camera is moving to, changed by input
+    target: Camera,
     next: Option<Camera>,
+    /// rate of change of the origin, radius and angles of `previous`
+    velocity: [f32; 6],
+    /// jumps to the target on the next apply instead of moving smoothly
+    snap: bool,
+    mode: CameraMode,
+    /// view at the time the mode last changed, blended into the current view
+    transition: Option<Transition>,
+    /// tracer added by the Follow Tracer button, removed once it is no longer followed
+    tracer: Option<Entity>,
 }
 impl DeltaCamera {
-    //checks if next is used and if not creates next from previous
+    /// seconds taken to blend between the views of two modes
+    const TRANSITION_TIME: f32 = 0.5;
+    /// seconds between frames when the frame time is not known
+    pub const FRAME_TIME: f32 = 1.0 / 60.0;
+    /// angular frequency of the springs, the camera settles in about 5 / SPRING_RATE seconds
+    const SPRING_RATE: f32 = 15.0;
+    /// smallest distance between the eye and the water surface
+    const CLEARANCE: f32 = 0.5;
+    //checks if next is used and if not creates next from target
     fn new_next(&mut self) {
         if self.next.is_none() {
-            self.next = Some(self.previous.clone());
+            self.next = Some(self.target.clone());
         }
     }
     pub fn new(origin: Vector3<f32>, radius: f32, phi: f32, theta: f32) -> Self {
+        let camera = Camera {
+            origin,
+            radius,
+            phi,
+            theta,
+        };
         Self {
-            previous: Camera {
-                origin,
-                radius,
-                phi,
-                theta,
-            },
+            previous: camera.clone(),
+            target: camera,
             next: None,
+            velocity: [0.0; 6],
+            snap: false,
+            mode: CameraMode::Orbit,
+            transition: None,
+            tracer: None,
+        }
+    }
+    pub fn mode(&self) -> CameraMode {
+        self.mode
+    }
+    /// Changes the mode, the view blends from the old mode over the next half second
+    pub fn set_mode(&mut self, mode: CameraMode) {
+        if mode == self.mode {
+            return;
         }
+        self.transition = Some(Transition {
+            from: self.view(),
+            elapsed: 0.0,
+        });
+        self.mode = mode;
     }
     pub fn rotate_phi(&mut self, delta_phi: f32) {
-        self.new_next();
-        self.next.as_mut().unwrap().phi += delta_phi;
+        self.rotate(delta_phi, 0.0);
     }
     pub fn rotate_theta(&mut self, delta_theta: f32) {
+        self.rotate(0.0, delta_theta);
+    }
+    /// Rotates about the origin, or about the eye when flying
+    fn rotate(&mut self, delta_phi: f32, delta_theta: f32) {
         self.new_next();
-        self.next.as_mut().unwrap().theta += delta_theta;
+        let next = self.next.as_mut().unwrap();
+        let eye = next.eye();
+        next.phi += delta_phi;
+        next.theta += delta_theta;
+        if self.mode == CameraMode::FreeFly {
+            next.origin = eye - next.offset();
+        }
     }
     /// Increases by value proportional to delta radius
     pub fn update_radius(&mut self, delta_radius: f32) {
@@ -49,51 +132,239 @@ impl DeltaCamera {
         next.radius = radius;
     }
     pub fn get_radius(&self) -> f32 {
-        self.previous.radius
+        self.target.radius
+    }
+    /// Pose the camera is moving to
+    pub fn pose(&self) -> CameraPose {
+        CameraPose {
+            origin: self.target.origin,
+            radius: self.target.radius,
+            phi: self.target.phi,
+            theta: self.target.theta,
+        }
     }
+    /// Replaces the origin, radius and angles on the next apply
+    pub fn set_pose(&mut self, pose: &CameraPose) {
+        self.next = Some(Camera {
+            origin: pose.origin,
+            radius: pose.radius,
+            phi: pose.phi,
+            theta: pose.theta,
+        });
+    }
+    /// Moves along the world axes, or relative to the view direction when flying
+    /// where -z is forward and +x is right
     pub fn translate(&mut self, translation: &Vector3<f32>) {
         self.new_next();
-        self.next.as_mut().unwrap().origin += translation;
+        let next = self.next.as_mut().unwrap();
+        let translation = if self.mode == CameraMode::FreeFly {
+            let forward = -next.offset().normalize();
+            let up = Vector3::new(0.0, 1.0, 0.0);
+            let right = forward
+                .cross(&up)
+                .try_normalize(f32::EPSILON)
+                .unwrap_or_else(|| Vector3::new(1.0, 0.0, 0.0));
+            right * translation.x + up * translation.y - forward * translation.z
+        } else {
+            *translation
+        };
+        next.origin += translation;
     }
     pub fn set_translation(&mut self, translation: Vector3<f32>) {
         self.new_next();
         self.next.as_mut().unwrap().origin = translation;
     }
-    /// applies delta changes to self
-    pub fn apply(&mut self, world: &World) {
-        if let Some(next) = self.next.as_ref() {
-            if let Some(terrain) = <&Terrain>::query().iter(world).next() {
-                self.previous = next.clone();
-                self.previous.origin.y = terrain
-                    .get_transform_rounded(&Vector2::new(
-                        self.previous.origin.x,
-                        self.previous.origin.z,
-                    ))
-                    .y;
+    /// Moves straight to the target on the next apply, used when the scene changes
+    pub fn skip_smoothing(&mut self) {
+        self.snap = true;
+    }
+    /// applies delta changes to self, `delta_time` is the seconds since the last
+    /// apply
+    pub fn apply(&mut self, world: &World, delta_time: f32) {
+        if let CameraMode::Follow(entity) = self.mode {
+            let followed = world
+                .entry_ref(entity)
+                .ok()
+                .and_then(|entry| entry.get_component::<Tracer>().ok().map(|t| t.position));
+            match followed {
+                Some(position) => {
+                    self.new_next();
+                    self.next.as_mut().unwrap().origin = position;
+                }
+                None => self.set_mode(CameraMode::Orbit),
             }
         }
-        self.next = None;
+        self.update(<&Terrain>::query().iter(world).next(), delta_time);
+    }
+    /// Moves the target by the changes of this frame and the drawn camera towards it
+    fn update(&mut self, terrain: Option<&Terrain>, delta_time: f32) {
+        if let Some(next) = self.next.take() {
+            self.target = next;
+        }
+        if let Some(terrain) = terrain {
+            if self.mode != CameraMode::FreeFly {
+                self.target.origin.y = terrain.surface_height_clamped(Vector2::new(
+                    self.target.origin.x,
+                    self.target.origin.z,
+                ));
+            }
+        }
+        if self.snap {
+            self.previous = self.target.clone();
+            self.velocity = [0.0; 6];
+            self.snap = false;
+        } else {
+            let mut values = self.previous.values();
+            let target = self.target.values();
+            for i in 0..values.len() {
+                spring(
+                    &mut values[i],
+                    &mut self.velocity[i],
+                    target[i],
+                    Self::SPRING_RATE,
+                    delta_time,
+                );
+            }
+            self.previous = Camera::from_values(values);
+        }
+        if let Some(terrain) = terrain {
+            self.keep_above(terrain);
+        }
+        self.advance_transition(delta_time);
+    }
+    /// Keeps the eye above the water surface by raising the angle of the camera, or by
+    /// moving it up when flying
+    fn keep_above(&mut self, terrain: &Terrain) {
+        let eye = self.previous.eye();
+        let ground = terrain.surface_height_clamped(Vector2::new(eye.x, eye.z)) + Self::CLEARANCE;
+        if eye.y >= ground {
+            return;
+        }
+        match self.mode {
+            CameraMode::Orbit | CameraMode::Follow(_) => {
+                let sin = (ground - self.previous.origin.y) / self.previous.radius;
+                let min_theta = sin.clamp(-1.0, 1.0).asin();
+                if self.previous.theta < min_theta {
+                    self.previous.theta = min_theta;
+                    self.velocity[5] = self.velocity[5].max(0.0);
+                }
+                self.target.theta = self.target.theta.max(min_theta);
+            }
+            CameraMode::FreeFly => {
+                let lift = ground - eye.y;
+                self.previous.origin.y += lift;
+                self.target.origin.y = self.target.origin.y.max(self.previous.origin.y);
+                self.velocity[1] = self.velocity[1].max(0.0);
+            }
+            // the orthographic projection draws everything below and above the eye
+            CameraMode::TopDown => (),
+        }
+    }
+    fn advance_transition(&mut self, delta_time: f32) {
+        if let Some(transition) = self.transition.as_mut() {
+            transition.elapsed += delta_time;
+            if transition.elapsed >= Self::TRANSITION_TIME {
+                self.transition = None;
+            }
+        }
+    }
+    /// View of the current mode blended with the view of the previous mode
+    fn view(&self) -> View {
+        let view = self.previous.view(self.mode);
+        match self.transition.as_ref() {
+            Some(transition) => {
+                let t = (transition.elapsed / Self::TRANSITION_TIME).min(1.0);
+                transition.from.blend(&view, t * t * (3.0 - 2.0 * t))
+            }
+            None => view,
+        }
+    }
+    /// Position of the eye in world space
+    pub fn get_position(&self) -> Vector3<f32> {
+        self.view().eye
     }
     pub fn get_matrix(&self, screen_resolution: Vector2<u32>) -> Matrix4<f32> {
-        let delta_position = self.previous.radius
-            * Vector3::new(
-                self.previous.phi.cos() * self.previous.theta.cos(),
-                self.previous.theta.sin(),
-                (self.previous.phi).sin() * self.previous.theta.cos(),
-            );
-        let face = Matrix4::look_at_rh(
-            &Point::from(delta_position + self.previous.origin),
-            &Point::from(self.previous.origin),
-            &Vector3::new(0.0, 1.0, 0.0),
-        );
-        let cam = Matrix4::new_perspective(
-            screen_resolution.x as f32 / screen_resolution.y as f32,
-            std::f32::consts::PI / 3.0,
-            0.1,
-            1000.0,
+        let view = self.view();
+        let aspect = screen_resolution.x as f32 / screen_resolution.y as f32;
+        let face = Matrix4::look_at_rh(&Point::from(view.eye), &Point::from(view.target), &view.up);
+        let perspective = Matrix4::new_perspective(aspect, Camera::FOV, 0.1, 1000.0);
+        let orthographic = Matrix4::new_orthographic(
+            -view.half_height * aspect,
+            view.half_height * aspect,
+            -view.half_height,
+            view.half_height,
+            -Camera::FAR_CLIP,
+            Camera::FAR_CLIP,
         );
+        let cam = perspective * (1.0 - view.orthographic) + orthographic * view.orthographic;
         cam * face
     }
+    /// Ray through a point on the screen given in pixels from the top left corner.
+    /// Returns the origin on the near plane and the unit direction in world space
+    pub fn screen_ray(
+        &self,
+        position: Vector2<f32>,
+        screen_resolution: Vector2<u32>,
+    ) -> (Vector3<f32>, Vector3<f32>) {
+        let inverse = self
+            .get_matrix(screen_resolution)
+            .try_inverse()
+            .unwrap_or_else(Matrix4::identity);
+        let x = 2.0 * position.x / screen_resolution.x as f32 - 1.0;
+        let y = 1.0 - 2.0 * position.y / screen_resolution.y as f32;
+        let unproject = |z: f32| {
+            let point = inverse * Vector4::new(x, y, z, 1.0);
+            point.xyz() / point.w
+        };
+        let near = unproject(-1.0);
+        let direction = (unproject(1.0) - near)
+            .try_normalize(f32::EPSILON)
+            .unwrap_or_else(|| Vector3::new(0.0, -1.0, 0.0));
+        (near, direction)
+    }
+    /// Mode selection, following drops a tracer at the origin. The tracer replaces the
+    /// one dropped before and is removed when the camera stops following it
+    pub fn draw_gui(&mut self, context: &mut CtxRef, world: &mut World) {
+        let mut mode = self.mode;
+        egui::Window::new("Camera").show(context, |ui| {
+            for choice in [CameraMode::Orbit, CameraMode::FreeFly, CameraMode::TopDown].iter() {
+                ui.radio_value(&mut mode, *choice, choice.name());
+            }
+            if let CameraMode::Follow(_) = mode {
+                ui.label("Following a tracer");
+            }
+            if ui.button("Follow Tracer").clicked {
+                let origin = self.target.origin;
+                let tracer = <&Terrain>::query()
+                    .iter(world)
+                    .next()
+                    .map(|terrain| Tracer::new(Vector2::new(origin.x, origin.z), terrain));
+                if let Some(tracer) = tracer {
+                    if let Some(old) = self.tracer.take() {
+                        world.remove(old);
+                    }
+                    let entity = world.push((tracer,));
+                    self.tracer = Some(entity);
+                    mode = CameraMode::Follow(entity);
+                }
+            }
+        });
+        self.set_mode(mode);
+        if let Some(entity) = self.tracer {
+            if self.mode != CameraMode::Follow(entity) {
+                world.remove(entity);
+                self.tracer = None;
+            }
+        }
+    }
+}
+/// Where the camera orbits and how it looks at its origin
+#[derive(Clone, Debug, PartialEq)]
+pub struct CameraPose {
+    pub origin: Vector3<f32>,
+    pub radius: f32,
+    pub phi: f32,
+    pub theta: f32,
 }
 #[derive(Clone, Debug)]
 struct Camera {
@@ -107,4 +378,189 @@ struct Camera {
 }
 impl Camera {
     const FAR_CLIP: f32 = 1000.0;
+    /// vertical field of view of the perspective projection
+    const FOV: f32 = std::f32::consts::PI / 3.0;
+    /// Eye relative to the origin
+    fn offset(&self) -> Vector3<f32> {
+        self.radius
+            * Vector3::new(
+                self.phi.cos() * self.theta.cos(),
+                self.theta.sin(),
+                self.phi.sin() * self.theta.cos(),
+            )
+    }
+    fn eye(&self) -> Vector3<f32> {
+        self.origin + self.offset()
+    }
+    fn values(&self) -> [f32; 6] {
+        [
+            self.origin.x,
+            self.origin.y,
+            self.origin.z,
+            self.radius,
+            self.phi,
+            self.theta,
+        ]
+    }
+    fn from_values(values: [f32; 6]) -> Self {
+        Self {
+            origin: Vector3::new(values[0], values[1], values[2]),
+            radius: values[3],
+            phi: values[4],
+            theta: values[5],
+        }
+    }
+    fn view(&self, mode: CameraMode) -> View {
+        // matches the size of the origin on screen between the projections
+        let half_height = self.radius * (Self::FOV / 2.0).tan();
+        match mode {
+            CameraMode::TopDown => View {
+                eye: self.origin + Vector3::new(0.0, self.radius, 0.0),
+                target: self.origin,
+                // keeps the heading of the other modes
+                up: -Vector3::new(self.phi.cos(), 0.0, self.phi.sin()),
+                orthographic: 1.0,
+                half_height,
+            },
+            _ => View {
+                eye: self.eye(),
+                target: self.origin,
+                up: Vector3::new(0.0, 1.0, 0.0),
+                orthographic: 0.0,
+                half_height,
+            },
+        }
+    }
+}
+/// Moves a value towards a target with a critically damped spring, the fastest
+/// motion that does not overshoot. Solved exactly so large time steps stay stable
+fn spring(value: &mut f32, velocity: &mut f32, target: f32, rate: f32, time: f32) {
+    let offset = *value - target;
+    let decay = (-rate * time).exp();
+    let change = (*velocity + rate * offset) * time;
+    *velocity = (*velocity - rate * change) * decay;
+    *value = target + (offset + change) * decay;
+}
+/// Everything needed to build the view and projection matrices
+#[derive(Clone, Debug)]
+struct View {
+    eye: Vector3<f32>,
+    target: Vector3<f32>,
+    up: Vector3<f32>,
+    /// 0 for a perspective projection and 1 for orthographic
+    orthographic: f32,
+    /// half the height of the orthographic projection in world units
+    half_height: f32,
+}
+impl View {
+    fn blend(&self, other: &View, t: f32) -> View {
+        View {
+            eye: self.eye.lerp(&other.eye, t),
+            target: self.target.lerp(&other.target, t),
+            up: self
+                .up
+                .lerp(&other.up, t)
+                .try_normalize(f32::EPSILON)
+                .unwrap_or(other.up),
+            orthographic: self.orthographic + (other.orthographic - self.orthographic) * t,
+            half_height: self.half_height + (other.half_height - self.half_height) * t,
+        }
+    }
+}
+#[derive(Clone, Debug)]
+struct Transition {
+    from: View,
+    /// seconds since the mode changed
+    elapsed: f32,
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    fn camera() -> DeltaCamera {
+        DeltaCamera::new(Vector3::new(10.0, 0.0, 10.0), 5.0, 0.3, 0.6)
+    }
+    /// applies changes without a world or smoothing
+    fn apply(camera: &mut DeltaCamera) {
+        camera.skip_smoothing();
+        camera.update(None, DeltaCamera::FRAME_TIME);
+    }
+    #[test]
+    fn free_fly_looks_around_the_eye() {
+        let mut camera = camera();
+        camera.set_mode(CameraMode::FreeFly);
+        let eye = camera.previous.eye();
+        camera.rotate_phi(0.5);
+        camera.rotate_theta(-0.2);
+        apply(&mut camera);
+        assert!((camera.previous.eye() - eye).norm() < 1e-4);
+        // forward moves towards the origin
+        let forward = (camera.previous.origin - eye).normalize();
+        camera.translate(&Vector3::new(0.0, 0.0, -1.0));
+        apply(&mut camera);
+        let moved = camera.previous.eye() - eye;
+        assert!((moved - forward).norm() < 1e-4);
+    }
+    #[test]
+    fn transition_blends_views() {
+        let mut camera = camera();
+        let orbit_eye = camera.get_position();
+        camera.set_mode(CameraMode::TopDown);
+        assert!((camera.get_position() - orbit_eye).norm() < 1e-4);
+        // the blend takes the same time at any frame rate
+        for _ in 0..15 {
+            camera.update(None, 1.0 / 60.0);
+        }
+        let halfway = camera.get_position();
+        for _ in 0..16 {
+            camera.update(None, 1.0 / 60.0);
+        }
+        let mut fast = self::camera();
+        fast.set_mode(CameraMode::TopDown);
+        for _ in 0..30 {
+            fast.update(None, 1.0 / 120.0);
+        }
+        assert!((fast.get_position() - halfway).norm() < 1e-4);
+        assert!(camera.transition.is_none());
+        assert!((camera.get_position() - Vector3::new(10.0, 5.0, 10.0)).norm() < 1e-4);
+    }
+    #[test]
+    fn top_down_centers_origin() {
+        let mut camera = camera();
+        camera.set_mode(CameraMode::TopDown);
+        camera.transition = None;
+        let screen = Vector2::new(200, 100);
+        let (origin, direction) = camera.screen_ray(Vector2::new(100.0, 50.0), screen);
+        assert!((direction - Vector3::new(0.0, -1.0, 0.0)).norm() < 1e-4);
+        assert!((origin.xz() - Vector2::new(10.0, 10.0)).norm() < 1e-3);
+        // rays stay parallel away from the center
+        let (_, corner) = camera.screen_ray(Vector2::new(0.0, 0.0), screen);
+        assert!((corner - direction).norm() < 1e-4);
+    }
+    #[test]
+    fn springs_settle_without_overshoot() {
+        let mut value = 0.0;
+        let mut velocity = 0.0;
+        let mut previous = value;
+        for _ in 0..120 {
+            spring(&mut value, &mut velocity, 1.0, 15.0, 1.0 / 60.0);
+            assert!(value >= previous && value <= 1.0);
+            previous = value;
+        }
+        assert!((value - 1.0).abs() < 1e-3);
+        // large steps jump close to the target instead of blowing up
+        spring(&mut value, &mut velocity, -5.0, 15.0, 10.0);
+        assert!((value + 5.0).abs() < 1e-3);
+    }
+    #[test]
+    fn zoom_is_smoothed() {
+        let mut camera = camera();
+        camera.update_radius(1.0);
+        camera.update(None, DeltaCamera::FRAME_TIME);
+        assert_eq!(camera.get_radius(), 10.0);
+        assert!(camera.previous.radius > 5.0 && camera.previous.radius < 10.0);
+        for _ in 0..60 {
+            camera.update(None, DeltaCamera::FRAME_TIME);
+        }
+        assert!((camera.previous.radius - 10.0).abs() < 1e-2);
+    }
 }
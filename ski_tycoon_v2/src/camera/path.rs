@@ -0,0 +1,433 @@
+use super::{CameraMode, CameraPose, DeltaCamera};
+use egui::CtxRef;
+use nalgebra::Vector3;
+/// First line of a camera paths file
+const HEADER: &str = "# ski tycoon camera paths";
+/// File the camera window saves to and loads from
+#[cfg(not(target_arch = "wasm32"))]
+const CAMERA_PATHS_FILE: &str = "camera_paths.txt";
+#[derive(PartialEq, Debug)]
+pub enum CameraPathError {
+    MissingHeader,
+    InvalidLine {
+        line: usize,
+        text: String,
+    },
+    /// keyframes have to be written in time order
+    KeyframeOrder {
+        line: usize,
+    },
+}
+/// Named camera pose to jump back to
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bookmark {
+    pub name: String,
+    pub pose: CameraPose,
+}
+#[derive(Clone, Debug, PartialEq)]
+pub struct Keyframe {
+    /// simulation steps since the path started playing
+    pub time: f32,
+    pub pose: CameraPose,
+}
+/// Keyframes sorted by time, the pose between them follows a cubic hermite spline
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CameraPath {
+    keyframes: Vec<Keyframe>,
+}
+impl CameraPath {
+    pub fn keyframes(&self) -> &[Keyframe] {
+        &self.keyframes
+    }
+    /// Adds a keyframe in time order, replacing one at the same time
+    pub fn insert(&mut self, keyframe: Keyframe) {
+        match self.keyframes.iter().position(|k| k.time >= keyframe.time) {
+            Some(i) if self.keyframes[i].time == keyframe.time => self.keyframes[i] = keyframe,
+            Some(i) => self.keyframes.insert(i, keyframe),
+            None => self.keyframes.push(keyframe),
+        }
+    }
+    pub fn remove(&mut self, index: usize) -> Keyframe {
+        self.keyframes.remove(index)
+    }
+    /// Time of the last keyframe
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map(|k| k.time).unwrap_or(0.0)
+    }
+    /// Pose at a time, held at the first and last keyframes. Tangents are the slope
+    /// between the neighbouring keyframes so the motion has no kinks at keyframes
+    pub fn sample(&self, time: f32) -> Option<CameraPose> {
+        let first = self.keyframes.first()?;
+        let last = self.keyframes.last()?;
+        if time <= first.time {
+            return Some(first.pose.clone());
+        }
+        if time >= last.time {
+            return Some(last.pose.clone());
+        }
+        let i = self.keyframes.iter().rposition(|k| k.time <= time)?;
+        let (start, end) = (&self.keyframes[i], &self.keyframes[i + 1]);
+        let h = end.time - start.time;
+        let s = (time - start.time) / h;
+        let (p0, p1) = (pose_values(&start.pose), pose_values(&end.pose));
+        let (m0, m1) = (self.tangent(i), self.tangent(i + 1));
+        let h00 = 2.0 * s * s * s - 3.0 * s * s + 1.0;
+        let h10 = s * s * s - 2.0 * s * s + s;
+        let h01 = -2.0 * s * s * s + 3.0 * s * s;
+        let h11 = s * s * s - s * s;
+        let mut values = [0.0; 6];
+        for (j, value) in values.iter_mut().enumerate() {
+            *value = h00 * p0[j] + h10 * h * m0[j] + h01 * p1[j] + h11 * h * m1[j];
+        }
+        Some(pose_from_values(values))
+    }
+    /// Rate of change of the pose at a keyframe
+    fn tangent(&self, i: usize) -> [f32; 6] {
+        let before = &self.keyframes[i.saturating_sub(1)];
+        let after = &self.keyframes[(i + 1).min(self.keyframes.len() - 1)];
+        let (a, b) = (pose_values(&before.pose), pose_values(&after.pose));
+        let dt = after.time - before.time;
+        let mut tangent = [0.0; 6];
+        if dt > 0.0 {
+            for (j, value) in tangent.iter_mut().enumerate() {
+                *value = (b[j] - a[j]) / dt;
+            }
+        }
+        tangent
+    }
+}
+fn pose_values(pose: &CameraPose) -> [f32; 6] {
+    [
+        pose.origin.x,
+        pose.origin.y,
+        pose.origin.z,
+        pose.radius,
+        pose.phi,
+        pose.theta,
+    ]
+}
+fn pose_from_values(values: [f32; 6]) -> CameraPose {
+    CameraPose {
+        origin: Vector3::new(values[0], values[1], values[2]),
+        radius: values[3],
+        phi: values[4],
+        theta: values[5],
+    }
+}
+/// Bookmarks and a keyframe path for the camera, stored as a resource. The path plays
+/// back on the simulation clock so a fly-through lines up with the water every time
+#[derive(Default)]
+pub struct CameraPaths {
+    pub bookmarks: Vec<Bookmark>,
+    pub path: CameraPath,
+    /// time along the path, `None` when the path is not playing
+    clock: Option<f32>,
+    /// time given to the next keyframe added from the gui
+    next_keyframe_time: f32,
+}
+impl CameraPaths {
+    /// Default spacing of keyframes added from the gui in simulation steps
+    const KEYFRAME_SPACING: f32 = 100.0;
+    /// Plays the path from the start, does nothing without keyframes
+    pub fn play(&mut self) {
+        if !self.path.keyframes().is_empty() {
+            self.clock = Some(0.0);
+        }
+    }
+    pub fn stop(&mut self) {
+        self.clock = None;
+    }
+    pub fn playing(&self) -> bool {
+        self.clock.is_some()
+    }
+    /// Moves the camera to the pose on the path at the current time
+    pub fn update(&mut self, camera: &mut DeltaCamera) {
+        if let Some(pose) = self.clock.and_then(|clock| self.path.sample(clock)) {
+            camera.set_mode(CameraMode::Orbit);
+            camera.set_pose(&pose);
+            // the spline is already smooth and has to be followed exactly
+            camera.skip_smoothing();
+        }
+    }
+    /// Advances by the simulation steps run this frame, playback stops on the frame
+    /// after the last keyframe was reached
+    pub fn advance(&mut self, steps: u32) {
+        if let Some(clock) = self.clock {
+            if clock >= self.path.duration() {
+                self.clock = None;
+            } else {
+                self.clock = Some(clock + steps as f32);
+            }
+        }
+    }
+    /// Writes the bookmarks and keyframes as text, one per line
+    pub fn to_text(&self) -> String {
+        let mut text = format!("{}\n", HEADER);
+        for bookmark in self.bookmarks.iter() {
+            text += &format!(
+                "bookmark {} {}\n",
+                encode_pose(&bookmark.pose),
+                bookmark.name
+            );
+        }
+        for keyframe in self.path.keyframes().iter() {
+            text += &format!(
+                "keyframe {} {}\n",
+                keyframe.time,
+                encode_pose(&keyframe.pose)
+            );
+        }
+        text
+    }
+    /// Reads text written by `to_text`, lines are counted from 1
+    pub fn from_text(text: &str) -> Result<Self, CameraPathError> {
+        let mut lines = text.lines().map(str::trim).enumerate();
+        if lines.next().map(|(_, line)| line) != Some(HEADER) {
+            return Err(CameraPathError::MissingHeader);
+        }
+        let mut paths = Self::default();
+        for (i, line) in lines {
+            let line_number = i + 1;
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || CameraPathError::InvalidLine {
+                line: line_number,
+                text: line.to_string(),
+            };
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.split_first() {
+                Some((&"bookmark", fields)) if fields.len() > 6 => {
+                    paths.bookmarks.push(Bookmark {
+                        name: fields[6..].join(" "),
+                        pose: decode_pose(&fields[..6]).ok_or_else(invalid)?,
+                    });
+                }
+                Some((&"keyframe", fields)) if fields.len() == 7 => {
+                    let keyframe = Keyframe {
+                        time: fields[0]
+                            .parse()
+                            .ok()
+                            .filter(|t: &f32| t.is_finite())
+                            .ok_or_else(invalid)?,
+                        pose: decode_pose(&fields[1..]).ok_or_else(invalid)?,
+                    };
+                    if keyframe.time <= paths.path.duration() && !paths.path.keyframes.is_empty() {
+                        return Err(CameraPathError::KeyframeOrder { line: line_number });
+                    }
+                    paths.path.keyframes.push(keyframe);
+                }
+                _ => return Err(invalid()),
+            }
+        }
+        paths.next_keyframe_time = paths.path.duration() + Self::KEYFRAME_SPACING;
+        Ok(paths)
+    }
+    /// Replaces the bookmarks and keyframes, stopping playback
+    pub fn load(&mut self, text: &str) -> Result<(), CameraPathError> {
+        *self = Self::from_text(text)?;
+        Ok(())
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    fn save_file(&self) {
+        match std::fs::write(CAMERA_PATHS_FILE, self.to_text()) {
+            Ok(()) => log::info!("saved camera paths to {}", CAMERA_PATHS_FILE),
+            Err(e) => log::error!("failed to save camera paths: {}", e),
+        }
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    fn load_file(&mut self) {
+        let result = std::fs::read_to_string(CAMERA_PATHS_FILE)
+            .map_err(|e| e.to_string())
+            .and_then(|text| self.load(&text).map_err(|e| format!("{:?}", e)));
+        match result {
+            Ok(()) => log::info!("loaded camera paths from {}", CAMERA_PATHS_FILE),
+            Err(e) => log::error!("failed to load camera paths: {}", e),
+        }
+    }
+    pub fn draw_gui(&mut self, context: &mut CtxRef, camera: &mut DeltaCamera) {
+        egui::Window::new("Camera Paths").show(context, |ui| {
+            if ui.button("Add Bookmark").clicked {
+                self.bookmarks.push(Bookmark {
+                    name: format!("Bookmark {}", self.bookmarks.len() + 1),
+                    pose: camera.pose(),
+                });
+            }
+            let mut removed = None;
+            for (i, bookmark) in self.bookmarks.iter().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.label(bookmark.name.to_string());
+                    if ui.button("Go").clicked {
+                        camera.set_mode(CameraMode::Orbit);
+                        camera.set_pose(&bookmark.pose);
+                    }
+                    if ui.button("Remove").clicked {
+                        removed = Some(i);
+                    }
+                });
+            }
+            if let Some(i) = removed {
+                self.bookmarks.remove(i);
+            }
+            ui.separator();
+            ui.add(
+                egui::Slider::f32(&mut self.next_keyframe_time, 0.0..=10000.0)
+                    .text("Keyframe Step"),
+            );
+            if ui.button("Add Keyframe").clicked {
+                self.path.insert(Keyframe {
+                    time: self.next_keyframe_time,
+                    pose: camera.pose(),
+                });
+                self.next_keyframe_time += Self::KEYFRAME_SPACING;
+            }
+            let mut removed = None;
+            for (i, keyframe) in self.path.keyframes().iter().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.label(format!("Step {}", keyframe.time));
+                    if ui.button("Remove").clicked {
+                        removed = Some(i);
+                    }
+                });
+            }
+            if let Some(i) = removed {
+                self.path.remove(i);
+            }
+            match self.clock {
+                Some(clock) => {
+                    ui.label(format!(
+                        "Playing step {} of {}",
+                        clock,
+                        self.path.duration()
+                    ));
+                    if ui.button("Stop").clicked {
+                        self.stop();
+                    }
+                }
+                None => {
+                    if ui.button("Play").clicked {
+                        self.play();
+                    }
+                }
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            {
+                ui.separator();
+                if ui.button("Save").clicked {
+                    self.save_file();
+                }
+                if ui.button("Load").clicked {
+                    self.load_file();
+                }
+            }
+        });
+    }
+}
+/// Origin, radius and angles separated by spaces
+fn encode_pose(pose: &CameraPose) -> String {
+    pose_values(pose)
+        .iter()
+        .map(|value| value.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+fn decode_pose(fields: &[&str]) -> Option<CameraPose> {
+    if fields.len() != 6 {
+        return None;
+    }
+    let mut values = [0.0; 6];
+    for (value, field) in values.iter_mut().zip(fields.iter()) {
+        *value = field.parse().ok().filter(|v: &f32| v.is_finite())?;
+    }
+    Some(pose_from_values(values))
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    fn pose(x: f32, radius: f32) -> CameraPose {
+        CameraPose {
+            origin: Vector3::new(x, 1.0, 2.0),
+            radius,
+            phi: 0.5,
+            theta: 0.25,
+        }
+    }
+    fn path() -> CameraPath {
+        let mut path = CameraPath::default();
+        path.insert(Keyframe {
+            time: 100.0,
+            pose: pose(10.0, 5.0),
+        });
+        path.insert(Keyframe {
+            time: 0.0,
+            pose: pose(0.0, 5.0),
+        });
+        path.insert(Keyframe {
+            time: 200.0,
+            pose: pose(20.0, 10.0),
+        });
+        path
+    }
+    #[test]
+    fn spline_passes_through_keyframes() {
+        let path = path();
+        assert_eq!(path.duration(), 200.0);
+        assert_eq!(path.sample(-5.0), Some(pose(0.0, 5.0)));
+        assert_eq!(path.sample(300.0), Some(pose(20.0, 10.0)));
+        let middle = path.sample(100.0).unwrap();
+        assert!((middle.origin.x - 10.0).abs() < 1e-4);
+        // evenly spaced keyframes on a line are followed at constant speed
+        let quarter = path.sample(50.0).unwrap();
+        assert!((quarter.origin.x - 5.0).abs() < 1e-4);
+        assert!((quarter.phi - 0.5).abs() < 1e-4);
+        assert!(CameraPath::default().sample(0.0).is_none());
+    }
+    #[test]
+    fn playback_follows_steps() {
+        let mut paths = CameraPaths {
+            path: path(),
+            ..CameraPaths::default()
+        };
+        paths.play();
+        let mut camera = DeltaCamera::new(Vector3::new(0.0, 0.0, 0.0), 1.0, 0.0, 0.0);
+        for _ in 0..4 {
+            paths.advance(50);
+        }
+        paths.update(&mut camera);
+        assert_eq!(camera.next.as_ref().map(|c| c.origin.x), Some(20.0));
+        assert!(paths.playing());
+        paths.advance(50);
+        assert!(!paths.playing());
+    }
+    #[test]
+    fn text_round_trip() {
+        let mut paths = CameraPaths {
+            path: path(),
+            ..CameraPaths::default()
+        };
+        paths.bookmarks.push(Bookmark {
+            name: "top of the hill".to_string(),
+            pose: pose(-1.5, 0.1),
+        });
+        let read = CameraPaths::from_text(&paths.to_text()).expect("paths are valid");
+        assert_eq!(read.bookmarks, paths.bookmarks);
+        assert_eq!(read.path, paths.path);
+        assert_eq!(
+            CameraPaths::from_text("keyframe 0 0 0 0 1 0 0").err(),
+            Some(CameraPathError::MissingHeader)
+        );
+        let text = format!("{}\nkeyframe 5 0 0 0 1 0 0\nkeyframe 5 0 0 0 1 0 0", HEADER);
+        assert_eq!(
+            CameraPaths::from_text(&text).err(),
+            Some(CameraPathError::KeyframeOrder { line: 3 })
+        );
+        let text = format!("{}\nbookmark 0 0 0 1 0 0", HEADER);
+        assert_eq!(
+            CameraPaths::from_text(&text).err(),
+            Some(CameraPathError::InvalidLine {
+                line: 2,
+                text: "bookmark 0 0 0 1 0 0".to_string()
+            })
+        );
+    }
+}
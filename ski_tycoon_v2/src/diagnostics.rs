@@ -0,0 +1,234 @@
+use super::gui::line_plot;
+use egui::{CtxRef, Srgba};
+use std::collections::VecDeque;
+/// Totals over the grid after one simulation step, see `Terrain::diagnostics`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Diagnostics {
+    /// water depth summed over every cell
+    pub volume: f32,
+    /// kinetic plus potential energy of the water
+    pub energy: f32,
+    pub max_speed: f32,
+    /// bed material raised or lowered by the sculpt brushes, the simulation
+    /// does not erode the bed
+    pub sculpted_bed: f32,
+}
+/// One of the values in `Diagnostics`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Diagnostic {
+    Volume,
+    Energy,
+    MaxSpeed,
+    SculptedBed,
+}
+impl Diagnostic {
+    pub const ALL: [Self; 4] = [
+        Self::Volume,
+        Self::Energy,
+        Self::MaxSpeed,
+        Self::SculptedBed,
+    ];
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Volume => "Total Volume",
+            Self::Energy => "Energy",
+            Self::MaxSpeed => "Max Speed",
+            Self::SculptedBed => "Sculpted Bed Change",
+        }
+    }
+    pub fn value(&self, diagnostics: &Diagnostics) -> f32 {
+        match self {
+            Self::Volume => diagnostics.volume,
+            Self::Energy => diagnostics.energy,
+            Self::MaxSpeed => diagnostics.max_speed,
+            Self::SculptedBed => diagnostics.sculpted_bed,
+        }
+    }
+    /// Color of the line in the plot
+    fn color(&self) -> Srgba {
+        match self {
+            Self::Volume => Srgba::from_rgb(80, 160, 255),
+            Self::Energy => Srgba::from_rgb(255, 170, 60),
+            Self::MaxSpeed => Srgba::from_rgb(240, 80, 80),
+            Self::SculptedBed => Srgba::from_rgb(170, 120, 70),
+        }
+    }
+}
+/// Diagnostics of the last `capacity` simulation steps, stored as a resource and
+/// drawn as line plots
+pub struct DiagnosticsHistory {
+    /// step number and diagnostics after that step, oldest first
+    samples: VecDeque<(u64, Diagnostics)>,
+    /// number of steps kept
+    capacity: usize,
+    /// diagnostics drawn in the gui
+    pub shown: Vec<Diagnostic>,
+}
+impl Default for DiagnosticsHistory {
+    fn default() -> Self {
+        Self {
+            samples: VecDeque::new(),
+            capacity: 500,
+            shown: Diagnostic::ALL.to_vec(),
+        }
+    }
+}
+impl DiagnosticsHistory {
+    const MAX_CAPACITY: usize = 10000;
+    /// Adds the diagnostics after step `step`, dropping the oldest step once full. A
+    /// step that is not after the last one means the simulation restarted so the
+    /// history is cleared first
+    pub fn push(&mut self, step: u64, diagnostics: Diagnostics) {
+        if let Some((last, _)) = self.samples.back() {
+            if step <= *last {
+                self.samples.clear();
+            }
+        }
+        self.samples.push_back((step, diagnostics));
+        self.truncate();
+    }
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+    /// Changes the number of steps kept, at least one
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity.clamp(1, Self::MAX_CAPACITY);
+        self.truncate();
+    }
+    fn truncate(&mut self) {
+        while self.samples.len() > self.capacity {
+            self.samples.pop_front();
+        }
+    }
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+    pub fn latest(&self) -> Option<&Diagnostics> {
+        self.samples.back().map(|(_, diagnostics)| diagnostics)
+    }
+    /// Values of one diagnostic, oldest first
+    pub fn series(&self, diagnostic: Diagnostic) -> Vec<f32> {
+        self.samples
+            .iter()
+            .map(|(_, diagnostics)| diagnostic.value(diagnostics))
+            .collect()
+    }
+    pub fn draw_gui(&mut self, context: &mut CtxRef) {
+        egui::Window::new("Diagnostics").show(context, |ui| {
+            let mut capacity = self.capacity;
+            ui.add(
+                egui::Slider::usize(&mut capacity, 10..=Self::MAX_CAPACITY).text("History (steps)"),
+            );
+            if capacity != self.capacity {
+                self.set_capacity(capacity);
+            }
+            if let (Some((first, _)), Some((last, _))) = (self.samples.front(), self.samples.back())
+            {
+                ui.label(format!("Steps {} to {}", first, last));
+            }
+            for diagnostic in Diagnostic::ALL.iter() {
+                let mut show = self.shown.contains(diagnostic);
+                ui.checkbox(&mut show, diagnostic.name());
+                self.shown.retain(|shown| shown != diagnostic);
+                if show {
+                    self.shown.push(*diagnostic);
+                }
+            }
+            for diagnostic in Diagnostic::ALL.iter() {
+                if !self.shown.contains(diagnostic) {
+                    continue;
+                }
+                let series = self.series(*diagnostic);
+                ui.separator();
+                if let (Some(first), Some(last)) = (series.first(), series.last()) {
+                    let min = series.iter().copied().fold(f32::INFINITY, f32::min);
+                    let max = series.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+                    ui.label(format!(
+                        "{}: {:.4} (change {:+.4}, range {:.4} to {:.4})",
+                        diagnostic.name(),
+                        last,
+                        last - first,
+                        min,
+                        max
+                    ));
+                } else {
+                    ui.label(diagnostic.name().to_string());
+                }
+                line_plot(ui, &[(&series, diagnostic.color())]);
+            }
+        });
+    }
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::prelude::{BrushTool, Falloff, Sculptor, SimulationParameters, Terrain, TerrainHit};
+    use nalgebra::{Vector2, Vector3};
+    fn sample(volume: f32) -> Diagnostics {
+        Diagnostics {
+            volume,
+            ..Diagnostics::default()
+        }
+    }
+    #[test]
+    fn ring_buffer_keeps_latest() {
+        let mut history = DiagnosticsHistory::default();
+        history.set_capacity(3);
+        for step in 1..=5 {
+            history.push(step, sample(step as f32));
+        }
+        assert_eq!(history.series(Diagnostic::Volume), vec![3.0, 4.0, 5.0]);
+        history.set_capacity(2);
+        assert_eq!(history.series(Diagnostic::Volume), vec![4.0, 5.0]);
+        // the clock going back means the scenario was rebuilt
+        history.push(1, sample(10.0));
+        assert_eq!(history.series(Diagnostic::Volume), vec![10.0]);
+        assert_eq!(history.latest(), Some(&sample(10.0)));
+    }
+    #[test]
+    fn still_water() {
+        let terrain = Terrain::flat(Vector2::new(4, 5), 2.0);
+        let diagnostics = terrain.diagnostics(0.5);
+        assert_eq!(diagnostics.volume, 40.0);
+        assert_eq!(diagnostics.energy, 20.0);
+        assert_eq!(diagnostics.max_speed, 0.0);
+        assert_eq!(diagnostics.sculpted_bed, 0.0);
+    }
+    #[test]
+    fn sculpted_bed_follows_the_brushes() {
+        let mut terrain = Terrain::flat(Vector2::new(5, 5), 1.0);
+        let mut sculptor = Sculptor::default();
+        sculptor.brush.falloff = Falloff::Constant;
+        sculptor.brush.radius = 0.5;
+        sculptor.brush.strength = 0.25;
+        let hit = Some(TerrainHit {
+            cell: Vector2::new(2, 2),
+            position: Vector3::new(2.0, 0.0, 2.0),
+        });
+        sculptor.tool = Some(BrushTool::Raise);
+        sculptor.update(true, hit, Some(&mut terrain));
+        sculptor.update(false, hit, Some(&mut terrain));
+        let sculpted = |terrain: &Terrain| terrain.diagnostics(0.5).sculpted_bed;
+        assert!((sculpted(&terrain) - 0.25).abs() < 1e-6);
+        sculptor.tool = Some(BrushTool::Lower);
+        for _ in 0..3 {
+            sculptor.update(true, hit, Some(&mut terrain));
+        }
+        sculptor.update(false, hit, Some(&mut terrain));
+        // lowering past where the bed started counts as a change too
+        assert!((sculpted(&terrain) - 0.5).abs() < 1e-6);
+        sculptor.undo(&mut terrain);
+        assert!((sculpted(&terrain) - 0.25).abs() < 1e-6);
+        sculptor.undo(&mut terrain);
+        assert!(sculpted(&terrain).abs() < 1e-6);
+        // running the simulation leaves the bed alone
+        terrain.water_simulation(&SimulationParameters::default());
+        assert!(sculpted(&terrain).abs() < 1e-6);
+    }
+}
@@ -1,12 +1,58 @@
 use js_sys::Map as JsMap;
-use log::info;
-use nalgebra::{Vector2, Vector3};
+use nalgebra::Vector2;
 use wasm_bindgen::prelude::*;
 #[derive(Clone, Debug, PartialEq)]
 pub enum MouseButton {
     LeftClick,
     MiddleClick,
     RightClick,
+    /// usually the thumb button for going back a page
+    Back,
+    Forward,
+}
+impl MouseButton {
+    /// Bits of the `buttons` field of a browser mouse event
+    const BITMASK: [(u32, MouseButton); 5] = [
+        (1, MouseButton::LeftClick),
+        (2, MouseButton::RightClick),
+        (4, MouseButton::MiddleClick),
+        (8, MouseButton::Back),
+        (16, MouseButton::Forward),
+    ];
+    /// Buttons held down in the `buttons` field of a browser mouse event, bits
+    /// without a known button are ignored
+    pub fn from_bitmask(mask: u32) -> Vec<MouseButton> {
+        Self::BITMASK
+            .iter()
+            .filter(|(bit, _)| mask & bit != 0)
+            .map(|(_, button)| button.clone())
+            .collect()
+    }
+    /// Inverse of `from_bitmask`
+    pub fn to_bitmask(buttons: &[MouseButton]) -> u32 {
+        Self::BITMASK
+            .iter()
+            .filter(|(_, button)| buttons.contains(button))
+            .fold(0, |mask, (bit, _)| mask | bit)
+    }
+    /// Button in the `button` field of a browser mouse event
+    pub fn from_index(index: u32) -> Option<MouseButton> {
+        match index {
+            0 => Some(MouseButton::LeftClick),
+            1 => Some(MouseButton::MiddleClick),
+            2 => Some(MouseButton::RightClick),
+            3 => Some(MouseButton::Back),
+            4 => Some(MouseButton::Forward),
+            _ => None,
+        }
+    }
+}
+/// Modifier keys held down during an event
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
 }
 #[derive(Clone, Debug)]
 pub struct MouseClick {
@@ -22,6 +68,7 @@ pub enum Event {
         y: f32,
         delta_time_ms: f32,
         buttons_pressed: Vec<MouseButton>,
+        modifiers: Modifiers,
     },
     ScreenSizeChange {
         new_size: Vector2<u32>,
@@ -29,134 +76,455 @@ pub enum Event {
     Scroll {
         delta_y: f32,
         delta_time_ms: f32,
-    },
-    CameraMove {
-        direction: Vector3<f32>,
+        modifiers: Modifiers,
     },
     MouseDown {
         x: f32,
         y: f32,
         button: MouseButton,
+        modifiers: Modifiers,
     },
     MouseUp {
         x: f32,
         y: f32,
+        modifiers: Modifiers,
     },
     MouseClick(MouseClick),
+    /// Character typed on the keyboard, or the name of a key that does not type one
+    /// such as `Backspace` or `ArrowLeft`
+    KeyPress {
+        key: String,
+        modifiers: Modifiers,
+    },
+    /// Saves the world view to disk at the end of the frame
+    Screenshot,
+    /// Time since the previous frame
+    FrameTime {
+        delta_time_ms: f32,
+    },
+    /// Physical pixels per logical pixel of the display, the gui is drawn this much
+    /// larger
+    ScaleFactorChange {
+        scale_factor: f32,
+    },
+}
+/// Reason an event sent from javascript could not be read
+#[derive(Clone, Debug, PartialEq)]
+pub enum EventDecodeError {
+    /// the event is not a `Map`
+    NotAMap,
+    MissingName,
+    UnknownEvent(String),
+    MissingField {
+        event: String,
+        field: &'static str,
+    },
+    /// the field has the wrong type or is out of range
+    InvalidField {
+        event: String,
+        field: &'static str,
+        value: String,
+    },
 }
 impl Event {
-    pub fn from_map(map: JsMap) -> Option<Self> {
-        let name: String = map.get(&JsValue::from_str("name")).as_string().unwrap();
+    /// Seconds covered by the `FrameTime` events of one frame, `None` when the frame
+    /// has none
+    pub fn frame_time(events: &[Event]) -> Option<f32> {
+        events
+            .iter()
+            .filter_map(|event| match event {
+                Event::FrameTime { delta_time_ms } => Some(delta_time_ms / 1000.0),
+                _ => None,
+            })
+            .fold(None, |sum, time| Some(sum.unwrap_or(0.0) + time))
+    }
+    pub fn from_map(map: JsMap) -> Result<Self, EventDecodeError> {
+        let name = map
+            .get(&JsValue::from_str("name"))
+            .as_string()
+            .ok_or(EventDecodeError::MissingName)?;
+        let fields = Fields {
+            map: &map,
+            event: &name,
+        };
         match name.as_str() {
-            "mouse_move" => Some(Self::from_mouse_move_map(map)),
-            "mousedown" => Self::from_mousedown(map),
-            "mouseup" => Self::from_mouseup(map),
-            "wheel" => Some(Self::from_wheel_map(map)),
-            "keypress" => Self::from_keypress(map),
-            "screen_size_change" => Some(Self::from_screen_size_change(map)),
-            _ => panic!("invalid name"),
+            "mouse_move" => fields.mouse_move(),
+            "mousedown" => fields.mouse_down(),
+            "mouseup" => fields.mouse_up(),
+            "wheel" => fields.wheel(),
+            "keypress" => fields.keypress(),
+            "screen_size_change" => fields.screen_size_change(),
+            "frame_time" => fields.frame_time(),
+            "scale_factor_change" => fields.scale_factor_change(),
+            _ => Err(EventDecodeError::UnknownEvent(name.clone())),
         }
     }
-    fn from_screen_size_change(map: JsMap) -> Self {
-        let x = map.get(&"x".into()).as_f64().unwrap() as u32;
-        let y = map.get(&"y".into()).as_f64().unwrap() as u32;
-        Self::ScreenSizeChange {
-            new_size: Vector2::new(x, y),
+}
+/// Fields of one event sent from javascript
+struct Fields<'a> {
+    map: &'a JsMap,
+    event: &'a str,
+}
+impl<'a> Fields<'a> {
+    fn get(&self, field: &'static str) -> Result<JsValue, EventDecodeError> {
+        let value = self.map.get(&JsValue::from_str(field));
+        if value.is_undefined() {
+            Err(EventDecodeError::MissingField {
+                event: self.event.to_string(),
+                field,
+            })
+        } else {
+            Ok(value)
         }
     }
-    fn from_mousedown(map: JsMap) -> Option<Self> {
-        let button_i32 = map.get(&JsValue::from_str("buttons")).as_f64().unwrap();
-        let button_vec = Self::get_mouse_button(button_i32 as i32);
-        if button_vec.is_empty() {
-            None
-        } else {
-            let button = button_vec[0].clone();
-            info!("button: {:?}", button);
-            let x = map.get(&JsValue::from_str("x")).as_f64().unwrap() as f32;
-            let y = map.get(&JsValue::from_str("y")).as_f64().unwrap() as f32;
-            Some(Self::MouseDown { button, x, y })
+    fn invalid(&self, field: &'static str, value: String) -> EventDecodeError {
+        EventDecodeError::InvalidField {
+            event: self.event.to_string(),
+            field,
+            value,
+        }
+    }
+    fn number(&self, field: &'static str) -> Result<f32, EventDecodeError> {
+        let value = self.get(field)?;
+        match value.as_f64() {
+            Some(number) if number.is_finite() => Ok(number as f32),
+            _ => Err(self.invalid(field, format!("{:?}", value))),
         }
     }
-    fn from_mouseup(map: JsMap) -> Option<Self> {
-        let button_i32 = map.get(&JsValue::from_str("buttons")).as_f64().unwrap();
-        let button_vec = Self::get_mouse_button(button_i32 as i32);
-        if button_vec.is_empty() {
-            let x = map.get(&JsValue::from_str("x")).as_f64().unwrap() as f32;
-            let y = map.get(&JsValue::from_str("y")).as_f64().unwrap() as f32;
-            Some(Self::MouseUp { x, y })
+    /// Whole number that is not negative
+    fn unsigned(&self, field: &'static str) -> Result<u32, EventDecodeError> {
+        let number = self.number(field)?;
+        if number >= 0.0 && number.fract() == 0.0 && number <= u32::MAX as f32 {
+            Ok(number as u32)
         } else {
-            let button = button_vec[0].clone();
-            info!("button: {:?}", button);
-            let x = map.get(&JsValue::from_str("x")).as_f64().unwrap() as f32;
-            let y = map.get(&JsValue::from_str("y")).as_f64().unwrap() as f32;
-            Some(Self::MouseUp { x, y })
+            Err(self.invalid(field, number.to_string()))
         }
     }
-    fn from_keypress(map: JsMap) -> Option<Self> {
-        let key: String = map.get(&JsValue::from_str("key")).as_string().unwrap();
-        match key.as_str() {
-            "w" => Some(Self::CameraMove {
-                direction: Vector3::new(0.0, 0.0, -1.0),
-            }),
-            "a" => Some(Self::CameraMove {
-                direction: Vector3::new(-1.0, 0.0, 0.0),
-            }),
-            "s" => Some(Self::CameraMove {
-                direction: Vector3::new(0.0, 0.0, 1.0),
-            }),
-            "d" => Some(Self::CameraMove {
-                direction: Vector3::new(1.0, 0.0, 0.0),
-            }),
-            _ => None,
+    fn string(&self, field: &'static str) -> Result<String, EventDecodeError> {
+        let value = self.get(field)?;
+        value
+            .as_string()
+            .ok_or_else(|| self.invalid(field, format!("{:?}", value)))
+    }
+    /// Modifier keys are optional and default to not pressed
+    fn modifiers(&self) -> Modifiers {
+        let flag = |field: &str| {
+            self.map
+                .get(&JsValue::from_str(field))
+                .as_bool()
+                .unwrap_or(false)
+        };
+        Modifiers {
+            shift: flag("shift"),
+            ctrl: flag("ctrl"),
+            alt: flag("alt"),
         }
     }
-    fn from_wheel_map(map: JsMap) -> Self {
-        let delta_y = map.get(&JsValue::from_str("delta_y")).as_f64().unwrap() as f32;
-        let delta_time_ms = map
-            .get(&JsValue::from_str("delta_time_ms"))
-            .as_f64()
-            .unwrap() as f32;
-        Event::Scroll {
-            delta_y,
-            delta_time_ms,
+    fn screen_size_change(&self) -> Result<Event, EventDecodeError> {
+        Ok(Event::ScreenSizeChange {
+            new_size: Vector2::new(self.unsigned("x")?, self.unsigned("y")?),
+        })
+    }
+    fn frame_time(&self) -> Result<Event, EventDecodeError> {
+        Ok(Event::FrameTime {
+            delta_time_ms: self.number("delta_time_ms")?,
+        })
+    }
+    fn scale_factor_change(&self) -> Result<Event, EventDecodeError> {
+        let scale_factor = self.number("scale_factor")?;
+        if scale_factor > 0.0 {
+            Ok(Event::ScaleFactorChange { scale_factor })
+        } else {
+            Err(self.invalid("scale_factor", scale_factor.to_string()))
         }
     }
-    fn get_mouse_button(mouse: i32) -> Vec<MouseButton> {
-        match mouse {
-            0 => vec![],
-            1 => vec![MouseButton::RightClick],
-            2 => vec![MouseButton::LeftClick],
-            3 => vec![MouseButton::LeftClick, MouseButton::RightClick],
-            4 => vec![MouseButton::MiddleClick],
-            5 => vec![MouseButton::RightClick, MouseButton::MiddleClick],
-            6 => vec![MouseButton::MiddleClick, MouseButton::LeftClick],
-            7 => vec![
+    fn mouse_down(&self) -> Result<Event, EventDecodeError> {
+        // older pages only send the buttons held after the press
+        let button = if self.map.has(&JsValue::from_str("button")) {
+            let index = self.unsigned("button")?;
+            MouseButton::from_index(index)
+                .ok_or_else(|| self.invalid("button", index.to_string()))?
+        } else {
+            let mask = self.unsigned("buttons")?;
+            MouseButton::from_bitmask(mask)
+                .into_iter()
+                .next()
+                .ok_or_else(|| self.invalid("buttons", mask.to_string()))?
+        };
+        Ok(Event::MouseDown {
+            x: self.number("x")?,
+            y: self.number("y")?,
+            button,
+            modifiers: self.modifiers(),
+        })
+    }
+    fn mouse_up(&self) -> Result<Event, EventDecodeError> {
+        Ok(Event::MouseUp {
+            x: self.number("x")?,
+            y: self.number("y")?,
+            modifiers: self.modifiers(),
+        })
+    }
+    fn keypress(&self) -> Result<Event, EventDecodeError> {
+        Ok(Event::KeyPress {
+            key: self.string("key")?,
+            modifiers: self.modifiers(),
+        })
+    }
+    fn wheel(&self) -> Result<Event, EventDecodeError> {
+        Ok(Event::Scroll {
+            delta_y: self.number("delta_y")?,
+            delta_time_ms: self.number("delta_time_ms")?,
+            modifiers: self.modifiers(),
+        })
+    }
+    fn mouse_move(&self) -> Result<Event, EventDecodeError> {
+        Ok(Event::MouseMove {
+            delta_x: self.number("delta_x")?,
+            delta_y: self.number("delta_y")?,
+            x: self.number("x")?,
+            y: self.number("y")?,
+            delta_time_ms: self.number("delta_time_ms")?,
+            buttons_pressed: MouseButton::from_bitmask(self.unsigned("buttons")?),
+            modifiers: self.modifiers(),
+        })
+    }
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn bitmask() {
+        assert_eq!(MouseButton::from_bitmask(0), vec![]);
+        assert_eq!(MouseButton::from_bitmask(1), vec![MouseButton::LeftClick]);
+        assert_eq!(
+            MouseButton::from_bitmask(7),
+            vec![
                 MouseButton::LeftClick,
-                MouseButton::MiddleClick,
                 MouseButton::RightClick,
+                MouseButton::MiddleClick
+            ]
+        );
+        assert_eq!(
+            MouseButton::from_bitmask(24),
+            vec![MouseButton::Back, MouseButton::Forward]
+        );
+        // unknown bits are ignored
+        assert_eq!(
+            MouseButton::from_bitmask(2 | 64),
+            vec![MouseButton::RightClick]
+        );
+        let buttons = vec![MouseButton::Forward, MouseButton::LeftClick];
+        assert_eq!(MouseButton::to_bitmask(&buttons), 17);
+        assert_eq!(MouseButton::to_bitmask(&[]), 0);
+    }
+    #[test]
+    fn frame_time() {
+        assert_eq!(Event::frame_time(&[Event::Screenshot]), None);
+        let events = vec![
+            Event::FrameTime {
+                delta_time_ms: 250.0,
+            },
+            Event::Screenshot,
+            Event::FrameTime {
+                delta_time_ms: 500.0,
+            },
+        ];
+        assert_eq!(Event::frame_time(&events), Some(0.75));
+    }
+    #[test]
+    fn button_index() {
+        assert_eq!(MouseButton::from_index(0), Some(MouseButton::LeftClick));
+        assert_eq!(MouseButton::from_index(2), Some(MouseButton::RightClick));
+        assert_eq!(MouseButton::from_index(5), None);
+    }
+}
+/// `js_sys` only works inside a javascript engine, run with `wasm-pack test --node`
+#[cfg(all(test, target_arch = "wasm32"))]
+mod wasm_test {
+    use super::*;
+    use wasm_bindgen_test::*;
+    fn map(name: &str, fields: &[(&str, JsValue)]) -> JsMap {
+        let map = JsMap::new();
+        map.set(&JsValue::from_str("name"), &JsValue::from_str(name));
+        for (field, value) in fields.iter() {
+            map.set(&JsValue::from_str(field), value);
+        }
+        map
+    }
+    fn number(value: f64) -> JsValue {
+        JsValue::from_f64(value)
+    }
+    fn invalid(event: &str, field: &'static str, value: &str) -> EventDecodeError {
+        EventDecodeError::InvalidField {
+            event: event.to_string(),
+            field,
+            value: value.to_string(),
+        }
+    }
+    #[wasm_bindgen_test]
+    fn missing_name() {
+        let map = JsMap::new();
+        map.set(&JsValue::from_str("x"), &number(1.0));
+        assert_eq!(
+            Event::from_map(map).unwrap_err(),
+            EventDecodeError::MissingName
+        );
+        // a name that is not a string counts as missing
+        let map = JsMap::new();
+        map.set(&JsValue::from_str("name"), &number(3.0));
+        assert_eq!(
+            Event::from_map(map).unwrap_err(),
+            EventDecodeError::MissingName
+        );
+    }
+    #[wasm_bindgen_test]
+    fn unknown_event() {
+        assert_eq!(
+            Event::from_map(map("teleport", &[])).unwrap_err(),
+            EventDecodeError::UnknownEvent("teleport".to_string())
+        );
+    }
+    #[wasm_bindgen_test]
+    fn missing_field() {
+        let event = map("wheel", &[("delta_y", number(-3.0))]);
+        assert_eq!(
+            Event::from_map(event).unwrap_err(),
+            EventDecodeError::MissingField {
+                event: "wheel".to_string(),
+                field: "delta_time_ms",
+            }
+        );
+    }
+    #[wasm_bindgen_test]
+    fn invalid_fields() {
+        let event = map("keypress", &[("key", number(65.0))]);
+        assert!(matches!(
+            Event::from_map(event).unwrap_err(),
+            EventDecodeError::InvalidField { field: "key", .. }
+        ));
+        let event = map("frame_time", &[("delta_time_ms", number(f64::NAN))]);
+        assert!(matches!(
+            Event::from_map(event).unwrap_err(),
+            EventDecodeError::InvalidField {
+                field: "delta_time_ms",
+                ..
+            }
+        ));
+        let event = map(
+            "screen_size_change",
+            &[("x", number(-4.0)), ("y", number(3.0))],
+        );
+        assert_eq!(
+            Event::from_map(event).unwrap_err(),
+            invalid("screen_size_change", "x", "-4")
+        );
+        let event = map(
+            "screen_size_change",
+            &[("x", number(4.5)), ("y", number(3.0))],
+        );
+        assert_eq!(
+            Event::from_map(event).unwrap_err(),
+            invalid("screen_size_change", "x", "4.5")
+        );
+        let event = map(
+            "mousedown",
+            &[
+                ("x", number(1.0)),
+                ("y", number(2.0)),
+                ("button", number(7.0)),
             ],
-            _ => panic!("invalid button number"),
+        );
+        assert_eq!(
+            Event::from_map(event).unwrap_err(),
+            invalid("mousedown", "button", "7")
+        );
+    }
+    #[wasm_bindgen_test]
+    fn scale_factor_must_be_positive() {
+        for scale_factor in [0.0, -2.0].iter() {
+            let event = map(
+                "scale_factor_change",
+                &[("scale_factor", number(*scale_factor))],
+            );
+            assert!(matches!(
+                Event::from_map(event).unwrap_err(),
+                EventDecodeError::InvalidField {
+                    field: "scale_factor",
+                    ..
+                }
+            ));
         }
+        let event = map("scale_factor_change", &[("scale_factor", number(2.0))]);
+        assert!(matches!(
+            Event::from_map(event).unwrap(),
+            Event::ScaleFactorChange { scale_factor } if scale_factor == 2.0
+        ));
     }
-    fn from_mouse_move_map(map: JsMap) -> Self {
-        let buttons_pressed_number: i32 =
-            map.get(&JsValue::from_str("buttons")).as_f64().unwrap() as i32;
-        let buttons_pressed = Self::get_mouse_button(buttons_pressed_number);
-        let delta_x = map.get(&JsValue::from_str("delta_x")).as_f64().unwrap() as f32;
-        let delta_y = map.get(&JsValue::from_str("delta_y")).as_f64().unwrap() as f32;
-        let x = map.get(&JsValue::from_str("x")).as_f64().unwrap() as f32;
-        let y = map.get(&JsValue::from_str("y")).as_f64().unwrap() as f32;
-        let delta_time_ms = map
-            .get(&JsValue::from_str("delta_time_ms"))
-            .as_f64()
-            .unwrap() as f32;
-        Event::MouseMove {
-            delta_x,
-            delta_y,
-            x,
-            y,
-            buttons_pressed,
-            delta_time_ms,
+    #[wasm_bindgen_test]
+    fn mouse_down_button() {
+        let event = map(
+            "mousedown",
+            &[
+                ("x", number(10.0)),
+                ("y", number(20.0)),
+                ("button", number(2.0)),
+                ("buttons", number(1.0)),
+                ("shift", JsValue::from_bool(true)),
+            ],
+        );
+        match Event::from_map(event).unwrap() {
+            Event::MouseDown {
+                x,
+                y,
+                button,
+                modifiers,
+            } => {
+                assert_eq!((x, y), (10.0, 20.0));
+                // `button` wins over `buttons` when both are sent
+                assert_eq!(button, MouseButton::RightClick);
+                assert!(modifiers.shift && !modifiers.ctrl && !modifiers.alt);
+            }
+            event => panic!("decoded {:?}", event),
         }
     }
+    #[wasm_bindgen_test]
+    fn mouse_down_from_buttons() {
+        // older pages only send the bitmask of held buttons
+        let event = map(
+            "mousedown",
+            &[
+                ("x", number(1.0)),
+                ("y", number(2.0)),
+                ("buttons", number(4.0)),
+            ],
+        );
+        assert!(matches!(
+            Event::from_map(event).unwrap(),
+            Event::MouseDown {
+                button: MouseButton::MiddleClick,
+                ..
+            }
+        ));
+        let event = map(
+            "mousedown",
+            &[
+                ("x", number(1.0)),
+                ("y", number(2.0)),
+                ("buttons", number(0.0)),
+            ],
+        );
+        assert_eq!(
+            Event::from_map(event).unwrap_err(),
+            invalid("mousedown", "buttons", "0")
+        );
+        let event = map("mousedown", &[("x", number(1.0)), ("y", number(2.0))]);
+        assert_eq!(
+            Event::from_map(event).unwrap_err(),
+            EventDecodeError::MissingField {
+                event: "mousedown".to_string(),
+                field: "buttons",
+            }
+        );
+    }
 }
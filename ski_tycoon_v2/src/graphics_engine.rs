@@ -1,13 +1,19 @@
-#[cfg(not(target_arch = "wasm32"))]
+mod backend;
+#[cfg(all(not(target_arch = "wasm32"), not(feature = "software")))]
 mod gfx;
 mod mesh;
-#[cfg(target_arch = "wasm32")]
+#[cfg(any(test, feature = "software"))]
+mod software;
+#[cfg(all(target_arch = "wasm32", not(feature = "software")))]
 mod webgl;
-#[cfg(not(target_arch = "wasm32"))]
+pub use backend::RenderBackend;
+#[cfg(all(not(target_arch = "wasm32"), not(feature = "software")))]
 pub use gfx::*;
-pub use mesh::{ItemDesc, Mesh, Vertex};
+pub use mesh::{Indices, ItemDesc, Mesh, Vertex};
 use nalgebra::{Matrix4, Vector3};
-#[cfg(target_arch = "wasm32")]
+#[cfg(feature = "software")]
+pub use software::*;
+#[cfg(all(target_arch = "wasm32", not(feature = "software")))]
 pub use webgl::*;
 #[derive(Debug, Clone)]
 pub struct Transform {
@@ -0,0 +1,111 @@
+use super::super::prelude::Texture;
+use super::Mesh;
+use nalgebra::{Matrix4, Vector2, Vector3, Vector4};
+/// Api shared by every rendering backend. The backend used by the game is
+/// selected at compile time through `RenderingContext`
+pub trait RenderBackend {
+    type Shader;
+    type Mesh;
+    type Texture;
+    type DepthTexture;
+    type Framebuffer;
+    type Error: std::fmt::Debug;
+    fn change_viewport(&mut self, screen_size: &Vector2<u32>) -> Result<(), Self::Error>;
+    fn build_world_shader(&mut self) -> Result<Self::Shader, Self::Error>;
+    /// Builds translucent shader used for the water surface. Meshes drawn with it
+    /// use the layout of `ItemDesc::water_model`
+    fn build_water_shader(&mut self) -> Result<Self::Shader, Self::Error>;
+    /// Builds depth only shader used to render shadow maps. Meshes drawn with it
+    /// use the layout of `ItemDesc::default_model`
+    fn build_shadow_shader(&mut self) -> Result<Self::Shader, Self::Error>;
+    /// Builds depth only shader used to render the water surface into shadow maps.
+    /// Meshes drawn with it use the layout of `ItemDesc::water_model`
+    fn build_water_shadow_shader(&mut self) -> Result<Self::Shader, Self::Error>;
+    /// Builds shader used for screenspace
+    fn build_screen_shader(&mut self) -> Result<Self::Shader, Self::Error>;
+    fn build_gui_shader(&mut self) -> Result<Self::Shader, Self::Error>;
+    fn bind_shader(&mut self, shader: &Self::Shader) -> Result<(), Self::Error>;
+    fn build_mesh(&mut self, mesh: Mesh, shader: &Self::Shader) -> Result<Self::Mesh, Self::Error>;
+    /// Overwrites part of the vertex data of a mesh, `offset` is counted in floats.
+    /// The number of vertices in the mesh does not change
+    fn update_mesh(
+        &mut self,
+        mesh: &mut Self::Mesh,
+        offset: usize,
+        vertices: &[f32],
+    ) -> Result<(), Self::Error>;
+    fn delete_mesh(&mut self, mesh: &mut Self::Mesh) -> Result<(), Self::Error>;
+    fn send_float_uniform(
+        &mut self,
+        shader: &Self::Shader,
+        uniform_name: &str,
+        data: f32,
+    ) -> Result<(), Self::Error>;
+    fn send_vec3_uniform(
+        &mut self,
+        shader: &Self::Shader,
+        uniform_name: &str,
+        data: Vector3<f32>,
+    ) -> Result<(), Self::Error>;
+    fn send_vec4_uniform(
+        &mut self,
+        shader: &Self::Shader,
+        uniform_name: &str,
+        data: Vector4<f32>,
+    ) -> Result<(), Self::Error>;
+    fn send_mat4_uniform(
+        &mut self,
+        shader: &Self::Shader,
+        uniform_name: &str,
+        data: Matrix4<f32>,
+    ) -> Result<(), Self::Error>;
+    fn build_depth_texture(
+        &mut self,
+        dimensions: Vector2<u32>,
+        shader: &Self::Shader,
+    ) -> Result<Self::DepthTexture, Self::Error>;
+    fn delete_depth_buffer(&mut self, texture: &mut Self::DepthTexture) -> Result<(), Self::Error>;
+    fn build_texture(
+        &mut self,
+        texture: Texture,
+        shader: &Self::Shader,
+    ) -> Result<Self::Texture, Self::Error>;
+    /// Replaces the pixels of a texture without building a new one, for textures
+    /// that change every frame. The dimensions may change as long as the texture is
+    /// not attached to a framebuffer
+    fn update_texture(
+        &mut self,
+        texture: &mut Self::Texture,
+        data: Texture,
+    ) -> Result<(), Self::Error>;
+    fn delete_texture(&mut self, texture: &mut Self::Texture);
+    /// Copies a texture that has been rendered to back to the cpu. The first row
+    /// of the result is the top of the rendered image. Leaves the default
+    /// framebuffer bound
+    fn read_texture(&mut self, texture: &Self::Texture) -> Result<Texture, Self::Error>;
+    fn build_framebuffer(
+        &mut self,
+        texture_attachment: &mut Self::Texture,
+        depth_attachment: &mut Self::DepthTexture,
+    ) -> Result<Self::Framebuffer, Self::Error>;
+    fn delete_framebuffer(
+        &mut self,
+        framebuffer: &mut Self::Framebuffer,
+    ) -> Result<(), Self::Error>;
+    fn bind_default_framebuffer(&mut self);
+    fn clear_screen(&mut self, color: Vector4<f32>);
+    fn clear_depth(&mut self);
+    fn bind_texture(&mut self, texture: &Self::Texture, shader: &Self::Shader);
+    /// Binds a depth texture that has been rendered to as the shadow map sampled
+    /// by `shader`. The texture must not be attached to the bound framebuffer
+    fn bind_shadow_map(&mut self, texture: &Self::DepthTexture, shader: &Self::Shader);
+    fn bind_framebuffer(&mut self, framebuffer: &Self::Framebuffer);
+    fn draw_mesh(&mut self, mesh: &Self::Mesh);
+    fn draw_lines(&mut self, mesh: &Self::Mesh);
+    /// Called once all drawing for a frame is done
+    fn finish_frame(&mut self);
+    fn send_model_matrix(&mut self, matrix: Matrix4<f32>, shader: &Self::Shader);
+    fn send_view_matrix(&mut self, matrix: Matrix4<f32>, shader: &Self::Shader);
+    /// Checks that the backend is in a valid state, panics if it is not
+    fn get_error(&self);
+}
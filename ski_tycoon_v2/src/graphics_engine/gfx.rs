@@ -2,7 +2,7 @@
 mod bind_arena;
 mod shader;
 use super::super::prelude::Texture;
-use super::Mesh;
+use super::{Indices, ItemDesc, Mesh, RenderBackend};
 use bind_arena::{BindArena, BindArenaIndex};
 use gfx_hal::{
     adapter::PhysicalDevice,
@@ -11,6 +11,7 @@ use gfx_hal::{
     device::Device,
     format,
     format::{AsFormat, ChannelType, Rgba8Srgb as ColorFormat, Swizzle},
+    image::Layout,
     memory, pass,
     pass::Subpass,
     pool,
@@ -24,8 +25,9 @@ use gfx_hal::{
 };
 use shader::ShaderData;
 
+use log::error;
 use std::collections::HashMap;
-use std::{cmp::max, io::Cursor, iter, mem, mem::ManuallyDrop, ptr};
+use std::{borrow::Borrow, iter, mem::ManuallyDrop, ptr, sync::Arc};
 
 use nalgebra::{Matrix4, Vector2, Vector3, Vector4};
 mod circular_buffer;
@@ -50,128 +52,211 @@ extern crate gfx_backend_vulkan as back;
 
 pub type RuntimeMesh = RuntimeGfxMesh<back::Backend>;
 pub type RuntimeTexture = RuntimeGfxTexture<back::Backend>;
-#[derive(Clone)]
+/// Format used by depth attachments
+const DEPTH_FORMAT: format::Format = format::Format::D32Sfloat;
+/// Usage of every color texture. Textures can be sampled, rendered to and read
+/// back so every texture can be attached to a framebuffer
+const TEXTURE_USAGE: gfx_hal::image::Usage = gfx_hal::image::Usage::from_bits_truncate(
+    gfx_hal::image::Usage::TRANSFER_DST.bits()
+        | gfx_hal::image::Usage::TRANSFER_SRC.bits()
+        | gfx_hal::image::Usage::SAMPLED.bits()
+        | gfx_hal::image::Usage::COLOR_ATTACHMENT.bits(),
+);
+/// Usage of every depth texture. Depth textures can be sampled as shadow maps
+const DEPTH_USAGE: gfx_hal::image::Usage = gfx_hal::image::Usage::from_bits_truncate(
+    gfx_hal::image::Usage::DEPTH_STENCIL_ATTACHMENT.bits() | gfx_hal::image::Usage::SAMPLED.bits(),
+);
+/// Layout depth textures are kept in, general so that they can be both rendered to
+/// and sampled without transitions between passes
+const DEPTH_LAYOUT: Layout = Layout::General;
+/// Maximum number of shaders that can be alive at once
+const MAX_SHADERS: usize = 16;
+/// Maximum number of uniforms a single shader can have
+const MAX_UNIFORMS: usize = 8;
+/// Size in bytes of the buffer the uniforms of one submission are written into
+const UNIFORM_RING_SIZE: u64 = 1 << 20;
+/// Maximum number of texture sets one submission can allocate
+const MAX_TEXTURE_SETS: usize = 1024;
+/// Image with its backing memory and a view into it
+pub struct GfxImage<B: gfx_hal::Backend> {
+    image: B::Image,
+    memory: B::Memory,
+    view: B::ImageView,
+}
+/// Buffer with its backing cpu visible memory
+pub struct GfxBuffer<B: gfx_hal::Backend> {
+    buffer: B::Buffer,
+    memory: B::Memory,
+}
+/// Buffer every draw of a submission writes its uniforms into, the uniforms are
+/// bound at dynamic offsets so they are not overwritten by later draws
+struct UniformRing<B: gfx_hal::Backend> {
+    buffer: GfxBuffer<B>,
+    /// start of the free part of the buffer
+    offset: u64,
+    /// alignment of dynamic offsets required by the device
+    alignment: u64,
+}
+/// Resource deleted while commands using it may still be executing, destroyed
+/// once the submission has finished
+enum Garbage<B: gfx_hal::Backend> {
+    Buffer(GfxBuffer<B>),
+    Framebuffer(FramebufferData<B>),
+    /// images can also be kept alive by a shader they are bound to
+    Image(Arc<GfxImage<B>>),
+}
+/// Target of the render pass being recorded
+#[derive(Clone, PartialEq)]
+enum PassTarget {
+    Surface,
+    Framebuffer(BindArenaIndex),
+}
 pub struct RuntimeGfxTexture<B: gfx_hal::Backend> {
-    image_buffer: B::Buffer,
-    image_memory: B::Memory,
-    image_logo: B::Image,
+    image: Option<Arc<GfxImage<B>>>,
     extent: gfx_hal::image::Extent,
 }
 #[derive(Debug)]
 pub enum ErrorType {}
-#[allow(dead_code)]
-pub struct GfxFramebuffer<B: gfx_hal::Backend> {
+pub struct GfxFramebuffer {
+    index: BindArenaIndex,
+}
+pub type Framebuffer = GfxFramebuffer;
+/// Framebuffer owned by the rendering context. Owns its own views of the attachments
+/// so that they can be passed in when beginning a render pass
+struct FramebufferData<B: gfx_hal::Backend> {
     framebuffer: B::Framebuffer,
+    color_view: B::ImageView,
+    depth_view: B::ImageView,
+    extent: gfx_hal::image::Extent,
 }
-pub type Framebuffer = GfxFramebuffer<back::Backend>;
-#[derive(Clone)]
 pub struct RuntimeGfxMesh<B: gfx_hal::Backend> {
-    vertex_buffer: B::Buffer,
-    vertex_memory: B::Memory,
+    vertex_buffer: Option<GfxBuffer<B>>,
+    index_buffer: Option<(GfxBuffer<B>, gfx_hal::IndexType)>,
+    /// number of vertices or indices drawn
+    count: u32,
 }
 pub type RuntimeDepthTexture = RuntimeGfxDepthTexture<back::Backend>;
-#[allow(dead_code)]
 pub struct RuntimeGfxDepthTexture<B: gfx_hal::Backend> {
-    image_logo: B::Image,
-    image_buffer: B::Buffer,
-    image_memory: B::Memory,
+    image: Option<Arc<GfxImage<B>>>,
     extent: gfx_hal::image::Extent,
 }
-#[allow(dead_code)]
-struct Vertex {
-    pos: [f32; 3],
-    uv: [f32; 2],
-}
-const QUAD: [Vertex; 6] = [
-    Vertex {
-        pos: [-1.0, 1.0, 0.0],
-        uv: [0.0, 1.0],
-    },
-    Vertex {
-        pos: [1.0, 1.0, 0.0],
-        uv: [1.0, 1.0],
-    },
-    Vertex {
-        pos: [1.0, -1.0, 0.0],
-        uv: [1.0, 0.0],
-    },
-    Vertex {
-        pos: [-1.0, 1.0, 0.0],
-        uv: [0.0, 1.0],
-    },
-    Vertex {
-        pos: [1.0, -1.0, 0.0],
-        uv: [1.0, 0.0],
-    },
-    Vertex {
-        pos: [-1.0, -1.0, 0.0],
-        uv: [0.0, 0.0],
-    },
-];
 pub struct Window<B: gfx_hal::Backend> {
     pub instance: B::Instance,
     pub surface: B::Surface,
     pub adapter: gfx_hal::adapter::Adapter<B>,
     pub window_dimensions: window::Extent2D,
 }
+/// Render passes used for a target. Both passes are compatible so pipelines
+/// built for one can be used with the other
+struct RenderPasses<B: gfx_hal::Backend> {
+    /// clears attachments at the start of the pass
+    clear: B::RenderPass,
+    /// keeps the previous contents of the attachments
+    load: B::RenderPass,
+}
+#[derive(Clone, Copy)]
+enum DrawMode {
+    Triangles,
+    Lines,
+}
 
+/// Records every command of a frame into one command buffer that is submitted by
+/// `finish_frame`. The next frame waits for it before reusing any per frame resource,
+/// so the cpu only blocks once per frame
 #[allow(dead_code)]
 pub struct GfxRenderingContext<B: gfx_hal::Backend> {
+    /// uniform sets, allocated once per shader
     desc_pool: ManuallyDrop<B::DescriptorPool>,
+    /// texture sets, reset once the submission using them has finished
+    frame_desc_pool: ManuallyDrop<B::DescriptorPool>,
+    uniform_ring: ManuallyDrop<UniformRing<B>>,
     surface: ManuallyDrop<B::Surface>,
     format: gfx_hal::format::Format,
     dimensions: window::Extent2D,
-    viewport: pso::Viewport,
     supported_formats: std::vec::Vec<format::Format>,
-    render_pass: ManuallyDrop<B::RenderPass>,
+    /// passes used when rendering to the swapchain
+    surface_passes: ManuallyDrop<RenderPasses<B>>,
+    /// passes used when rendering to a texture
+    offscreen_passes: ManuallyDrop<RenderPasses<B>>,
+    /// framebuffer for the swapchain images
     framebuffer: ManuallyDrop<B::Framebuffer>,
-    pipeline: ManuallyDrop<B::GraphicsPipeline>,
-    pipeline_layout: ManuallyDrop<B::PipelineLayout>,
-    desc_set: Option<B::DescriptorSet>,
+    surface_depth: Option<RuntimeGfxDepthTexture<B>>,
+    swapchain_image: Option<<B::Surface as PresentationSurface<B>>::SwapchainImage>,
+    /// layout of the texture and sampler descriptor set. Shared by all shaders
     set_layout: ManuallyDrop<B::DescriptorSetLayout>,
-    submission_complete_semaphore: B::Semaphore,
-    submission_complete_fence: B::Fence,
-    command_pool: B::CommandPool,
-    command_buffer: B::CommandBuffer,
-    vertex_buffer: ManuallyDrop<B::Buffer>,
-    buffer_memory: ManuallyDrop<B::Memory>,
+    sampler: ManuallyDrop<B::Sampler>,
+    /// unfiltered sampler used for shadow maps
+    shadow_sampler: ManuallyDrop<B::Sampler>,
+    /// signaled when the last submission has finished executing
+    submission_complete_fence: ManuallyDrop<B::Fence>,
+    /// signaled when the commands drawing to the swapchain image have finished, waited
+    /// on by the presentation
+    rendering_complete: ManuallyDrop<B::Semaphore>,
+    command_pool: ManuallyDrop<B::CommandPool>,
+    command_buffer: ManuallyDrop<B::CommandBuffer>,
+    /// the command buffer has been started and not submitted yet
+    recording: bool,
+    /// a submission has been made that has not been waited on
+    in_flight: bool,
+    /// counts the submissions waited on. Texture sets and uniform offsets are only
+    /// valid during the submission they were made in
+    submission: u64,
+    /// render pass currently being recorded
+    open_pass: Option<PassTarget>,
+    garbage: Vec<Garbage<B>>,
+    shaders: BindArena<GfxShader<B>>,
+    framebuffers: BindArena<FramebufferData<B>>,
     //should be dropped in decleration order
     device: B::Device,
     adapter: gfx_hal::adapter::Adapter<B>,
     queue_group: QueueGroup<B>,
     instance: B::Instance,
-    shaders: BindArena<GfxShader<B>>,
 }
 pub struct Shader {
     index: BindArenaIndex,
 }
-#[allow(dead_code)]
-struct RuntimeUniform<B: gfx_hal::Backend> {
-    buffer: B::Buffer,
-    memory: B::Memory,
+/// Pipelines for drawing triangles and lines into one kind of target
+struct PipelineSet<B: gfx_hal::Backend> {
+    triangles: B::GraphicsPipeline,
+    lines: B::GraphicsPipeline,
 }
-#[allow(dead_code)]
 pub struct GfxShader<B: gfx_hal::Backend> {
-    pipeline: B::GraphicsPipeline,
-    fragment_shader_uniform_buffers: HashMap<String, RuntimeUniform<B>>,
-    vertex_shader_uniform_buffers: HashMap<String, RuntimeUniform<B>>,
+    /// pipelines used when drawing to the swapchain
+    surface: PipelineSet<B>,
+    /// pipelines used when drawing to a texture
+    offscreen: PipelineSet<B>,
+    pipeline_layout: B::PipelineLayout,
+    uniform_set_layout: B::DescriptorSetLayout,
+    texture: Option<Arc<GfxImage<B>>>,
+    shadow_map: Option<Arc<GfxImage<B>>>,
+    /// set 0, the bound texture and sampler followed by the shadow map and its sampler.
+    /// Allocated again when the bindings change or a new submission starts
+    texture_set: Option<(u64, B::DescriptorSet)>,
+    /// set 1, every uniform points into the uniform ring
+    uniform_set: B::DescriptorSet,
+    vertex_layout: Vec<ItemDesc>,
+    uniforms: HashMap<String, ShaderUniform>,
+    /// offsets into the uniform ring of the values last written and the submission
+    /// they were written in
+    uniform_offsets: Option<(u64, Vec<u32>)>,
+}
+/// Latest value of a uniform, copied into the uniform ring before a draw
+struct ShaderUniform {
+    binding: u32,
+    data: Vec<u8>,
 }
 pub type RenderingContext = GfxRenderingContext<back::Backend>;
 pub type InitContext = Window<back::Backend>;
-const DIMS: window::Extent2D = window::Extent2D {
-    width: 1024,
-    height: 768,
-};
 #[allow(dead_code, unused_variables)]
 impl<B: gfx_hal::Backend> GfxRenderingContext<B> {
     pub fn new(mut window: Window<B>) -> Result<Self, ErrorType> {
-        let window_dimensions = DIMS;
+        let window_dimensions = window.window_dimensions;
         let memory_types = window
             .adapter
             .physical_device
             .memory_properties()
             .memory_types;
-        let limits = window.adapter.physical_device.limits();
+        println!("Memory Types: {:?}", memory_types);
         let family = window
             .adapter
             .queue_families
@@ -190,8 +275,12 @@ impl<B: gfx_hal::Backend> GfxRenderingContext<B> {
         };
         let queue_group = gpu.queue_groups.pop().unwrap();
         let device = gpu.device;
+        // Command buffers are reset after every submission so they need to be individually resettable
         let mut command_pool = unsafe {
-            device.create_command_pool(queue_group.family, pool::CommandPoolCreateFlags::empty())
+            device.create_command_pool(
+                queue_group.family,
+                pool::CommandPoolCreateFlags::RESET_INDIVIDUAL,
+            )
         }
         .expect("failed to create_command pool");
         let set_layout = ManuallyDrop::new(
@@ -216,6 +305,24 @@ impl<B: gfx_hal::Backend> GfxRenderingContext<B> {
                             stage_flags: ShaderStageFlags::FRAGMENT,
                             immutable_samplers: false,
                         },
+                        pso::DescriptorSetLayoutBinding {
+                            binding: 2,
+                            ty: pso::DescriptorType::Image {
+                                ty: pso::ImageDescriptorType::Sampled {
+                                    with_sampler: false,
+                                },
+                            },
+                            count: 1,
+                            stage_flags: ShaderStageFlags::FRAGMENT,
+                            immutable_samplers: false,
+                        },
+                        pso::DescriptorSetLayoutBinding {
+                            binding: 3,
+                            ty: pso::DescriptorType::Sampler,
+                            count: 1,
+                            stage_flags: ShaderStageFlags::FRAGMENT,
+                            immutable_samplers: false,
+                        },
                     ]
                     .into_iter(),
                     iter::empty(),
@@ -223,10 +330,30 @@ impl<B: gfx_hal::Backend> GfxRenderingContext<B> {
             }
             .expect("failed to create descriptor set layouyt"),
         );
-        let mut desc_pool = ManuallyDrop::new(
+        // every shader allocates one uniform set
+        let desc_pool = ManuallyDrop::new(
             unsafe {
                 device.create_descriptor_pool(
-                    1,
+                    MAX_SHADERS,
+                    iter::once(pso::DescriptorRangeDesc {
+                        ty: pso::DescriptorType::Buffer {
+                            ty: pso::BufferDescriptorType::Uniform,
+                            format: pso::BufferDescriptorFormat::Structured {
+                                dynamic_offset: true,
+                            },
+                        },
+                        count: MAX_SHADERS * MAX_UNIFORMS,
+                    }),
+                    pso::DescriptorPoolCreateFlags::empty(),
+                )
+            }
+            .expect("failed to create desc pool"),
+        );
+        // every texture set holds a texture and a shadow map
+        let frame_desc_pool = ManuallyDrop::new(
+            unsafe {
+                device.create_descriptor_pool(
+                    MAX_TEXTURE_SETS,
                     vec![
                         pso::DescriptorRangeDesc {
                             ty: pso::DescriptorType::Image {
@@ -234,182 +361,37 @@ impl<B: gfx_hal::Backend> GfxRenderingContext<B> {
                                     with_sampler: false,
                                 },
                             },
-                            count: 1,
+                            count: MAX_TEXTURE_SETS * 2,
                         },
                         pso::DescriptorRangeDesc {
                             ty: pso::DescriptorType::Sampler,
-                            count: 1,
+                            count: MAX_TEXTURE_SETS * 2,
                         },
                     ]
                     .into_iter(),
                     pso::DescriptorPoolCreateFlags::empty(),
                 )
             }
-            .expect("failed to create desc pool"),
-        );
-        let mut desc_set = unsafe { desc_pool.allocate_one(&set_layout) }.unwrap();
-        println!("Memory Types: {:?}", memory_types);
-        let non_coherent_alignment = limits.non_coherent_atom_size as u64;
-        let buffer_stride = mem::size_of::<Vertex>() as u64;
-        let buffer_len = QUAD.len() as u64 * buffer_stride;
-        let padded_buffer_len = ((buffer_len + non_coherent_alignment - 1)
-            / non_coherent_alignment)
-            * non_coherent_alignment;
-        let mut vertex_buffer = ManuallyDrop::new(
-            unsafe { device.create_buffer(padded_buffer_len, buffer::Usage::VERTEX) }.unwrap(),
-        );
-
-        let buffer_req = unsafe { device.get_buffer_requirements(&vertex_buffer) };
-        let upload_type = memory_types
-            .iter()
-            .enumerate()
-            .position(|(id, mem_type)| {
-                // type_mask is a bit field where each bit represents a memory type. If the bit is set
-                // to 1 it means we can use that type for our buffer. So this code finds the first
-                // memory type that has a `1` (or, is allowed), and is visible to the CPU.
-                buffer_req.type_mask & (1 << id) != 0
-                    && mem_type
-                        .properties
-                        .contains(memory::Properties::CPU_VISIBLE)
-            })
-            .unwrap()
-            .into();
-        let buffer_memory = unsafe {
-            println!("buffer_len: {} spirv len: {}", buffer_req.size, buffer_len);
-            let mut memory = device
-                .allocate_memory(upload_type, max(buffer_req.size, buffer_len))
-                .unwrap();
-            device
-                .bind_buffer_memory(&memory, 0, &mut vertex_buffer)
-                .unwrap();
-            let mapping = device
-                .map_memory(&mut memory, memory::Segment::ALL)
-                .unwrap();
-            ptr::copy_nonoverlapping(QUAD.as_ptr() as *const u8, mapping, buffer_len as usize);
-            device
-                .flush_mapped_memory_ranges(iter::once((&memory, memory::Segment::ALL)))
-                .unwrap();
-            device.unmap_memory(&mut memory);
-            ManuallyDrop::new(memory)
-        };
-        let img_data = include_bytes!("./gfx/png/Untitled.png");
-        let img = image::load(Cursor::new(&img_data[..]), image::ImageFormat::Png)
-            .unwrap()
-            .to_rgba8();
-        let (width, height) = img.dimensions();
-        let kind = gfx_hal::image::Kind::D2(
-            width as gfx_hal::image::Size,
-            height as gfx_hal::image::Size,
-            1,
-            1,
-        );
-        let row_alignment_mask = limits.optimal_buffer_copy_pitch_alignment as u32 - 1;
-        let image_stride = 4usize;
-        let row_pitch = (width * image_stride as u32 + row_alignment_mask) & !row_alignment_mask;
-        let upload_size = (height * row_pitch) as u64;
-        let padded_upload_size = ((upload_size + non_coherent_alignment - 1)
-            / non_coherent_alignment)
-            * non_coherent_alignment;
-
-        let mut image_upload_buffer = ManuallyDrop::new(
-            unsafe { device.create_buffer(padded_upload_size, buffer::Usage::TRANSFER_SRC) }
-                .expect("failed to create image upload buffer"),
-        );
-        let image_mem_reqs = unsafe { device.get_buffer_requirements(&image_upload_buffer) };
-        let image_upload_memory = unsafe {
-            let mut memory = device
-                .allocate_memory(upload_type, image_mem_reqs.size)
-                .unwrap();
-            device
-                .bind_buffer_memory(&memory, 0, &mut image_upload_buffer)
-                .expect("failed to bind image memory");
-            let mapping = device
-                .map_memory(&mut memory, memory::Segment::ALL)
-                .unwrap();
-            for y in 0..height as usize {
-                let row = &(*img)
-                    [width as usize * y * image_stride..width as usize * (y + 1) * image_stride];
-                ptr::copy_nonoverlapping(
-                    row.as_ptr(),
-                    mapping.offset(y as isize * row_pitch as isize),
-                    width as usize * image_stride,
-                );
-            }
-            device
-                .flush_mapped_memory_ranges(iter::once((&memory, gfx_hal::memory::Segment::ALL)))
-                .unwrap();
-            device.unmap_memory(&mut memory);
-            ManuallyDrop::new(memory)
-        };
-        let mut image_logo = ManuallyDrop::new(
-            unsafe {
-                device.create_image(
-                    kind,
-                    1,
-                    ColorFormat::SELF,
-                    gfx_hal::image::Tiling::Optimal,
-                    gfx_hal::image::Usage::TRANSFER_DST | gfx_hal::image::Usage::SAMPLED,
-                    gfx_hal::image::ViewCapabilities::empty(),
-                )
-            }
-            .unwrap(),
-        );
-        let image_req = unsafe { device.get_image_requirements(&image_logo) };
-        let device_type = memory_types
-            .iter()
-            .enumerate()
-            .position(|(id, memory_type)| {
-                image_req.type_mask & (1 << id) != 0
-                    && memory_type
-                        .properties
-                        .contains(gfx_hal::memory::Properties::DEVICE_LOCAL)
-            })
-            .unwrap()
-            .into();
-        println!("device type: {:?}", device_type);
-        let image_memory = ManuallyDrop::new(
-            unsafe { device.allocate_memory(device_type, image_req.size) }.unwrap(),
+            .expect("failed to create frame desc pool"),
         );
-        unsafe { device.bind_image_memory(&image_memory, 0, &mut image_logo) }.unwrap();
-        let image_srv = ManuallyDrop::new(
+        let sampler = ManuallyDrop::new(
             unsafe {
-                device.create_image_view(
-                    &image_logo,
-                    gfx_hal::image::ViewKind::D2,
-                    ColorFormat::SELF,
-                    Swizzle::NO,
-                    gfx_hal::image::SubresourceRange {
-                        aspects: gfx_hal::format::Aspects::COLOR,
-                        ..Default::default()
-                    },
-                )
+                device.create_sampler(&gfx_hal::image::SamplerDesc::new(
+                    gfx_hal::image::Filter::Linear,
+                    gfx_hal::image::WrapMode::Clamp,
+                ))
             }
             .unwrap(),
         );
-        let sampler = ManuallyDrop::new(
+        let shadow_sampler = ManuallyDrop::new(
             unsafe {
                 device.create_sampler(&gfx_hal::image::SamplerDesc::new(
-                    gfx_hal::image::Filter::Linear,
+                    gfx_hal::image::Filter::Nearest,
                     gfx_hal::image::WrapMode::Clamp,
                 ))
             }
             .unwrap(),
         );
-        unsafe {
-            device.write_descriptor_set(pso::DescriptorSetWrite {
-                set: &mut desc_set,
-                binding: 0,
-                array_offset: 0,
-                descriptors: vec![
-                    pso::Descriptor::Image(
-                        &*image_srv,
-                        gfx_hal::image::Layout::ShaderReadOnlyOptimal,
-                    ),
-                    pso::Descriptor::Sampler(&*sampler),
-                ]
-                .into_iter(),
-            });
-        }
         let caps = window.surface.capabilities(&window.adapter.physical_device);
         let supported_formats = window
             .surface
@@ -435,54 +417,26 @@ impl<B: gfx_hal::Backend> GfxRenderingContext<B> {
                 .configure_swapchain(&device, swap_config)
                 .expect("Can not config swapchain")
         };
-        let render_pass = {
-            let attachment = pass::Attachment {
-                format: Some(format),
-                samples: 1,
-                ops: pass::AttachmentOps::new(
-                    pass::AttachmentLoadOp::Clear,
-                    pass::AttachmentStoreOp::Store,
-                ),
-                stencil_ops: pass::AttachmentOps::DONT_CARE,
-                layouts: gfx_hal::image::Layout::Undefined..gfx_hal::image::Layout::Present,
-            };
-            let subpass = pass::SubpassDesc {
-                colors: &[(0, gfx_hal::image::Layout::ColorAttachmentOptimal)],
-                depth_stencil: None,
-                inputs: &[],
-                resolves: &[],
-                preserves: &[],
-            };
-            ManuallyDrop::new(
-                unsafe {
-                    device.create_render_pass(
-                        iter::once(attachment),
-                        iter::once(subpass),
-                        iter::empty(),
-                    )
-                }
-                .expect("failed to create render pass"),
-            )
-        };
-        let framebuffer = ManuallyDrop::new(
-            unsafe {
-                device.create_framebuffer(
-                    &render_pass,
-                    iter::once(fat),
-                    gfx_hal::image::Extent {
-                        width: window_dimensions.width,
-                        height: window_dimensions.height,
-                        depth: 1,
-                    },
-                )
-            }
-            .expect("failed to create framebuffer"),
+        let surface_passes =
+            ManuallyDrop::new(build_render_passes::<B>(&device, format, Layout::Present));
+        let offscreen_passes = ManuallyDrop::new(build_render_passes::<B>(
+            &device,
+            ColorFormat::SELF,
+            Layout::ShaderReadOnlyOptimal,
+        ));
+        let framebuffer = ManuallyDrop::new(build_surface_framebuffer::<B>(
+            &device,
+            &surface_passes.load,
+            fat,
+            extent,
+        ));
+        let submission_complete_fence =
+            ManuallyDrop::new(device.create_fence(false).expect("failed to create fence"));
+        let rendering_complete = ManuallyDrop::new(
+            device
+                .create_semaphore()
+                .expect("failed to create semaphore"),
         );
-        //maximum number of frames that can be computed at the same time
-        let submission_complete_semaphore = device
-            .create_semaphore()
-            .expect("failed to create semaphore");
-        let submission_complete_fence = device.create_fence(false).expect("failed to create fence");
         // Note: We don't really need a different command pool per frame in such a simple demo like this,
         // but in a more 'real' application, it's generally seen as optimal to have one command pool per
         // thread per frame. There is a flag that lets a command pool reset individual command buffers
@@ -492,140 +446,59 @@ impl<B: gfx_hal::Backend> GfxRenderingContext<B> {
         // usually best to just make a command pool for each set of buffers which need to be reset at the
         // same time (each frame). In our case, each pool will only have one command buffer created from it,
         // though.
-        let command_buffer = unsafe { command_pool.allocate_one(command::Level::Primary) };
-
-        let pipeline_layout = ManuallyDrop::new(
-            unsafe { device.create_pipeline_layout(iter::once(&*set_layout), iter::empty()) }
-                .expect("failed to create pipeline layout"),
-        );
-        let pipeline = {
-            let vs_module = {
-                let spirv = gfx_auxil::read_spirv(Cursor::new(&include_bytes!(
-                    "./gfx/compiled_shader/shader.vert.spv"
-                )))
-                .unwrap();
-                unsafe { device.create_shader_module(&spirv).unwrap() }
-            };
-
-            let fs_module = {
-                let spirv = gfx_auxil::read_spirv(Cursor::new(&include_bytes!(
-                    "./gfx/compiled_shader/shader.frag.spv"
-                )))
-                .unwrap();
-                unsafe { device.create_shader_module(&spirv).unwrap() }
-            };
-            let (vs_entry, fs_entry) = {
-                (
-                    pso::EntryPoint {
-                        entry: "main",
-                        module: &vs_module,
-                        specialization: gfx_hal::spec_const_list![0.8f32],
-                    },
-                    pso::EntryPoint {
-                        entry: "main",
-                        module: &fs_module,
-                        specialization: pso::Specialization::default(),
-                    },
-                )
-            };
-            let subpass = Subpass {
-                index: 0,
-                main_pass: &*render_pass,
-            };
-            let vertex_buffers = vec![pso::VertexBufferDesc {
-                binding: 0,
-                stride: mem::size_of::<Vertex>() as u32,
-                rate: VertexInputRate::Vertex,
-            }];
-            let attributes = vec![
-                pso::AttributeDesc {
-                    location: 0,
-                    binding: 0,
-                    element: pso::Element {
-                        format: gfx_hal::format::Format::Rg32Sfloat,
-                        offset: 0,
-                    },
-                },
-                pso::AttributeDesc {
-                    location: 1,
-                    binding: 0,
-                    element: pso::Element {
-                        format: gfx_hal::format::Format::Rg32Sfloat,
-                        offset: (std::mem::size_of::<f32>() * 3) as u32,
-                    },
-                },
-            ];
-            let mut pipeline_desc = pso::GraphicsPipelineDesc::new(
-                pso::PrimitiveAssemblerDesc::Vertex {
-                    buffers: &vertex_buffers,
-                    attributes: &attributes,
-                    input_assembler: pso::InputAssemblerDesc {
-                        primitive: pso::Primitive::TriangleList,
-                        with_adjacency: false,
-                        restart_index: None,
-                    },
-                    vertex: vs_entry,
-                    geometry: None,
-                    tessellation: None,
-                },
-                pso::Rasterizer::FILL,
-                Some(fs_entry),
-                &*pipeline_layout,
-                subpass,
-            );
-            pipeline_desc.blender.targets.push(pso::ColorBlendDesc {
-                mask: pso::ColorMask::ALL,
-                blend: Some(pso::BlendState::ALPHA),
-            });
-            let pipeline = unsafe {
-                device
-                    .create_graphics_pipeline(&pipeline_desc, None)
-                    .unwrap()
-            };
-            unsafe {
-                device.destroy_shader_module(vs_module);
-                device.destroy_shader_module(fs_module);
-            }
-            pipeline
-        };
-        let viewport = pso::Viewport {
-            rect: pso::Rect {
-                x: 0,
-                y: 0,
-                w: extent.width as _,
-                h: extent.height as _,
-            },
-            depth: 0.0..1.0,
-        };
+        let command_buffer =
+            ManuallyDrop::new(unsafe { command_pool.allocate_one(command::Level::Primary) });
 
-        Ok(Self {
+        let uniform_ring = ManuallyDrop::new(UniformRing {
+            buffer: create_buffer::<B>(
+                &device,
+                &window.adapter,
+                UNIFORM_RING_SIZE,
+                buffer::Usage::UNIFORM,
+            ),
+            offset: 0,
+            alignment: window
+                .adapter
+                .physical_device
+                .limits()
+                .min_uniform_buffer_offset_alignment
+                .max(1),
+        });
+        let mut context = Self {
             adapter: window.adapter,
-            buffer_memory,
-            supported_formats: supported_formats.unwrap(),
+            supported_formats: supported_formats.unwrap_or_default(),
             command_buffer,
-            command_pool,
+            command_pool: ManuallyDrop::new(command_pool),
+            recording: false,
+            in_flight: false,
+            submission: 0,
+            open_pass: None,
+            garbage: vec![],
             queue_group,
-            vertex_buffer,
             device,
             desc_pool,
+            frame_desc_pool,
+            uniform_ring,
             format,
             instance: window.instance,
-            dimensions: window_dimensions,
-            viewport,
+            dimensions: extent,
             framebuffer,
-            pipeline: ManuallyDrop::new(pipeline),
-            pipeline_layout,
-            render_pass,
-            desc_set: Some(desc_set),
+            surface_passes,
+            offscreen_passes,
+            surface_depth: None,
+            swapchain_image: None,
             set_layout,
-            submission_complete_semaphore,
+            sampler,
+            shadow_sampler,
             submission_complete_fence,
+            rendering_complete,
             surface: ManuallyDrop::new(window.surface),
             shaders: BindArena::default(),
-        })
-    }
-    pub fn change_viewport(&self, screen_size: &Vector2<u32>) -> Result<(), ErrorType> {
-        todo!()
+            framebuffers: BindArena::default(),
+        };
+        context.surface_depth =
+            Some(context.create_depth_texture(Vector2::new(extent.width, extent.height)));
+        Ok(context)
     }
     ///Builds shader and inserts the shaer into self.shaders
     fn build_shader(&mut self, shaders: ShaderData) -> Result<Shader, ErrorType> {
@@ -639,244 +512,871 @@ impl<B: gfx_hal::Backend> GfxRenderingContext<B> {
                 .create_shader_module(&shaders.vertex_shader_data)
                 .unwrap()
         };
-        let vs_entry = pso::EntryPoint {
-            entry: "main",
-            module: &vertex_shader,
-            specialization: gfx_hal::spec_const_list![0.8f32],
-        };
-        let fs_entry = pso::EntryPoint {
-            entry: "main",
-            module: &fragment_shader,
-            specialization: pso::Specialization::default(),
-        };
-        let subpass = Subpass {
-            index: 0,
-            main_pass: &*self.render_pass,
-        };
-        let vertex_buffers = vec![pso::VertexBufferDesc {
-            binding: 0,
-            stride: mem::size_of::<Vertex>() as u32,
-            rate: VertexInputRate::Vertex,
-        }];
-        let attributes = vec![
-            pso::AttributeDesc {
-                location: 0,
-                binding: 0,
-                element: pso::Element {
-                    format: gfx_hal::format::Format::Rg32Sfloat,
-                    offset: 0,
-                },
-            },
-            pso::AttributeDesc {
-                location: 1,
-                binding: 0,
-                element: pso::Element {
-                    format: gfx_hal::format::Format::Rg32Sfloat,
-                    offset: (std::mem::size_of::<f32>() * 3) as u32,
-                },
-            },
-        ];
-        let mut pipeline_desc = pso::GraphicsPipelineDesc::new(
-            pso::PrimitiveAssemblerDesc::Vertex {
-                buffers: &vertex_buffers,
-                attributes: &attributes,
-                input_assembler: pso::InputAssemblerDesc {
-                    primitive: pso::Primitive::TriangleList,
-                    with_adjacency: false,
-                    restart_index: None,
-                },
-                vertex: vs_entry,
-                geometry: None,
-                tessellation: None,
-            },
-            pso::Rasterizer::FILL,
-            Some(fs_entry),
-            &*self.pipeline_layout,
-            subpass,
+        let uniform_set_layout = unsafe {
+            self.device.create_descriptor_set_layout(
+                shaders
+                    .vertex_uniform_layout
+                    .values()
+                    .chain(shaders.fragment_uniform_layout.values())
+                    .map(|uniform| uniform.layout_binding.clone()),
+                iter::empty(),
+            )
+        }
+        .expect("failed to create uniform set layout");
+        let pipeline_layout = unsafe {
+            self.device.create_pipeline_layout(
+                vec![&*self.set_layout, &uniform_set_layout].into_iter(),
+                iter::empty(),
+            )
+        }
+        .expect("failed to create pipeline layout");
+        let surface = PipelineSet::new(
+            &self.device,
+            &vertex_shader,
+            &fragment_shader,
+            &pipeline_layout,
+            &self.surface_passes.load,
+            &shaders.vertex_layout,
+        );
+        let offscreen = PipelineSet::new(
+            &self.device,
+            &vertex_shader,
+            &fragment_shader,
+            &pipeline_layout,
+            &self.offscreen_passes.load,
+            &shaders.vertex_layout,
         );
-        pipeline_desc.blender.targets.push(pso::ColorBlendDesc {
-            mask: pso::ColorMask::ALL,
-            blend: Some(pso::BlendState::ALPHA),
-        });
-        let pipeline = unsafe {
-            self.device
-                .create_graphics_pipeline(&pipeline_desc, None)
-                .unwrap()
-        };
         unsafe {
             self.device.destroy_shader_module(fragment_shader);
             self.device.destroy_shader_module(vertex_shader);
         }
-        let fragment_shader_uniform_buffers = shaders
-            .fragment_uniform_layout
-            .iter()
-            .map(|(name, uniform)| {
-                let buffer = unsafe {
-                    self.device
-                        .create_buffer(uniform.data_type.size() as u64, buffer::Usage::UNIFORM)
-                        .expect("failed to create buffer")
-                };
-                let memory = self.allocate_memory(&buffer);
-                (name.clone(), RuntimeUniform { buffer, memory })
-            })
-            .collect();
-        let vertex_shader_uniform_buffers = shaders
+        let mut uniform_set = unsafe { self.desc_pool.allocate_one(&uniform_set_layout) }
+            .expect("failed to allocate uniform set");
+        let mut uniforms = HashMap::new();
+        for (name, uniform) in shaders
             .vertex_uniform_layout
             .iter()
-            .map(|(name, uniform)| {
-                let buffer = unsafe {
-                    self.device
-                        .create_buffer(uniform.data_type.size() as u64, buffer::Usage::UNIFORM)
-                        .expect("failed to create buffer")
-                };
-                let memory = self.allocate_memory(&buffer);
-                (name.clone(), RuntimeUniform { buffer, memory })
-            })
-            .collect();
+            .chain(shaders.fragment_uniform_layout.iter())
+        {
+            let size = uniform.data_type.size();
+            // the offset into the ring is given when drawing
+            unsafe {
+                self.device.write_descriptor_set(pso::DescriptorSetWrite {
+                    set: &mut uniform_set,
+                    binding: uniform.layout_binding.binding,
+                    array_offset: 0,
+                    descriptors: iter::once(pso::Descriptor::Buffer(
+                        &self.uniform_ring.buffer.buffer,
+                        buffer::SubRange {
+                            offset: 0,
+                            size: Some(size as u64),
+                        },
+                    )),
+                });
+            }
+            uniforms.insert(
+                name.clone(),
+                ShaderUniform {
+                    binding: uniform.layout_binding.binding,
+                    data: vec![0; size],
+                },
+            );
+        }
         let shader = GfxShader {
-            pipeline,
-            fragment_shader_uniform_buffers,
-            vertex_shader_uniform_buffers,
+            surface,
+            offscreen,
+            pipeline_layout,
+            uniform_set_layout,
+            texture: None,
+            shadow_map: None,
+            texture_set: None,
+            uniform_set,
+            vertex_layout: shaders.vertex_layout,
+            uniforms,
+            uniform_offsets: None,
         };
         Ok(Shader {
             index: self.shaders.insert(shader),
         })
     }
-    pub fn build_world_shader(&mut self) -> Result<Shader, ErrorType> {
-        let shaders = shader::get_world();
-        self.build_shader(shaders)
+    /// Creates buffer backed by cpu visible memory
+    fn create_buffer(&self, size: u64, usage: buffer::Usage) -> GfxBuffer<B> {
+        create_buffer::<B>(&self.device, &self.adapter, size, usage)
     }
-    fn allocate_memory_properties(
-        &mut self,
-        buffer: &B::Buffer,
-        properties: memory::Properties,
-    ) -> B::Memory {
-        let memory_types = self
-            .adapter
-            .physical_device
-            .memory_properties()
-            .memory_types;
-        let buffer_reqs = unsafe { self.device.get_buffer_requirements(buffer) };
-        let upload_type = memory_types
-            .iter()
-            .enumerate()
-            .position(|(id, mem_type)| {
-                // type_mask is a bit field where each bit represents a memory type. If the bit is set
-                // to 1 it means we can use that type for our buffer. So this code finds the first
-                // memory type that has a `1` (or, is allowed), and is visible to the CPU.
-                buffer_reqs.type_mask & (1 << id) != 0 && mem_type.properties.contains(properties)
-            })
-            .unwrap()
-            .into();
+    fn destroy_buffer(&self, buffer: GfxBuffer<B>) {
         unsafe {
-            self.device
-                .allocate_memory(upload_type, buffer_reqs.size)
-                .unwrap()
+            self.device.destroy_buffer(buffer.buffer);
+            self.device.free_memory(buffer.memory);
         }
     }
-    fn allocate_memory(&mut self, buffer: &B::Buffer) -> B::Memory {
-        self.allocate_memory_properties(buffer, memory::Properties::CPU_VISIBLE)
-    }
-    /// Builds shader used for screenspace
-    pub fn build_screen_shader(&mut self) -> Result<Shader, ErrorType> {
-        let shaders = shader::get_screen();
-        self.build_shader(shaders)
-    }
-    pub fn build_gui_shader(&mut self) -> Result<Shader, ErrorType> {
-        let shaders = shader::get_screen();
-        self.build_shader(shaders)
-    }
-    pub fn bind_shader(&mut self, shader: &Shader) -> Result<(), ErrorType> {
-        self.shaders.bind(shader.index.clone());
-        Ok(())
-    }
-    pub fn build_mesh(
-        &mut self,
-        mesh: Mesh,
-        shader: &Shader,
-    ) -> Result<RuntimeGfxMesh<B>, ErrorType> {
-        let data = mesh.to_bytes();
-        let buffer = unsafe {
-            self.device.create_buffer(
-                data.len() as u64 * std::mem::size_of::<f32>() as u64,
-                gfx_hal::buffer::Usage::VERTEX,
+    /// Creates device local image and a view into it
+    fn create_image(
+        &self,
+        dimensions: Vector2<u32>,
+        format: format::Format,
+        usage: gfx_hal::image::Usage,
+        aspects: format::Aspects,
+    ) -> GfxImage<B> {
+        let kind = gfx_hal::image::Kind::D2(
+            dimensions.x as gfx_hal::image::Size,
+            dimensions.y as gfx_hal::image::Size,
+            1,
+            1,
+        );
+        let mut image = unsafe {
+            self.device.create_image(
+                kind,
+                1,
+                format,
+                gfx_hal::image::Tiling::Optimal,
+                usage,
+                gfx_hal::image::ViewCapabilities::empty(),
             )
         }
-        .expect("failed to create buffer");
-        let mut memory = self.allocate_memory(&buffer);
-        let memory_ptr = unsafe {
-            self.device.map_memory(
-                &mut memory,
-                gfx_hal::memory::Segment {
-                    offset: 0,
-                    size: None,
+        .expect("failed to create image");
+        let requirements = unsafe { self.device.get_image_requirements(&image) };
+        let memory_type = find_memory_type(
+            &self.adapter,
+            requirements.type_mask,
+            memory::Properties::DEVICE_LOCAL,
+        );
+        let memory = unsafe {
+            let memory = self
+                .device
+                .allocate_memory(memory_type, requirements.size)
+                .expect("failed to allocate image memory");
+            self.device
+                .bind_image_memory(&memory, 0, &mut image)
+                .expect("failed to bind image memory");
+            memory
+        };
+        let view = unsafe {
+            self.device.create_image_view(
+                &image,
+                gfx_hal::image::ViewKind::D2,
+                format,
+                Swizzle::NO,
+                gfx_hal::image::SubresourceRange {
+                    aspects,
+                    ..Default::default()
                 },
             )
         }
-        .expect("failed to map memory");
-        unsafe {
-            std::ptr::copy_nonoverlapping(
-                data.as_ptr() as *const u8,
-                memory_ptr,
-                data.len() * std::mem::size_of::<f32>(),
-            )
-        };
-        unsafe {
-            self.device.unmap_memory(&mut memory);
+        .expect("failed to create image view");
+        GfxImage {
+            image,
+            memory,
+            view,
         }
-        Ok(RuntimeGfxMesh {
-            vertex_buffer: buffer,
-            vertex_memory: memory,
-        })
     }
-    pub fn delete_mesh(&mut self, mesh: &mut RuntimeMesh) -> Result<(), ErrorType> {
-        todo!()
+    fn destroy_image(&self, image: GfxImage<B>) {
+        unsafe {
+            self.device.destroy_image_view(image.view);
+            self.device.destroy_image(image.image);
+            self.device.free_memory(image.memory);
+        }
     }
+    /// Keeps the value until the next draw with the shader copies it into the uniform ring
     unsafe fn send_uniform(
         &mut self,
-        shader: &mut Shader,
+        shader: &Shader,
         uniform_name: &str,
         data: *const u8,
         data_size: usize,
     ) {
         let shader = self.shaders.get_mut(shader.index.clone()).unwrap();
-        let memory = if shader
-            .fragment_shader_uniform_buffers
-            .contains_key(uniform_name)
-        {
-            &mut shader
-                .fragment_shader_uniform_buffers
-                .get_mut(uniform_name)
-                .unwrap()
-                .memory
-        } else if shader
-            .vertex_shader_uniform_buffers
-            .contains_key(uniform_name)
-        {
-            &mut shader
-                .vertex_shader_uniform_buffers
-                .get_mut(uniform_name)
-                .unwrap()
-                .memory
-        } else {
-            panic!("uniform: {} not found", uniform_name)
-        };
-        let memory_ptr = self
-            .device
-            .map_memory(
-                memory,
-                gfx_hal::memory::Segment {
-                    offset: 0,
-                    size: None,
+        let uniform = shader
+            .uniforms
+            .get_mut(uniform_name)
+            .unwrap_or_else(|| panic!("uniform: {} not found", uniform_name));
+        assert!(data_size <= uniform.data.len());
+        ptr::copy_nonoverlapping(data, uniform.data.as_mut_ptr(), data_size);
+        shader.uniform_offsets = None;
+    }
+    fn create_depth_texture(&mut self, dimensions: Vector2<u32>) -> RuntimeGfxDepthTexture<B> {
+        let image = self.create_image(
+            dimensions,
+            DEPTH_FORMAT,
+            DEPTH_USAGE,
+            format::Aspects::DEPTH,
+        );
+        // render passes expect depth attachments to already be in the attachment layout
+        unsafe {
+            self.begin_transfer();
+            self.command_buffer.pipeline_barrier(
+                PipelineStage::TOP_OF_PIPE..PipelineStage::EARLY_FRAGMENT_TESTS,
+                gfx_hal::memory::Dependencies::empty(),
+                iter::once(gfx_hal::memory::Barrier::Image {
+                    states: (gfx_hal::image::Access::empty(), Layout::Undefined)
+                        ..(
+                            gfx_hal::image::Access::DEPTH_STENCIL_ATTACHMENT_READ
+                                | gfx_hal::image::Access::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                            DEPTH_LAYOUT,
+                        ),
+                    target: &image.image,
+                    families: None,
+                    range: gfx_hal::image::SubresourceRange {
+                        aspects: gfx_hal::format::Aspects::DEPTH,
+                        ..Default::default()
+                    },
+                }),
+            );
+        }
+        let extent = gfx_hal::image::Extent {
+            width: dimensions.x,
+            height: dimensions.y,
+            depth: 1,
+        };
+        RuntimeGfxDepthTexture {
+            image: Some(Arc::new(image)),
+            extent,
+        }
+    }
+    fn draw(&mut self, mesh: &RuntimeGfxMesh<B>, mode: DrawMode) {
+        let index = self.shaders.bound_index().expect("no shader bound");
+        unsafe {
+            self.begin_commands();
+            while !self.prepare_sets(&index) {
+                // the uniform ring or the texture sets of this submission ran out
+                self.flush();
+                self.begin_commands();
+            }
+            self.begin_render_pass(None);
+            let shader = self.shaders.get(index).expect("shader not found");
+            let pipelines = if self.framebuffers.get_bound().is_some() {
+                &shader.offscreen
+            } else {
+                &shader.surface
+            };
+            self.command_buffer.bind_graphics_pipeline(match mode {
+                DrawMode::Triangles => &pipelines.triangles,
+                DrawMode::Lines => &pipelines.lines,
+            });
+            let offsets = &shader
+                .uniform_offsets
+                .as_ref()
+                .expect("uniforms not written")
+                .1;
+            // shaders without a texture or shadow map do not use set 0
+            if let Some((_, texture_set)) = shader.texture_set.as_ref() {
+                self.command_buffer.bind_graphics_descriptor_sets(
+                    &shader.pipeline_layout,
+                    0,
+                    vec![texture_set, &shader.uniform_set].into_iter(),
+                    offsets.iter().copied(),
+                );
+            } else {
+                self.command_buffer.bind_graphics_descriptor_sets(
+                    &shader.pipeline_layout,
+                    1,
+                    iter::once(&shader.uniform_set),
+                    offsets.iter().copied(),
+                );
+            }
+            self.command_buffer.bind_vertex_buffers(
+                0,
+                iter::once((
+                    &mesh
+                        .vertex_buffer
+                        .as_ref()
+                        .expect("mesh was deleted")
+                        .buffer,
+                    buffer::SubRange::WHOLE,
+                )),
+            );
+            if let Some((index_buffer, index_type)) = mesh.index_buffer.as_ref() {
+                self.command_buffer.bind_index_buffer(
+                    &index_buffer.buffer,
+                    buffer::SubRange::WHOLE,
+                    *index_type,
+                );
+                self.command_buffer.draw_indexed(0..mesh.count, 0, 0..1);
+            } else {
+                self.command_buffer.draw(0..mesh.count, 0..1);
+            }
+        }
+    }
+    /// Copies the uniforms of a shader into the uniform ring and allocates its texture
+    /// set, unless the ones made earlier in this submission are still valid. Returns
+    /// false if the ring or the texture sets have run out
+    unsafe fn prepare_sets(&mut self, index: &BindArenaIndex) -> bool {
+        let submission = self.submission;
+        let shader = self
+            .shaders
+            .get_mut(index.clone())
+            .expect("shader not found");
+        if shader
+            .texture_set
+            .as_ref()
+            .map_or(true, |(made_in, _)| *made_in != submission)
+        {
+            shader.texture_set = None;
+            if shader.texture.is_some() || shader.shadow_map.is_some() {
+                let mut set = match self.frame_desc_pool.allocate_one(&self.set_layout) {
+                    Ok(set) => set,
+                    Err(_) => return false,
+                };
+                if let Some(texture) = shader.texture.as_ref() {
+                    self.device.write_descriptor_set(pso::DescriptorSetWrite {
+                        set: &mut set,
+                        binding: 0,
+                        array_offset: 0,
+                        descriptors: vec![
+                            pso::Descriptor::Image(&texture.view, Layout::ShaderReadOnlyOptimal),
+                            pso::Descriptor::Sampler(&*self.sampler),
+                        ]
+                        .into_iter(),
+                    });
+                }
+                if let Some(shadow_map) = shader.shadow_map.as_ref() {
+                    self.device.write_descriptor_set(pso::DescriptorSetWrite {
+                        set: &mut set,
+                        binding: 2,
+                        array_offset: 0,
+                        descriptors: vec![
+                            pso::Descriptor::Image(&shadow_map.view, DEPTH_LAYOUT),
+                            pso::Descriptor::Sampler(&*self.shadow_sampler),
+                        ]
+                        .into_iter(),
+                    });
+                }
+                shader.texture_set = Some((submission, set));
+            }
+        }
+        if shader
+            .uniform_offsets
+            .as_ref()
+            .map_or(true, |(made_in, _)| *made_in != submission)
+        {
+            let ring = &mut *self.uniform_ring;
+            let mut uniforms: Vec<&ShaderUniform> = shader.uniforms.values().collect();
+            // dynamic offsets are given in the order of the bindings
+            uniforms.sort_by_key(|uniform| uniform.binding);
+            let mut data = vec![];
+            let mut offsets = vec![];
+            for uniform in uniforms {
+                offsets.push((ring.offset + data.len() as u64) as u32);
+                data.extend_from_slice(&uniform.data);
+                let aligned =
+                    (data.len() as u64 + ring.alignment - 1) / ring.alignment * ring.alignment;
+                data.resize(aligned as usize, 0);
+            }
+            if ring.offset + data.len() as u64 > UNIFORM_RING_SIZE {
+                return false;
+            }
+            write_memory::<B>(
+                &self.device,
+                &mut ring.buffer.memory,
+                ring.offset as usize,
+                data.as_ptr(),
+                data.len(),
+            );
+            ring.offset += data.len() as u64;
+            shader.uniform_offsets = Some((submission, offsets));
+        }
+        true
+    }
+    /// Makes sure a render pass into the currently bound framebuffer is being recorded.
+    /// If `clear_color` is set a new pass clearing the attachments is started, otherwise
+    /// the open pass is kept or a pass keeping the previous contents is started.
+    /// Returns the area being rendered to
+    unsafe fn begin_render_pass(&mut self, clear_color: Option<Vector4<f32>>) -> pso::Rect {
+        self.begin_commands();
+        let target = match self.framebuffers.bound_index() {
+            Some(index) => PassTarget::Framebuffer(index),
+            None => PassTarget::Surface,
+        };
+        if target == PassTarget::Surface && self.swapchain_image.is_none() {
+            let (image, _suboptimal) = self
+                .surface
+                .acquire_image(!0)
+                .expect("failed to acquire swapchain image");
+            self.swapchain_image = Some(image);
+        }
+        let extent = match self.framebuffers.get_bound() {
+            Some(data) => data.extent,
+            None => self.dimensions.to_extent(),
+        };
+        let rect = pso::Rect {
+            x: 0,
+            y: 0,
+            w: extent.width as i16,
+            h: extent.height as i16,
+        };
+        if clear_color.is_none() && self.open_pass.as_ref() == Some(&target) {
+            return rect;
+        }
+        self.end_render_pass();
+        let clear_values = [
+            command::ClearValue {
+                color: command::ClearColor {
+                    float32: clear_color.map_or([0.0; 4], |c| [c.x, c.y, c.z, c.w]),
                 },
+            },
+            command::ClearValue {
+                depth_stencil: command::ClearDepthStencil {
+                    depth: 1.0,
+                    stencil: 0,
+                },
+            },
+        ];
+        let (passes, framebuffer, color_view, depth_view): (
+            &RenderPasses<B>,
+            &B::Framebuffer,
+            &B::ImageView,
+            &B::ImageView,
+        ) = if let Some(data) = self.framebuffers.get_bound() {
+            (
+                &*self.offscreen_passes,
+                &data.framebuffer,
+                &data.color_view,
+                &data.depth_view,
             )
-            .expect("failed to map memory");
-        std::ptr::copy_nonoverlapping(data, memory_ptr, data_size);
-        self.device.unmap_memory(memory);
+        } else {
+            (
+                &*self.surface_passes,
+                &*self.framebuffer,
+                Borrow::<B::ImageView>::borrow(self.swapchain_image.as_ref().unwrap()),
+                &self
+                    .surface_depth
+                    .as_ref()
+                    .and_then(|depth| depth.image.as_ref())
+                    .expect("surface depth missing")
+                    .view,
+            )
+        };
+        self.command_buffer.set_viewports(
+            0,
+            iter::once(pso::Viewport {
+                rect,
+                depth: 0.0..1.0,
+            }),
+        );
+        self.command_buffer.set_scissors(0, iter::once(rect));
+        self.command_buffer.begin_render_pass(
+            if clear_color.is_some() {
+                &passes.clear
+            } else {
+                &passes.load
+            },
+            framebuffer,
+            rect,
+            vec![
+                command::RenderAttachmentInfo {
+                    image_view: color_view,
+                    clear_value: clear_values[0],
+                },
+                command::RenderAttachmentInfo {
+                    image_view: depth_view,
+                    clear_value: clear_values[1],
+                },
+            ]
+            .into_iter(),
+            command::SubpassContents::Inline,
+        );
+        self.open_pass = Some(target);
+        rect
+    }
+    /// Ends the render pass being recorded, if there is one
+    unsafe fn end_render_pass(&mut self) {
+        if self.open_pass.take().is_some() {
+            self.command_buffer.end_render_pass();
+        }
+    }
+    /// Starts recording outside of a render pass, for copies and barriers
+    unsafe fn begin_transfer(&mut self) {
+        self.begin_commands();
+        self.end_render_pass();
     }
-    pub fn send_vec3_uniform(
+    /// Starts recording into the command buffer unless it already is. The previous
+    /// submission is waited for first since its command buffer, uniform ring and
+    /// texture sets are reused
+    unsafe fn begin_commands(&mut self) {
+        if self.recording {
+            return;
+        }
+        self.wait_for_submission();
+        self.command_buffer
+            .begin_primary(command::CommandBufferFlags::ONE_TIME_SUBMIT);
+        self.recording = true;
+    }
+    /// Copies the pixels of `texture` into `image` through a staging buffer. `previous`
+    /// is the access and layout the image was left in, afterwards it is ready to be sampled
+    unsafe fn upload_texture(
         &mut self,
-        shader: &mut Shader,
+        image: &B::Image,
+        texture: &Texture,
+        previous: (gfx_hal::image::Access, Layout),
+    ) {
+        let length = texture.pixels.len() * 4;
+        let mut staging = self.create_buffer(length as u64, buffer::Usage::TRANSFER_SRC);
+        write_memory::<B>(
+            &self.device,
+            &mut staging.memory,
+            0,
+            texture.pixels.as_ptr() as *const u8,
+            length,
+        );
+        self.begin_transfer();
+        let image_barrier = gfx_hal::memory::Barrier::Image {
+            states: previous
+                ..(
+                    gfx_hal::image::Access::TRANSFER_WRITE,
+                    Layout::TransferDstOptimal,
+                ),
+            target: image,
+            families: None,
+            range: gfx_hal::image::SubresourceRange {
+                aspects: gfx_hal::format::Aspects::COLOR,
+                ..Default::default()
+            },
+        };
+        self.command_buffer.pipeline_barrier(
+            PipelineStage::FRAGMENT_SHADER..PipelineStage::TRANSFER,
+            gfx_hal::memory::Dependencies::empty(),
+            iter::once(image_barrier),
+        );
+        self.command_buffer.copy_buffer_to_image(
+            &staging.buffer,
+            image,
+            Layout::TransferDstOptimal,
+            iter::once(command::BufferImageCopy {
+                buffer_offset: 0,
+                // buffer width and height are in texels
+                buffer_width: texture.width(),
+                buffer_height: texture.height(),
+                image_layers: gfx_hal::image::SubresourceLayers {
+                    aspects: gfx_hal::format::Aspects::COLOR,
+                    level: 0,
+                    layers: 0..1,
+                },
+                image_offset: gfx_hal::image::Offset { x: 0, y: 0, z: 0 },
+                image_extent: gfx_hal::image::Extent {
+                    width: texture.width(),
+                    height: texture.height(),
+                    depth: 1,
+                },
+            }),
+        );
+        let image_barrier = gfx_hal::memory::Barrier::Image {
+            states: (
+                gfx_hal::image::Access::TRANSFER_WRITE,
+                Layout::TransferDstOptimal,
+            )
+                ..(
+                    gfx_hal::image::Access::SHADER_READ,
+                    Layout::ShaderReadOnlyOptimal,
+                ),
+            target: image,
+            families: None,
+            range: gfx_hal::image::SubresourceRange {
+                aspects: gfx_hal::format::Aspects::COLOR,
+                ..Default::default()
+            },
+        };
+        self.command_buffer.pipeline_barrier(
+            PipelineStage::TRANSFER..PipelineStage::FRAGMENT_SHADER,
+            gfx_hal::memory::Dependencies::empty(),
+            iter::once(image_barrier),
+        );
+        // the staging buffer is read when the submission executes
+        self.garbage.push(Garbage::Buffer(staging));
+    }
+    /// Submits the recorded commands without waiting for them. With `present` the
+    /// semaphore the presentation of the swapchain image waits on is signaled
+    unsafe fn submit(&mut self, present: bool) {
+        if !self.recording {
+            return;
+        }
+        self.end_render_pass();
+        self.command_buffer.finish();
+        let signal = if present {
+            Some(&*self.rendering_complete)
+        } else {
+            None
+        };
+        self.queue_group.queues[0].submit(
+            iter::once(&*self.command_buffer),
+            iter::empty(),
+            signal.into_iter(),
+            Some(&mut *self.submission_complete_fence),
+        );
+        self.recording = false;
+        self.in_flight = true;
+    }
+    /// Waits for the last submission to finish executing, then releases everything
+    /// it used
+    unsafe fn wait_for_submission(&mut self) {
+        if !self.in_flight {
+            return;
+        }
+        self.device
+            .wait_for_fence(&self.submission_complete_fence, !0)
+            .expect("failed to wait for fence");
+        self.device
+            .reset_fence(&mut self.submission_complete_fence)
+            .expect("failed to reset fence");
+        self.command_buffer.reset(false);
+        self.frame_desc_pool.reset();
+        self.uniform_ring.offset = 0;
+        self.submission += 1;
+        self.in_flight = false;
+        self.release_garbage();
+    }
+    /// Submits everything recorded so far and waits for it, for results needed on the
+    /// cpu right away and when the per submission resources run out
+    unsafe fn flush(&mut self) {
+        self.submit(false);
+        self.wait_for_submission();
+    }
+    /// Destroys resources that were deleted while a submission could still use them.
+    /// Images still bound to a shader are kept until the shader binds something else
+    fn release_garbage(&mut self) {
+        let mut images = vec![];
+        for garbage in std::mem::take(&mut self.garbage) {
+            match garbage {
+                Garbage::Buffer(buffer) => self.destroy_buffer(buffer),
+                Garbage::Framebuffer(data) => self.destroy_framebuffer(data),
+                Garbage::Image(image) => images.push(image),
+            }
+        }
+        // framebuffers have views of the images so they are destroyed first
+        for image in images {
+            match Arc::try_unwrap(image) {
+                Ok(image) => self.destroy_image(image),
+                Err(image) => self.garbage.push(Garbage::Image(image)),
+            }
+        }
+    }
+    fn destroy_framebuffer(&self, data: FramebufferData<B>) {
+        unsafe {
+            self.device.destroy_framebuffer(data.framebuffer);
+            self.device.destroy_image_view(data.color_view);
+            self.device.destroy_image_view(data.depth_view);
+        }
+    }
+}
+impl<B: gfx_hal::Backend> RenderBackend for GfxRenderingContext<B> {
+    type Shader = Shader;
+    type Mesh = RuntimeGfxMesh<B>;
+    type Texture = RuntimeGfxTexture<B>;
+    type DepthTexture = RuntimeGfxDepthTexture<B>;
+    type Framebuffer = GfxFramebuffer;
+    type Error = ErrorType;
+    /// Resizes the swapchain. Commands recorded so far are submitted but any image
+    /// acquired this frame is dropped without being presented
+    fn change_viewport(&mut self, screen_size: &Vector2<u32>) -> Result<(), ErrorType> {
+        unsafe { self.flush() };
+        self.device.wait_idle().expect("failed to wait for idle");
+        self.swapchain_image = None;
+        let caps = self.surface.capabilities(&self.adapter.physical_device);
+        let swap_config = window::SwapchainConfig::from_caps(
+            &caps,
+            self.format,
+            window::Extent2D {
+                width: screen_size.x,
+                height: screen_size.y,
+            },
+        );
+        let fat = swap_config.framebuffer_attachment();
+        self.dimensions = swap_config.extent;
+        unsafe {
+            self.surface
+                .configure_swapchain(&self.device, swap_config)
+                .expect("Can not config swapchain");
+            self.device
+                .destroy_framebuffer(ManuallyDrop::into_inner(ptr::read(&self.framebuffer)));
+        }
+        self.framebuffer = ManuallyDrop::new(build_surface_framebuffer::<B>(
+            &self.device,
+            &self.surface_passes.load,
+            fat,
+            self.dimensions,
+        ));
+        if let Some(mut depth) = self.surface_depth.take() {
+            self.delete_depth_buffer(&mut depth)?;
+        }
+        self.surface_depth = Some(
+            self.create_depth_texture(Vector2::new(self.dimensions.width, self.dimensions.height)),
+        );
+        Ok(())
+    }
+    fn build_world_shader(&mut self) -> Result<Shader, ErrorType> {
+        let shaders = shader::get_world();
+        self.build_shader(shaders)
+    }
+    fn build_water_shader(&mut self) -> Result<Shader, ErrorType> {
+        let shaders = shader::get_water();
+        self.build_shader(shaders)
+    }
+    fn build_shadow_shader(&mut self) -> Result<Shader, ErrorType> {
+        let shaders = shader::get_shadow();
+        self.build_shader(shaders)
+    }
+    fn build_water_shadow_shader(&mut self) -> Result<Shader, ErrorType> {
+        let shaders = shader::get_water_shadow();
+        self.build_shader(shaders)
+    }
+    fn build_screen_shader(&mut self) -> Result<Shader, ErrorType> {
+        let shaders = shader::get_screen();
+        self.build_shader(shaders)
+    }
+    fn build_gui_shader(&mut self) -> Result<Shader, ErrorType> {
+        let shaders = shader::get_gui();
+        self.build_shader(shaders)
+    }
+    fn bind_shader(&mut self, shader: &Shader) -> Result<(), ErrorType> {
+        self.shaders.bind(shader.index.clone());
+        Ok(())
+    }
+    fn build_mesh(&mut self, mesh: Mesh, shader: &Shader) -> Result<RuntimeGfxMesh<B>, ErrorType> {
+        let vertex_layout = &self
+            .shaders
+            .get(shader.index.clone())
+            .expect("shader not found")
+            .vertex_layout;
+        if mesh
+            .description
+            .iter()
+            .map(|desc| &desc.name)
+            .ne(vertex_layout.iter().map(|desc| &desc.name))
+        {
+            panic!(
+                "mesh layout {:?} does not match shader layout {:?}",
+                mesh.description, vertex_layout
+            );
+        }
+        let data = mesh.to_bytes();
+        let size = data.len() * std::mem::size_of::<f32>();
+        let mut vertex_buffer = self.create_buffer(
+            size as u64,
+            buffer::Usage::VERTEX | buffer::Usage::TRANSFER_DST,
+        );
+        unsafe {
+            write_memory::<B>(
+                &self.device,
+                &mut vertex_buffer.memory,
+                0,
+                data.as_ptr() as *const u8,
+                size,
+            )
+        };
+        let index_buffer = mesh.indices.as_ref().map(|indices| {
+            let (data, size, index_type) = match indices {
+                Indices::U16(data) => (
+                    data.as_ptr() as *const u8,
+                    data.len() * std::mem::size_of::<u16>(),
+                    gfx_hal::IndexType::U16,
+                ),
+                Indices::U32(data) => (
+                    data.as_ptr() as *const u8,
+                    data.len() * std::mem::size_of::<u32>(),
+                    gfx_hal::IndexType::U32,
+                ),
+            };
+            let mut buffer = self.create_buffer(size as u64, buffer::Usage::INDEX);
+            unsafe { write_memory::<B>(&self.device, &mut buffer.memory, 0, data, size) };
+            (buffer, index_type)
+        });
+        Ok(RuntimeGfxMesh {
+            vertex_buffer: Some(vertex_buffer),
+            index_buffer,
+            count: mesh.num_elements() as u32,
+        })
+    }
+    /// Copies through a staging buffer, so draws recorded earlier in the frame still
+    /// read the old vertices
+    fn update_mesh(
+        &mut self,
+        mesh: &mut RuntimeGfxMesh<B>,
+        offset: usize,
+        vertices: &[f32],
+    ) -> Result<(), ErrorType> {
+        let size = (vertices.len() * std::mem::size_of::<f32>()) as u64;
+        if size == 0 {
+            return Ok(());
+        }
+        let mut staging = self.create_buffer(size, buffer::Usage::TRANSFER_SRC);
+        unsafe {
+            write_memory::<B>(
+                &self.device,
+                &mut staging.memory,
+                0,
+                vertices.as_ptr() as *const u8,
+                size as usize,
+            );
+            self.begin_transfer();
+            let vertex_buffer = &mesh
+                .vertex_buffer
+                .as_ref()
+                .expect("mesh already deleted")
+                .buffer;
+            let range = buffer::SubRange {
+                offset: (offset * std::mem::size_of::<f32>()) as u64,
+                size: Some(size),
+            };
+            self.command_buffer.pipeline_barrier(
+                PipelineStage::VERTEX_INPUT..PipelineStage::TRANSFER,
+                gfx_hal::memory::Dependencies::empty(),
+                iter::once(gfx_hal::memory::Barrier::Buffer {
+                    states: buffer::Access::VERTEX_BUFFER_READ..buffer::Access::TRANSFER_WRITE,
+                    target: vertex_buffer,
+                    range: range.clone(),
+                    families: None,
+                }),
+            );
+            self.command_buffer.copy_buffer(
+                &staging.buffer,
+                vertex_buffer,
+                iter::once(command::BufferCopy {
+                    src: 0,
+                    dst: range.offset,
+                    size,
+                }),
+            );
+            self.command_buffer.pipeline_barrier(
+                PipelineStage::TRANSFER..PipelineStage::VERTEX_INPUT,
+                gfx_hal::memory::Dependencies::empty(),
+                iter::once(gfx_hal::memory::Barrier::Buffer {
+                    states: buffer::Access::TRANSFER_WRITE..buffer::Access::VERTEX_BUFFER_READ,
+                    target: vertex_buffer,
+                    range,
+                    families: None,
+                }),
+            );
+        }
+        self.garbage.push(Garbage::Buffer(staging));
+        Ok(())
+    }
+    /// The buffers are destroyed once the frame using them has finished
+    fn delete_mesh(&mut self, mesh: &mut RuntimeGfxMesh<B>) -> Result<(), ErrorType> {
+        if let Some(buffer) = mesh.vertex_buffer.take() {
+            self.garbage.push(Garbage::Buffer(buffer));
+        }
+        if let Some((buffer, _)) = mesh.index_buffer.take() {
+            self.garbage.push(Garbage::Buffer(buffer));
+        }
+        Ok(())
+    }
+    fn send_float_uniform(
+        &mut self,
+        shader: &Shader,
+        uniform_name: &str,
+        data: f32,
+    ) -> Result<(), ErrorType> {
+        unsafe {
+            self.send_uniform(
+                shader,
+                uniform_name,
+                &data as *const f32 as *const u8,
+                std::mem::size_of::<f32>(),
+            )
+        };
+        Ok(())
+    }
+    fn send_vec3_uniform(
+        &mut self,
+        shader: &Shader,
         uniform_name: &str,
         data: Vector3<f32>,
     ) -> Result<(), ErrorType> {
@@ -890,9 +1390,9 @@ impl<B: gfx_hal::Backend> GfxRenderingContext<B> {
         };
         Ok(())
     }
-    pub fn send_vec4_uniform(
+    fn send_vec4_uniform(
         &mut self,
-        shader: &mut Shader,
+        shader: &Shader,
         uniform_name: &str,
         data: Vector4<f32>,
     ) -> Result<(), ErrorType> {
@@ -906,342 +1406,768 @@ impl<B: gfx_hal::Backend> GfxRenderingContext<B> {
         };
         Ok(())
     }
-    pub fn build_depth_texture(
+    fn send_mat4_uniform(
         &mut self,
-        dimensions: Vector2<u32>,
         shader: &Shader,
-    ) -> Result<RuntimeGfxDepthTexture<B>, ErrorType> {
-        let (image_logo, image_buffer, image_memory) = self.allocate_texture(
-            Vector2::new(dimensions.x as usize, dimensions.y as usize),
-            gfx_hal::image::Usage::DEPTH_STENCIL_ATTACHMENT,
-        );
-        let extent = gfx_hal::image::Extent {
-            width: dimensions.x,
-            height: dimensions.y,
-            depth: 1,
+        uniform_name: &str,
+        data: Matrix4<f32>,
+    ) -> Result<(), ErrorType> {
+        unsafe {
+            self.send_uniform(
+                shader,
+                uniform_name,
+                data.as_ptr() as *const u8,
+                16 * std::mem::size_of::<f32>(),
+            )
         };
-        Ok(RuntimeGfxDepthTexture {
-            image_logo,
-            image_buffer,
-            image_memory,
-            extent,
-        })
+        Ok(())
     }
-    pub fn delete_depth_buffer(
+    fn build_depth_texture(
         &mut self,
-        texture: &mut RuntimeDepthTexture,
-    ) -> Result<(), ErrorType> {
-        todo!()
+        dimensions: Vector2<u32>,
+        shader: &Shader,
+    ) -> Result<RuntimeGfxDepthTexture<B>, ErrorType> {
+        Ok(self.create_depth_texture(dimensions))
     }
-    fn allocate_texture(
+    fn delete_depth_buffer(
         &mut self,
-        dimensions: Vector2<usize>,
-        usage: gfx_hal::image::Usage,
-    ) -> (B::Image, B::Buffer, B::Memory) {
-        let length = dimensions.x * dimensions.y * 4;
-        let image_upload_buffer = unsafe {
-            self.device
-                .create_buffer(length as u64, gfx_hal::buffer::Usage::TRANSFER_SRC)
-        }
-        .expect("failed to create buffer");
-        let memory = self.allocate_memory(&image_upload_buffer);
-        let kind = gfx_hal::image::Kind::D2(
-            dimensions.x as gfx_hal::image::Size,
-            dimensions.y as gfx_hal::image::Size,
-            1,
-            1,
-        );
-        let image_logo = unsafe {
-            self.device.create_image(
-                kind,
-                1,
-                ColorFormat::SELF,
-                gfx_hal::image::Tiling::Optimal,
-                gfx_hal::image::Usage::TRANSFER_DST | usage,
-                gfx_hal::image::ViewCapabilities::empty(),
-            )
+        texture: &mut RuntimeGfxDepthTexture<B>,
+    ) -> Result<(), ErrorType> {
+        if let Some(image) = texture.image.take() {
+            self.garbage.push(Garbage::Image(image));
         }
-        .unwrap();
-
-        (image_logo, image_upload_buffer, memory)
+        Ok(())
     }
-    pub fn build_texture(
+    fn build_texture(
         &mut self,
         texture: Texture,
         shader: &Shader,
     ) -> Result<RuntimeGfxTexture<B>, ErrorType> {
-        let (mut image_logo, image_buffer, mut memory) = self.allocate_texture(
-            Vector2::new(texture.width() as usize, texture.height() as usize),
-            gfx_hal::image::Usage::SAMPLED,
+        let image = self.create_image(
+            texture.dimensions,
+            ColorFormat::SELF,
+            TEXTURE_USAGE,
+            format::Aspects::COLOR,
         );
-        let length = texture.pixels.len() * 4;
-
-        let memory_ptr = unsafe {
-            self.device.map_memory(
-                &mut memory,
-                gfx_hal::memory::Segment {
-                    offset: 0,
-                    size: None,
-                },
+        unsafe {
+            self.upload_texture(
+                &image.image,
+                &texture,
+                (gfx_hal::image::Access::empty(), Layout::Undefined),
             )
+        };
+        let extent = gfx_hal::image::Extent {
+            width: texture.dimensions.x,
+            height: texture.dimensions.y,
+            depth: 1,
+        };
+        Ok(RuntimeGfxTexture {
+            image: Some(Arc::new(image)),
+            extent,
+        })
+    }
+    /// Copies into the existing image, a new image is only created when the
+    /// dimensions change. Shaders keep sampling the old image until the texture is
+    /// bound again
+    fn update_texture(
+        &mut self,
+        texture: &mut RuntimeGfxTexture<B>,
+        data: Texture,
+    ) -> Result<(), ErrorType> {
+        let extent = gfx_hal::image::Extent {
+            width: data.dimensions.x,
+            height: data.dimensions.y,
+            depth: 1,
+        };
+        if extent != texture.extent {
+            self.delete_texture(texture);
+            let image = self.create_image(
+                data.dimensions,
+                ColorFormat::SELF,
+                TEXTURE_USAGE,
+                format::Aspects::COLOR,
+            );
+            unsafe {
+                self.upload_texture(
+                    &image.image,
+                    &data,
+                    (gfx_hal::image::Access::empty(), Layout::Undefined),
+                )
+            };
+            texture.image = Some(Arc::new(image));
+            texture.extent = extent;
+            return Ok(());
         }
-        .expect("failed to map memory");
+        let image = texture.image.as_ref().expect("texture already deleted");
         unsafe {
-            std::ptr::copy_nonoverlapping(texture.pixels.as_ptr() as *const u8, memory_ptr, length);
+            self.upload_texture(
+                &image.image,
+                &data,
+                (
+                    gfx_hal::image::Access::SHADER_READ,
+                    Layout::ShaderReadOnlyOptimal,
+                ),
+            )
         };
-        unsafe {
-            self.device.unmap_memory(&mut memory);
+        Ok(())
+    }
+    fn delete_texture(&mut self, texture: &mut RuntimeGfxTexture<B>) {
+        if let Some(image) = texture.image.take() {
+            self.garbage.push(Garbage::Image(image));
         }
-        let find_image_memory = |image: &B::Image| {
-            let memory_types = self
-                .adapter
-                .physical_device
-                .memory_properties()
-                .memory_types;
-            let buffer_reqs = unsafe { self.device.get_image_requirements(image) };
-            let upload_type = memory_types
-                .iter()
-                .enumerate()
-                .position(|(id, mem_type)| {
-                    // type_mask is a bit field where each bit represents a memory type. If the bit is set
-                    // to 1 it means we can use that type for our buffer. So this code finds the first
-                    // memory type that has a `1` (or, is allowed), and is visible to the CPU.
-                    buffer_reqs.type_mask & (1 << id) != 0
-                        && mem_type
-                            .properties
-                            .contains(memory::Properties::DEVICE_LOCAL)
-                })
-                .unwrap()
-                .into();
-            unsafe {
-                self.device
-                    .allocate_memory(upload_type, buffer_reqs.size)
-                    .unwrap()
-            }
+    }
+    /// Rendered textures are left in `ShaderReadOnlyOptimal` by the offscreen passes.
+    /// The vertex shaders flip y so the first row in memory is already the top of the image
+    fn read_texture(&mut self, texture: &RuntimeGfxTexture<B>) -> Result<Texture, ErrorType> {
+        self.bind_default_framebuffer();
+        let image = texture.image.as_ref().expect("texture already deleted");
+        let length = (texture.extent.width * texture.extent.height * 4) as usize;
+        let mut staging = self.create_buffer(length as u64, buffer::Usage::TRANSFER_DST);
+        let range = gfx_hal::image::SubresourceRange {
+            aspects: gfx_hal::format::Aspects::COLOR,
+            ..Default::default()
         };
-        let image_memory = find_image_memory(&image_logo);
-        unsafe {
-            self.device
-                .bind_image_memory(&image_memory, 0, &mut image_logo)
-                .expect("failed to bind image memory");
-        }
-
-        //copying image
         unsafe {
-            self.command_buffer
-                .begin_primary(command::CommandBufferFlags::ONE_TIME_SUBMIT);
+            self.begin_transfer();
             let image_barrier = gfx_hal::memory::Barrier::Image {
                 states: (
-                    gfx_hal::image::Access::empty(),
-                    gfx_hal::image::Layout::Undefined,
+                    gfx_hal::image::Access::SHADER_READ,
+                    Layout::ShaderReadOnlyOptimal,
                 )
                     ..(
-                        gfx_hal::image::Access::TRANSFER_WRITE,
-                        gfx_hal::image::Layout::TransferDstOptimal,
+                        gfx_hal::image::Access::TRANSFER_READ,
+                        Layout::TransferSrcOptimal,
                     ),
-                target: &image_logo,
+                target: &image.image,
                 families: None,
-                range: gfx_hal::image::SubresourceRange {
-                    aspects: gfx_hal::format::Aspects::COLOR,
-                    ..Default::default()
-                },
+                range: range.clone(),
             };
             self.command_buffer.pipeline_barrier(
-                PipelineStage::TOP_OF_PIPE..PipelineStage::TRANSFER,
+                PipelineStage::COLOR_ATTACHMENT_OUTPUT..PipelineStage::TRANSFER,
                 gfx_hal::memory::Dependencies::empty(),
                 iter::once(image_barrier),
             );
-            self.command_buffer.copy_buffer_to_image(
-                &image_buffer,
-                &image_logo,
-                gfx_hal::image::Layout::TransferDstOptimal,
+            self.command_buffer.copy_image_to_buffer(
+                &image.image,
+                Layout::TransferSrcOptimal,
+                &staging.buffer,
                 iter::once(command::BufferImageCopy {
                     buffer_offset: 0,
-                    buffer_width: texture.width() * 4,
-                    buffer_height: texture.height(),
+                    buffer_width: texture.extent.width,
+                    buffer_height: texture.extent.height,
                     image_layers: gfx_hal::image::SubresourceLayers {
                         aspects: gfx_hal::format::Aspects::COLOR,
                         level: 0,
                         layers: 0..1,
                     },
                     image_offset: gfx_hal::image::Offset { x: 0, y: 0, z: 0 },
-                    image_extent: gfx_hal::image::Extent {
-                        width: texture.width(),
-                        height: texture.height(),
-                        depth: 1,
-                    },
+                    image_extent: texture.extent,
                 }),
             );
             let image_barrier = gfx_hal::memory::Barrier::Image {
                 states: (
-                    gfx_hal::image::Access::TRANSFER_WRITE,
-                    gfx_hal::image::Layout::TransferDstOptimal,
+                    gfx_hal::image::Access::TRANSFER_READ,
+                    Layout::TransferSrcOptimal,
                 )
                     ..(
                         gfx_hal::image::Access::SHADER_READ,
-                        gfx_hal::image::Layout::ShaderReadOnlyOptimal,
+                        Layout::ShaderReadOnlyOptimal,
                     ),
-                target: &image_logo,
+                target: &image.image,
                 families: None,
-                range: gfx_hal::image::SubresourceRange {
-                    aspects: gfx_hal::format::Aspects::COLOR,
-                    ..Default::default()
-                },
+                range,
             };
             self.command_buffer.pipeline_barrier(
                 PipelineStage::TRANSFER..PipelineStage::FRAGMENT_SHADER,
                 gfx_hal::memory::Dependencies::empty(),
                 iter::once(image_barrier),
             );
-            self.command_buffer.finish();
-            if self
-                .device
-                .get_fence_status(&self.submission_complete_fence)
-                .expect("failed to get fence")
-            {
-                panic!("fence not reset at submission of command queue");
-            } else {
-                println!("fence properly reset");
-            }
-            self.queue_group.queues[0].submit(
-                iter::once(&self.command_buffer),
-                iter::empty(),
-                iter::empty(),
-                Some(&mut self.submission_complete_fence),
-            );
-            self.device
-                .wait_for_fence(&self.submission_complete_fence, !0)
-                .expect("failed to wait for fence");
-            self.device
-                .reset_fence(&mut self.submission_complete_fence)
-                .expect("failed to reset fence");
+            // the pixels are needed now so the frame so far is submitted and waited for
+            self.flush();
         }
+        let mut data = vec![0u8; length];
         unsafe {
-            self.device.free_memory(memory);
+            read_memory::<B>(&self.device, &mut staging.memory, data.as_mut_ptr(), length);
         }
-        let extent = gfx_hal::image::Extent {
-            width: texture.dimensions.x,
-            height: texture.dimensions.y,
-            depth: 1,
-        };
-        Ok(RuntimeGfxTexture {
-            image_buffer,
-            image_memory,
-            image_logo,
-            extent,
+        self.destroy_buffer(staging);
+        let pixels = data
+            .chunks_exact(4)
+            .map(|p| Vector4::new(p[0], p[1], p[2], p[3]))
+            .collect();
+        Ok(Texture {
+            dimensions: Vector2::new(texture.extent.width, texture.extent.height),
+            pixels,
         })
     }
-    pub fn delete_texture(&mut self, texture: &mut RuntimeTexture) {
-        todo!()
-    }
-    pub fn build_framebuffer(
+    fn build_framebuffer(
         &mut self,
         texture_attachment: &mut RuntimeGfxTexture<B>,
         depth_attachment: &mut RuntimeGfxDepthTexture<B>,
-    ) -> Result<GfxFramebuffer<B>, ErrorType> {
+    ) -> Result<GfxFramebuffer, ErrorType> {
         assert_eq!(texture_attachment.extent, depth_attachment.extent);
         let framebuffer = unsafe {
             self.device.create_framebuffer(
-                &self.render_pass,
-                [
+                &self.offscreen_passes.load,
+                vec![
                     gfx_hal::image::FramebufferAttachment {
-                        usage: gfx_hal::image::Usage::TRANSFER_DST
-                            | gfx_hal::image::Usage::COLOR_ATTACHMENT,
+                        usage: TEXTURE_USAGE,
                         view_caps: gfx_hal::image::ViewCapabilities::empty(),
                         format: ColorFormat::SELF,
                     },
                     gfx_hal::image::FramebufferAttachment {
-                        usage: gfx_hal::image::Usage::TRANSFER_DST
-                            | gfx_hal::image::Usage::DEPTH_STENCIL_ATTACHMENT,
+                        usage: DEPTH_USAGE,
                         view_caps: gfx_hal::image::ViewCapabilities::empty(),
-                        format: ColorFormat::SELF,
+                        format: DEPTH_FORMAT,
                     },
                 ]
-                .iter()
-                .cloned(),
+                .into_iter(),
                 texture_attachment.extent,
             )
         }
         .expect("failed to create framebuffer");
-        Ok(GfxFramebuffer { framebuffer })
-    }
-    pub fn delete_framebuffer(&mut self, framebuffer: &mut Framebuffer) -> Result<(), ErrorType> {
-        todo!()
+        let create_view = |image: &B::Image, format, aspects| unsafe {
+            self.device
+                .create_image_view(
+                    image,
+                    gfx_hal::image::ViewKind::D2,
+                    format,
+                    Swizzle::NO,
+                    gfx_hal::image::SubresourceRange {
+                        aspects,
+                        ..Default::default()
+                    },
+                )
+                .expect("failed to create attachment view")
+        };
+        let color_view = create_view(
+            &texture_attachment
+                .image
+                .as_ref()
+                .expect("texture was deleted")
+                .image,
+            ColorFormat::SELF,
+            format::Aspects::COLOR,
+        );
+        let depth_view = create_view(
+            &depth_attachment
+                .image
+                .as_ref()
+                .expect("depth texture was deleted")
+                .image,
+            DEPTH_FORMAT,
+            format::Aspects::DEPTH,
+        );
+        Ok(GfxFramebuffer {
+            index: self.framebuffers.insert(FramebufferData {
+                framebuffer,
+                color_view,
+                depth_view,
+                extent: texture_attachment.extent,
+            }),
+        })
     }
-    pub fn bind_default_framebuffer(&mut self) {
-        todo!()
+    /// The framebuffer is destroyed once the frame using it has finished
+    fn delete_framebuffer(&mut self, framebuffer: &mut Framebuffer) -> Result<(), ErrorType> {
+        if self.open_pass == Some(PassTarget::Framebuffer(framebuffer.index.clone())) {
+            unsafe { self.end_render_pass() };
+        }
+        if let Some(data) = self.framebuffers.remove(framebuffer.index.clone()) {
+            self.garbage.push(Garbage::Framebuffer(data));
+        }
+        Ok(())
     }
-    pub fn clear_screen(&mut self, color: Vector4<f32>) {
-        todo!()
+    fn bind_default_framebuffer(&mut self) {
+        self.framebuffers.unbind();
     }
-    pub fn clear_depth(&mut self) {
-        todo!()
+    /// Starts a render pass clearing the bound framebuffer, later draws into the same
+    /// framebuffer are recorded into it
+    fn clear_screen(&mut self, color: Vector4<f32>) {
+        unsafe { self.begin_render_pass(Some(color)) };
     }
-    pub fn bind_texture(&mut self, texture: &RuntimeTexture, shader: &Shader) {
-        todo!()
+    fn clear_depth(&mut self) {
+        unsafe {
+            let rect = self.begin_render_pass(None);
+            self.command_buffer.clear_attachments(
+                iter::once(command::AttachmentClear::DepthStencil {
+                    depth: Some(1.0),
+                    stencil: None,
+                }),
+                iter::once(pso::ClearRect { rect, layers: 0..1 }),
+            );
+        }
     }
-    pub fn bind_framebuffer(&mut self, framebuffer: &Framebuffer) {
-        todo!()
+    /// The texture set is written before the next draw with the shader
+    fn bind_texture(&mut self, texture: &RuntimeGfxTexture<B>, shader: &Shader) {
+        let shader = self
+            .shaders
+            .get_mut(shader.index.clone())
+            .expect("shader not found");
+        shader.texture = Some(Arc::clone(
+            texture.image.as_ref().expect("texture was deleted"),
+        ));
+        shader.texture_set = None;
     }
-    pub fn draw_mesh(&mut self, mesh: &RuntimeMesh) {
-        todo!()
+    fn bind_shadow_map(&mut self, texture: &RuntimeGfxDepthTexture<B>, shader: &Shader) {
+        let shader = self
+            .shaders
+            .get_mut(shader.index.clone())
+            .expect("shader not found");
+        shader.shadow_map = Some(Arc::clone(
+            texture.image.as_ref().expect("depth texture was deleted"),
+        ));
+        shader.texture_set = None;
     }
-    pub fn draw_lines(&mut self, mesh: &RuntimeMesh) {
-        todo!()
+    fn bind_framebuffer(&mut self, framebuffer: &Framebuffer) {
+        self.framebuffers.bind(framebuffer.index.clone());
     }
-    pub fn send_model_matrix(&mut self, matrix: Matrix4<f32>, shader: &Shader) {
-        todo!()
+    fn draw_mesh(&mut self, mesh: &RuntimeGfxMesh<B>) {
+        self.draw(mesh, DrawMode::Triangles);
     }
-    pub fn send_view_matrix(&mut self, matrix: Matrix4<f32>, shader: &Shader) {
-        todo!()
+    fn draw_lines(&mut self, mesh: &RuntimeGfxMesh<B>) {
+        self.draw(mesh, DrawMode::Lines);
     }
-    /// Checks all preconditions,
-    /// currently the only precondition is that the fence must always be unsignaled
-    pub fn get_error(&mut self) {
-        assert_eq!(
-            unsafe {
-                self.device
-                    .get_fence_status(&self.submission_complete_fence)
+    /// Submits the commands recorded this frame without waiting for them and presents
+    /// the swapchain image if anything was drawn to it. A swapchain that no longer
+    /// matches the surface is configured again for the next frame
+    fn finish_frame(&mut self) {
+        let present = self.swapchain_image.is_some();
+        unsafe {
+            // the presentation waits on the semaphore even if a flush already submitted
+            // everything drawn to the image
+            if present {
+                self.begin_commands();
             }
-            .expect("failed to get fence status"),
-            false
-        );
-        //nothing to do here
+            self.submit(present);
+        }
+        if let Some(image) = self.swapchain_image.take() {
+            let result = unsafe {
+                self.queue_group.queues[0].present(
+                    &mut *self.surface,
+                    image,
+                    Some(&mut *self.rendering_complete),
+                )
+            };
+            let outdated = match result {
+                Ok(suboptimal) => suboptimal.is_some(),
+                Err(e) => {
+                    error!("failed to present: {:?}", e);
+                    true
+                }
+            };
+            if outdated {
+                let size = Vector2::new(self.dimensions.width, self.dimensions.height);
+                if let Err(e) = self.change_viewport(&size) {
+                    error!("failed to reconfigure swapchain: {:?}", e);
+                }
+            }
+        }
+    }
+    fn send_model_matrix(&mut self, matrix: Matrix4<f32>, shader: &Shader) {
+        unsafe {
+            self.send_uniform(
+                shader,
+                "model",
+                matrix.as_ptr() as *const u8,
+                16 * std::mem::size_of::<f32>(),
+            )
+        }
+    }
+    fn send_view_matrix(&mut self, matrix: Matrix4<f32>, shader: &Shader) {
+        unsafe {
+            self.send_uniform(
+                shader,
+                "camera",
+                matrix.as_ptr() as *const u8,
+                16 * std::mem::size_of::<f32>(),
+            )
+        }
+    }
+    /// The only precondition is that the fence is unsignaled unless a submission has
+    /// not been waited on yet
+    fn get_error(&self) {
+        if !self.in_flight {
+            assert_eq!(
+                unsafe {
+                    self.device
+                        .get_fence_status(&self.submission_complete_fence)
+                }
+                .expect("failed to get fence status"),
+                false
+            );
+        }
+    }
+}
+impl<B: gfx_hal::Backend> PipelineSet<B> {
+    fn new(
+        device: &B::Device,
+        vertex_shader: &B::ShaderModule,
+        fragment_shader: &B::ShaderModule,
+        layout: &B::PipelineLayout,
+        render_pass: &B::RenderPass,
+        vertex_layout: &[ItemDesc],
+    ) -> Self {
+        let build = |primitive| {
+            build_pipeline::<B>(
+                device,
+                vertex_shader,
+                fragment_shader,
+                layout,
+                render_pass,
+                vertex_layout,
+                primitive,
+            )
+        };
+        Self {
+            triangles: build(pso::Primitive::TriangleList),
+            lines: build(pso::Primitive::LineList),
+        }
+    }
+    fn destroy(self, device: &B::Device) {
+        unsafe {
+            device.destroy_graphics_pipeline(self.triangles);
+            device.destroy_graphics_pipeline(self.lines);
+        }
+    }
+}
+fn build_pipeline<B: gfx_hal::Backend>(
+    device: &B::Device,
+    vertex_shader: &B::ShaderModule,
+    fragment_shader: &B::ShaderModule,
+    layout: &B::PipelineLayout,
+    render_pass: &B::RenderPass,
+    vertex_layout: &[ItemDesc],
+    primitive: pso::Primitive,
+) -> B::GraphicsPipeline {
+    let vs_entry = pso::EntryPoint {
+        entry: "main",
+        module: vertex_shader,
+        specialization: pso::Specialization::default(),
+    };
+    let fs_entry = pso::EntryPoint {
+        entry: "main",
+        module: fragment_shader,
+        specialization: pso::Specialization::default(),
+    };
+    let subpass = Subpass {
+        index: 0,
+        main_pass: render_pass,
+    };
+    let stride: usize = vertex_layout
+        .iter()
+        .map(|desc| desc.number_components * desc.size_component)
+        .sum();
+    let vertex_buffers = vec![pso::VertexBufferDesc {
+        binding: 0,
+        stride: stride as u32,
+        rate: VertexInputRate::Vertex,
+    }];
+    let mut offset = 0;
+    let attributes: Vec<pso::AttributeDesc> = vertex_layout
+        .iter()
+        .enumerate()
+        .map(|(location, desc)| {
+            let attribute = pso::AttributeDesc {
+                location: location as u32,
+                binding: 0,
+                element: pso::Element {
+                    format: match desc.number_components {
+                        1 => format::Format::R32Sfloat,
+                        2 => format::Format::Rg32Sfloat,
+                        3 => format::Format::Rgb32Sfloat,
+                        4 => format::Format::Rgba32Sfloat,
+                        n => panic!("unsupported number of components: {}", n),
+                    },
+                    offset: offset as u32,
+                },
+            };
+            offset += desc.number_components * desc.size_component;
+            attribute
+        })
+        .collect();
+    let mut pipeline_desc = pso::GraphicsPipelineDesc::new(
+        pso::PrimitiveAssemblerDesc::Vertex {
+            buffers: &vertex_buffers,
+            attributes: &attributes,
+            input_assembler: pso::InputAssemblerDesc {
+                primitive,
+                with_adjacency: false,
+                restart_index: None,
+            },
+            vertex: vs_entry,
+            geometry: None,
+            tessellation: None,
+        },
+        pso::Rasterizer::FILL,
+        Some(fs_entry),
+        layout,
+        subpass,
+    );
+    pipeline_desc.blender.targets.push(pso::ColorBlendDesc {
+        mask: pso::ColorMask::ALL,
+        blend: Some(pso::BlendState::PREMULTIPLIED_ALPHA),
+    });
+    pipeline_desc.depth_stencil = pso::DepthStencilDesc {
+        depth: Some(pso::DepthTest {
+            fun: pso::Comparison::Less,
+            write: true,
+        }),
+        depth_bounds: false,
+        stencil: None,
+    };
+    unsafe { device.create_graphics_pipeline(&pipeline_desc, None) }
+        .expect("failed to create pipeline")
+}
+impl<B: gfx_hal::Backend> RenderPasses<B> {
+    fn destroy(self, device: &B::Device) {
+        unsafe {
+            device.destroy_render_pass(self.clear);
+            device.destroy_render_pass(self.load);
+        }
+    }
+}
+/// Builds clear and load passes for a color attachment of `color_format` that ends in `color_layout`
+fn build_render_passes<B: gfx_hal::Backend>(
+    device: &B::Device,
+    color_format: format::Format,
+    color_layout: Layout,
+) -> RenderPasses<B> {
+    // all passes of a frame are recorded into one command buffer, so the attachments
+    // written by a pass have to be made visible to the passes, shaders and copies after it
+    let attachment_stages = PipelineStage::COLOR_ATTACHMENT_OUTPUT
+        | PipelineStage::EARLY_FRAGMENT_TESTS
+        | PipelineStage::LATE_FRAGMENT_TESTS;
+    let attachment_writes = gfx_hal::image::Access::COLOR_ATTACHMENT_WRITE
+        | gfx_hal::image::Access::DEPTH_STENCIL_ATTACHMENT_WRITE;
+    let attachment_access = attachment_writes
+        | gfx_hal::image::Access::COLOR_ATTACHMENT_READ
+        | gfx_hal::image::Access::DEPTH_STENCIL_ATTACHMENT_READ;
+    let build = |load_op, color_initial, depth_initial| {
+        let color = pass::Attachment {
+            format: Some(color_format),
+            samples: 1,
+            ops: pass::AttachmentOps::new(load_op, pass::AttachmentStoreOp::Store),
+            stencil_ops: pass::AttachmentOps::DONT_CARE,
+            layouts: color_initial..color_layout,
+        };
+        let depth = pass::Attachment {
+            format: Some(DEPTH_FORMAT),
+            samples: 1,
+            ops: pass::AttachmentOps::new(load_op, pass::AttachmentStoreOp::Store),
+            stencil_ops: pass::AttachmentOps::DONT_CARE,
+            layouts: depth_initial..DEPTH_LAYOUT,
+        };
+        let subpass = pass::SubpassDesc {
+            colors: &[(0, Layout::ColorAttachmentOptimal)],
+            depth_stencil: Some(&(1, DEPTH_LAYOUT)),
+            inputs: &[],
+            resolves: &[],
+            preserves: &[],
+        };
+        unsafe {
+            device.create_render_pass(
+                vec![color, depth].into_iter(),
+                iter::once(subpass),
+                vec![
+                    pass::SubpassDependency {
+                        passes: None..Some(0),
+                        stages: attachment_stages
+                            | PipelineStage::FRAGMENT_SHADER
+                            | PipelineStage::TRANSFER
+                            ..attachment_stages | PipelineStage::FRAGMENT_SHADER,
+                        accesses: attachment_writes | gfx_hal::image::Access::TRANSFER_WRITE
+                            ..attachment_access | gfx_hal::image::Access::SHADER_READ,
+                        flags: gfx_hal::memory::Dependencies::empty(),
+                    },
+                    pass::SubpassDependency {
+                        passes: Some(0)..None,
+                        stages: attachment_stages
+                            ..attachment_stages
+                                | PipelineStage::FRAGMENT_SHADER
+                                | PipelineStage::TRANSFER,
+                        accesses: attachment_writes
+                            ..attachment_access
+                                | gfx_hal::image::Access::SHADER_READ
+                                | gfx_hal::image::Access::TRANSFER_READ,
+                        flags: gfx_hal::memory::Dependencies::empty(),
+                    },
+                ]
+                .into_iter(),
+            )
+        }
+        .expect("failed to create render pass")
+    };
+    RenderPasses {
+        clear: build(
+            pass::AttachmentLoadOp::Clear,
+            Layout::Undefined,
+            Layout::Undefined,
+        ),
+        load: build(pass::AttachmentLoadOp::Load, color_layout, DEPTH_LAYOUT),
+    }
+}
+/// Builds framebuffer used for swapchain images
+fn build_surface_framebuffer<B: gfx_hal::Backend>(
+    device: &B::Device,
+    render_pass: &B::RenderPass,
+    swapchain_attachment: gfx_hal::image::FramebufferAttachment,
+    extent: window::Extent2D,
+) -> B::Framebuffer {
+    unsafe {
+        device.create_framebuffer(
+            render_pass,
+            vec![
+                swapchain_attachment,
+                gfx_hal::image::FramebufferAttachment {
+                    usage: DEPTH_USAGE,
+                    view_caps: gfx_hal::image::ViewCapabilities::empty(),
+                    format: DEPTH_FORMAT,
+                },
+            ]
+            .into_iter(),
+            extent.to_extent(),
+        )
     }
+    .expect("failed to create framebuffer")
+}
+/// Finds memory type allowed by `type_mask` that has `properties`
+fn find_memory_type<B: gfx_hal::Backend>(
+    adapter: &gfx_hal::adapter::Adapter<B>,
+    type_mask: u32,
+    properties: memory::Properties,
+) -> gfx_hal::MemoryTypeId {
+    adapter
+        .physical_device
+        .memory_properties()
+        .memory_types
+        .iter()
+        .enumerate()
+        .position(|(id, mem_type)| {
+            // type_mask is a bit field where each bit represents a memory type. If the bit is set
+            // to 1 it means we can use that type for our buffer. So this code finds the first
+            // memory type that has a `1` (or, is allowed), and has the requested properties.
+            type_mask & (1 << id) != 0 && mem_type.properties.contains(properties)
+        })
+        .expect("no suitable memory type")
+        .into()
+}
+/// Creates buffer backed by cpu visible memory
+fn create_buffer<B: gfx_hal::Backend>(
+    device: &B::Device,
+    adapter: &gfx_hal::adapter::Adapter<B>,
+    size: u64,
+    usage: buffer::Usage,
+) -> GfxBuffer<B> {
+    let mut buffer = unsafe { device.create_buffer(size, usage) }.expect("failed to create buffer");
+    let requirements = unsafe { device.get_buffer_requirements(&buffer) };
+    let memory_type = find_memory_type(
+        adapter,
+        requirements.type_mask,
+        memory::Properties::CPU_VISIBLE,
+    );
+    let memory = unsafe {
+        let memory = device
+            .allocate_memory(memory_type, requirements.size)
+            .expect("failed to allocate memory");
+        device
+            .bind_buffer_memory(&memory, 0, &mut buffer)
+            .expect("failed to bind buffer memory");
+        memory
+    };
+    GfxBuffer { buffer, memory }
+}
+/// Copies `size` bytes from `data` into cpu visible memory starting `offset` bytes in
+unsafe fn write_memory<B: gfx_hal::Backend>(
+    device: &B::Device,
+    memory: &mut B::Memory,
+    offset: usize,
+    data: *const u8,
+    size: usize,
+) {
+    let memory_ptr = device
+        .map_memory(memory, memory::Segment::ALL)
+        .expect("failed to map memory");
+    ptr::copy_nonoverlapping(data, memory_ptr.add(offset), size);
+    device
+        .flush_mapped_memory_ranges(iter::once((&*memory, memory::Segment::ALL)))
+        .expect("failed to flush memory");
+    device.unmap_memory(memory);
+}
+/// Copies `size` bytes out of cpu visible memory
+unsafe fn read_memory<B: gfx_hal::Backend>(
+    device: &B::Device,
+    memory: &mut B::Memory,
+    data: *mut u8,
+    size: usize,
+) {
+    let memory_ptr = device
+        .map_memory(memory, memory::Segment::ALL)
+        .expect("failed to map memory");
+    device
+        .invalidate_mapped_memory_ranges(iter::once((&*memory, memory::Segment::ALL)))
+        .expect("failed to invalidate memory");
+    ptr::copy_nonoverlapping(memory_ptr, data, size);
+    device.unmap_memory(memory);
 }
 impl<B: gfx_hal::Backend> Drop for GfxRenderingContext<B> {
     fn drop(&mut self) {
         self.device.wait_idle().unwrap();
+        self.swapchain_image = None;
+        if let Some(mut depth) = self.surface_depth.take() {
+            self.delete_depth_buffer(&mut depth).ok();
+        }
+        let framebuffers: Vec<_> = self.framebuffers.drain().collect();
+        for data in framebuffers {
+            self.destroy_framebuffer(data);
+        }
+        let shaders: Vec<_> = self.shaders.drain().collect();
+        for shader in shaders {
+            shader.surface.destroy(&self.device);
+            shader.offscreen.destroy(&self.device);
+            unsafe {
+                self.device.destroy_pipeline_layout(shader.pipeline_layout);
+                self.device
+                    .destroy_descriptor_set_layout(shader.uniform_set_layout);
+            }
+        }
+        // the shaders no longer hold on to the bound images
+        self.release_garbage();
+        let uniform_ring = unsafe { ManuallyDrop::into_inner(ptr::read(&self.uniform_ring)) };
+        self.destroy_buffer(uniform_ring.buffer);
         unsafe {
             self.device
                 .destroy_descriptor_pool(ManuallyDrop::into_inner(ptr::read(&self.desc_pool)));
+            self.device
+                .destroy_descriptor_pool(ManuallyDrop::into_inner(ptr::read(
+                    &self.frame_desc_pool,
+                )));
             self.device
                 .destroy_descriptor_set_layout(ManuallyDrop::into_inner(ptr::read(
                     &self.set_layout,
                 )));
             self.device
-                .destroy_buffer(ManuallyDrop::into_inner(ptr::read(&self.vertex_buffer)));
-            // self.device.destroy_command_pool(self.cmd_buffer.0);
-
-            //self.device
-            //    .destroy_semaphore(self.submission_complete_semaphore);
-            //self.device.destroy_fence(self.submission_complete_fence);
+                .destroy_sampler(ManuallyDrop::into_inner(ptr::read(&self.sampler)));
+            self.device
+                .destroy_sampler(ManuallyDrop::into_inner(ptr::read(&self.shadow_sampler)));
+            let mut command_pool = ManuallyDrop::into_inner(ptr::read(&self.command_pool));
+            command_pool.free(iter::once(ManuallyDrop::into_inner(ptr::read(
+                &self.command_buffer,
+            ))));
+            self.device.destroy_command_pool(command_pool);
+            self.device
+                .destroy_fence(ManuallyDrop::into_inner(ptr::read(
+                    &self.submission_complete_fence,
+                )));
             self.device
-                .destroy_render_pass(ManuallyDrop::into_inner(ptr::read(&self.render_pass)));
+                .destroy_semaphore(ManuallyDrop::into_inner(ptr::read(
+                    &self.rendering_complete,
+                )));
+            ManuallyDrop::into_inner(ptr::read(&self.surface_passes)).destroy(&self.device);
+            ManuallyDrop::into_inner(ptr::read(&self.offscreen_passes)).destroy(&self.device);
 
             self.device
                 .destroy_framebuffer(ManuallyDrop::into_inner(ptr::read(&self.framebuffer)));
             self.surface.unconfigure_swapchain(&self.device);
 
-            self.device
-                .free_memory(ManuallyDrop::into_inner(ptr::read(&self.buffer_memory)));
-            self.device
-                .destroy_graphics_pipeline(ManuallyDrop::into_inner(ptr::read(&self.pipeline)));
-            self.device
-                .destroy_pipeline_layout(ManuallyDrop::into_inner(ptr::read(
-                    &self.pipeline_layout,
-                )));
-
             self.instance
                 .destroy_surface(ManuallyDrop::into_inner(ptr::read(&self.surface)));
         }
@@ -1,5 +1,5 @@
 use generational_arena::{Arena, Index};
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 pub struct BindArenaIndex {
     index: Index,
 }
@@ -28,9 +28,27 @@ impl<T> BindArena<T> {
             None
         }
     }
+    pub fn bound_index(&self) -> Option<BindArenaIndex> {
+        self.currently_bound.map(|index| BindArenaIndex { index })
+    }
     pub fn bind(&mut self, idx: BindArenaIndex) {
         self.currently_bound = Some(idx.index);
     }
+    pub fn unbind(&mut self) {
+        self.currently_bound = None;
+    }
+    /// Removes item, unbinding it if it is currently bound
+    pub fn remove(&mut self, idx: BindArenaIndex) -> Option<T> {
+        if self.currently_bound == Some(idx.index) {
+            self.currently_bound = None;
+        }
+        self.arena.remove(idx.index)
+    }
+    /// Removes all items from the arena
+    pub fn drain(&mut self) -> impl Iterator<Item = T> + '_ {
+        self.currently_bound = None;
+        self.arena.drain().map(|(_, data)| data)
+    }
 }
 impl<T> Default for BindArena<T> {
     fn default() -> Self {
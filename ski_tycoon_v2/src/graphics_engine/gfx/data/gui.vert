@@ -2,14 +2,13 @@
 #extension GL_ARB_separate_shader_objects: enable
 layout(location = 0) in vec3 position;
 layout(location = 1) in vec2 uv;
-layout(location = 2) in vec3 normal;
-layout(location = 3) in vec4 vertex_color;
+layout(location = 2) in vec4 vertex_color;
 layout(location = 0) out vec2 o_uv;
 layout(location=1) out vec3 o_normal;
 layout(location=2) out vec4 o_color;
 
-layout(binding = 0) uniform Camera{ mat4 matrix;} camera;
-layout(binding = 1) uniform Model{mat4 matrix;} model;
+layout(set = 1, binding = 0) uniform Camera{ mat4 matrix;} camera;
+layout(set = 1, binding = 1) uniform Model{mat4 matrix;} model;
 
 
 out gl_PerVertex{
@@ -17,8 +16,11 @@ out gl_PerVertex{
 };
 void main(){
     o_uv = uv;
-    o_normal = normal;
+    o_normal = vec3(0.0, 0.0, 1.0);
     o_color = vertex_color;
     gl_Position = vec4(1.0*position,1.0);
+    // gui positions are given in opengl clip space
+    gl_Position.y = -gl_Position.y;
+    gl_Position.z = (gl_Position.z + gl_Position.w) / 2.0;
 
 }
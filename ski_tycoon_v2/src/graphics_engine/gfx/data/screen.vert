@@ -3,13 +3,11 @@
 layout(location = 0) in vec3 position;
 layout(location = 1) in vec2 uv;
 layout(location = 2) in vec3 normal;
-layout(location = 3) in vec4 vertex_color;
 layout(location = 0) out vec2 o_uv;
 layout(location=1) out vec3 o_normal;
-layout(location=2) out vec4 o_color;
 
-layout(binding = 0) uniform Camera{ mat4 matrix;} camera;
-layout(binding = 1) uniform Model{mat4 matrix;} model;
+layout(set = 1, binding = 0) uniform Camera{ mat4 matrix;} camera;
+layout(set = 1, binding = 1) uniform Model{mat4 matrix;} model;
 
 
 out gl_PerVertex{
@@ -18,7 +16,9 @@ out gl_PerVertex{
 void main(){
     o_uv = uv;
     o_normal = normal;
-    o_color = vertex_color;
-    gl_Position = vec4(1.0*position,1.0);
+    gl_Position = camera.matrix*model.matrix*vec4(1.0*position,1.0);
+    // matrices are built for opengl clip space, vulkan has y flipped and z in 0..1
+    gl_Position.y = -gl_Position.y;
+    gl_Position.z = (gl_Position.z + gl_Position.w) / 2.0;
 
 }
@@ -2,17 +2,44 @@
 #extension GL_ARB_separate_shader_objects: enable
 layout(location = 0) in vec2 v_uv;
 layout(location = 1) in vec3 o_normal;
+layout(location = 2) in vec4 o_light_position;
 layout(location = 0) out vec4 target0;
 
 layout(set=0, binding = 0) uniform texture2D u_texture;
 layout(set=0, binding = 1) uniform sampler u_sampler;
-layout(binding = 2) uniform SunDirection{vec3 vector;} sun_direction;
-layout(binding = 3) uniform SunColor{vec4 vector;} sun_color;
+layout(set=0, binding = 2) uniform texture2D shadow_map;
+layout(set=0, binding = 3) uniform sampler shadow_sampler;
+layout(set = 1, binding = 2) uniform SunDirection{vec3 vector;} sun_direction;
+layout(set = 1, binding = 3) uniform SunColor{vec4 vector;} sun_color;
+// light that still reaches surfaces in shadow or facing away from the sun
+const float AMBIENT = 0.3;
+// keeps surfaces from shadowing themselves
+const float SHADOW_BIAS = 0.002;
 vec4 onify(vec4 v){
     return v*vec4(0.0,0.0,0.0,0.0)+vec4(1.0,1.0,1.0,1.0);
 }
+// fraction of the 3x3 texels around the fragment in the shadow map that see the sun
+float shadow(){
+    vec3 ndc = o_light_position.xyz/o_light_position.w;
+    // the shadow map was rendered with y flipped
+    vec2 uv = vec2(0.5 + 0.5*ndc.x, 0.5 - 0.5*ndc.y);
+    float depth = 0.5 + 0.5*ndc.z;
+    if (depth > 1.0) {
+        return 1.0;
+    }
+    vec2 texel = 1.0/vec2(textureSize(sampler2D(shadow_map, shadow_sampler), 0));
+    float lit = 0.0;
+    for (int x = -1; x <= 1; x++) {
+        for (int y = -1; y <= 1; y++) {
+            float occluder = texture(sampler2D(shadow_map, shadow_sampler), uv + vec2(x, y)*texel).r;
+            lit += depth - SHADOW_BIAS > occluder ? 0.0 : 1.0;
+        }
+    }
+    return lit/9.0;
+}
 float sun(){
-    return dot(-1.0*sun_direction.vector,o_normal);
+    float diffuse = max(dot(-1.0*sun_direction.vector,o_normal), 0.0);
+    return AMBIENT + (1.0 - AMBIENT)*diffuse*shadow();
 }
 vec4 sun_vec(){
     float s = sun();
@@ -22,4 +49,3 @@ vec4 sun_vec(){
 void main(){
     target0 = sun_vec()*sun_color.vector*texture(sampler2D(u_texture,u_sampler),v_uv);
 }
-
@@ -5,17 +5,21 @@ layout(location = 1) in vec2 uv;
 layout(location = 2) in vec3 normal;
 layout(location = 0) out vec2 o_uv;
 layout(location=1) out vec3 o_normal;
+layout(location = 2) out vec4 o_light_position;
 
-layout(binding = 0) uniform Camera{ mat4 matrix;} camera;
-layout(binding = 1) uniform Model{mat4 matrix;} model;
+layout(set = 1, binding = 0) uniform Camera{ mat4 matrix;} camera;
+layout(set = 1, binding = 1) uniform Model{mat4 matrix;} model;
+layout(set = 1, binding = 6) uniform Light{ mat4 matrix;} light;
 
-
-out gl_PerVertex{
-    vec4 gl_Position;
-};
 void main(){
+    vec4 world_position = model.matrix*vec4(1.0*position,1.0);
     o_uv = uv;
     o_normal = normal;
-    gl_Position = camera.matrix*model.matrix*vec4(1.0*position,1.0);
+    // left in opengl clip space, the fragment shader converts it to shadow map coordinates
+    o_light_position = light.matrix*world_position;
+    gl_Position = camera.matrix*world_position;
+    // matrices are built for opengl clip space, vulkan has y flipped and z in 0..1
+    gl_Position.y = -gl_Position.y;
+    gl_Position.z = (gl_Position.z + gl_Position.w) / 2.0;
 
 }
@@ -0,0 +1,8 @@
+#version 450
+#extension GL_ARB_separate_shader_objects: enable
+layout(location = 0) out vec4 target0;
+
+// only the depth is used
+void main(){
+    target0 = vec4(0.0);
+}
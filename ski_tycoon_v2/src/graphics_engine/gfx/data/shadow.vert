@@ -0,0 +1,15 @@
+#version 450
+#extension GL_ARB_separate_shader_objects: enable
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec2 uv;
+layout(location = 2) in vec3 normal;
+
+layout(set = 1, binding = 0) uniform Camera{ mat4 matrix;} camera;
+layout(set = 1, binding = 1) uniform Model{mat4 matrix;} model;
+
+void main(){
+    gl_Position = camera.matrix*model.matrix*vec4(position,1.0);
+    // matrices are built for opengl clip space, vulkan has y flipped and z in 0..1
+    gl_Position.y = -gl_Position.y;
+    gl_Position.z = (gl_Position.z + gl_Position.w) / 2.0;
+}
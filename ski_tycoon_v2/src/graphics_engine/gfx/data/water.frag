@@ -0,0 +1,70 @@
+#version 450
+#extension GL_ARB_separate_shader_objects: enable
+layout(location = 0) in vec2 v_uv;
+layout(location = 1) in vec3 o_normal;
+layout(location = 2) in vec3 o_position;
+// depth and speed of the water
+layout(location = 3) in vec2 o_water;
+layout(location = 4) in vec4 o_light_position;
+layout(location = 0) out vec4 target0;
+
+layout(set=0, binding = 0) uniform texture2D u_texture;
+layout(set=0, binding = 1) uniform sampler u_sampler;
+layout(set=0, binding = 2) uniform texture2D shadow_map;
+layout(set=0, binding = 3) uniform sampler shadow_sampler;
+layout(set = 1, binding = 2) uniform SunDirection{vec3 vector;} sun_direction;
+layout(set = 1, binding = 3) uniform SunColor{vec4 vector;} sun_color;
+layout(set = 1, binding = 4) uniform CameraPosition{vec3 vector;} camera_position;
+// 1 replaces the water with the texture as an opaque false color overlay
+layout(set = 1, binding = 5) uniform Overlay{float value;} overlay;
+// light that still reaches water in shadow or facing away from the sun
+const float AMBIENT = 0.3;
+// keeps the surface from shadowing itself
+const float SHADOW_BIAS = 0.002;
+// depth at which the water absorbs 63% of the light passing through it
+const float ABSORPTION_DEPTH = 2.0;
+// speeds at which foam starts to form and fully covers the water
+const float FOAM_START = 0.2;
+const float FOAM_FULL = 0.8;
+// water shallower than this fades out so that dry ground shows no water
+const float MIN_DEPTH = 0.01;
+const float SHININESS = 64.0;
+// fraction of the 3x3 texels around the fragment in the shadow map that see the sun
+float shadow(){
+    vec3 ndc = o_light_position.xyz/o_light_position.w;
+    // the shadow map was rendered with y flipped
+    vec2 uv = vec2(0.5 + 0.5*ndc.x, 0.5 - 0.5*ndc.y);
+    float depth = 0.5 + 0.5*ndc.z;
+    if (depth > 1.0) {
+        return 1.0;
+    }
+    vec2 texel = 1.0/vec2(textureSize(sampler2D(shadow_map, shadow_sampler), 0));
+    float lit = 0.0;
+    for (int x = -1; x <= 1; x++) {
+        for (int y = -1; y <= 1; y++) {
+            float occluder = texture(sampler2D(shadow_map, shadow_sampler), uv + vec2(x, y)*texel).r;
+            lit += depth - SHADOW_BIAS > occluder ? 0.0 : 1.0;
+        }
+    }
+    return lit/9.0;
+}
+
+void main(){
+    vec3 normal = normalize(o_normal);
+    float absorption = 1.0 - exp(-max(o_water.x, 0.0) / ABSORPTION_DEPTH);
+    vec4 water_color = texture(sampler2D(u_texture,u_sampler),v_uv);
+    vec3 body = mix(water_color.rgb, 0.3*water_color.rgb, absorption);
+    float foam = smoothstep(FOAM_START, FOAM_FULL, o_water.y);
+    float diffuse = max(dot(-1.0*sun_direction.vector, normal), 0.0);
+    vec3 view = normalize(camera_position.vector - o_position);
+    vec3 halfway = normalize(view - sun_direction.vector);
+    float sun = shadow();
+    float specular = pow(max(dot(normal, halfway), 0.0), SHININESS)*(1.0 - foam)*sun;
+    float alpha = mix(mix(0.3, 0.9, absorption), 1.0, foam)
+        *smoothstep(0.0, MIN_DEPTH, o_water.x)*sun_color.vector.a;
+    vec3 lit = mix(body, vec3(1.0), foam)*(AMBIENT + (1.0 - AMBIENT)*diffuse*sun)
+        *sun_color.vector.rgb;
+    vec4 water = vec4((lit + specular*sun_color.vector.rgb)*alpha, alpha);
+    vec4 overlay_color = vec4(water_color.rgb*(0.5 + 0.5*diffuse), 1.0);
+    target0 = mix(water, overlay_color, overlay.value);
+}
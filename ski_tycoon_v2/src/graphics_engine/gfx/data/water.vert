@@ -0,0 +1,29 @@
+#version 450
+#extension GL_ARB_separate_shader_objects: enable
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec2 uv;
+layout(location = 2) in vec3 normal;
+layout(location = 3) in vec2 water;
+layout(location = 0) out vec2 o_uv;
+layout(location = 1) out vec3 o_normal;
+layout(location = 2) out vec3 o_position;
+layout(location = 3) out vec2 o_water;
+layout(location = 4) out vec4 o_light_position;
+
+layout(set = 1, binding = 0) uniform Camera{ mat4 matrix;} camera;
+layout(set = 1, binding = 1) uniform Model{mat4 matrix;} model;
+layout(set = 1, binding = 6) uniform Light{ mat4 matrix;} light;
+
+void main(){
+    vec4 world_position = model.matrix*vec4(position,1.0);
+    o_uv = uv;
+    o_normal = normal;
+    o_position = world_position.xyz;
+    o_water = water;
+    // left in opengl clip space, the fragment shader converts it to shadow map coordinates
+    o_light_position = light.matrix*world_position;
+    gl_Position = camera.matrix*world_position;
+    // matrices are built for opengl clip space, vulkan has y flipped and z in 0..1
+    gl_Position.y = -gl_Position.y;
+    gl_Position.z = (gl_Position.z + gl_Position.w) / 2.0;
+}
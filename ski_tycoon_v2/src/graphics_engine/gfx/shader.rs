@@ -1,3 +1,4 @@
+use super::super::ItemDesc;
 use gfx_hal::{pso, pso::DescriptorSetLayoutBinding};
 use std::collections::HashMap;
 use std::io::Cursor;
@@ -8,6 +9,7 @@ pub enum ShaderTypes {
 #[allow(dead_code)]
 #[derive(Clone)]
 pub enum UniformDataType {
+    Float,
     Vec3,
     Vec4,
     Mat3,
@@ -16,6 +18,7 @@ pub enum UniformDataType {
 impl UniformDataType {
     pub fn size(&self) -> usize {
         match self {
+            Self::Float => std::mem::size_of::<f32>(),
             Self::Vec3 => 3 * std::mem::size_of::<f32>(),
             Self::Vec4 => 4 * std::mem::size_of::<f32>(),
             Self::Mat3 => 3 * 3 * std::mem::size_of::<f32>(),
@@ -33,6 +36,8 @@ pub struct ShaderData {
     pub vertex_shader_data: Vec<u32>,
     pub vertex_uniform_layout: HashMap<String, UniformData>,
     pub fragment_uniform_layout: HashMap<String, UniformData>,
+    /// Layout of the vertices the pipeline reads, must match the description of meshes drawn with it
+    pub vertex_layout: Vec<ItemDesc>,
 }
 #[allow(dead_code)]
 fn get_vec(data: &'static [u8]) -> Vec<u32> {
@@ -65,7 +70,7 @@ pub fn get_world() -> ShaderData {
                         ty: pso::DescriptorType::Buffer {
                             ty: pso::BufferDescriptorType::Uniform,
                             format: pso::BufferDescriptorFormat::Structured {
-                                dynamic_offset: false,
+                                dynamic_offset: true,
                             },
                         },
                         count: 1,
@@ -83,7 +88,25 @@ pub fn get_world() -> ShaderData {
                         ty: pso::DescriptorType::Buffer {
                             ty: pso::BufferDescriptorType::Uniform,
                             format: pso::BufferDescriptorFormat::Structured {
-                                dynamic_offset: false,
+                                dynamic_offset: true,
+                            },
+                        },
+                        count: 1,
+                        stage_flags: pso::ShaderStageFlags::VERTEX,
+                        immutable_samplers: false,
+                    },
+                    data_type: UniformDataType::Mat4,
+                },
+            ),
+            (
+                "light".to_string(),
+                UniformData {
+                    layout_binding: DescriptorSetLayoutBinding {
+                        binding: 6,
+                        ty: pso::DescriptorType::Buffer {
+                            ty: pso::BufferDescriptorType::Uniform,
+                            format: pso::BufferDescriptorFormat::Structured {
+                                dynamic_offset: true,
                             },
                         },
                         count: 1,
@@ -106,7 +129,7 @@ pub fn get_world() -> ShaderData {
                         ty: pso::DescriptorType::Buffer {
                             ty: pso::BufferDescriptorType::Uniform,
                             format: pso::BufferDescriptorFormat::Structured {
-                                dynamic_offset: false,
+                                dynamic_offset: true,
                             },
                         },
                         count: 1,
@@ -124,7 +147,7 @@ pub fn get_world() -> ShaderData {
                         ty: pso::DescriptorType::Buffer {
                             ty: pso::BufferDescriptorType::Uniform,
                             format: pso::BufferDescriptorFormat::Structured {
-                                dynamic_offset: false,
+                                dynamic_offset: true,
                             },
                         },
                         count: 1,
@@ -138,6 +161,88 @@ pub fn get_world() -> ShaderData {
         .iter()
         .cloned()
         .collect(),
+        vertex_layout: ItemDesc::default_model(),
+    }
+}
+/// Same uniforms as the world shader plus the position of the camera for specular
+/// highlights and the strength of the false color overlay
+pub fn get_water() -> ShaderData {
+    let world = get_world();
+    let mut fragment_uniform_layout = world.fragment_uniform_layout;
+    fragment_uniform_layout.insert(
+        "camera_position".to_string(),
+        UniformData {
+            layout_binding: DescriptorSetLayoutBinding {
+                binding: 4,
+                ty: pso::DescriptorType::Buffer {
+                    ty: pso::BufferDescriptorType::Uniform,
+                    format: pso::BufferDescriptorFormat::Structured {
+                        dynamic_offset: true,
+                    },
+                },
+                count: 1,
+                stage_flags: pso::ShaderStageFlags::FRAGMENT,
+                immutable_samplers: false,
+            },
+            data_type: UniformDataType::Vec3,
+        },
+    );
+    fragment_uniform_layout.insert(
+        "overlay".to_string(),
+        UniformData {
+            layout_binding: DescriptorSetLayoutBinding {
+                binding: 5,
+                ty: pso::DescriptorType::Buffer {
+                    ty: pso::BufferDescriptorType::Uniform,
+                    format: pso::BufferDescriptorFormat::Structured {
+                        dynamic_offset: true,
+                    },
+                },
+                count: 1,
+                stage_flags: pso::ShaderStageFlags::FRAGMENT,
+                immutable_samplers: false,
+            },
+            data_type: UniformDataType::Float,
+        },
+    );
+    ShaderData {
+        fragment_shader_data: gfx_auxil::read_spirv(Cursor::new(&include_bytes!(
+            "./compiled_shader/water.frag.spv"
+        )))
+        .unwrap(),
+        vertex_shader_data: gfx_auxil::read_spirv(Cursor::new(&include_bytes!(
+            "./compiled_shader/water.vert.spv"
+        )))
+        .unwrap(),
+        vertex_uniform_layout: world.vertex_uniform_layout,
+        fragment_uniform_layout,
+        vertex_layout: ItemDesc::water_model(),
+    }
+}
+/// Depth only shader drawing the same meshes as the world shader, the camera is the
+/// view and projection of the sun
+pub fn get_shadow() -> ShaderData {
+    let mut vertex_uniform_layout = get_world().vertex_uniform_layout;
+    vertex_uniform_layout.remove("light");
+    ShaderData {
+        fragment_shader_data: gfx_auxil::read_spirv(Cursor::new(&include_bytes!(
+            "./compiled_shader/shadow.frag.spv"
+        )))
+        .unwrap(),
+        vertex_shader_data: gfx_auxil::read_spirv(Cursor::new(&include_bytes!(
+            "./compiled_shader/shadow.vert.spv"
+        )))
+        .unwrap(),
+        vertex_uniform_layout,
+        fragment_uniform_layout: HashMap::new(),
+        vertex_layout: ItemDesc::default_model(),
+    }
+}
+/// Shadow shader for water meshes, only the layout of the vertices differs
+pub fn get_water_shadow() -> ShaderData {
+    ShaderData {
+        vertex_layout: ItemDesc::water_model(),
+        ..get_shadow()
     }
 }
 pub fn get_screen() -> ShaderData {
@@ -159,7 +264,7 @@ pub fn get_screen() -> ShaderData {
                         ty: pso::DescriptorType::Buffer {
                             ty: pso::BufferDescriptorType::Uniform,
                             format: pso::BufferDescriptorFormat::Structured {
-                                dynamic_offset: false,
+                                dynamic_offset: true,
                             },
                         },
                         count: 1,
@@ -177,7 +282,7 @@ pub fn get_screen() -> ShaderData {
                         ty: pso::DescriptorType::Buffer {
                             ty: pso::BufferDescriptorType::Uniform,
                             format: pso::BufferDescriptorFormat::Structured {
-                                dynamic_offset: false,
+                                dynamic_offset: true,
                             },
                         },
                         count: 1,
@@ -192,9 +297,9 @@ pub fn get_screen() -> ShaderData {
         .cloned()
         .collect(),
         fragment_uniform_layout: HashMap::new(),
+        vertex_layout: ItemDesc::default_model(),
     }
 }
-#[allow(dead_code)]
 pub fn get_gui() -> ShaderData {
     ShaderData {
         fragment_shader_data: gfx_auxil::read_spirv(Cursor::new(&include_bytes!(
@@ -214,7 +319,7 @@ pub fn get_gui() -> ShaderData {
                         ty: pso::DescriptorType::Buffer {
                             ty: pso::BufferDescriptorType::Uniform,
                             format: pso::BufferDescriptorFormat::Structured {
-                                dynamic_offset: false,
+                                dynamic_offset: true,
                             },
                         },
                         count: 1,
@@ -232,7 +337,7 @@ pub fn get_gui() -> ShaderData {
                         ty: pso::DescriptorType::Buffer {
                             ty: pso::BufferDescriptorType::Uniform,
                             format: pso::BufferDescriptorFormat::Structured {
-                                dynamic_offset: false,
+                                dynamic_offset: true,
                             },
                         },
                         count: 1,
@@ -247,5 +352,22 @@ pub fn get_gui() -> ShaderData {
         .cloned()
         .collect(),
         fragment_uniform_layout: HashMap::new(),
+        vertex_layout: vec![
+            ItemDesc {
+                number_components: 3,
+                size_component: std::mem::size_of::<f32>(),
+                name: "position".to_string(),
+            },
+            ItemDesc {
+                number_components: 2,
+                size_component: std::mem::size_of::<f32>(),
+                name: "uv".to_string(),
+            },
+            ItemDesc {
+                number_components: 4,
+                size_component: std::mem::size_of::<f32>(),
+                name: "vertex_color".to_string(),
+            },
+        ],
     }
 }
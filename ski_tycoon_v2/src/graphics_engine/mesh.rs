@@ -32,12 +32,57 @@ impl ItemDesc {
             },
         ]
     }
+    /// Layout of water surface meshes, the default model followed by the water depth
+    /// and speed
+    pub fn water_model() -> Vec<ItemDesc> {
+        let mut description = Self::default_model();
+        description.push(ItemDesc {
+            number_components: 2,
+            size_component: std::mem::size_of::<f32>(),
+            name: "water".to_string(),
+        });
+        description
+    }
+}
+/// Index buffer of a mesh
+#[derive(Clone, Debug, PartialEq)]
+pub enum Indices {
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+}
+impl Indices {
+    /// Uses u16 indices if every vertex can be addressed by them
+    pub fn new(indices: Vec<u32>, num_vertices: usize) -> Self {
+        if num_vertices <= u16::MAX as usize + 1 {
+            Self::U16(indices.iter().map(|i| *i as u16).collect())
+        } else {
+            Self::U32(indices)
+        }
+    }
+    pub fn len(&self) -> usize {
+        match self {
+            Self::U16(indices) => indices.len(),
+            Self::U32(indices) => indices.len(),
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// gets index at position `i`
+    pub fn get(&self, i: usize) -> usize {
+        match self {
+            Self::U16(indices) => indices[i] as usize,
+            Self::U32(indices) => indices[i] as usize,
+        }
+    }
 }
 #[derive(Clone, Debug)]
 pub struct Mesh {
     pub vertices: Vec<f32>,
     //for now description only covers extra items
     pub description: Vec<ItemDesc>,
+    /// If present vertices are drawn in the order given by the indices
+    pub indices: Option<Indices>,
 }
 impl Mesh {
     /// gets number of vertices
@@ -45,6 +90,12 @@ impl Mesh {
         let vertex_size: usize = self.description.iter().map(|d| d.number_components).sum();
         self.vertices.len() / vertex_size
     }
+    /// gets number of vertices that are drawn, the number of indices for indexed meshes
+    pub fn num_elements(&self) -> usize {
+        self.indices
+            .as_ref()
+            .map_or_else(|| self.num_vertices(), |indices| indices.len())
+    }
 
     /// gets size of vertex in bytes
     pub fn vertex_size(&self) -> usize {
@@ -61,19 +112,19 @@ impl Mesh {
                 .expect("loaded obj")
                 .0)[0]
                 .mesh;
-        let vertices: Vec<f32> = loaded_obj
-            .indices
-            .iter()
+        // loaded with a single index so positions, uvs and normals share indices
+        let num_vertices = loaded_obj.positions.len() / 3;
+        let vertices: Vec<f32> = (0..num_vertices)
             .map(|i| {
                 vec![
-                    loaded_obj.positions[*i as usize * 3],
-                    loaded_obj.positions[*i as usize * 3 + 1],
-                    loaded_obj.positions[*i as usize * 3 + 2],
-                    loaded_obj.texcoords[*i as usize * 2],
-                    loaded_obj.texcoords[*i as usize * 2 + 1],
-                    loaded_obj.normals[*i as usize],
-                    loaded_obj.normals[*i as usize + 1],
-                    loaded_obj.normals[*i as usize + 2],
+                    loaded_obj.positions[i * 3],
+                    loaded_obj.positions[i * 3 + 1],
+                    loaded_obj.positions[i * 3 + 2],
+                    loaded_obj.texcoords[i * 2],
+                    loaded_obj.texcoords[i * 2 + 1],
+                    loaded_obj.normals[i * 3],
+                    loaded_obj.normals[i * 3 + 1],
+                    loaded_obj.normals[i * 3 + 2],
                 ]
             })
             .flatten()
@@ -82,6 +133,7 @@ impl Mesh {
         Self {
             vertices,
             description,
+            indices: Some(Indices::new(loaded_obj.indices.clone(), num_vertices)),
         }
     }
     /// Returns a 2x2 plane aligended with the x-y plane centerd at (0,0,0)
@@ -114,6 +166,7 @@ impl Mesh {
                 0.0,0.0,1.0,
             ],
             description: ItemDesc::default_model(),
+            indices: None,
         }
     }
     pub fn to_bytes(&self) -> Vec<f32> {
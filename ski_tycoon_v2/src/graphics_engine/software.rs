@@ -0,0 +1,1235 @@
+// cpu rendering engine, used when no gpu is available
+use super::super::prelude::Texture;
+use super::{Mesh, RenderBackend};
+use nalgebra::{Matrix4, Vector2, Vector3, Vector4};
+use std::collections::HashMap;
+/// Used to reject vertices that lie on or behind the camera plane
+const W_EPSILON: f32 = 1e-6;
+/// Most vertices a triangle can have after being clipped by the near plane
+const MAX_CLIPPED_VERTICES: usize = 4;
+pub type RuntimeMesh = SoftwareMesh;
+pub type RuntimeTexture = SoftwareTexture;
+pub type RuntimeDepthTexture = SoftwareDepthTexture;
+pub type Framebuffer = SoftwareFramebuffer;
+pub type ErrorType = SoftwareError;
+pub type Shader = SoftwareShader;
+pub type RenderingContext = SoftwareRenderingContext;
+/// Size of the default framebuffer
+pub type InitContext = Vector2<u32>;
+#[derive(Debug)]
+pub enum SoftwareError {}
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ShaderKind {
+    World,
+    Water,
+    Shadow,
+    Screen,
+    Gui,
+}
+pub struct SoftwareShader {
+    id: u32,
+}
+pub struct SoftwareMesh {
+    id: u32,
+}
+pub struct SoftwareTexture {
+    id: u32,
+}
+pub struct SoftwareDepthTexture {
+    id: u32,
+}
+pub struct SoftwareFramebuffer {
+    id: u32,
+}
+/// Vertex after its attributes have been pulled out of the interleaved buffer.
+/// `attribute` holds the normal for world meshes and the vertex color for gui meshes
+#[derive(Clone, Copy, Debug)]
+struct SoftwareVertex {
+    position: Vector3<f32>,
+    uv: Vector2<f32>,
+    attribute: Vector4<f32>,
+    /// depth and speed of water meshes
+    water: Vector2<f32>,
+}
+/// Outputs of the vertex stage that are interpolated across primitives
+#[derive(Clone, Copy, Debug)]
+struct Varyings {
+    uv: Vector2<f32>,
+    attribute: Vector4<f32>,
+    /// position in world space
+    position: Vector3<f32>,
+    water: Vector2<f32>,
+}
+impl std::ops::Add for Varyings {
+    type Output = Self;
+    fn add(self, other: Self) -> Self {
+        Self {
+            uv: self.uv + other.uv,
+            attribute: self.attribute + other.attribute,
+            position: self.position + other.position,
+            water: self.water + other.water,
+        }
+    }
+}
+impl std::ops::Mul<f32> for Varyings {
+    type Output = Self;
+    fn mul(self, weight: f32) -> Self {
+        Self {
+            uv: self.uv * weight,
+            attribute: self.attribute * weight,
+            position: self.position * weight,
+            water: self.water * weight,
+        }
+    }
+}
+/// Vertex after the vertex stage
+#[derive(Clone, Copy, Debug)]
+struct ClipVertex {
+    clip: Vector4<f32>,
+    varyings: Varyings,
+}
+/// Color image stored bottom row first, matching the uv convention of the gpu backends
+struct ColorImage {
+    dimensions: Vector2<u32>,
+    pixels: Vec<Vector4<f32>>,
+}
+impl ColorImage {
+    fn new(dimensions: Vector2<u32>, color: Vector4<f32>) -> Self {
+        Self {
+            dimensions,
+            pixels: vec![color; (dimensions.x * dimensions.y) as usize],
+        }
+    }
+    /// Bilinear sample with clamp to edge addressing
+    fn sample(&self, uv: Vector2<f32>) -> Vector4<f32> {
+        if self.pixels.is_empty() {
+            return Vector4::new(0.0, 0.0, 0.0, 0.0);
+        }
+        let x = uv.x * self.dimensions.x as f32 - 0.5;
+        let y = uv.y * self.dimensions.y as f32 - 0.5;
+        let x0 = x.floor();
+        let y0 = y.floor();
+        let fx = x - x0;
+        let fy = y - y0;
+        let texel = |x: f32, y: f32| {
+            let x = (x.max(0.0) as u32).min(self.dimensions.x - 1);
+            let y = (y.max(0.0) as u32).min(self.dimensions.y - 1);
+            self.pixels[(y * self.dimensions.x + x) as usize]
+        };
+        let bottom = texel(x0, y0) * (1.0 - fx) + texel(x0 + 1.0, y0) * fx;
+        let top = texel(x0, y0 + 1.0) * (1.0 - fx) + texel(x0 + 1.0, y0 + 1.0) * fx;
+        bottom * (1.0 - fy) + top * fy
+    }
+    /// Converts from bytes, rows are stored in the order they are given
+    fn import(texture: &Texture) -> Self {
+        Self {
+            dimensions: texture.dimensions,
+            pixels: texture
+                .pixels
+                .iter()
+                .map(|p| p.map(|c| c as f32 / 255.0))
+                .collect(),
+        }
+    }
+    /// Converts to bytes with the top row first
+    fn export(&self) -> Texture {
+        let dimensions = self.dimensions;
+        let mut pixels = Vec::with_capacity(self.pixels.len());
+        for y in (0..dimensions.y).rev() {
+            for x in 0..dimensions.x {
+                let p = self.pixels[(y * dimensions.x + x) as usize];
+                pixels.push(Vector4::new(
+                    to_byte(p.x),
+                    to_byte(p.y),
+                    to_byte(p.z),
+                    to_byte(p.w),
+                ));
+            }
+        }
+        Texture { dimensions, pixels }
+    }
+}
+struct DepthImage {
+    dimensions: Vector2<u32>,
+    depth: Vec<f32>,
+}
+impl DepthImage {
+    fn new(dimensions: Vector2<u32>) -> Self {
+        Self {
+            dimensions,
+            depth: vec![1.0; (dimensions.x * dimensions.y) as usize],
+        }
+    }
+    /// Depth of the nearest texel, clamped to the edges
+    fn sample(&self, position: Vector2<f32>) -> f32 {
+        let x = (position.x.max(0.0) as u32).min(self.dimensions.x - 1);
+        let y = (position.y.max(0.0) as u32).min(self.dimensions.y - 1);
+        self.depth[(y * self.dimensions.x + x) as usize]
+    }
+}
+struct ShaderData {
+    kind: ShaderKind,
+    float_uniforms: HashMap<String, f32>,
+    vec3_uniforms: HashMap<String, Vector3<f32>>,
+    vec4_uniforms: HashMap<String, Vector4<f32>>,
+    mat4_uniforms: HashMap<String, Matrix4<f32>>,
+    camera: Matrix4<f32>,
+    model: Matrix4<f32>,
+    /// depth texture bound as the shadow map
+    shadow_map: Option<u32>,
+}
+impl ShaderData {
+    fn new(kind: ShaderKind) -> Self {
+        Self {
+            kind,
+            float_uniforms: HashMap::new(),
+            vec3_uniforms: HashMap::new(),
+            vec4_uniforms: HashMap::new(),
+            mat4_uniforms: HashMap::new(),
+            camera: Matrix4::identity(),
+            model: Matrix4::identity(),
+            shadow_map: None,
+        }
+    }
+    fn vertex(&self, vertex: &SoftwareVertex) -> ClipVertex {
+        let position = vertex.position.push(1.0);
+        let world_position = self.model * position;
+        let clip = match self.kind {
+            ShaderKind::Gui => position,
+            ShaderKind::World | ShaderKind::Water | ShaderKind::Shadow | ShaderKind::Screen => {
+                self.camera * world_position
+            }
+        };
+        ClipVertex {
+            clip,
+            varyings: Varyings {
+                uv: vertex.uv,
+                attribute: vertex.attribute,
+                position: world_position.xyz(),
+                water: vertex.water,
+            },
+        }
+    }
+    fn vec3_uniform(&self, name: &str) -> Vector3<f32> {
+        self.vec3_uniforms
+            .get(name)
+            .copied()
+            .unwrap_or_else(Vector3::zeros)
+    }
+    fn sun_color(&self) -> Vector4<f32> {
+        self.vec4_uniforms
+            .get("sun_color")
+            .copied()
+            .unwrap_or_else(|| Vector4::new(1.0, 1.0, 1.0, 1.0))
+    }
+    fn fragment(
+        &self,
+        varyings: &Varyings,
+        texture: Vector4<f32>,
+        shadow_map: Option<&DepthImage>,
+    ) -> Vector4<f32> {
+        let attribute = varyings.attribute;
+        match self.kind {
+            ShaderKind::World => {
+                const AMBIENT: f32 = 0.3;
+                let sun_color = self.sun_color();
+                let diffuse = (-1.0 * self.vec3_uniform("sun_direction"))
+                    .dot(&attribute.xyz())
+                    .max(0.0);
+                let shadow = shadow_map.map_or(1.0, |map| self.shadow(varyings, map));
+                let s = AMBIENT + (1.0 - AMBIENT) * diffuse * shadow;
+                let color = sun_color.component_mul(&texture);
+                Vector4::new(s * color.x, s * color.y, s * color.z, color.w)
+            }
+            ShaderKind::Water => {
+                let shadow = shadow_map.map_or(1.0, |map| self.shadow(varyings, map));
+                self.water_fragment(varyings, texture, shadow)
+            }
+            // only the depth is used
+            ShaderKind::Shadow => Vector4::zeros(),
+            ShaderKind::Screen => texture,
+            ShaderKind::Gui => {
+                let color = attribute.component_mul(&texture);
+                Vector4::new(
+                    srgb_from_linear(color.x),
+                    srgb_from_linear(color.y),
+                    srgb_from_linear(color.z),
+                    color.w,
+                )
+            }
+        }
+    }
+    /// Fraction of the 3x3 texels around the fragment in the shadow map that see the
+    /// sun, same as the world and water shaders of the gpu backends
+    fn shadow(&self, varyings: &Varyings, shadow_map: &DepthImage) -> f32 {
+        const SHADOW_BIAS: f32 = 0.002;
+        let light = self
+            .mat4_uniforms
+            .get("light")
+            .copied()
+            .unwrap_or_else(Matrix4::identity);
+        let position = to_window(
+            &(light * varyings.position.push(1.0)),
+            shadow_map.dimensions,
+        );
+        if position.z > 1.0 {
+            return 1.0;
+        }
+        let mut lit = 0.0;
+        for x in -1..=1 {
+            for y in -1..=1 {
+                let depth = shadow_map.sample(position.xy() + Vector2::new(x as f32, y as f32));
+                if position.z - SHADOW_BIAS <= depth {
+                    lit += 1.0;
+                }
+            }
+        }
+        lit / 9.0
+    }
+    /// Same lighting as the water shader of the gpu backends, `shadow` is the fraction
+    /// of the sun reaching the surface
+    fn water_fragment(
+        &self,
+        varyings: &Varyings,
+        texture: Vector4<f32>,
+        shadow: f32,
+    ) -> Vector4<f32> {
+        const AMBIENT: f32 = 0.3;
+        const ABSORPTION_DEPTH: f32 = 2.0;
+        const FOAM_START: f32 = 0.2;
+        const FOAM_FULL: f32 = 0.8;
+        const MIN_DEPTH: f32 = 0.01;
+        const SHININESS: f32 = 64.0;
+        let sun_direction = self.vec3_uniform("sun_direction");
+        let sun_color = self.sun_color();
+        let normal = varyings
+            .attribute
+            .xyz()
+            .try_normalize(f32::EPSILON)
+            .unwrap_or_else(|| Vector3::new(0.0, 1.0, 0.0));
+        let depth = varyings.water.x;
+        let absorption = 1.0 - (-depth.max(0.0) / ABSORPTION_DEPTH).exp();
+        let body = texture.xyz().lerp(&(texture.xyz() * 0.3), absorption);
+        let foam = smoothstep(FOAM_START, FOAM_FULL, varyings.water.y);
+        let diffuse = (-1.0 * sun_direction).dot(&normal).max(0.0);
+        let view = (self.vec3_uniform("camera_position") - varyings.position)
+            .try_normalize(f32::EPSILON)
+            .unwrap_or_else(Vector3::zeros);
+        let halfway = (view - sun_direction)
+            .try_normalize(f32::EPSILON)
+            .unwrap_or_else(Vector3::zeros);
+        let specular = normal.dot(&halfway).max(0.0).powf(SHININESS) * (1.0 - foam) * shadow;
+        let alpha = mix(mix(0.3, 0.9, absorption), 1.0, foam)
+            * smoothstep(0.0, MIN_DEPTH, depth)
+            * sun_color.w;
+        let lit = body
+            .lerp(&Vector3::new(1.0, 1.0, 1.0), foam)
+            .component_mul(&sun_color.xyz())
+            * (AMBIENT + (1.0 - AMBIENT) * diffuse * shadow);
+        let water = ((lit + sun_color.xyz() * specular) * alpha).push(alpha);
+        let overlay_color = (texture.xyz() * (0.5 + 0.5 * diffuse)).push(1.0);
+        let overlay = self.float_uniforms.get("overlay").copied().unwrap_or(0.0);
+        water.lerp(&overlay_color, overlay)
+    }
+}
+fn mix(a: f32, b: f32, t: f32) -> f32 {
+    a * (1.0 - t) + b * t
+}
+/// Hermite interpolation between 0 and 1, same as glsl
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+/// 0-1 sRGB from 0-1 linear, same curve as the gui shader of the gpu backends
+fn srgb_from_linear(c: f32) -> f32 {
+    if c < 0.0031308 {
+        c * 3294.6 / 255.0
+    } else {
+        (269.025 * c.powf(1.0 / 2.4) - 14.025) / 255.0
+    }
+}
+/// Render target as given by the currently bound framebuffer
+#[derive(Clone, Copy, PartialEq)]
+enum Target {
+    Screen,
+    Texture { color: u32, depth: u32 },
+}
+pub struct SoftwareRenderingContext {
+    screen: ColorImage,
+    screen_depth: DepthImage,
+    textures: HashMap<u32, ColorImage>,
+    depth_textures: HashMap<u32, DepthImage>,
+    meshes: HashMap<u32, Mesh>,
+    framebuffers: HashMap<u32, Target>,
+    shaders: HashMap<u32, ShaderData>,
+    bound_shader: Option<u32>,
+    bound_texture: Option<u32>,
+    target: Target,
+    next_id: u32,
+}
+impl SoftwareRenderingContext {
+    pub fn new(screen_size: InitContext) -> Result<Self, ErrorType> {
+        Ok(Self {
+            screen: ColorImage::new(screen_size, Vector4::new(0.0, 0.0, 0.0, 1.0)),
+            screen_depth: DepthImage::new(screen_size),
+            textures: HashMap::new(),
+            depth_textures: HashMap::new(),
+            meshes: HashMap::new(),
+            framebuffers: HashMap::new(),
+            shaders: HashMap::new(),
+            bound_shader: None,
+            bound_texture: None,
+            target: Target::Screen,
+            next_id: 0,
+        })
+    }
+    /// Returns the contents of the default framebuffer with the top row first
+    pub fn screen_texture(&self) -> Texture {
+        self.screen.export()
+    }
+    fn next_id(&mut self) -> u32 {
+        self.next_id += 1;
+        self.next_id
+    }
+    fn build_shader(&mut self, kind: ShaderKind) -> Result<Shader, ErrorType> {
+        let id = self.next_id();
+        self.shaders.insert(id, ShaderData::new(kind));
+        Ok(SoftwareShader { id })
+    }
+    fn shader_data(&mut self, shader: &Shader) -> &mut ShaderData {
+        self.shaders
+            .get_mut(&shader.id)
+            .expect("shader does not exist")
+    }
+    /// Gets the color and depth images of the bound framebuffer
+    fn target_images(&mut self) -> (&mut ColorImage, &mut DepthImage) {
+        match self.target {
+            Target::Screen => (&mut self.screen, &mut self.screen_depth),
+            Target::Texture { color, depth } => (
+                self.textures
+                    .get_mut(&color)
+                    .expect("framebuffer color attachment deleted"),
+                self.depth_textures
+                    .get_mut(&depth)
+                    .expect("framebuffer depth attachment deleted"),
+            ),
+        }
+    }
+    /// Runs the bound shader over a mesh. The bound texture, shadow map and shader
+    /// are temporarily taken out of their maps so that they can be read while the
+    /// target is written to
+    fn draw(&mut self, mesh: &SoftwareMesh, lines: bool) {
+        let shader = self
+            .shaders
+            .get(&self.bound_shader.expect("no shader bound"))
+            .expect("bound shader deleted");
+        let mesh = &self.meshes[&mesh.id];
+        let transformed: Vec<ClipVertex> =
+            unpack_vertices(mesh).map(|v| shader.vertex(&v)).collect();
+        let vertices: Vec<ClipVertex> = match mesh.indices.as_ref() {
+            Some(indices) => (0..indices.len())
+                .map(|i| transformed[indices.get(i)])
+                .collect(),
+            None => transformed,
+        };
+        let shadow_map = shader.shadow_map;
+        let texture = self
+            .bound_texture
+            .and_then(|id| self.textures.remove(&id).map(|t| (id, t)));
+        let shadow_map = shadow_map.and_then(|id| self.depth_textures.remove(&id).map(|t| (id, t)));
+        let shader = self.shaders.remove(&self.bound_shader.unwrap()).unwrap();
+        {
+            let (color, depth) = self.target_images();
+            let mut rasterizer = Rasterizer {
+                shader: &shader,
+                texture: texture.as_ref().map(|(_, t)| t),
+                shadow_map: shadow_map.as_ref().map(|(_, t)| t),
+                color,
+                depth,
+            };
+            if lines {
+                for line in vertices.chunks_exact(2) {
+                    rasterizer.line(&line[0], &line[1]);
+                }
+            } else {
+                for triangle in vertices.chunks_exact(3) {
+                    rasterizer.triangle(triangle);
+                }
+            }
+        }
+        self.shaders.insert(self.bound_shader.unwrap(), shader);
+        if let Some((id, texture)) = texture {
+            self.textures.insert(id, texture);
+        }
+        if let Some((id, shadow_map)) = shadow_map {
+            self.depth_textures.insert(id, shadow_map);
+        }
+    }
+}
+/// Pulls attributes used by the shaders out of the interleaved vertex data
+fn unpack_vertices(mesh: &Mesh) -> impl Iterator<Item = SoftwareVertex> + '_ {
+    let vertex_size: usize = mesh.description.iter().map(|d| d.number_components).sum();
+    mesh.vertices.chunks_exact(vertex_size).map(move |data| {
+        let mut vertex = SoftwareVertex {
+            position: Vector3::zeros(),
+            uv: Vector2::zeros(),
+            attribute: Vector4::zeros(),
+            water: Vector2::zeros(),
+        };
+        let mut offset = 0;
+        for desc in mesh.description.iter() {
+            let item = &data[offset..offset + desc.number_components];
+            let get = |i: usize| item.get(i).copied().unwrap_or(0.0);
+            match desc.name.as_str() {
+                "position" => vertex.position = Vector3::new(get(0), get(1), get(2)),
+                "uv" => vertex.uv = Vector2::new(get(0), get(1)),
+                "water" => vertex.water = Vector2::new(get(0), get(1)),
+                "normal" | "vertex_color" => {
+                    vertex.attribute = Vector4::new(get(0), get(1), get(2), get(3))
+                }
+                _ => (),
+            }
+            offset += desc.number_components;
+        }
+        vertex
+    })
+}
+fn to_byte(c: f32) -> u8 {
+    (c.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+/// Converts clip space into window coordinates, z is mapped to 0-1
+fn to_window(clip: &Vector4<f32>, dimensions: Vector2<u32>) -> Vector3<f32> {
+    let ndc = clip.xyz() / clip.w;
+    Vector3::new(
+        (ndc.x + 1.0) / 2.0 * dimensions.x as f32,
+        (ndc.y + 1.0) / 2.0 * dimensions.y as f32,
+        (ndc.z + 1.0) / 2.0,
+    )
+}
+/// Sutherland-Hodgman clip of a polygon against the near plane `z = -w`, same as
+/// the gpu backends. Varyings are interpolated in clip space, before the divide
+fn clip_near(polygon: &[ClipVertex]) -> Vec<ClipVertex> {
+    let mut clipped = Vec::with_capacity(MAX_CLIPPED_VERTICES);
+    for (i, current) in polygon.iter().enumerate() {
+        let next = &polygon[(i + 1) % polygon.len()];
+        let current_distance = current.clip.z + current.clip.w;
+        let next_distance = next.clip.z + next.clip.w;
+        if current_distance >= 0.0 {
+            clipped.push(*current);
+        }
+        if (current_distance >= 0.0) != (next_distance >= 0.0) {
+            let t = current_distance / (current_distance - next_distance);
+            clipped.push(ClipVertex {
+                clip: current.clip + (next.clip - current.clip) * t,
+                varyings: current.varyings * (1.0 - t) + next.varyings * t,
+            });
+        }
+    }
+    clipped
+}
+/// State needed to rasterize a single draw call
+struct Rasterizer<'a> {
+    shader: &'a ShaderData,
+    texture: Option<&'a ColorImage>,
+    shadow_map: Option<&'a DepthImage>,
+    color: &'a mut ColorImage,
+    depth: &'a mut DepthImage,
+}
+impl<'a> Rasterizer<'a> {
+    /// Depth tests, shades and blends a single pixel
+    fn shade(&mut self, x: u32, y: u32, z: f32, varyings: &Varyings) {
+        if !(0.0..=1.0).contains(&z) {
+            return;
+        }
+        let index = (y * self.color.dimensions.x + x) as usize;
+        if z >= self.depth.depth[index] {
+            return;
+        }
+        self.depth.depth[index] = z;
+        let texel = self
+            .texture
+            .map(|t| t.sample(varyings.uv))
+            .unwrap_or_else(|| Vector4::new(1.0, 1.0, 1.0, 1.0));
+        let src = self.shader.fragment(varyings, texel, self.shadow_map);
+        // premultiplied alpha blending, same as the gpu backends
+        self.color.pixels[index] = src + self.color.pixels[index] * (1.0 - src.w);
+    }
+    /// Clips a triangle against the near plane and draws what is left as a fan
+    fn triangle(&mut self, triangle: &[ClipVertex]) {
+        let polygon = clip_near(triangle);
+        if polygon.iter().any(|v| v.clip.w <= W_EPSILON) {
+            return;
+        }
+        for i in 1..polygon.len().saturating_sub(1) {
+            self.clipped_triangle(&[polygon[0], polygon[i], polygon[i + 1]]);
+        }
+    }
+    /// Draws a triangle that lies entirely in front of the camera
+    fn clipped_triangle(&mut self, triangle: &[ClipVertex]) {
+        let dimensions = self.color.dimensions;
+        let window: Vec<Vector3<f32>> = triangle
+            .iter()
+            .map(|v| to_window(&v.clip, dimensions))
+            .collect();
+        let edge = |a: &Vector3<f32>, b: &Vector3<f32>, x: f32, y: f32| {
+            (b.x - a.x) * (y - a.y) - (b.y - a.y) * (x - a.x)
+        };
+        let area = edge(&window[0], &window[1], window[2].x, window[2].y);
+        if area.abs() <= f32::EPSILON {
+            return;
+        }
+        let min_x = window.iter().map(|p| p.x).fold(f32::INFINITY, f32::min);
+        let max_x = window.iter().map(|p| p.x).fold(f32::NEG_INFINITY, f32::max);
+        let min_y = window.iter().map(|p| p.y).fold(f32::INFINITY, f32::min);
+        let max_y = window.iter().map(|p| p.y).fold(f32::NEG_INFINITY, f32::max);
+        let start_x = min_x.floor().max(0.0) as u32;
+        let end_x = (max_x.ceil().max(0.0) as u32).min(dimensions.x);
+        let start_y = min_y.floor().max(0.0) as u32;
+        let end_y = (max_y.ceil().max(0.0) as u32).min(dimensions.y);
+        for y in start_y..end_y {
+            for x in start_x..end_x {
+                let px = x as f32 + 0.5;
+                let py = y as f32 + 0.5;
+                let b0 = edge(&window[1], &window[2], px, py) / area;
+                let b1 = edge(&window[2], &window[0], px, py) / area;
+                let b2 = edge(&window[0], &window[1], px, py) / area;
+                if b0 < 0.0 || b1 < 0.0 || b2 < 0.0 {
+                    continue;
+                }
+                let z = b0 * window[0].z + b1 * window[1].z + b2 * window[2].z;
+                // perspective correct weights
+                let p0 = b0 / triangle[0].clip.w;
+                let p1 = b1 / triangle[1].clip.w;
+                let p2 = b2 / triangle[2].clip.w;
+                let sum = p0 + p1 + p2;
+                let (p0, p1, p2) = (p0 / sum, p1 / sum, p2 / sum);
+                let varyings = triangle[0].varyings * p0
+                    + triangle[1].varyings * p1
+                    + triangle[2].varyings * p2;
+                self.shade(x, y, z, &varyings);
+            }
+        }
+    }
+    fn line(&mut self, start: &ClipVertex, end: &ClipVertex) {
+        if start.clip.w <= W_EPSILON || end.clip.w <= W_EPSILON {
+            return;
+        }
+        let dimensions = self.color.dimensions;
+        let a = to_window(&start.clip, dimensions);
+        let b = to_window(&end.clip, dimensions);
+        let steps = (b.x - a.x).abs().max((b.y - a.y).abs()).ceil().max(1.0) as u32;
+        for i in 0..=steps {
+            let t = i as f32 / steps as f32;
+            let p = a * (1.0 - t) + b * t;
+            if p.x < 0.0 || p.y < 0.0 || p.x >= dimensions.x as f32 || p.y >= dimensions.y as f32 {
+                continue;
+            }
+            let varyings = start.varyings * (1.0 - t) + end.varyings * t;
+            self.shade(p.x as u32, p.y as u32, p.z, &varyings);
+        }
+    }
+}
+impl RenderBackend for SoftwareRenderingContext {
+    type Shader = Shader;
+    type Mesh = RuntimeMesh;
+    type Texture = RuntimeTexture;
+    type DepthTexture = RuntimeDepthTexture;
+    type Framebuffer = Framebuffer;
+    type Error = ErrorType;
+    fn change_viewport(&mut self, screen_size: &Vector2<u32>) -> Result<(), ErrorType> {
+        self.screen = ColorImage::new(*screen_size, Vector4::new(0.0, 0.0, 0.0, 1.0));
+        self.screen_depth = DepthImage::new(*screen_size);
+        Ok(())
+    }
+    fn build_world_shader(&mut self) -> Result<Shader, ErrorType> {
+        self.build_shader(ShaderKind::World)
+    }
+    fn build_water_shader(&mut self) -> Result<Shader, ErrorType> {
+        self.build_shader(ShaderKind::Water)
+    }
+    fn build_shadow_shader(&mut self) -> Result<Shader, ErrorType> {
+        self.build_shader(ShaderKind::Shadow)
+    }
+    /// Meshes keep their own layout so the shadow shader draws water meshes as well
+    fn build_water_shadow_shader(&mut self) -> Result<Shader, ErrorType> {
+        self.build_shader(ShaderKind::Shadow)
+    }
+    fn build_screen_shader(&mut self) -> Result<Shader, ErrorType> {
+        self.build_shader(ShaderKind::Screen)
+    }
+    fn build_gui_shader(&mut self) -> Result<Shader, ErrorType> {
+        self.build_shader(ShaderKind::Gui)
+    }
+    fn bind_shader(&mut self, shader: &Shader) -> Result<(), ErrorType> {
+        self.bound_shader = Some(shader.id);
+        Ok(())
+    }
+    fn build_mesh(&mut self, mesh: Mesh, _shader: &Shader) -> Result<RuntimeMesh, ErrorType> {
+        let id = self.next_id();
+        self.meshes.insert(id, mesh);
+        Ok(SoftwareMesh { id })
+    }
+    fn update_mesh(
+        &mut self,
+        mesh: &mut RuntimeMesh,
+        offset: usize,
+        vertices: &[f32],
+    ) -> Result<(), ErrorType> {
+        let data = &mut self
+            .meshes
+            .get_mut(&mesh.id)
+            .expect("mesh already deleted")
+            .vertices;
+        data[offset..offset + vertices.len()].copy_from_slice(vertices);
+        Ok(())
+    }
+    fn delete_mesh(&mut self, mesh: &mut RuntimeMesh) -> Result<(), ErrorType> {
+        self.meshes.remove(&mesh.id);
+        Ok(())
+    }
+    fn send_float_uniform(
+        &mut self,
+        shader: &Shader,
+        uniform_name: &str,
+        data: f32,
+    ) -> Result<(), ErrorType> {
+        self.shader_data(shader)
+            .float_uniforms
+            .insert(uniform_name.to_string(), data);
+        Ok(())
+    }
+    fn send_vec3_uniform(
+        &mut self,
+        shader: &Shader,
+        uniform_name: &str,
+        data: Vector3<f32>,
+    ) -> Result<(), ErrorType> {
+        self.shader_data(shader)
+            .vec3_uniforms
+            .insert(uniform_name.to_string(), data);
+        Ok(())
+    }
+    fn send_vec4_uniform(
+        &mut self,
+        shader: &Shader,
+        uniform_name: &str,
+        data: Vector4<f32>,
+    ) -> Result<(), ErrorType> {
+        self.shader_data(shader)
+            .vec4_uniforms
+            .insert(uniform_name.to_string(), data);
+        Ok(())
+    }
+    fn send_mat4_uniform(
+        &mut self,
+        shader: &Shader,
+        uniform_name: &str,
+        data: Matrix4<f32>,
+    ) -> Result<(), ErrorType> {
+        self.shader_data(shader)
+            .mat4_uniforms
+            .insert(uniform_name.to_string(), data);
+        Ok(())
+    }
+    fn build_depth_texture(
+        &mut self,
+        dimensions: Vector2<u32>,
+        _shader: &Shader,
+    ) -> Result<RuntimeDepthTexture, ErrorType> {
+        let id = self.next_id();
+        self.depth_textures.insert(id, DepthImage::new(dimensions));
+        Ok(SoftwareDepthTexture { id })
+    }
+    fn delete_depth_buffer(&mut self, texture: &mut RuntimeDepthTexture) -> Result<(), ErrorType> {
+        self.depth_textures.remove(&texture.id);
+        Ok(())
+    }
+    fn build_texture(
+        &mut self,
+        texture: Texture,
+        _shader: &Shader,
+    ) -> Result<RuntimeTexture, ErrorType> {
+        let id = self.next_id();
+        self.textures.insert(id, ColorImage::import(&texture));
+        Ok(SoftwareTexture { id })
+    }
+    fn update_texture(
+        &mut self,
+        texture: &mut RuntimeTexture,
+        data: Texture,
+    ) -> Result<(), ErrorType> {
+        *self
+            .textures
+            .get_mut(&texture.id)
+            .expect("texture already deleted") = ColorImage::import(&data);
+        Ok(())
+    }
+    fn delete_texture(&mut self, texture: &mut RuntimeTexture) {
+        self.textures.remove(&texture.id);
+    }
+    fn read_texture(&mut self, texture: &RuntimeTexture) -> Result<Texture, ErrorType> {
+        self.target = Target::Screen;
+        Ok(self.textures[&texture.id].export())
+    }
+    fn build_framebuffer(
+        &mut self,
+        texture_attachment: &mut RuntimeTexture,
+        depth_attachment: &mut RuntimeDepthTexture,
+    ) -> Result<Framebuffer, ErrorType> {
+        let id = self.next_id();
+        self.framebuffers.insert(
+            id,
+            Target::Texture {
+                color: texture_attachment.id,
+                depth: depth_attachment.id,
+            },
+        );
+        Ok(SoftwareFramebuffer { id })
+    }
+    fn delete_framebuffer(&mut self, framebuffer: &mut Framebuffer) -> Result<(), ErrorType> {
+        if self.framebuffers.remove(&framebuffer.id) == Some(self.target) {
+            self.target = Target::Screen;
+        }
+        Ok(())
+    }
+    fn bind_default_framebuffer(&mut self) {
+        self.target = Target::Screen;
+    }
+    fn clear_screen(&mut self, color: Vector4<f32>) {
+        let (image, depth) = self.target_images();
+        image.pixels.iter_mut().for_each(|p| *p = color);
+        depth.depth.iter_mut().for_each(|d| *d = 1.0);
+    }
+    fn clear_depth(&mut self) {
+        let (_, depth) = self.target_images();
+        depth.depth.iter_mut().for_each(|d| *d = 1.0);
+    }
+    fn bind_texture(&mut self, texture: &RuntimeTexture, _shader: &Shader) {
+        self.bound_texture = Some(texture.id);
+    }
+    fn bind_shadow_map(&mut self, texture: &RuntimeDepthTexture, shader: &Shader) {
+        self.shader_data(shader).shadow_map = Some(texture.id);
+    }
+    fn bind_framebuffer(&mut self, framebuffer: &Framebuffer) {
+        self.target = self.framebuffers[&framebuffer.id];
+    }
+    fn draw_mesh(&mut self, mesh: &RuntimeMesh) {
+        self.draw(mesh, false);
+    }
+    fn draw_lines(&mut self, mesh: &RuntimeMesh) {
+        self.draw(mesh, true);
+    }
+    fn finish_frame(&mut self) {}
+    fn send_model_matrix(&mut self, matrix: Matrix4<f32>, shader: &Shader) {
+        self.shader_data(shader).model = matrix;
+    }
+    fn send_view_matrix(&mut self, matrix: Matrix4<f32>, shader: &Shader) {
+        self.shader_data(shader).camera = matrix;
+    }
+    fn get_error(&self) {}
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::graphics_engine::{Indices, ItemDesc};
+    fn triangle() -> Mesh {
+        #[rustfmt::skip]
+        let vertices = vec![
+            -1.0, -1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0,
+            3.0, -1.0, 0.0, 2.0, 0.0, 0.0, 0.0, 1.0,
+            -1.0, 3.0, 0.0, 0.0, 2.0, 0.0, 0.0, 1.0,
+        ];
+        Mesh {
+            vertices,
+            description: ItemDesc::default_model(),
+            indices: None,
+        }
+    }
+    #[test]
+    fn clear() {
+        let mut ctx = SoftwareRenderingContext::new(Vector2::new(4, 4)).unwrap();
+        ctx.clear_screen(Vector4::new(1.0, 0.0, 0.0, 1.0));
+        let screen = ctx.screen_texture();
+        assert_eq!(screen.pixels.len(), 16);
+        for p in screen.pixels.iter() {
+            assert_eq!(*p, Vector4::new(255, 0, 0, 255));
+        }
+    }
+    #[test]
+    fn screen_shader_copies_texture() {
+        let mut ctx = SoftwareRenderingContext::new(Vector2::new(8, 8)).unwrap();
+        let shader = ctx.build_screen_shader().unwrap();
+        ctx.bind_shader(&shader).unwrap();
+        let texture = ctx
+            .build_texture(
+                Texture::constant_color(Vector4::new(0, 255, 0, 255), Vector2::new(2, 2)),
+                &shader,
+            )
+            .unwrap();
+        let mesh = ctx.build_mesh(Mesh::plane(), &shader).unwrap();
+        ctx.clear_screen(Vector4::new(0.0, 0.0, 0.0, 1.0));
+        ctx.bind_texture(&texture, &shader);
+        ctx.draw_mesh(&mesh);
+        for p in ctx.screen_texture().pixels.iter() {
+            assert_eq!(*p, Vector4::new(0, 255, 0, 255));
+        }
+    }
+    #[test]
+    fn update_texture() {
+        let mut ctx = SoftwareRenderingContext::new(Vector2::new(4, 4)).unwrap();
+        let shader = ctx.build_screen_shader().unwrap();
+        ctx.bind_shader(&shader).unwrap();
+        let mut texture = ctx
+            .build_texture(
+                Texture::constant_color(Vector4::new(0, 255, 0, 255), Vector2::new(2, 2)),
+                &shader,
+            )
+            .unwrap();
+        let id = texture.id;
+        ctx.update_texture(
+            &mut texture,
+            Texture::constant_color(Vector4::new(0, 0, 255, 255), Vector2::new(3, 3)),
+        )
+        .unwrap();
+        assert_eq!(texture.id, id);
+        let mesh = ctx.build_mesh(Mesh::plane(), &shader).unwrap();
+        ctx.clear_screen(Vector4::new(0.0, 0.0, 0.0, 1.0));
+        ctx.bind_texture(&texture, &shader);
+        ctx.draw_mesh(&mesh);
+        for p in ctx.screen_texture().pixels.iter() {
+            assert_eq!(*p, Vector4::new(0, 0, 255, 255));
+        }
+    }
+    #[test]
+    fn framebuffer_and_depth() {
+        let mut ctx = SoftwareRenderingContext::new(Vector2::new(4, 4)).unwrap();
+        let shader = ctx.build_screen_shader().unwrap();
+        ctx.bind_shader(&shader).unwrap();
+        let mut color = ctx
+            .build_texture(
+                Texture::constant_color(Vector4::new(0, 0, 0, 0), Vector2::new(4, 4)),
+                &shader,
+            )
+            .unwrap();
+        let mut depth = ctx
+            .build_depth_texture(Vector2::new(4, 4), &shader)
+            .unwrap();
+        let framebuffer = ctx.build_framebuffer(&mut color, &mut depth).unwrap();
+        let white = ctx
+            .build_texture(
+                Texture::constant_color(Vector4::new(255, 255, 255, 255), Vector2::new(1, 1)),
+                &shader,
+            )
+            .unwrap();
+        let mesh = ctx.build_mesh(triangle(), &shader).unwrap();
+        ctx.bind_framebuffer(&framebuffer);
+        ctx.clear_screen(Vector4::new(0.0, 0.0, 0.0, 1.0));
+        ctx.bind_texture(&white, &shader);
+        ctx.draw_mesh(&mesh);
+        // the same triangle at the same depth fails the depth test
+        let black = ctx
+            .build_texture(
+                Texture::constant_color(Vector4::new(0, 0, 0, 255), Vector2::new(1, 1)),
+                &shader,
+            )
+            .unwrap();
+        ctx.send_model_matrix(Matrix4::new_scaling(0.5), &shader);
+        ctx.bind_texture(&black, &shader);
+        ctx.draw_mesh(&mesh);
+        let read = ctx.read_texture(&color).unwrap();
+        assert_eq!(read.dimensions, Vector2::new(4, 4));
+        assert!(read
+            .pixels
+            .iter()
+            .all(|p| *p == Vector4::new(255, 255, 255, 255)));
+        // screen is untouched
+        assert!(ctx
+            .screen_texture()
+            .pixels
+            .iter()
+            .all(|p| *p == Vector4::new(0, 0, 0, 255)));
+    }
+    #[test]
+    fn world_shader_lighting() {
+        let mut ctx = SoftwareRenderingContext::new(Vector2::new(2, 2)).unwrap();
+        let shader = ctx.build_world_shader().unwrap();
+        ctx.bind_shader(&shader).unwrap();
+        ctx.send_vec3_uniform(&shader, "sun_direction", Vector3::new(0.0, 0.0, -1.0))
+            .unwrap();
+        ctx.send_vec4_uniform(&shader, "sun_color", Vector4::new(0.5, 0.5, 0.5, 1.0))
+            .unwrap();
+        let texture = ctx
+            .build_texture(
+                Texture::constant_color(Vector4::new(255, 255, 255, 255), Vector2::new(1, 1)),
+                &shader,
+            )
+            .unwrap();
+        let mesh = ctx.build_mesh(triangle(), &shader).unwrap();
+        ctx.clear_screen(Vector4::new(0.0, 0.0, 0.0, 1.0));
+        ctx.bind_texture(&texture, &shader);
+        ctx.draw_mesh(&mesh);
+        for p in ctx.screen_texture().pixels.iter() {
+            assert_eq!(*p, Vector4::new(128, 128, 128, 255));
+        }
+    }
+    #[test]
+    fn world_shader_shadow() {
+        let mut ctx = SoftwareRenderingContext::new(Vector2::new(2, 2)).unwrap();
+        let shadow_shader = ctx.build_shadow_shader().unwrap();
+        let world_shader = ctx.build_world_shader().unwrap();
+        let mut shadow_color = ctx
+            .build_texture(
+                Texture::constant_color(Vector4::new(0, 0, 0, 0), Vector2::new(4, 4)),
+                &shadow_shader,
+            )
+            .unwrap();
+        let mut shadow_depth = ctx
+            .build_depth_texture(Vector2::new(4, 4), &shadow_shader)
+            .unwrap();
+        let framebuffer = ctx
+            .build_framebuffer(&mut shadow_color, &mut shadow_depth)
+            .unwrap();
+        // occluder in front of the ground as seen by the sun
+        let occluder = ctx.build_mesh(triangle(), &shadow_shader).unwrap();
+        ctx.bind_shader(&shadow_shader).unwrap();
+        ctx.bind_framebuffer(&framebuffer);
+        ctx.clear_screen(Vector4::new(0.0, 0.0, 0.0, 0.0));
+        ctx.send_model_matrix(
+            Matrix4::new_translation(&Vector3::new(0.0, 0.0, -0.5)),
+            &shadow_shader,
+        );
+        ctx.draw_mesh(&occluder);
+        ctx.bind_default_framebuffer();
+        ctx.bind_shader(&world_shader).unwrap();
+        ctx.send_vec3_uniform(&world_shader, "sun_direction", Vector3::new(0.0, 0.0, -1.0))
+            .unwrap();
+        ctx.send_vec4_uniform(&world_shader, "sun_color", Vector4::new(1.0, 1.0, 1.0, 1.0))
+            .unwrap();
+        ctx.send_mat4_uniform(&world_shader, "light", Matrix4::identity())
+            .unwrap();
+        ctx.bind_shadow_map(&shadow_depth, &world_shader);
+        let texture = ctx
+            .build_texture(
+                Texture::constant_color(Vector4::new(255, 255, 255, 255), Vector2::new(1, 1)),
+                &world_shader,
+            )
+            .unwrap();
+        let ground = ctx.build_mesh(triangle(), &world_shader).unwrap();
+        ctx.clear_screen(Vector4::new(0.0, 0.0, 0.0, 1.0));
+        ctx.bind_texture(&texture, &world_shader);
+        ctx.draw_mesh(&ground);
+        // only the ambient light reaches the ground
+        for p in ctx.screen_texture().pixels.iter() {
+            assert_eq!(*p, Vector4::new(77, 77, 77, 255));
+        }
+    }
+    #[test]
+    fn water_shader_depth_and_foam() {
+        let mut ctx = SoftwareRenderingContext::new(Vector2::new(2, 2)).unwrap();
+        let shader = ctx.build_water_shader().unwrap();
+        ctx.bind_shader(&shader).unwrap();
+        ctx.send_vec3_uniform(&shader, "sun_direction", Vector3::new(0.0, 0.0, -1.0))
+            .unwrap();
+        ctx.send_vec4_uniform(&shader, "sun_color", Vector4::new(1.0, 1.0, 1.0, 1.0))
+            .unwrap();
+        // looking from the side so that there is no specular highlight
+        ctx.send_vec3_uniform(&shader, "camera_position", Vector3::new(10.0, 0.0, 0.0))
+            .unwrap();
+        let texture = ctx
+            .build_texture(
+                Texture::constant_color(Vector4::new(0, 0, 255, 255), Vector2::new(1, 1)),
+                &shader,
+            )
+            .unwrap();
+        ctx.bind_texture(&texture, &shader);
+        let mut draw = |depth: f32, speed: f32| {
+            let vertices = triangle()
+                .vertices
+                .chunks_exact(8)
+                .flat_map(|v| v.iter().copied().chain(vec![depth, speed]))
+                .collect();
+            let mut mesh = ctx
+                .build_mesh(
+                    Mesh {
+                        vertices,
+                        description: ItemDesc::water_model(),
+                        indices: None,
+                    },
+                    &shader,
+                )
+                .unwrap();
+            ctx.clear_screen(Vector4::new(1.0, 1.0, 1.0, 1.0));
+            ctx.clear_depth();
+            ctx.draw_mesh(&mesh);
+            ctx.delete_mesh(&mut mesh).unwrap();
+            ctx.screen_texture().pixels[0]
+        };
+        // dry ground shows no water
+        assert_eq!(draw(0.0, 0.0), Vector4::new(255, 255, 255, 255));
+        // deep water is tinted by the water color
+        let deep = draw(4.0, 0.0);
+        assert!(deep.x < 128 && deep.y < 128 && deep.z > deep.x);
+        // fast water is covered in white foam
+        assert_eq!(draw(4.0, 2.0), Vector4::new(255, 255, 255, 255));
+        let shallow = draw(0.5, 0.0);
+        assert!(shallow.x > deep.x);
+    }
+    #[test]
+    fn water_shader_shadow() {
+        let mut ctx = SoftwareRenderingContext::new(Vector2::new(2, 2)).unwrap();
+        let shadow_shader = ctx.build_water_shadow_shader().unwrap();
+        let water_shader = ctx.build_water_shader().unwrap();
+        let mut shadow_color = ctx
+            .build_texture(
+                Texture::constant_color(Vector4::new(0, 0, 0, 0), Vector2::new(4, 4)),
+                &shadow_shader,
+            )
+            .unwrap();
+        let mut shadow_depth = ctx
+            .build_depth_texture(Vector2::new(4, 4), &shadow_shader)
+            .unwrap();
+        let framebuffer = ctx
+            .build_framebuffer(&mut shadow_color, &mut shadow_depth)
+            .unwrap();
+        // fast water so that the surface is opaque white foam when lit
+        let vertices: Vec<f32> = triangle()
+            .vertices
+            .chunks_exact(8)
+            .flat_map(|v| v.iter().copied().chain(vec![4.0, 2.0]))
+            .collect();
+        let water = || Mesh {
+            vertices: vertices.clone(),
+            description: ItemDesc::water_model(),
+            indices: None,
+        };
+        // a wave in front of the surface as seen by the sun
+        let wave = ctx.build_mesh(water(), &shadow_shader).unwrap();
+        ctx.bind_shader(&shadow_shader).unwrap();
+        ctx.bind_framebuffer(&framebuffer);
+        ctx.clear_screen(Vector4::new(0.0, 0.0, 0.0, 0.0));
+        ctx.send_model_matrix(
+            Matrix4::new_translation(&Vector3::new(0.0, 0.0, -0.5)),
+            &shadow_shader,
+        );
+        ctx.draw_mesh(&wave);
+        ctx.bind_default_framebuffer();
+        ctx.bind_shader(&water_shader).unwrap();
+        ctx.send_vec3_uniform(&water_shader, "sun_direction", Vector3::new(0.0, 0.0, -1.0))
+            .unwrap();
+        ctx.send_vec4_uniform(&water_shader, "sun_color", Vector4::new(1.0, 1.0, 1.0, 1.0))
+            .unwrap();
+        ctx.send_vec3_uniform(
+            &water_shader,
+            "camera_position",
+            Vector3::new(10.0, 0.0, 0.0),
+        )
+        .unwrap();
+        let texture = ctx
+            .build_texture(
+                Texture::constant_color(Vector4::new(0, 0, 255, 255), Vector2::new(1, 1)),
+                &water_shader,
+            )
+            .unwrap();
+        let surface = ctx.build_mesh(water(), &water_shader).unwrap();
+        ctx.bind_texture(&texture, &water_shader);
+        let mut draw = |light: Matrix4<f32>| {
+            ctx.send_mat4_uniform(&water_shader, "light", light)
+                .unwrap();
+            ctx.bind_shadow_map(&shadow_depth, &water_shader);
+            ctx.clear_screen(Vector4::new(0.0, 0.0, 0.0, 1.0));
+            ctx.clear_depth();
+            ctx.draw_mesh(&surface);
+            ctx.screen_texture().pixels[0]
+        };
+        // only the ambient light reaches the foam
+        assert_eq!(draw(Matrix4::identity()), Vector4::new(77, 77, 77, 255));
+        // moved out of the shadow map the surface is fully lit
+        assert_eq!(
+            draw(Matrix4::new_translation(&Vector3::new(0.0, 0.0, 3.0))),
+            Vector4::new(255, 255, 255, 255)
+        );
+    }
+    #[test]
+    fn indexed_mesh() {
+        let mut ctx = SoftwareRenderingContext::new(Vector2::new(4, 4)).unwrap();
+        let shader = ctx.build_screen_shader().unwrap();
+        ctx.bind_shader(&shader).unwrap();
+        let mut mesh = Mesh::plane();
+        // keep only the unique corners of the plane
+        mesh.vertices = mesh.vertices[..8 * 3]
+            .iter()
+            .chain(mesh.vertices[8 * 4..8 * 5].iter())
+            .copied()
+            .collect();
+        mesh.indices = Some(Indices::new(vec![0, 1, 2, 0, 3, 2], 4));
+        let mesh = ctx.build_mesh(mesh, &shader).unwrap();
+        ctx.clear_screen(Vector4::new(0.0, 0.0, 0.0, 1.0));
+        ctx.draw_mesh(&mesh);
+        for p in ctx.screen_texture().pixels.iter() {
+            assert_eq!(*p, Vector4::new(255, 255, 255, 255));
+        }
+    }
+    #[test]
+    fn update_mesh() {
+        let mut ctx = SoftwareRenderingContext::new(Vector2::new(2, 2)).unwrap();
+        let shader = ctx.build_screen_shader().unwrap();
+        ctx.bind_shader(&shader).unwrap();
+        let mut mesh = ctx.build_mesh(triangle(), &shader).unwrap();
+        // move the first vertex so that the triangle no longer covers the screen
+        ctx.update_mesh(&mut mesh, 0, &[3.0, 3.0]).unwrap();
+        ctx.clear_screen(Vector4::new(0.0, 0.0, 0.0, 1.0));
+        ctx.draw_mesh(&mesh);
+        let covered = ctx
+            .screen_texture()
+            .pixels
+            .iter()
+            .filter(|p| **p == Vector4::new(255, 255, 255, 255))
+            .count();
+        assert_eq!(covered, 0);
+    }
+    #[test]
+    fn triangle_behind_camera_is_rejected() {
+        let mut ctx = SoftwareRenderingContext::new(Vector2::new(2, 2)).unwrap();
+        let shader = ctx.build_screen_shader().unwrap();
+        ctx.bind_shader(&shader).unwrap();
+        let mut flip = Matrix4::identity();
+        flip[(3, 3)] = -1.0;
+        ctx.send_view_matrix(flip, &shader);
+        let mesh = ctx.build_mesh(triangle(), &shader).unwrap();
+        ctx.clear_screen(Vector4::new(0.0, 0.0, 0.0, 1.0));
+        ctx.draw_mesh(&mesh);
+        assert!(ctx
+            .screen_texture()
+            .pixels
+            .iter()
+            .all(|p| *p == Vector4::new(0, 0, 0, 255)));
+
+        let mut ctx = SoftwareRenderingContext::new(Vector2::new(4, 4)).unwrap();
+        let shader = ctx.build_screen_shader().unwrap();
+        ctx.bind_shader(&shader).unwrap();
+        let camera = Matrix4::new_perspective(1.0, std::f32::consts::FRAC_PI_2, 0.5, 10.0);
+        ctx.send_view_matrix(camera, &shader);
+        // the top vertex is on the camera plane, the near plane cuts the triangle
+        // halfway up so only the bottom half of the screen is covered
+        #[rustfmt::skip]
+        let vertices = vec![
+            -1.0, -1.0, -1.0, 0.0, 0.0, 0.0, 0.0, 1.0,
+            1.0, -1.0, -1.0, 1.0, 0.0, 0.0, 0.0, 1.0,
+            0.0, 1.0, 0.0, 0.5, 1.0, 0.0, 0.0, 1.0,
+        ];
+        let mesh = Mesh {
+            vertices,
+            description: ItemDesc::default_model(),
+            indices: None,
+        };
+        let mesh = ctx.build_mesh(mesh, &shader).unwrap();
+        ctx.clear_screen(Vector4::new(0.0, 0.0, 0.0, 1.0));
+        ctx.draw_mesh(&mesh);
+        let screen = ctx.screen_texture();
+        let (top, bottom) = screen.pixels.split_at(8);
+        assert!(top.iter().all(|p| *p == Vector4::new(0, 0, 0, 255)));
+        assert!(bottom
+            .iter()
+            .all(|p| *p == Vector4::new(255, 255, 255, 255)));
+    }
+}
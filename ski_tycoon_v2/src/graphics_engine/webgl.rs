@@ -1,7 +1,7 @@
 use super::super::prelude::Texture;
 use shader::{shader_library, RuntimeAttribute};
 mod shader;
-use super::Mesh;
+use super::{Indices, Mesh, RenderBackend};
 use log::{debug, error, info};
 use nalgebra::{Matrix4, Vector2, Vector3, Vector4};
 pub use shader::{Shader, ShaderText};
@@ -25,21 +25,30 @@ unsafe impl Sync for RuntimeMesh {}
 unsafe impl Sync for RuntimeTexture {}
 unsafe impl Send for RuntimeMesh {}
 unsafe impl Send for RuntimeTexture {}
+unsafe impl Sync for WebFramebuffer {}
+unsafe impl Send for WebFramebuffer {}
 #[derive(Clone)]
 pub struct WebGlMesh {
     vertex_array_object: Option<WebGlVertexArrayObject>,
     position_buffer: Option<WebGlBuffer>,
+    /// index buffer and the type of its indices
+    index_buffer: Option<(Option<WebGlBuffer>, u32)>,
     count: i32,
 }
 #[derive(Clone)]
 pub struct WebGlRenderTexture {
     texture: Option<WebGlTexture>,
+    dimensions: Vector2<u32>,
 }
 pub struct WebFramebuffer {
     framebuffer: Option<WebGlFramebuffer>,
+    /// size of the attachments, used as the viewport while bound
+    dimensions: Vector2<u32>,
 }
 pub struct RenderingContext {
     context: WebGl2RenderingContext,
+    /// viewport of the default framebuffer
+    screen_size: Vector2<u32>,
 }
 #[allow(dead_code)]
 impl RenderingContext {
@@ -59,23 +68,11 @@ impl RenderingContext {
             WebGl2RenderingContext::ONE,
             WebGl2RenderingContext::ONE_MINUS_SRC_ALPHA,
         );
-        Ok(Self { context })
-    }
-    pub fn change_viewport(&self, screen_size: &Vector2<u32>) -> Result<(), ErrorType> {
-        self.context
-            .viewport(0, 0, screen_size.x as i32, screen_size.y as i32);
-        Ok(())
-    }
-    pub fn build_world_shader(&mut self) -> Result<Shader, ErrorType> {
-        Ok(self.build_shader(shader_library::WORLD_SHADER)?)
-    }
-    /// Builds shader used for screenspace
-    pub fn build_screen_shader(&mut self) -> Result<Shader, JsValue> {
-        self.build_shader(shader_library::SCREEN_SHADER)
-    }
-    pub fn build_gui_shader(&mut self) -> Result<Shader, ErrorType> {
-        info!("building gui shader");
-        self.build_shader(shader_library::GUI_SHADER)
+        let screen_size = Vector2::new(canvas.width(), canvas.height());
+        Ok(Self {
+            context,
+            screen_size,
+        })
     }
     fn build_shader(&mut self, text: ShaderText) -> Result<Shader, JsValue> {
         let vertex_shader = Self::compile_shader(
@@ -128,11 +125,105 @@ impl RenderingContext {
             vertex_shader_source: text.vertex_shader,
         })
     }
-    pub fn bind_shader(&mut self, shader: &Shader) -> Result<(), JsValue> {
+    /// Draws a mesh with the primitive type `mode`, uses the index buffer if the mesh has one
+    fn draw(&mut self, mesh: &RuntimeMesh, mode: u32) {
+        self.context
+            .bind_vertex_array(mesh.vertex_array_object.as_ref());
+        if let Some((_, index_type)) = mesh.index_buffer.as_ref() {
+            self.context
+                .draw_elements_with_i32(mode, mesh.count, *index_type, 0);
+        } else {
+            self.context.draw_arrays(mode, 0, mesh.count);
+        }
+    }
+    fn compile_shader(
+        context: &WebGl2RenderingContext,
+        shader_type: u32,
+        source: &str,
+    ) -> Result<WebGlShader, String> {
+        let shader = context
+            .create_shader(shader_type)
+            .ok_or_else(|| String::from("Unable to create shader object"))?;
+        context.shader_source(&shader, source);
+        context.compile_shader(&shader);
+
+        if context
+            .get_shader_parameter(&shader, WebGl2RenderingContext::COMPILE_STATUS)
+            .as_bool()
+            .unwrap_or(false)
+        {
+            Ok(shader)
+        } else {
+            Err(context
+                .get_shader_info_log(&shader)
+                .unwrap_or_else(|| String::from("Unknown error creating shader")))
+        }
+    }
+    fn link_program(
+        context: &WebGl2RenderingContext,
+        vert_shader: &WebGlShader,
+        frag_shader: &WebGlShader,
+    ) -> Result<WebGlProgram, String> {
+        let program = context
+            .create_program()
+            .ok_or_else(|| String::from("Unable to create shader object"))?;
+
+        context.attach_shader(&program, vert_shader);
+        context.attach_shader(&program, frag_shader);
+        context.link_program(&program);
+
+        if context
+            .get_program_parameter(&program, WebGl2RenderingContext::LINK_STATUS)
+            .as_bool()
+            .unwrap_or(false)
+        {
+            Ok(program)
+        } else {
+            Err(context
+                .get_program_info_log(&program)
+                .unwrap_or_else(|| String::from("Unknvhown error creating program object")))
+        }
+    }
+}
+impl RenderBackend for RenderingContext {
+    type Shader = Shader;
+    type Mesh = RuntimeMesh;
+    type Texture = RuntimeTexture;
+    type DepthTexture = RuntimeDepthTexture;
+    type Framebuffer = Framebuffer;
+    type Error = ErrorType;
+    fn change_viewport(&mut self, screen_size: &Vector2<u32>) -> Result<(), ErrorType> {
+        self.screen_size = *screen_size;
+        self.context
+            .viewport(0, 0, screen_size.x as i32, screen_size.y as i32);
+        Ok(())
+    }
+    fn build_world_shader(&mut self) -> Result<Shader, ErrorType> {
+        Ok(self.build_shader(shader_library::WORLD_SHADER)?)
+    }
+    fn build_water_shader(&mut self) -> Result<Shader, ErrorType> {
+        self.build_shader(shader_library::WATER_SHADER)
+    }
+    fn build_shadow_shader(&mut self) -> Result<Shader, ErrorType> {
+        self.build_shader(shader_library::SHADOW_SHADER)
+    }
+    /// The position of water meshes has the same fixed location as for the world
+    /// shader, so the same program draws them
+    fn build_water_shadow_shader(&mut self) -> Result<Shader, ErrorType> {
+        self.build_shader(shader_library::SHADOW_SHADER)
+    }
+    fn build_screen_shader(&mut self) -> Result<Shader, ErrorType> {
+        self.build_shader(shader_library::SCREEN_SHADER)
+    }
+    fn build_gui_shader(&mut self) -> Result<Shader, ErrorType> {
+        info!("building gui shader");
+        self.build_shader(shader_library::GUI_SHADER)
+    }
+    fn bind_shader(&mut self, shader: &Shader) -> Result<(), ErrorType> {
         self.context.use_program(Some(&shader.program));
         Ok(())
     }
-    pub fn build_mesh(&mut self, mesh: Mesh, shader: &Shader) -> Result<RuntimeMesh, ErrorType> {
+    fn build_mesh(&mut self, mesh: Mesh, shader: &Shader) -> Result<RuntimeMesh, ErrorType> {
         debug!("building mesh");
         let position_buffer = self.context.create_buffer();
 
@@ -178,23 +269,91 @@ impl RenderingContext {
             );
             addn += desc.number_components * desc.size_component;
         }
+        // the element buffer binding is stored in the vertex array object
+        let index_buffer = mesh.indices.as_ref().map(|indices| {
+            let buffer = self.context.create_buffer();
+            self.context.bind_buffer(
+                WebGl2RenderingContext::ELEMENT_ARRAY_BUFFER,
+                buffer.as_ref(),
+            );
+            // same as the vertex buffer, no allocations can happen while the view is alive
+            let index_type = unsafe {
+                match indices {
+                    Indices::U16(data) => {
+                        self.context.buffer_data_with_array_buffer_view(
+                            WebGl2RenderingContext::ELEMENT_ARRAY_BUFFER,
+                            &js_sys::Uint16Array::view(data),
+                            WebGl2RenderingContext::STATIC_DRAW,
+                        );
+                        WebGl2RenderingContext::UNSIGNED_SHORT
+                    }
+                    Indices::U32(data) => {
+                        self.context.buffer_data_with_array_buffer_view(
+                            WebGl2RenderingContext::ELEMENT_ARRAY_BUFFER,
+                            &js_sys::Uint32Array::view(data),
+                            WebGl2RenderingContext::STATIC_DRAW,
+                        );
+                        WebGl2RenderingContext::UNSIGNED_INT
+                    }
+                }
+            };
+            (buffer, index_type)
+        });
+        self.context.bind_vertex_array(None);
         self.get_error();
         //custom verticies
 
         Ok(WebGlMesh {
             vertex_array_object: vao,
             position_buffer,
-            count: mesh.num_vertices() as i32,
+            index_buffer,
+            count: mesh.num_elements() as i32,
         })
     }
-    pub fn delete_mesh(&mut self, mesh: &mut WebGlMesh) -> Result<(), ErrorType> {
+    fn update_mesh(
+        &mut self,
+        mesh: &mut RuntimeMesh,
+        offset: usize,
+        vertices: &[f32],
+    ) -> Result<(), ErrorType> {
+        self.context.bind_buffer(
+            WebGl2RenderingContext::ARRAY_BUFFER,
+            mesh.position_buffer.as_ref(),
+        );
+        // no allocations can happen while the view is alive, see `build_mesh`
+        unsafe {
+            let vert_array = js_sys::Float32Array::view(vertices);
+            self.context.buffer_sub_data_with_i32_and_array_buffer_view(
+                WebGl2RenderingContext::ARRAY_BUFFER,
+                (offset * std::mem::size_of::<f32>()) as i32,
+                &vert_array,
+            );
+        }
+        self.get_error();
+        Ok(())
+    }
+    fn delete_mesh(&mut self, mesh: &mut RuntimeMesh) -> Result<(), ErrorType> {
         self.context
             .delete_vertex_array(mesh.vertex_array_object.as_ref());
         self.context.delete_buffer(mesh.position_buffer.as_ref());
+        if let Some((index_buffer, _)) = mesh.index_buffer.take() {
+            self.context.delete_buffer(index_buffer.as_ref());
+        }
+        Ok(())
+    }
+    fn send_float_uniform(
+        &mut self,
+        shader: &Shader,
+        uniform_name: &str,
+        data: f32,
+    ) -> Result<(), ErrorType> {
+        self.context
+            .uniform1f(Some(&shader.uniforms[uniform_name].as_ref().unwrap()), data);
+        self.get_error();
         Ok(())
     }
-    pub fn send_vec3_uniform(
-        &self,
+    fn send_vec3_uniform(
+        &mut self,
         shader: &Shader,
         uniform_name: &str,
         data: Vector3<f32>,
@@ -208,8 +367,8 @@ impl RenderingContext {
         self.get_error();
         Ok(())
     }
-    pub fn send_vec4_uniform(
-        &self,
+    fn send_vec4_uniform(
+        &mut self,
         shader: &Shader,
         uniform_name: &str,
         data: Vector4<f32>,
@@ -224,7 +383,21 @@ impl RenderingContext {
         self.get_error();
         Ok(())
     }
-    pub fn build_depth_texture(
+    fn send_mat4_uniform(
+        &mut self,
+        shader: &Shader,
+        uniform_name: &str,
+        data: Matrix4<f32>,
+    ) -> Result<(), ErrorType> {
+        self.context.uniform_matrix4fv_with_f32_array(
+            Some(&shader.uniforms[uniform_name].as_ref().unwrap()),
+            false,
+            data.as_slice(),
+        );
+        self.get_error();
+        Ok(())
+    }
+    fn build_depth_texture(
         &mut self,
         dimensions: Vector2<u32>,
         shader: &Shader,
@@ -262,11 +435,19 @@ impl RenderingContext {
             shader.texture_sampler_location.as_ref(),
             texture_unit as i32,
         );
-        self.context.tex_parameteri(
-            WebGl2RenderingContext::TEXTURE_2D,
+        // depth textures can not be filtered, they are only sampled as shadow maps
+        for filter in [
             WebGl2RenderingContext::TEXTURE_MIN_FILTER,
-            WebGl2RenderingContext::LINEAR as i32,
-        );
+            WebGl2RenderingContext::TEXTURE_MAG_FILTER,
+        ]
+        .iter()
+        {
+            self.context.tex_parameteri(
+                WebGl2RenderingContext::TEXTURE_2D,
+                *filter,
+                WebGl2RenderingContext::NEAREST as i32,
+            );
+        }
         self.context.tex_parameteri(
             WebGl2RenderingContext::TEXTURE_2D,
             WebGl2RenderingContext::TEXTURE_WRAP_S,
@@ -280,18 +461,16 @@ impl RenderingContext {
         Ok(RuntimeDepthTexture {
             texture: WebGlRenderTexture {
                 texture: gl_texture,
+                dimensions,
             },
         })
     }
-    pub fn delete_depth_buffer(
-        &mut self,
-        texture: &mut RuntimeDepthTexture,
-    ) -> Result<(), ErrorType> {
+    fn delete_depth_buffer(&mut self, texture: &mut RuntimeDepthTexture) -> Result<(), ErrorType> {
         self.context
             .delete_texture(texture.texture.texture.as_ref());
         Ok(())
     }
-    pub fn build_texture(
+    fn build_texture(
         &mut self,
         texture: Texture,
         shader: &Shader,
@@ -347,12 +526,95 @@ impl RenderingContext {
         );
         Ok(WebGlRenderTexture {
             texture: gl_texture,
+            dimensions: texture.dimensions,
         })
     }
-    pub fn delete_texture(&mut self, texture: &mut RuntimeTexture) {
+    /// Uploads into the existing texture object, the storage is only reallocated
+    /// when the dimensions change
+    fn update_texture(
+        &mut self,
+        texture: &mut RuntimeTexture,
+        data: Texture,
+    ) -> Result<(), ErrorType> {
+        self.context
+            .active_texture(WebGl2RenderingContext::TEXTURE0);
+        self.context
+            .bind_texture(WebGl2RenderingContext::TEXTURE_2D, texture.texture.as_ref());
+        let level = 0;
+        if data.dimensions == texture.dimensions {
+            self.context
+                .tex_sub_image_2d_with_i32_and_i32_and_u32_and_type_and_opt_u8_array(
+                    WebGl2RenderingContext::TEXTURE_2D,
+                    level,
+                    0,
+                    0,
+                    data.dimensions.x as i32,
+                    data.dimensions.y as i32,
+                    WebGl2RenderingContext::RGBA,
+                    WebGl2RenderingContext::UNSIGNED_BYTE,
+                    Some(data.get_raw_vector().as_slice()),
+                )?;
+        } else {
+            self.context
+                .tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_u8_array_and_src_offset(
+                    WebGl2RenderingContext::TEXTURE_2D,
+                    level,
+                    WebGl2RenderingContext::RGBA as i32,
+                    data.dimensions.x as i32,
+                    data.dimensions.y as i32,
+                    0,
+                    WebGl2RenderingContext::RGBA,
+                    WebGl2RenderingContext::UNSIGNED_BYTE,
+                    data.get_raw_vector().as_slice(),
+                    0,
+                )?;
+            texture.dimensions = data.dimensions;
+        }
+        self.get_error();
+        Ok(())
+    }
+    fn delete_texture(&mut self, texture: &mut RuntimeTexture) {
         self.context.delete_texture(texture.texture.as_ref())
     }
-    pub fn build_framebuffer(
+    fn read_texture(&mut self, texture: &RuntimeTexture) -> Result<Texture, ErrorType> {
+        let framebuffer = self.context.create_framebuffer();
+        self.context
+            .bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, framebuffer.as_ref());
+        self.context.framebuffer_texture_2d(
+            WebGl2RenderingContext::FRAMEBUFFER,
+            WebGl2RenderingContext::COLOR_ATTACHMENT0,
+            WebGl2RenderingContext::TEXTURE_2D,
+            texture.texture.as_ref(),
+            0,
+        );
+        let width = texture.dimensions.x as usize;
+        let mut data = vec![0u8; width * texture.dimensions.y as usize * 4];
+        let result = self.context.read_pixels_with_opt_u8_array(
+            0,
+            0,
+            texture.dimensions.x as i32,
+            texture.dimensions.y as i32,
+            WebGl2RenderingContext::RGBA,
+            WebGl2RenderingContext::UNSIGNED_BYTE,
+            Some(&mut data),
+        );
+        self.context
+            .bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, None);
+        self.context.delete_framebuffer(framebuffer.as_ref());
+        result?;
+        // webgl returns the bottom row first
+        let pixels = data
+            .chunks_exact(width * 4)
+            .rev()
+            .flat_map(|row| row.chunks_exact(4))
+            .map(|p| Vector4::new(p[0], p[1], p[2], p[3]))
+            .collect();
+        Ok(Texture {
+            dimensions: texture.dimensions,
+            pixels,
+        })
+    }
+    fn build_framebuffer(
         &mut self,
         texture_attachment: &mut RuntimeTexture,
         depth_attachment: &mut RuntimeDepthTexture,
@@ -385,22 +647,24 @@ impl RenderingContext {
         }
         // rebinding to default framebuffer to prevent side effects
         self.bind_default_framebuffer();
-        Ok(WebFramebuffer { framebuffer })
+        Ok(WebFramebuffer {
+            framebuffer,
+            dimensions: texture_attachment.dimensions,
+        })
     }
-    pub fn delete_framebuffer(
-        &mut self,
-        framebuffer: &mut WebFramebuffer,
-    ) -> Result<(), ErrorType> {
+    fn delete_framebuffer(&mut self, framebuffer: &mut Framebuffer) -> Result<(), ErrorType> {
         self.context
             .delete_framebuffer(framebuffer.framebuffer.as_ref());
         Ok(())
     }
-    pub fn bind_default_framebuffer(&mut self) {
+    fn bind_default_framebuffer(&mut self) {
         debug!("binding default framebuffer");
         self.context
             .bind_framebuffer(WebGl2RenderingContext::FRAMEBUFFER, None);
+        self.context
+            .viewport(0, 0, self.screen_size.x as i32, self.screen_size.y as i32);
     }
-    pub fn clear_screen(&mut self, color: Vector4<f32>) {
+    fn clear_screen(&mut self, color: Vector4<f32>) {
         self.context.clear_depth(1.0);
         self.context.depth_func(WebGl2RenderingContext::LESS);
         self.context.clear_color(color.x, color.y, color.z, color.w);
@@ -408,12 +672,12 @@ impl RenderingContext {
             WebGl2RenderingContext::COLOR_BUFFER_BIT | WebGl2RenderingContext::DEPTH_BUFFER_BIT,
         );
     }
-    pub fn clear_depth(&mut self) {
+    fn clear_depth(&mut self) {
         self.context.clear_depth(1.0);
         self.context.depth_func(WebGl2RenderingContext::LESS);
         self.context.clear(WebGl2RenderingContext::DEPTH_BUFFER_BIT);
     }
-    pub fn bind_texture(&mut self, texture: &RuntimeTexture, shader: &Shader) {
+    fn bind_texture(&mut self, texture: &RuntimeTexture, shader: &Shader) {
         debug!("binding texture");
         self.context
             .active_texture(WebGl2RenderingContext::TEXTURE0);
@@ -422,29 +686,44 @@ impl RenderingContext {
         self.context
             .uniform1i(shader.texture_sampler_location.as_ref(), 0);
     }
-    pub fn bind_framebuffer(&mut self, framebuffer: &Framebuffer) {
+    fn bind_shadow_map(&mut self, texture: &RuntimeDepthTexture, shader: &Shader) {
+        debug!("binding shadow map");
+        // unit 0 is used by bind_texture
+        self.context
+            .active_texture(WebGl2RenderingContext::TEXTURE1);
+        self.context.bind_texture(
+            WebGl2RenderingContext::TEXTURE_2D,
+            texture.texture.texture.as_ref(),
+        );
+        self.context
+            .uniform1i(shader.uniforms["shadow_map"].as_ref(), 1);
+        self.context
+            .active_texture(WebGl2RenderingContext::TEXTURE0);
+    }
+    fn bind_framebuffer(&mut self, framebuffer: &Framebuffer) {
         debug!("binding framebuffer");
         self.context.bind_framebuffer(
             WebGl2RenderingContext::FRAMEBUFFER,
             framebuffer.framebuffer.as_ref(),
         );
+        self.context.viewport(
+            0,
+            0,
+            framebuffer.dimensions.x as i32,
+            framebuffer.dimensions.y as i32,
+        );
     }
-    pub fn draw_mesh(&mut self, mesh: &RuntimeMesh) {
+    fn draw_mesh(&mut self, mesh: &RuntimeMesh) {
         debug!("drawing mesh");
-        self.context
-            .bind_vertex_array(mesh.vertex_array_object.as_ref());
-        self.context
-            .draw_arrays(WebGl2RenderingContext::TRIANGLES, 0, mesh.count);
+        self.draw(mesh, WebGl2RenderingContext::TRIANGLES);
         self.get_error();
     }
-    pub fn draw_lines(&mut self, mesh: &RuntimeMesh) {
+    fn draw_lines(&mut self, mesh: &RuntimeMesh) {
         debug!("drawing lines");
-        self.context
-            .bind_vertex_array(mesh.vertex_array_object.as_ref());
-        self.context
-            .draw_arrays(WebGl2RenderingContext::LINES, 0, mesh.count);
+        self.draw(mesh, WebGl2RenderingContext::LINES);
     }
-    pub fn send_model_matrix(&mut self, matrix: Matrix4<f32>, shader: &Shader) {
+    fn finish_frame(&mut self) {}
+    fn send_model_matrix(&mut self, matrix: Matrix4<f32>, shader: &Shader) {
         self.context.uniform_matrix4fv_with_f32_array(
             shader.uniforms["model"].as_ref(),
             false,
@@ -452,7 +731,7 @@ impl RenderingContext {
         );
         self.get_error();
     }
-    pub fn send_view_matrix(&mut self, matrix: Matrix4<f32>, shader: &Shader) {
+    fn send_view_matrix(&mut self, matrix: Matrix4<f32>, shader: &Shader) {
         self.context.uniform_matrix4fv_with_f32_array(
             shader.uniforms["camera"].as_ref(),
             false,
@@ -460,55 +739,7 @@ impl RenderingContext {
         );
         self.get_error();
     }
-    fn compile_shader(
-        context: &WebGl2RenderingContext,
-        shader_type: u32,
-        source: &str,
-    ) -> Result<WebGlShader, String> {
-        let shader = context
-            .create_shader(shader_type)
-            .ok_or_else(|| String::from("Unable to create shader object"))?;
-        context.shader_source(&shader, source);
-        context.compile_shader(&shader);
-
-        if context
-            .get_shader_parameter(&shader, WebGl2RenderingContext::COMPILE_STATUS)
-            .as_bool()
-            .unwrap_or(false)
-        {
-            Ok(shader)
-        } else {
-            Err(context
-                .get_shader_info_log(&shader)
-                .unwrap_or_else(|| String::from("Unknown error creating shader")))
-        }
-    }
-    fn link_program(
-        context: &WebGl2RenderingContext,
-        vert_shader: &WebGlShader,
-        frag_shader: &WebGlShader,
-    ) -> Result<WebGlProgram, String> {
-        let program = context
-            .create_program()
-            .ok_or_else(|| String::from("Unable to create shader object"))?;
-
-        context.attach_shader(&program, vert_shader);
-        context.attach_shader(&program, frag_shader);
-        context.link_program(&program);
-
-        if context
-            .get_program_parameter(&program, WebGl2RenderingContext::LINK_STATUS)
-            .as_bool()
-            .unwrap_or(false)
-        {
-            Ok(program)
-        } else {
-            Err(context
-                .get_program_info_log(&program)
-                .unwrap_or_else(|| String::from("Unknvhown error creating program object")))
-        }
-    }
-    pub fn get_error(&self) {
+    fn get_error(&self) {
         let e = self.context.get_error();
         if e != WebGl2RenderingContext::NO_ERROR {
             error!("error: {}", e);
@@ -80,15 +80,20 @@ pub mod shader_library {
     pub const WORLD_SHADER: ShaderText = ShaderText {
         name: "WORLD_SHADER",
         vertex_shader: r#"#version 300 es
-        in vec3 position;
+        // fixed location so meshes built for this shader can be drawn by the shadow shader
+        layout(location = 0) in vec3 position;
         in vec2 uv;
         in vec3 normal;
         out vec2 o_uv;
         out vec3 o_normal;
+        out vec4 o_light_position;
         uniform mat4 camera;
         uniform mat4 model;
+        uniform mat4 light;
         void main() {
-            gl_Position = camera*model*vec4(position,1.0);
+            vec4 world_position = model*vec4(position,1.0);
+            gl_Position = camera*world_position;
+            o_light_position = light*world_position;
             o_normal = normal;
             o_uv = uv;
         }
@@ -98,14 +103,37 @@ pub mod shader_library {
         out vec4 color;
         in vec2 o_uv;
         in vec3 o_normal;
+        in vec4 o_light_position;
         uniform vec3 sun_direction;
         uniform vec4 sun_color;
         uniform sampler2D u_texture;
+        uniform sampler2D shadow_map;
+        // light that still reaches surfaces in shadow or facing away from the sun
+        const float AMBIENT = 0.3;
+        // keeps surfaces from shadowing themselves
+        const float SHADOW_BIAS = 0.002;
         vec4 onify(vec4 v){
             return v*vec4(0.0,0.0,0.0,0.0)+vec4(1.0,1.0,1.0,1.0);
         }
+        // fraction of the 3x3 texels around the fragment in the shadow map that see the sun
+        float shadow(){
+            vec3 position = o_light_position.xyz/o_light_position.w*0.5 + 0.5;
+            if (position.z > 1.0) {
+                return 1.0;
+            }
+            vec2 texel = 1.0/vec2(textureSize(shadow_map, 0));
+            float lit = 0.0;
+            for (int x = -1; x <= 1; x++) {
+                for (int y = -1; y <= 1; y++) {
+                    float depth = texture(shadow_map, position.xy + vec2(x, y)*texel).r;
+                    lit += position.z - SHADOW_BIAS > depth ? 0.0 : 1.0;
+                }
+            }
+            return lit/9.0;
+        }
         float sun(){
-            return dot(-1.0*sun_direction,o_normal);
+            float diffuse = max(dot(-1.0*sun_direction,o_normal), 0.0);
+            return AMBIENT + (1.0 - AMBIENT)*diffuse*shadow();
         }
         vec4 sun_vec(){
             float s = sun();
@@ -115,7 +143,153 @@ pub mod shader_library {
             color = sun_vec()*sun_color*texture(u_texture,o_uv);
         }
     "#,
-        uniforms: &["camera", "model", "sun_direction", "sun_color"],
+        uniforms: &[
+            "camera",
+            "model",
+            "light",
+            "sun_direction",
+            "sun_color",
+            "shadow_map",
+        ],
+        attributes: &[
+            Attribute {
+                size: 3,
+                name: "position",
+            },
+            Attribute {
+                size: 2,
+                name: "uv",
+            },
+            Attribute {
+                size: 3,
+                name: "normal",
+            },
+        ],
+    };
+    /// Only writes depth, the camera is the view and projection of the sun
+    pub const SHADOW_SHADER: ShaderText = ShaderText {
+        name: "SHADOW_SHADER",
+        vertex_shader: r#"#version 300 es
+        layout(location = 0) in vec3 position;
+        uniform mat4 camera;
+        uniform mat4 model;
+        void main() {
+            gl_Position = camera*model*vec4(position,1.0);
+        }
+    "#,
+        fragment_shader: r#"#version 300 es
+        precision highp float;
+        out vec4 color;
+        void main() {
+            color = vec4(0.0);
+        }
+    "#,
+        uniforms: &["camera", "model"],
+        attributes: &[Attribute {
+            size: 3,
+            name: "position",
+        }],
+    };
+    pub const WATER_SHADER: ShaderText = ShaderText {
+        name: "WATER_SHADER",
+        vertex_shader: r#"#version 300 es
+        // fixed location so meshes built for this shader can be drawn by the shadow shader
+        layout(location = 0) in vec3 position;
+        in vec2 uv;
+        in vec3 normal;
+        in vec2 water;
+        out vec2 o_uv;
+        out vec3 o_normal;
+        out vec3 o_position;
+        out vec2 o_water;
+        out vec4 o_light_position;
+        uniform mat4 camera;
+        uniform mat4 model;
+        uniform mat4 light;
+        void main() {
+            vec4 world_position = model*vec4(position,1.0);
+            gl_Position = camera*world_position;
+            o_light_position = light*world_position;
+            o_position = world_position.xyz;
+            o_normal = normal;
+            o_uv = uv;
+            o_water = water;
+        }
+    "#,
+        fragment_shader: r#"#version 300 es
+        precision highp float;
+        out vec4 color;
+        in vec2 o_uv;
+        in vec3 o_normal;
+        in vec3 o_position;
+        // depth and speed of the water
+        in vec2 o_water;
+        in vec4 o_light_position;
+        uniform vec3 sun_direction;
+        uniform vec4 sun_color;
+        uniform vec3 camera_position;
+        // 1 replaces the water with the texture as an opaque false color overlay
+        uniform float overlay;
+        uniform sampler2D u_texture;
+        uniform sampler2D shadow_map;
+        // light that still reaches water in shadow or facing away from the sun
+        const float AMBIENT = 0.3;
+        // keeps the surface from shadowing itself
+        const float SHADOW_BIAS = 0.002;
+        // depth at which the water absorbs 63% of the light passing through it
+        const float ABSORPTION_DEPTH = 2.0;
+        // speeds at which foam starts to form and fully covers the water
+        const float FOAM_START = 0.2;
+        const float FOAM_FULL = 0.8;
+        // water shallower than this fades out so that dry ground shows no water
+        const float MIN_DEPTH = 0.01;
+        const float SHININESS = 64.0;
+        // fraction of the 3x3 texels around the fragment in the shadow map that see the sun
+        float shadow(){
+            vec3 position = o_light_position.xyz/o_light_position.w*0.5 + 0.5;
+            if (position.z > 1.0) {
+                return 1.0;
+            }
+            vec2 texel = 1.0/vec2(textureSize(shadow_map, 0));
+            float lit = 0.0;
+            for (int x = -1; x <= 1; x++) {
+                for (int y = -1; y <= 1; y++) {
+                    float depth = texture(shadow_map, position.xy + vec2(x, y)*texel).r;
+                    lit += position.z - SHADOW_BIAS > depth ? 0.0 : 1.0;
+                }
+            }
+            return lit/9.0;
+        }
+        void main() {
+            vec3 normal = normalize(o_normal);
+            float absorption = 1.0 - exp(-max(o_water.x, 0.0) / ABSORPTION_DEPTH);
+            vec4 water_color = texture(u_texture,o_uv);
+            vec3 body = mix(water_color.rgb, 0.3*water_color.rgb, absorption);
+            float foam = smoothstep(FOAM_START, FOAM_FULL, o_water.y);
+            float diffuse = max(dot(-1.0*sun_direction, normal), 0.0);
+            vec3 view = normalize(camera_position - o_position);
+            vec3 halfway = normalize(view - sun_direction);
+            float sun = shadow();
+            float specular = pow(max(dot(normal, halfway), 0.0), SHININESS)*(1.0 - foam)*sun;
+            float alpha = mix(mix(0.3, 0.9, absorption), 1.0, foam)
+                *smoothstep(0.0, MIN_DEPTH, o_water.x)*sun_color.a;
+            vec3 lit = mix(body, vec3(1.0), foam)*(AMBIENT + (1.0 - AMBIENT)*diffuse*sun)
+                *sun_color.rgb;
+            vec4 water = vec4((lit + specular*sun_color.rgb)*alpha, alpha);
+            vec4 overlay_color = vec4(water_color.rgb*(0.5 + 0.5*diffuse), 1.0);
+            color = mix(water, overlay_color, overlay);
+        }
+    "#,
+        uniforms: &[
+            "camera",
+            "model",
+            "light",
+            "sun_direction",
+            "sun_color",
+            "camera_position",
+            "overlay",
+            "shadow_map",
+        ],
         attributes: &[
             Attribute {
                 size: 3,
@@ -129,6 +303,10 @@ pub mod shader_library {
                 size: 3,
                 name: "normal",
             },
+            Attribute {
+                size: 2,
+                name: "water",
+            },
         ],
     };
     pub const SCREEN_SHADER: ShaderText = ShaderText {
@@ -1,6 +1,7 @@
 use super::prelude::{
-    AssetManager, DeltaCamera, ErrorType, GuiRuntimeModel, GuiTransform, Mesh, Model,
-    RenderingContext, RuntimeMesh, RuntimeTexture, Shader, ShaderBind, Terrain, Transform,
+    AssetManager, DeltaCamera, ErrorType, FlowLines, GuiRuntimeModel, GuiTransform, Mesh, Model,
+    RenderBackend, RenderingContext, RuntimeMesh, RuntimeTexture, Shader, ShaderBind, ShadowMap,
+    Terrain, TerrainChunks, Texture, Transform,
 };
 use legion::*;
 use log::debug;
@@ -9,9 +10,10 @@ pub struct RuntimeModel {
     pub mesh: RuntimeMesh,
     pub texture: RuntimeTexture,
 }
-/// Used for printing debug info
+/// Used for printing debug info, drawn as lines on top of the world
 pub struct RuntimeDebugMesh {
-    mesh: RuntimeMesh,
+    mesh: Option<RuntimeMesh>,
+    texture: RuntimeTexture,
 }
 pub struct GraphicsSettings {
     pub screen_size: Vector2<u32>,
@@ -35,37 +37,61 @@ impl RuntimeModel {
         let texture = graphics.build_texture(model.texture.clone(), bound_shader)?;
         Ok(Self { mesh, texture })
     }
+    /// Frees the gpu resources of the model
+    pub fn delete(mut self, graphics: &mut RenderingContext) -> Result<(), ErrorType> {
+        graphics.delete_mesh(&mut self.mesh)?;
+        graphics.delete_texture(&mut self.texture);
+        Ok(())
+    }
 }
 impl RuntimeDebugMesh {
     pub fn new(
-        mesh: Mesh,
+        mesh: Option<Mesh>,
+        texture: Texture,
         graphics: &mut RenderingContext,
         bound_shader: &Shader,
     ) -> Result<Self, ErrorType> {
-        let mesh = graphics.build_mesh(mesh, bound_shader)?;
-        Ok(Self { mesh })
+        let mesh = match mesh {
+            Some(mesh) => Some(graphics.build_mesh(mesh, bound_shader)?),
+            None => None,
+        };
+        let texture = graphics.build_texture(texture, bound_shader)?;
+        Ok(Self { mesh, texture })
+    }
+    /// Replaces the lines, `None` draws nothing
+    pub fn set_mesh(
+        &mut self,
+        mesh: Option<Mesh>,
+        graphics: &mut RenderingContext,
+        bound_shader: &Shader,
+    ) -> Result<(), ErrorType> {
+        if let Some(mut old) = self.mesh.take() {
+            graphics.delete_mesh(&mut old)?;
+        }
+        if let Some(mesh) = mesh {
+            self.mesh = Some(graphics.build_mesh(mesh, bound_shader)?);
+        }
+        Ok(())
+    }
+    /// Frees the gpu resources of the mesh
+    pub fn delete(&mut self, graphics: &mut RenderingContext) -> Result<(), ErrorType> {
+        if let Some(mut mesh) = self.mesh.take() {
+            graphics.delete_mesh(&mut mesh)?;
+        }
+        graphics.delete_texture(&mut self.texture);
+        Ok(())
     }
 }
 pub fn insert_terrain(
     terrain: Terrain,
     world: &mut World,
     graphics: &mut RenderingContext,
-    asset_manager: &mut AssetManager<RuntimeModel>,
-    bound_shader: &Shader,
+    shaders: &ShaderBind,
 ) -> Result<(), ErrorType> {
-    let model = terrain.model();
-    let transform = model.transform.clone();
-    asset_manager.overwrite(
-        "game_terrain",
-        RuntimeModel::new(&model, graphics, bound_shader).expect("created model"),
-    );
-    world.push((
-        terrain,
-        transform,
-        RuntimeModelId {
-            id: "game_terrain".to_string(),
-        },
-    ));
+    let chunks = TerrainChunks::new(&terrain, graphics, shaders)?;
+    let flow_lines =
+        RuntimeDebugMesh::new(None, FlowLines::texture(), graphics, &shaders["world"])?;
+    world.push((terrain, Transform::default(), chunks, flow_lines));
     Ok(())
 }
 
@@ -87,17 +113,95 @@ pub fn render_object(
     webgl.draw_mesh(&model.mesh);
 }
 #[system(for_each)]
-pub fn render_debug(
+pub fn render_terrain(
     transform: &Transform,
-    model: &RuntimeDebugMesh,
+    chunks: &TerrainChunks,
     #[resource] settings: &GraphicsSettings,
     #[resource] webgl: &mut RenderingContext,
     #[resource] shader: &ShaderBind,
     #[resource] camera: &DeltaCamera,
 ) {
+    webgl.send_view_matrix(camera.get_matrix(settings.screen_size), shader.get_bind());
+    webgl.send_model_matrix(transform.build(), shader.get_bind());
+    chunks.draw_bed(webgl, shader.get_bind());
+}
+/// Draws objects into the shadow map, expects the shadow shader to be bound
+#[system(for_each)]
+pub fn render_object_shadow(
+    transform: &Transform,
+    model: &RuntimeModelId,
+    #[resource] webgl: &mut RenderingContext,
+    #[resource] shader: &ShaderBind,
+    #[resource] shadow_map: &ShadowMap,
+    #[resource] asset_manager: &mut AssetManager<RuntimeModel>,
+) {
+    let model = asset_manager.get(&model.id).unwrap();
+    webgl.send_view_matrix(shadow_map.light(), shader.get_bind());
+    webgl.send_model_matrix(transform.build(), shader.get_bind());
+    webgl.draw_mesh(&model.mesh);
+}
+/// Draws the bed into the shadow map, expects the shadow shader to be bound
+#[system(for_each)]
+pub fn render_terrain_shadow(
+    transform: &Transform,
+    chunks: &TerrainChunks,
+    #[resource] webgl: &mut RenderingContext,
+    #[resource] shader: &ShaderBind,
+    #[resource] shadow_map: &ShadowMap,
+) {
+    webgl.send_view_matrix(shadow_map.light(), shader.get_bind());
     webgl.send_model_matrix(transform.build(), shader.get_bind());
+    chunks.draw_bed(webgl, shader.get_bind());
+}
+/// Draws the water surface into the shadow map, expects the water shadow shader to
+/// be bound
+#[system(for_each)]
+pub fn render_water_shadow(
+    transform: &Transform,
+    chunks: &TerrainChunks,
+    #[resource] webgl: &mut RenderingContext,
+    #[resource] shader: &ShaderBind,
+    #[resource] shadow_map: &ShadowMap,
+) {
+    webgl.send_view_matrix(shadow_map.light(), shader.get_bind());
+    webgl.send_model_matrix(transform.build(), shader.get_bind());
+    chunks.draw_water_shadow(webgl);
+}
+/// Draws the water surface, expects the water shader to be bound and everything
+/// under the water to already be drawn
+#[system(for_each)]
+pub fn render_water(
+    transform: &Transform,
+    chunks: &TerrainChunks,
+    #[resource] settings: &GraphicsSettings,
+    #[resource] webgl: &mut RenderingContext,
+    #[resource] shader: &ShaderBind,
+    #[resource] camera: &DeltaCamera,
+) {
+    webgl
+        .send_vec3_uniform(shader.get_bind(), "camera_position", camera.get_position())
+        .expect("failed to send camera position");
     webgl.send_view_matrix(camera.get_matrix(settings.screen_size), shader.get_bind());
-    webgl.draw_lines(&model.mesh);
+    webgl.send_model_matrix(transform.build(), shader.get_bind());
+    chunks
+        .draw_water(webgl, shader.get_bind())
+        .expect("failed to draw water");
+}
+#[system(for_each)]
+pub fn render_debug(
+    transform: &Transform,
+    model: &RuntimeDebugMesh,
+    #[resource] settings: &GraphicsSettings,
+    #[resource] webgl: &mut RenderingContext,
+    #[resource] shader: &ShaderBind,
+    #[resource] camera: &DeltaCamera,
+) {
+    if let Some(mesh) = model.mesh.as_ref() {
+        webgl.bind_texture(&model.texture, shader.get_bind());
+        webgl.send_model_matrix(transform.build(), shader.get_bind());
+        webgl.send_view_matrix(camera.get_matrix(settings.screen_size), shader.get_bind());
+        webgl.draw_lines(mesh);
+    }
 }
 #[system(for_each)]
 pub fn render_gui(
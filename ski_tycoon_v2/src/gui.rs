@@ -5,13 +5,27 @@ use super::prelude::{
 };
 use legion::*;
 mod egui_integration;
-use egui::CtxRef;
+use egui::{
+    math::{pos2, vec2},
+    CtxRef, Srgba, Ui,
+};
 use egui_integration::draw_egui;
 pub use egui_integration::EguiRawInputAdaptor;
 use nalgebra::{Vector2, Vector4};
 pub struct GuiRuntimeModel {
     pub model: RuntimeModel,
 }
+impl GuiRuntimeModel {
+    /// Replaces the pixels of the texture, for quads that show something new every
+    /// frame. The texture is updated in place instead of being rebuilt
+    pub fn set_texture(
+        &mut self,
+        texture: Texture,
+        graphics: &mut RenderingContext,
+    ) -> Result<(), ErrorType> {
+        graphics.update_texture(&mut self.model.texture, texture)
+    }
+}
 pub struct GuiTransform {
     pub transform: Transform,
 }
@@ -32,6 +46,17 @@ impl GuiModel {
             },
         }
     }
+    /// Quad showing `texture`, the plane from -1 to 1 is placed on the screen by
+    /// `transform`
+    pub fn textured_quad(texture: Texture, transform: Transform) -> Self {
+        Self {
+            model: Model {
+                mesh: Mesh::plane(),
+                texture,
+                transform,
+            },
+        }
+    }
     pub fn insert(
         &self,
         world: &mut World,
@@ -70,7 +95,79 @@ pub fn draw_gui(
 ) -> Result<(), ErrorType> {
     context.begin_frame(adaptor.process_events(input, screen_size));
     let (_, commands) = context.end_frame();
+    adaptor.end_frame(context);
     let paint_jobs = context.tesselate(commands);
-    draw_egui(&paint_jobs, &context.texture(), gl, shader, &screen_size)?;
+    draw_egui(
+        &paint_jobs,
+        &context.texture(),
+        gl,
+        shader,
+        &screen_size,
+        adaptor.pixels_per_point(),
+    )?;
     Ok(())
 }
+/// Size of the plots drawn by `line_plot`
+const PLOT_SIZE: [f32; 2] = [250.0, 60.0];
+/// Draws each series as a line through evenly spaced points, all on the same
+/// vertical scale
+pub fn line_plot(ui: &mut Ui, lines: &[(&[f32], Srgba)]) {
+    let rect = ui.allocate_space(vec2(PLOT_SIZE[0], PLOT_SIZE[1]));
+    let painter = ui.painter();
+    painter.rect_stroke(rect, 0.0, (1.0, Srgba::from_rgb(90, 90, 90)));
+    let to_screen = |[x, y]: [f32; 2]| {
+        pos2(
+            rect.left() + x * rect.width(),
+            rect.bottom() - y * rect.height(),
+        )
+    };
+    let range = value_range(lines.iter().flat_map(|(series, _)| series.iter()));
+    for (series, color) in lines.iter() {
+        let points = plot_points(series, range);
+        for pair in points.windows(2) {
+            painter.line_segment([to_screen(pair[0]), to_screen(pair[1])], (1.5, *color));
+        }
+    }
+}
+/// Smallest and largest value
+fn value_range<'a>(values: impl Iterator<Item = &'a f32>) -> (f32, f32) {
+    values.fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), value| {
+        (min.min(*value), max.max(*value))
+    })
+}
+/// Points of a line plot of `series` scaled to fit a unit square with `range` from
+/// bottom to top, a series in a range with no height is drawn through the middle
+fn plot_points(series: &[f32], (min, max): (f32, f32)) -> Vec<[f32; 2]> {
+    let last = series.len().saturating_sub(1).max(1) as f32;
+    series
+        .iter()
+        .enumerate()
+        .map(|(i, value)| {
+            let y = if max - min > f32::EPSILON * max.abs().max(1.0) {
+                (value - min) / (max - min)
+            } else {
+                0.5
+            };
+            [i as f32 / last, y]
+        })
+        .collect()
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn plot_fits_unit_square() {
+        let series = [1.0, 3.0, 2.0];
+        let range = value_range(series.iter());
+        assert_eq!(range, (1.0, 3.0));
+        assert_eq!(
+            plot_points(&series, range),
+            vec![[0.0, 0.0], [0.5, 1.0], [1.0, 0.5]]
+        );
+        assert_eq!(
+            plot_points(&[2.0, 2.0], (2.0, 2.0)),
+            vec![[0.0, 0.5], [1.0, 0.5]]
+        );
+        assert!(plot_points(&[], range).is_empty());
+    }
+}
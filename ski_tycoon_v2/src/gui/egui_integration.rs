@@ -1,60 +1,199 @@
 use super::prelude::{
-    ErrorType, Event, ItemDesc, Mesh, RenderingContext, ShaderBind, Texture as RGBATexture,
+    ErrorType, Event, Indices, ItemDesc, Mesh, Modifiers, MouseButton, RenderBackend,
+    RenderingContext, ShaderBind, Texture as RGBATexture,
 };
 use egui::{
     math::{Pos2, Rect, Vec2},
     paint::tessellator::Vertex as EguiVertex,
-    PaintJobs, RawInput, Texture,
+    CtxRef, Event as GuiEvent, Key, Modifiers as GuiModifiers, PaintJobs, RawInput, Texture,
 };
 use nalgebra::{Vector2, Vector3, Vector4};
 use std::sync::Arc;
-/// Struct used to get state
-///
+/// Translates game events into egui input, and remembers whether the gui used the
+/// pointer or keyboard so the camera and tools can leave them alone
 pub struct EguiRawInputAdaptor {
-    is_rightclick_down: bool,
+    /// buttons held down, the left button is egui's pointer
+    buttons: Vec<MouseButton>,
+    /// in physical pixels
     last_cursor_pos: Vector2<f32>,
-    frame_scroll: f32,
+    modifiers: Modifiers,
+    /// seconds since the first frame
+    time: f64,
+    /// physical pixels per egui point
+    pixels_per_point: f32,
+    /// the cursor was over a window or a widget was being dragged on the last frame
+    gui_wants_pointer: bool,
+    /// a text field had focus on the last frame
+    gui_wants_keyboard: bool,
+    /// a button was pressed over the gui, which keeps the pointer until released
+    pointer_captured: bool,
 }
 impl EguiRawInputAdaptor {
     #[allow(clippy::ptr_arg)]
     pub fn process_events(&mut self, events: &Vec<Event>, screen_size: Vector2<u32>) -> RawInput {
-        self.frame_scroll = 0.0;
+        let mut scroll = 0.0;
+        let mut gui_events = vec![];
+        // a click within one frame still has to be seen by egui as the pointer down
+        let mut clicked = false;
         for e in events.iter() {
             match e {
-                Event::MouseDown { .. } => {
-                    self.is_rightclick_down = true;
+                Event::MouseDown {
+                    x,
+                    y,
+                    button,
+                    modifiers,
+                } => {
+                    self.last_cursor_pos = Vector2::new(*x, *y);
+                    self.modifiers = *modifiers;
+                    if self.buttons.is_empty() {
+                        self.pointer_captured = self.gui_wants_pointer;
+                    }
+                    if !self.buttons.contains(button) {
+                        self.buttons.push(button.clone());
+                    }
+                    clicked |= *button == MouseButton::LeftClick;
                 }
-                Event::MouseUp { .. } => {
-                    self.is_rightclick_down = false;
+                Event::MouseUp { x, y, modifiers } => {
+                    // the released button is not known, the buttons still held are
+                    // restored by the next mouse move
+                    self.last_cursor_pos = Vector2::new(*x, *y);
+                    self.modifiers = *modifiers;
+                    self.buttons.clear();
+                    self.pointer_captured = false;
                 }
-                Event::MouseMove { x, y, .. } => self.last_cursor_pos = Vector2::new(*x, *y),
-                Event::Scroll { delta_y, .. } => self.frame_scroll += delta_y,
+                Event::MouseMove {
+                    x,
+                    y,
+                    buttons_pressed,
+                    modifiers,
+                    ..
+                } => {
+                    self.last_cursor_pos = Vector2::new(*x, *y);
+                    self.modifiers = *modifiers;
+                    self.buttons = buttons_pressed.clone();
+                    if self.buttons.is_empty() {
+                        self.pointer_captured = false;
+                    }
+                }
+                Event::Scroll {
+                    delta_y, modifiers, ..
+                } => {
+                    // browsers report scrolling down as positive, egui as negative
+                    scroll -= delta_y;
+                    self.modifiers = *modifiers;
+                }
+                Event::KeyPress { key, modifiers } => {
+                    self.modifiers = *modifiers;
+                    gui_events.append(&mut key_events(key, *modifiers));
+                }
+                Event::FrameTime { delta_time_ms } => self.time += *delta_time_ms as f64 / 1000.0,
+                Event::ScaleFactorChange { scale_factor } => self.pixels_per_point = *scale_factor,
                 _ => (),
             }
         }
-        #[allow(clippy::field_reassign_with_default)]
-        {
-            let mut input = RawInput::default();
-            input.mouse_down = self.is_rightclick_down;
-            input.mouse_pos = Some(Pos2::new(self.last_cursor_pos.x, self.last_cursor_pos.y));
-            input.scroll_delta = Vec2::new(0.0, self.frame_scroll);
-            input.screen_rect = Some(Rect {
+        let points = |pixels: f32| pixels / self.pixels_per_point;
+        RawInput {
+            mouse_down: clicked || self.buttons.contains(&MouseButton::LeftClick),
+            mouse_pos: Some(Pos2::new(
+                points(self.last_cursor_pos.x),
+                points(self.last_cursor_pos.y),
+            )),
+            scroll_delta: Vec2::new(0.0, points(scroll)),
+            screen_rect: Some(Rect {
                 min: Pos2::new(0.0, 0.0),
-                max: Pos2::new(screen_size.x as f32, screen_size.y as f32),
-            });
-            input
+                max: Pos2::new(points(screen_size.x as f32), points(screen_size.y as f32)),
+            }),
+            pixels_per_point: Some(self.pixels_per_point),
+            time: Some(self.time),
+            modifiers: gui_modifiers(self.modifiers),
+            events: gui_events,
+            ..RawInput::default()
         }
     }
+    /// Remembers what the gui used on the frame that just ended
+    pub fn end_frame(&mut self, context: &CtxRef) {
+        self.gui_wants_pointer = context.wants_mouse_input();
+        self.gui_wants_keyboard = context.wants_keyboard_input();
+    }
+    /// Whether mouse events belong to the gui rather than the camera and tools. A
+    /// press over a window keeps the pointer until every button is released, while a
+    /// drag that started on the terrain keeps going when it crosses a window
+    pub fn wants_pointer(&self) -> bool {
+        self.pointer_captured || (self.gui_wants_pointer && self.buttons.is_empty())
+    }
+    /// Whether typed keys belong to a text field rather than the key bindings
+    pub fn wants_keyboard(&self) -> bool {
+        self.gui_wants_keyboard
+    }
+    pub fn pixels_per_point(&self) -> f32 {
+        self.pixels_per_point
+    }
 }
 impl Default for EguiRawInputAdaptor {
     fn default() -> Self {
         Self {
-            is_rightclick_down: false,
+            buttons: vec![],
             last_cursor_pos: Vector2::new(0.0, 0.0),
-            frame_scroll: 0.0,
+            modifiers: Modifiers::default(),
+            time: 0.0,
+            pixels_per_point: 1.0,
+            gui_wants_pointer: false,
+            gui_wants_keyboard: false,
+            pointer_captured: false,
+        }
+    }
+}
+fn gui_modifiers(modifiers: Modifiers) -> GuiModifiers {
+    GuiModifiers {
+        alt: modifiers.alt,
+        ctrl: modifiers.ctrl,
+        shift: modifiers.shift,
+        mac_cmd: false,
+        command: modifiers.ctrl,
+    }
+}
+/// Named keys are pressed and released in the same frame, single characters are
+/// typed as text unless ctrl is held and other key names like `Shift` are ignored
+fn key_events(key: &str, modifiers: Modifiers) -> Vec<GuiEvent> {
+    match named_key(key) {
+        Some(key) => vec![
+            GuiEvent::Key {
+                key,
+                pressed: true,
+                modifiers: gui_modifiers(modifiers),
+            },
+            GuiEvent::Key {
+                key,
+                pressed: false,
+                modifiers: gui_modifiers(modifiers),
+            },
+        ],
+        None if modifiers.ctrl || key.chars().count() != 1 || key.chars().any(char::is_control) => {
+            vec![]
         }
+        None => vec![GuiEvent::Text(key.to_string())],
     }
 }
+/// Key with the name browsers give it in `KeyboardEvent.key`
+fn named_key(key: &str) -> Option<Key> {
+    Some(match key {
+        "ArrowDown" => Key::ArrowDown,
+        "ArrowLeft" => Key::ArrowLeft,
+        "ArrowRight" => Key::ArrowRight,
+        "ArrowUp" => Key::ArrowUp,
+        "Backspace" => Key::Backspace,
+        "Delete" => Key::Delete,
+        "End" => Key::End,
+        "Enter" => Key::Enter,
+        "Escape" => Key::Escape,
+        "Home" => Key::Home,
+        "Insert" => Key::Insert,
+        "PageDown" => Key::PageDown,
+        "PageUp" => Key::PageUp,
+        "Tab" => Key::Tab,
+        _ => return None,
+    })
+}
 #[allow(clippy::ptr_arg)]
 pub fn draw_egui(
     paint_jobs: &PaintJobs,
@@ -62,6 +201,7 @@ pub fn draw_egui(
     gl: &mut RenderingContext,
     shader: &ShaderBind,
     screen_size: &Vector2<u32>,
+    pixels_per_point: f32,
 ) -> Result<(), ErrorType> {
     let pixels = texture
         .srgba_pixels()
@@ -73,15 +213,7 @@ pub fn draw_egui(
     gl.bind_texture(&render_texture, shader.get_bind());
     let mut depth = -0.8;
     for (_rect, triangles) in paint_jobs.iter() {
-        let vertices = to_vertex(
-            &triangles
-                .indices
-                .iter()
-                .map(|i| triangles.vertices[*i as usize])
-                .collect(),
-            depth,
-            screen_size,
-        );
+        let vertices = to_vertex(&triangles.vertices, depth, screen_size, pixels_per_point);
         let mut runtime_mesh = gl.build_mesh(
             Mesh {
                 vertices,
@@ -102,6 +234,10 @@ pub fn draw_egui(
                         name: "vertex_color".to_string(),
                     },
                 ],
+                indices: Some(Indices::new(
+                    triangles.indices.clone(),
+                    triangles.vertices.len(),
+                )),
             },
             shader.get_bind(),
         )?;
@@ -112,11 +248,17 @@ pub fn draw_egui(
     gl.delete_texture(&mut render_texture);
     Ok(())
 }
+/// Vertices in egui points to clip space
 #[allow(clippy::ptr_arg)]
-fn to_vertex(vertex_list: &Vec<EguiVertex>, depth: f32, screen_size: &Vector2<u32>) -> Vec<f32> {
+fn to_vertex(
+    vertex_list: &Vec<EguiVertex>,
+    depth: f32,
+    screen_size: &Vector2<u32>,
+    pixels_per_point: f32,
+) -> Vec<f32> {
     let mut vertices = vec![];
-    let screen_x = screen_size.x as f32 / 2.0;
-    let screen_y = screen_size.y as f32 / 2.0;
+    let screen_x = screen_size.x as f32 / (2.0 * pixels_per_point);
+    let screen_y = screen_size.y as f32 / (2.0 * pixels_per_point);
     vertices.reserve(vertex_list.len() * (3 + 2 + 4));
     for vertex in vertex_list.iter() {
         let position = Vector3::new(
@@ -137,3 +279,132 @@ fn to_vertex(vertex_list: &Vec<EguiVertex>, depth: f32, screen_size: &Vector2<u3
     }
     vertices
 }
+#[cfg(test)]
+mod test {
+    use super::*;
+    fn mouse_down(button: MouseButton) -> Event {
+        Event::MouseDown {
+            x: 10.0,
+            y: 20.0,
+            button,
+            modifiers: Modifiers::default(),
+        }
+    }
+    fn mouse_up() -> Event {
+        Event::MouseUp {
+            x: 10.0,
+            y: 20.0,
+            modifiers: Modifiers::default(),
+        }
+    }
+    fn key(key: &str, ctrl: bool) -> Event {
+        Event::KeyPress {
+            key: key.to_string(),
+            modifiers: Modifiers {
+                ctrl,
+                ..Modifiers::default()
+            },
+        }
+    }
+    #[test]
+    fn left_button_is_the_pointer() {
+        let mut adaptor = EguiRawInputAdaptor::default();
+        let screen_size = Vector2::new(100, 100);
+        let input = adaptor.process_events(&vec![mouse_down(MouseButton::RightClick)], screen_size);
+        assert!(!input.mouse_down);
+        let input = adaptor.process_events(&vec![mouse_down(MouseButton::LeftClick)], screen_size);
+        assert!(input.mouse_down);
+        assert!(
+            !adaptor
+                .process_events(&vec![mouse_up()], screen_size)
+                .mouse_down
+        );
+        // a click within one frame is down for that frame
+        let click = vec![mouse_down(MouseButton::LeftClick), mouse_up()];
+        assert!(adaptor.process_events(&click, screen_size).mouse_down);
+        assert!(!adaptor.process_events(&vec![], screen_size).mouse_down);
+    }
+    #[test]
+    fn keys_and_text() {
+        let mut adaptor = EguiRawInputAdaptor::default();
+        let events = vec![
+            key("é", false),
+            key("Backspace", false),
+            key("c", true),
+            key("Shift", false),
+        ];
+        let input = adaptor.process_events(&events, Vector2::new(100, 100));
+        assert_eq!(input.events.len(), 3);
+        assert!(matches!(&input.events[0], GuiEvent::Text(text) if text == "é"));
+        assert!(matches!(
+            input.events[1],
+            GuiEvent::Key {
+                key: Key::Backspace,
+                pressed: true,
+                ..
+            }
+        ));
+        assert!(matches!(
+            input.events[2],
+            GuiEvent::Key {
+                key: Key::Backspace,
+                pressed: false,
+                ..
+            }
+        ));
+        assert!(!input.modifiers.shift && !input.modifiers.ctrl);
+    }
+    #[test]
+    fn time_and_scaling() {
+        let mut adaptor = EguiRawInputAdaptor::default();
+        let events = vec![
+            Event::ScaleFactorChange { scale_factor: 2.0 },
+            Event::FrameTime {
+                delta_time_ms: 250.0,
+            },
+            mouse_down(MouseButton::LeftClick),
+        ];
+        let input = adaptor.process_events(&events, Vector2::new(200, 100));
+        assert_eq!(input.pixels_per_point, Some(2.0));
+        assert_eq!(input.mouse_pos, Some(Pos2::new(5.0, 10.0)));
+        assert_eq!(
+            input.screen_rect.map(|rect| rect.max),
+            Some(Pos2::new(100.0, 50.0))
+        );
+        let events = vec![Event::FrameTime {
+            delta_time_ms: 750.0,
+        }];
+        let input = adaptor.process_events(&events, Vector2::new(200, 100));
+        assert_eq!(input.time, Some(1.0));
+    }
+    #[test]
+    fn pointer_stays_where_it_was_pressed() {
+        let mut adaptor = EguiRawInputAdaptor::default();
+        let screen_size = Vector2::new(100, 100);
+        let drag = |buttons_pressed| Event::MouseMove {
+            delta_x: 1.0,
+            delta_y: 0.0,
+            x: 11.0,
+            y: 20.0,
+            delta_time_ms: 16.0,
+            buttons_pressed,
+            modifiers: Modifiers::default(),
+        };
+        // pressed over a window
+        adaptor.gui_wants_pointer = true;
+        assert!(adaptor.wants_pointer());
+        adaptor.process_events(&vec![mouse_down(MouseButton::LeftClick)], screen_size);
+        adaptor.gui_wants_pointer = false;
+        adaptor.process_events(&vec![drag(vec![MouseButton::LeftClick])], screen_size);
+        assert!(adaptor.wants_pointer());
+        adaptor.process_events(&vec![mouse_up()], screen_size);
+        assert!(!adaptor.wants_pointer());
+        // pressed on the terrain and dragged over a window
+        adaptor.process_events(&vec![mouse_down(MouseButton::LeftClick)], screen_size);
+        adaptor.gui_wants_pointer = true;
+        adaptor.process_events(&vec![drag(vec![MouseButton::LeftClick])], screen_size);
+        assert!(!adaptor.wants_pointer());
+        adaptor.process_events(&vec![drag(vec![])], screen_size);
+        assert!(adaptor.wants_pointer());
+    }
+}
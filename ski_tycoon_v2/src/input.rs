@@ -0,0 +1,411 @@
+use super::prelude::{BrushTool, Event, Modifiers, MouseButton};
+use nalgebra::Vector2;
+/// Bindings used when no bindings file is loaded
+pub const DEFAULT_BINDINGS: &str = include_str!("bindings.cfg");
+/// Something the player can do, bound to keys and mouse buttons in the bindings file
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    PanForward,
+    PanBack,
+    PanLeft,
+    PanRight,
+    /// moves the camera with the mouse
+    Pan,
+    Orbit,
+    Zoom,
+    ZoomIn,
+    ZoomOut,
+    Pause,
+    Step,
+    Screenshot,
+    /// switches to the next camera mode
+    NextCameraMode,
+    /// applies the selected brush while held
+    Sculpt,
+    SelectTool(Option<BrushTool>),
+    /// draws the cross section line while held
+    Transect,
+}
+impl Action {
+    const FIXED: [(&'static str, Action); 15] = [
+        ("pan_forward", Action::PanForward),
+        ("pan_back", Action::PanBack),
+        ("pan_left", Action::PanLeft),
+        ("pan_right", Action::PanRight),
+        ("pan", Action::Pan),
+        ("orbit", Action::Orbit),
+        ("zoom", Action::Zoom),
+        ("zoom_in", Action::ZoomIn),
+        ("zoom_out", Action::ZoomOut),
+        ("pause", Action::Pause),
+        ("step", Action::Step),
+        ("screenshot", Action::Screenshot),
+        ("camera_mode", Action::NextCameraMode),
+        ("sculpt", Action::Sculpt),
+        ("transect", Action::Transect),
+    ];
+    /// Action with the name used in the bindings file
+    pub fn from_name(name: &str) -> Option<Action> {
+        if let Some((_, action)) = Self::FIXED.iter().find(|(n, _)| *n == name) {
+            return Some(*action);
+        }
+        let tool = name.strip_prefix("tool_")?;
+        if tool == "off" {
+            return Some(Action::SelectTool(None));
+        }
+        BrushTool::ALL
+            .iter()
+            .find(|t| t.name().to_lowercase().replace(' ', "_") == tool)
+            .map(|t| Action::SelectTool(Some(*t)))
+    }
+}
+#[derive(Clone, Debug, PartialEq)]
+pub enum Input {
+    /// typed character, compared without case
+    Key(String),
+    Button(MouseButton),
+    Scroll,
+}
+#[derive(Clone, Debug, PartialEq)]
+pub struct Binding {
+    pub input: Input,
+    pub modifiers: Modifiers,
+}
+impl Binding {
+    pub fn parse(text: &str) -> Option<Binding> {
+        let mut modifiers = Modifiers::default();
+        let mut rest = text.trim();
+        loop {
+            if let Some(r) = rest.strip_prefix("shift+") {
+                modifiers.shift = true;
+                rest = r;
+            } else if let Some(r) = rest.strip_prefix("ctrl+") {
+                modifiers.ctrl = true;
+                rest = r;
+            } else if let Some(r) = rest.strip_prefix("alt+") {
+                modifiers.alt = true;
+                rest = r;
+            } else {
+                break;
+            }
+        }
+        let input = match rest {
+            "mouse_left" => Input::Button(MouseButton::LeftClick),
+            "mouse_right" => Input::Button(MouseButton::RightClick),
+            "mouse_middle" => Input::Button(MouseButton::MiddleClick),
+            "mouse_back" => Input::Button(MouseButton::Back),
+            "mouse_forward" => Input::Button(MouseButton::Forward),
+            "scroll" => Input::Scroll,
+            "space" => Input::Key(" ".to_string()),
+            key if key.chars().count() == 1 => Input::Key(key.to_lowercase()),
+            _ => return None,
+        };
+        Some(Binding { input, modifiers })
+    }
+    /// Shift changes the typed character, so key bindings that do not ask for
+    /// shift ignore it
+    fn modifiers_match(&self, held: Modifiers) -> bool {
+        let shift = match self.input {
+            Input::Key(_) if !self.modifiers.shift => held.shift,
+            _ => self.modifiers.shift,
+        };
+        held == Modifiers {
+            shift,
+            ..self.modifiers
+        }
+    }
+}
+#[derive(PartialEq, Debug)]
+pub enum BindingError {
+    MissingSeparator { line: usize },
+    UnknownAction { line: usize, action: String },
+    InvalidBinding { line: usize, binding: String },
+}
+/// Input translated into actions
+#[derive(Clone, Debug, PartialEq)]
+pub enum ActionEvent {
+    Pressed(Action),
+    /// mouse dragged with a bound button held, or scrolled
+    Motion {
+        action: Action,
+        delta: Vector2<f32>,
+        delta_time_ms: f32,
+    },
+}
+/// Maps events onto actions, stored as a resource and updated at the start of each
+/// frame with that frame's events
+pub struct InputMap {
+    bindings: Vec<(Action, Binding)>,
+    buttons: Vec<MouseButton>,
+    modifiers: Modifiers,
+    actions: Vec<ActionEvent>,
+}
+impl Default for InputMap {
+    fn default() -> Self {
+        Self::from_config(DEFAULT_BINDINGS).expect("default bindings are valid")
+    }
+}
+impl InputMap {
+    /// Reads bindings in the format of `bindings.cfg`, lines are counted from 1
+    pub fn from_config(config: &str) -> Result<Self, BindingError> {
+        let mut bindings = vec![];
+        for (i, line) in config.lines().enumerate() {
+            let line_number = i + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut parts = line.splitn(2, '=');
+            let (name, binding) = match (parts.next(), parts.next()) {
+                (Some(name), Some(binding)) => (name.trim(), binding.trim()),
+                _ => {
+                    return Err(BindingError::MissingSeparator { line: line_number });
+                }
+            };
+            let action = Action::from_name(name).ok_or_else(|| BindingError::UnknownAction {
+                line: line_number,
+                action: name.to_string(),
+            })?;
+            let binding = Binding::parse(binding).ok_or_else(|| BindingError::InvalidBinding {
+                line: line_number,
+                binding: binding.to_string(),
+            })?;
+            bindings.push((action, binding));
+        }
+        Ok(Self {
+            bindings,
+            buttons: vec![],
+            modifiers: Modifiers::default(),
+            actions: vec![],
+        })
+    }
+    /// Replaces the bindings, the held buttons are kept
+    pub fn load(&mut self, config: &str) -> Result<(), BindingError> {
+        self.bindings = Self::from_config(config)?.bindings;
+        Ok(())
+    }
+    pub fn bindings(&self) -> &[(Action, Binding)] {
+        &self.bindings
+    }
+    /// Translates this frame's events, replacing the actions of the last frame
+    pub fn update(&mut self, events: &[Event]) {
+        self.actions.clear();
+        for event in events.iter() {
+            match event {
+                Event::KeyPress { key, modifiers } => {
+                    let key = Input::Key(key.to_lowercase());
+                    self.press(|binding| {
+                        binding.input == key && binding.modifiers_match(*modifiers)
+                    });
+                }
+                Event::MouseDown {
+                    button, modifiers, ..
+                } => {
+                    self.modifiers = *modifiers;
+                    if !self.buttons.contains(button) {
+                        self.buttons.push(button.clone());
+                    }
+                    let input = Input::Button(button.clone());
+                    self.press(|binding| {
+                        binding.input == input && binding.modifiers_match(*modifiers)
+                    });
+                }
+                Event::MouseUp { modifiers, .. } => {
+                    // the released button is not known, the buttons still held are
+                    // restored by the next mouse move
+                    self.modifiers = *modifiers;
+                    self.buttons.clear();
+                }
+                Event::MouseMove {
+                    delta_x,
+                    delta_y,
+                    delta_time_ms,
+                    buttons_pressed,
+                    modifiers,
+                    ..
+                } => {
+                    self.modifiers = *modifiers;
+                    self.buttons = buttons_pressed.clone();
+                    let buttons = &self.buttons;
+                    let motions = self
+                        .bindings
+                        .iter()
+                        .filter(|(_, binding)| match &binding.input {
+                            Input::Button(button) => {
+                                buttons.contains(button) && binding.modifiers_match(*modifiers)
+                            }
+                            _ => false,
+                        })
+                        .map(|(action, _)| ActionEvent::Motion {
+                            action: *action,
+                            delta: Vector2::new(*delta_x, *delta_y),
+                            delta_time_ms: *delta_time_ms,
+                        })
+                        .collect::<Vec<_>>();
+                    self.actions.extend(motions);
+                }
+                Event::Scroll {
+                    delta_y,
+                    delta_time_ms,
+                    modifiers,
+                } => {
+                    self.modifiers = *modifiers;
+                    let motions = self
+                        .bindings
+                        .iter()
+                        .filter(|(_, binding)| {
+                            binding.input == Input::Scroll && binding.modifiers_match(*modifiers)
+                        })
+                        .map(|(action, _)| ActionEvent::Motion {
+                            action: *action,
+                            delta: Vector2::new(0.0, *delta_y),
+                            delta_time_ms: *delta_time_ms,
+                        })
+                        .collect::<Vec<_>>();
+                    self.actions.extend(motions);
+                }
+                _ => (),
+            }
+        }
+    }
+    fn press(&mut self, matches: impl Fn(&Binding) -> bool) {
+        let pressed = self
+            .bindings
+            .iter()
+            .filter(|(_, binding)| matches(binding))
+            .map(|(action, _)| ActionEvent::Pressed(*action))
+            .collect::<Vec<_>>();
+        self.actions.extend(pressed);
+    }
+    /// Actions from this frame's events in the order they happened
+    pub fn actions(&self) -> &[ActionEvent] {
+        &self.actions
+    }
+    pub fn pressed(&self, action: Action) -> bool {
+        self.actions.contains(&ActionEvent::Pressed(action))
+    }
+    /// Whether a mouse button bound to the action is held down
+    pub fn held(&self, action: Action) -> bool {
+        self.bindings.iter().any(|(a, binding)| {
+            *a == action
+                && match &binding.input {
+                    Input::Button(button) => {
+                        self.buttons.contains(button) && binding.modifiers_match(self.modifiers)
+                    }
+                    _ => false,
+                }
+        })
+    }
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    fn shift() -> Modifiers {
+        Modifiers {
+            shift: true,
+            ..Modifiers::default()
+        }
+    }
+    fn mouse_move(buttons: Vec<MouseButton>, modifiers: Modifiers) -> Event {
+        Event::MouseMove {
+            delta_x: 2.0,
+            delta_y: -1.0,
+            x: 10.0,
+            y: 10.0,
+            delta_time_ms: 16.0,
+            buttons_pressed: buttons,
+            modifiers,
+        }
+    }
+    #[test]
+    fn default_bindings() {
+        let map = InputMap::default();
+        assert!(map.bindings().len() > 10);
+        assert!(map
+            .bindings()
+            .iter()
+            .any(|(action, _)| *action == Action::SelectTool(Some(BrushTool::RemoveWater))));
+    }
+    #[test]
+    fn keys_and_modifiers() {
+        let mut map =
+            InputMap::from_config("pan_forward = w\npause = space\nstep = ctrl+s").unwrap();
+        map.update(&[
+            Event::KeyPress {
+                key: "W".to_string(),
+                modifiers: shift(),
+            },
+            Event::KeyPress {
+                key: " ".to_string(),
+                modifiers: Modifiers::default(),
+            },
+            Event::KeyPress {
+                key: "s".to_string(),
+                modifiers: Modifiers::default(),
+            },
+        ]);
+        assert_eq!(
+            map.actions(),
+            &[
+                ActionEvent::Pressed(Action::PanForward),
+                ActionEvent::Pressed(Action::Pause)
+            ]
+        );
+        assert!(!map.pressed(Action::Step));
+    }
+    #[test]
+    fn drags_use_exact_modifiers() {
+        let mut map =
+            InputMap::from_config("orbit = mouse_left\npan = shift+mouse_left\nzoom = scroll")
+                .unwrap();
+        map.update(&[mouse_move(vec![MouseButton::LeftClick], shift())]);
+        assert_eq!(
+            map.actions(),
+            &[ActionEvent::Motion {
+                action: Action::Pan,
+                delta: Vector2::new(2.0, -1.0),
+                delta_time_ms: 16.0
+            }]
+        );
+        assert!(map.held(Action::Pan));
+        assert!(!map.held(Action::Orbit));
+        map.update(&[
+            mouse_move(vec![], Modifiers::default()),
+            Event::Scroll {
+                delta_y: 100.0,
+                delta_time_ms: 5.0,
+                modifiers: Modifiers::default(),
+            },
+        ]);
+        assert!(!map.held(Action::Pan));
+        assert_eq!(
+            map.actions(),
+            &[ActionEvent::Motion {
+                action: Action::Zoom,
+                delta: Vector2::new(0.0, 100.0),
+                delta_time_ms: 5.0
+            }]
+        );
+    }
+    #[test]
+    fn config_errors() {
+        assert_eq!(
+            InputMap::from_config("# comment\n\nfly = w").err(),
+            Some(BindingError::UnknownAction {
+                line: 3,
+                action: "fly".to_string()
+            })
+        );
+        assert_eq!(
+            InputMap::from_config("orbit = mouse_sideways").err(),
+            Some(BindingError::InvalidBinding {
+                line: 1,
+                binding: "mouse_sideways".to_string()
+            })
+        );
+        assert_eq!(
+            InputMap::from_config("orbit").err(),
+            Some(BindingError::MissingSeparator { line: 1 })
+        );
+    }
+}
@@ -1,40 +1,59 @@
 mod asset_manager;
 mod bindable;
 mod camera;
+mod diagnostics;
 mod graphics_engine;
 mod graphics_system;
 mod grid;
 mod gui;
+mod input;
+mod minimap;
 mod model;
+mod recording;
+mod shadow;
+mod simulation;
 mod terrain;
 mod texture;
+mod time_of_day;
 mod utils;
-#[cfg(not(target_arch = "wasm32"))]
+#[cfg(all(not(target_arch = "wasm32"), not(feature = "software")))]
 pub use graphics_engine::Window;
 use graphics_engine::{
-    ErrorType, Framebuffer, InitContext, Mesh, RenderingContext, RuntimeDepthTexture, Transform,
+    ErrorType, Framebuffer, InitContext, Mesh, RenderBackend, RenderingContext,
+    RuntimeDepthTexture, Transform,
 };
-use log::{debug, info};
+use input::{Action, ActionEvent, BindingError, InputMap};
+use log::{debug, error, info};
 use nalgebra::{Matrix4, Vector2, Vector3, Vector4};
-use terrain::Terrain;
+use recording::{Recording, Replay};
+use shadow::ShadowMap;
+use simulation::SimulationControl;
+use terrain::{CursorPick, FlowLines, Overlay, Sculptor, Terrain, TerrainChunks, Tracer, Transect};
 use texture::RGBATexture;
+use time_of_day::TimeOfDay;
 mod events;
 use asset_manager::AssetManager;
 use bindable::Bindable;
-use camera::DeltaCamera;
-use events::{Event, MouseButton};
+use camera::{CameraPathError, CameraPaths, DeltaCamera};
+use diagnostics::DiagnosticsHistory;
+use events::Event;
 //
-use graphics_system::{GraphicsSettings, RuntimeModel};
-use gui::GuiModel;
+use graphics_system::{GraphicsSettings, RuntimeDebugMesh, RuntimeModel};
+use gui::{EguiRawInputAdaptor, GuiModel};
 use legion::*;
+use minimap::Minimap;
 use wasm_bindgen::prelude::*;
 pub mod prelude {
     pub use super::asset_manager::AssetManager;
-    pub use super::camera::DeltaCamera;
-    pub use super::events::{Event, MouseButton};
+    pub use super::camera::{
+        Bookmark, CameraMode, CameraPath, CameraPathError, CameraPaths, CameraPose, DeltaCamera,
+        Keyframe,
+    };
+    pub use super::diagnostics::{Diagnostic, Diagnostics, DiagnosticsHistory};
+    pub use super::events::{Event, EventDecodeError, Modifiers, MouseButton};
     pub use super::graphics_engine::{
-        ErrorType, Framebuffer, ItemDesc, Mesh, RenderingContext, RuntimeMesh, RuntimeTexture,
-        Shader, Transform, Vertex,
+        ErrorType, Framebuffer, Indices, ItemDesc, Mesh, RenderBackend, RenderingContext,
+        RuntimeMesh, RuntimeTexture, Shader, Transform, Vertex,
     };
     pub type ShaderBind = super::Bindable<Shader>;
     pub use super::graphics_system::{
@@ -42,9 +61,18 @@ pub mod prelude {
     };
     pub use super::grid::Grid;
     pub use super::gui::{GuiModel, GuiRuntimeModel, GuiTransform};
+    pub use super::input::{Action, ActionEvent, Binding, BindingError, InputMap};
+    pub use super::minimap::{Minimap, MinimapField};
     pub use super::model::Model;
-    pub use super::terrain::Terrain;
+    pub use super::recording::{Recording, RecordingError, Replay};
+    pub use super::shadow::ShadowMap;
+    pub use super::simulation::{Boundaries, Boundary, SimulationControl, SimulationParameters};
+    pub use super::terrain::{
+        Brush, BrushTool, Colormap, CursorPick, Falloff, FlowLines, Overlay, ProfilePoint,
+        ScalarField, Sculptor, Terrain, TerrainChunks, TerrainHit, Tracer, Transect,
+    };
     pub use super::texture::RGBATexture as Texture;
+    pub use super::time_of_day::TimeOfDay;
     pub use wasm_bindgen::prelude::JsValue;
 }
 use prelude::ShaderBind;
@@ -54,7 +82,19 @@ pub struct Game {
     world_depth_texture: RuntimeDepthTexture,
     world_framebuffer: Framebuffer,
     world_render_surface: RuntimeModel,
+    /// full screen quad drawn behind the world
+    sky: RuntimeModel,
+    /// colors in the sky texture, it is only rebuilt when they change
+    sky_pixels: Vec<Vector4<u8>>,
+    screenshot_requested: bool,
+    /// events of every frame since `start_recording`
+    recording: Option<Recording>,
+    /// when set, recorded events are used instead of the events passed to `run_frame`
+    replay: Option<Replay>,
 }
+/// Directory screenshots taken with `Event::Screenshot` are saved to
+#[cfg(not(target_arch = "wasm32"))]
+const SCREENSHOT_DIRECTORY: &str = "screenshots";
 impl Game {
     pub fn new(screen_size: Vector2<u32>, init_context: InitContext) -> Result<Game, ErrorType> {
         utils::set_panic_hook();
@@ -63,26 +103,30 @@ impl Game {
         let mut webgl = RenderingContext::new(init_context)?;
         let mut shader_bind = Bindable::default();
         let model_manager: AssetManager<RuntimeModel> = AssetManager::default();
+        let time_of_day = TimeOfDay::default();
+        let sun_direction = time_of_day.light_direction();
         shader_bind.insert("world", webgl.build_world_shader()?);
-        shader_bind.bind("world");
-        webgl.bind_shader(shader_bind.get_bind()).ok().unwrap();
-        webgl
-            .send_vec3_uniform(
-                &mut shader_bind["world"],
-                "sun_direction",
-                Vector3::new(1.0, -1.0, 0.0).normalize(),
-            )
-            .ok()
-            .unwrap();
-        webgl
-            .send_vec4_uniform(
-                &mut shader_bind["world"],
-                "sun_color",
-                Vector4::new(1.0, 1.0, 1.0, 1.0),
-            )
-            .ok()
-            .unwrap();
+        shader_bind.insert("water", webgl.build_water_shader()?);
+        for name in ["world", "water"].iter() {
+            shader_bind.bind(name);
+            webgl.bind_shader(shader_bind.get_bind()).ok().unwrap();
+            webgl
+                .send_vec3_uniform(&mut shader_bind[*name], "sun_direction", sun_direction)
+                .ok()
+                .unwrap();
+            webgl
+                .send_vec4_uniform(
+                    &mut shader_bind[*name],
+                    "sun_color",
+                    time_of_day.light_color(),
+                )
+                .ok()
+                .unwrap();
+        }
         webgl.get_error();
+        shader_bind.insert("shadow", webgl.build_shadow_shader()?);
+        shader_bind.insert("water_shadow", webgl.build_water_shadow_shader()?);
+        let shadow_map = ShadowMap::new(sun_direction, &mut webgl, &shader_bind["shadow"])?;
         shader_bind.insert("screen", webgl.build_screen_shader()?);
         shader_bind.insert("gui", webgl.build_gui_shader()?);
         shader_bind.bind("world");
@@ -110,10 +154,18 @@ impl Game {
             mesh: fb_mesh,
             texture: world_framebuffer_texture,
         };
+        let sky = RuntimeModel {
+            mesh: webgl.build_mesh(Mesh::plane(), &shader_bind["screen"])?,
+            texture: webgl.build_texture(
+                RGBATexture::constant_color(Vector4::new(0, 0, 0, 255), Vector2::new(1, 1)),
+                &shader_bind["screen"],
+            )?,
+        };
         webgl.get_error();
         webgl.get_error();
         resources.insert(webgl);
         resources.insert(shader_bind);
+        resources.insert(shadow_map);
         resources.insert(GraphicsSettings { screen_size });
         resources.insert(DeltaCamera::new(
             Vector3::new(0.0, 0.0, 0.0),
@@ -125,15 +177,27 @@ impl Game {
         resources.insert(egui_context);
         resources.insert(egui_adaptor);
         resources.insert(model_manager);
-        let terrain_library = terrain::TerrainLibrary::default();
-        terrain_library.entries[0].build_scenario(
+        resources.insert(SimulationControl::default());
+        let mut terrain_library = terrain::TerrainLibrary::default();
+        terrain_library.build(
+            0,
             &mut world,
             &mut resources.get_mut().unwrap(),
             &mut resources.get_mut().unwrap(),
             &mut resources.get_mut().unwrap(),
-            &mut resources.get_mut().unwrap(),
+            &resources.get().unwrap(),
         );
         resources.insert(terrain_library);
+        resources.insert(Overlay::default());
+        resources.insert(FlowLines::default());
+        resources.insert(CursorPick::default());
+        resources.insert(Sculptor::default());
+        resources.insert(Transect::default());
+        resources.insert(Minimap::default());
+        resources.insert(InputMap::default());
+        resources.insert(CameraPaths::default());
+        resources.insert(DiagnosticsHistory::default());
+        resources.insert(time_of_day);
         // gui::insert_ui(&mut egui_context);
         let g = Game {
             world,
@@ -141,26 +205,72 @@ impl Game {
             world_depth_texture,
             world_framebuffer,
             world_render_surface,
+            sky,
+            sky_pixels: vec![],
+            screenshot_requested: false,
+            recording: None,
+            replay: None,
         };
         Ok(g)
     }
+    /// Replaces the input bindings with ones read from a bindings file
+    pub fn load_bindings(&mut self, config: &str) -> Result<(), BindingError> {
+        let input: &mut InputMap = &mut self.resources.get_mut().unwrap();
+        input.load(config)
+    }
+    /// Records the events of every following frame, replacing any earlier recording
+    pub fn start_recording(&mut self) {
+        let settings: &GraphicsSettings = &self.resources.get().unwrap();
+        self.recording = Some(Recording::new(settings.screen_size));
+    }
+    pub fn stop_recording(&mut self) -> Option<Recording> {
+        self.recording.take()
+    }
+    /// Plays back a recording over the following frames, live input is ignored until
+    /// it ends. Replaying from the first frame of a new game reproduces the session
+    /// exactly
+    pub fn replay(&mut self, recording: Recording) {
+        self.replay = Some(Replay::new(recording));
+    }
+    /// Camera bookmarks and keyframes in text form
+    pub fn camera_paths(&self) -> String {
+        let paths: &CameraPaths = &self.resources.get().unwrap();
+        paths.to_text()
+    }
+    /// Replaces the camera bookmarks and keyframes with ones read from text
+    pub fn load_camera_paths(&mut self, text: &str) -> Result<(), CameraPathError> {
+        let paths: &mut CameraPaths = &mut self.resources.get_mut().unwrap();
+        paths.load(text)
+    }
+    /// Plays the camera keyframes from the start on the simulation clock
+    pub fn play_camera_path(&mut self) {
+        let paths: &mut CameraPaths = &mut self.resources.get_mut().unwrap();
+        paths.play();
+    }
     pub fn run_frame(&mut self, events: Vec<Event>) {
+        let events = match self.replay.as_mut().map(Replay::next_frame) {
+            Some(Some(recorded)) => recorded,
+            Some(None) => {
+                info!("replay finished");
+                self.replay = None;
+                events
+            }
+            None => events,
+        };
+        if let Some(recording) = self.recording.as_mut() {
+            recording.push(&events);
+        }
+        // clicks and keys used by the gui on the last frame are not passed on
+        let (gui_pointer, gui_keyboard) = {
+            let adaptor: &EguiRawInputAdaptor = &self.resources.get().unwrap();
+            (adaptor.wants_pointer(), adaptor.wants_keyboard())
+        };
         {
+            let input: &mut InputMap = &mut self.resources.get_mut().unwrap();
+            input.update(&events);
             let camera: &mut DeltaCamera = &mut self.resources.get_mut().unwrap();
             for e in events.iter() {
                 match e {
-                    Event::MouseMove {
-                        delta_x,
-                        delta_y,
-                        delta_time_ms,
-                        buttons_pressed,
-                        ..
-                    } => {
-                        if buttons_pressed.contains(&MouseButton::RightClick) {
-                            camera.rotate_phi(delta_x * 0.0001 * delta_time_ms);
-                            camera.rotate_theta(delta_y * 0.0001 * delta_time_ms);
-                        }
-                    }
                     Event::ScreenSizeChange { new_size } => {
                         let shader: &mut ShaderBind = &mut self.resources.get_mut().unwrap();
                         shader.bind("screen");
@@ -202,67 +312,322 @@ impl Game {
                             texture: world_framebuffer_texture,
                         };
                     }
-                    Event::CameraMove { direction } => {
-                        camera.translate(&(0.1 * direction * camera.get_radius()))
+                    Event::Screenshot => self.screenshot_requested = true,
+                    _ => (),
+                }
+            }
+            let simulation: &mut SimulationControl = &mut self.resources.get_mut().unwrap();
+            let sculptor: &mut Sculptor = &mut self.resources.get_mut().unwrap();
+            let minimap: &mut Minimap = &mut self.resources.get_mut().unwrap();
+            // dragging on the minimap moves the camera instead of rotating it
+            let on_minimap = !gui_pointer
+                && minimap.handle_events(
+                    &events,
+                    camera,
+                    <&Terrain>::query().iter(&self.world).next(),
+                );
+            for action in input.actions() {
+                match action {
+                    ActionEvent::Motion {
+                        action: Action::Orbit,
+                        delta,
+                        delta_time_ms,
+                    } if !on_minimap && !gui_pointer => {
+                        camera.rotate_phi(delta.x * 0.0001 * delta_time_ms);
+                        camera.rotate_theta(delta.y * 0.0001 * delta_time_ms);
                     }
-                    Event::Scroll {
-                        delta_y,
+                    ActionEvent::Motion {
+                        action: Action::Pan,
+                        delta,
+                        ..
+                    } if !on_minimap && !gui_pointer => camera.translate(
+                        &(0.001 * Vector3::new(-delta.x, 0.0, -delta.y) * camera.get_radius()),
+                    ),
+                    ActionEvent::Motion {
+                        action: Action::Zoom,
+                        delta,
                         delta_time_ms,
-                    } => {
-                        camera.update_radius(0.000001 * delta_y * delta_time_ms);
+                    } if !gui_pointer => {
+                        camera.update_radius(0.000001 * delta.y * delta_time_ms);
                         debug!("zoomed");
                     }
+                    ActionEvent::Pressed(pressed) if !gui_keyboard => {
+                        let direction = match pressed {
+                            Action::PanForward => Some(Vector3::new(0.0, 0.0, -1.0)),
+                            Action::PanLeft => Some(Vector3::new(-1.0, 0.0, 0.0)),
+                            Action::PanBack => Some(Vector3::new(0.0, 0.0, 1.0)),
+                            Action::PanRight => Some(Vector3::new(1.0, 0.0, 0.0)),
+                            _ => None,
+                        };
+                        if let Some(direction) = direction {
+                            camera.translate(&(0.1 * direction * camera.get_radius()))
+                        }
+                        match pressed {
+                            Action::ZoomIn => camera.update_radius(-0.1),
+                            Action::ZoomOut => camera.update_radius(0.1),
+                            Action::Pause => simulation.toggle_pause(),
+                            Action::Step => simulation.step(),
+                            Action::Screenshot => self.screenshot_requested = true,
+                            Action::NextCameraMode => camera.set_mode(camera.mode().next()),
+                            Action::SelectTool(tool) => sculptor.tool = *tool,
+                            _ => (),
+                        }
+                    }
                     _ => (),
                 }
             }
-            camera.apply(&self.world);
-
-            //binding to world framebuffer and rendering to it
-
-            let gl: &mut RenderingContext = &mut self.resources.get_mut().unwrap();
-            gl.bind_framebuffer(&self.world_framebuffer);
-            gl.clear_screen(Vector4::new(1.0, 1.0, 1.0, 1.0));
-
-            let shader: &mut ShaderBind = &mut self.resources.get_mut().unwrap();
-            shader.bind("world");
-            gl.bind_shader(shader.get_bind()).ok().unwrap();
+            let camera_paths: &mut CameraPaths = &mut self.resources.get_mut().unwrap();
+            camera_paths.update(camera);
+            let delta_time = Event::frame_time(&events).unwrap_or(DeltaCamera::FRAME_TIME);
+            camera.apply(&self.world, delta_time);
         }
+        //finding the terrain under the mouse and sculpting it before the water moves
         {
-            let asset_manager: &mut AssetManager<RuntimeModel> =
-                &mut self.resources.get_mut().unwrap();
-            let shader: &mut ShaderBind = &mut self.resources.get_mut().unwrap();
+            let camera: &DeltaCamera = &self.resources.get().unwrap();
+            let settings: &GraphicsSettings = &self.resources.get().unwrap();
+            let pick: &mut CursorPick = &mut self.resources.get_mut().unwrap();
+            pick.update(
+                &events,
+                camera,
+                settings.screen_size,
+                <&Terrain>::query().iter(&self.world).next(),
+            );
+            let input: &InputMap = &self.resources.get().unwrap();
+            let sculptor: &mut Sculptor = &mut self.resources.get_mut().unwrap();
+            sculptor.update(
+                input.held(Action::Sculpt) && !gui_pointer,
+                pick.hit(),
+                <&mut Terrain>::query().iter_mut(&mut self.world).next(),
+            );
+            let transect: &mut Transect = &mut self.resources.get_mut().unwrap();
+            transect.update(input.held(Action::Transect) && !gui_pointer, pick.hit());
+        }
+        {
+            let camera: &DeltaCamera = &self.resources.get().unwrap();
             let graphics: &mut RenderingContext = &mut self.resources.get_mut().unwrap();
-
-            for terrain in <&mut Terrain>::query().iter_mut(&mut self.world) {
-                terrain.water_simulation();
-
-                let model = terrain.model();
-                asset_manager.overwrite(
-                    "game_terrain",
-                    RuntimeModel::new(&model, graphics, shader.get_bind()).expect("created model"),
-                );
+            let shader: &ShaderBind = &self.resources.get().unwrap();
+            let overlay: &mut Overlay = &mut self.resources.get_mut().unwrap();
+            let flow_lines: &FlowLines = &self.resources.get().unwrap();
+            let transect: &Transect = &self.resources.get().unwrap();
+            let simulation: &mut SimulationControl = &mut self.resources.get_mut().unwrap();
+            let steps = simulation.steps_this_frame();
+            let camera_paths: &mut CameraPaths = &mut self.resources.get_mut().unwrap();
+            camera_paths.advance(steps);
+            let history: &mut DiagnosticsHistory = &mut self.resources.get_mut().unwrap();
+            for (terrain, chunks, flow_line_mesh) in
+                <(&mut Terrain, &mut TerrainChunks, &mut RuntimeDebugMesh)>::query()
+                    .iter_mut(&mut self.world)
+            {
+                for step in 1..=steps as u64 {
+                    terrain.water_simulation(&simulation.parameters);
+                    history.push(
+                        simulation.step_count() + step,
+                        terrain.diagnostics(simulation.parameters.gravity),
+                    );
+                }
+                chunks
+                    .update(terrain, camera.get_position(), graphics, shader)
+                    .expect("updated terrain mesh");
+                chunks
+                    .set_overlay(overlay.texture(terrain), graphics, &shader["water"])
+                    .expect("updated overlay");
+                flow_line_mesh
+                    .set_mesh(
+                        flow_lines.mesh(terrain, &transect.line_points(terrain)),
+                        graphics,
+                        &shader["world"],
+                    )
+                    .expect("updated flow lines");
+            }
+            let (mut tracers, rest) = self.world.split::<&mut Tracer>();
+            if let Some(terrain) = <&Terrain>::query().iter(&rest).next() {
+                for tracer in <&mut Tracer>::query().iter_mut(&mut tracers) {
+                    for _ in 0..steps {
+                        tracer.advect(terrain, simulation.parameters.step_time());
+                    }
+                }
             }
+            simulation.finish_steps(steps);
         }
         info!("handled sceen resize");
         {
-            let library: &terrain::TerrainLibrary = &self.resources.get().unwrap();
+            let library: &mut terrain::TerrainLibrary = &mut self.resources.get_mut().unwrap();
+            let simulation: &mut SimulationControl = &mut self.resources.get_mut().unwrap();
             library.draw_gui(
                 &mut self.world,
                 &mut self.resources.get_mut().unwrap(),
-                &mut self.resources.get_mut().unwrap(),
+                simulation,
                 &mut self.resources.get_mut().unwrap(),
                 &mut self.resources.get_mut().unwrap(),
                 &self.resources.get().unwrap(),
             );
+            simulation.draw_gui(
+                &mut self.resources.get_mut().unwrap(),
+                <&Terrain>::query().iter(&self.world).next(),
+            );
+            if simulation.take_reset_request() {
+                library.reset(
+                    &mut self.world,
+                    &mut self.resources.get_mut().unwrap(),
+                    simulation,
+                    &mut self.resources.get_mut().unwrap(),
+                    &self.resources.get().unwrap(),
+                );
+            }
+            let overlay: &mut Overlay = &mut self.resources.get_mut().unwrap();
+            overlay.draw_gui(&mut self.resources.get_mut().unwrap());
+            let flow_lines: &mut FlowLines = &mut self.resources.get_mut().unwrap();
+            flow_lines.draw_gui(&mut self.resources.get_mut().unwrap());
+            let time_of_day: &mut TimeOfDay = &mut self.resources.get_mut().unwrap();
+            time_of_day.draw_gui(&mut self.resources.get_mut().unwrap());
+            let history: &mut DiagnosticsHistory = &mut self.resources.get_mut().unwrap();
+            history.draw_gui(&mut self.resources.get_mut().unwrap());
+            let camera: &mut DeltaCamera = &mut self.resources.get_mut().unwrap();
+            camera.draw_gui(&mut self.resources.get_mut().unwrap(), &mut self.world);
+            let camera_paths: &mut CameraPaths = &mut self.resources.get_mut().unwrap();
+            camera_paths.draw_gui(&mut self.resources.get_mut().unwrap(), camera);
+            let transect: &mut Transect = &mut self.resources.get_mut().unwrap();
+            transect.draw_gui(
+                &mut self.resources.get_mut().unwrap(),
+                <&Terrain>::query().iter(&self.world).next(),
+            );
+            let sculptor: &mut Sculptor = &mut self.resources.get_mut().unwrap();
+            sculptor.draw_gui(
+                &mut self.resources.get_mut().unwrap(),
+                <&mut Terrain>::query().iter_mut(&mut self.world).next(),
+            );
+            let minimap: &mut Minimap = &mut self.resources.get_mut().unwrap();
+            minimap.draw_gui(&mut self.resources.get_mut().unwrap());
+        }
+        //drawing the minimap after the gui may have loaded a new scenario
+        {
+            let minimap: &mut Minimap = &mut self.resources.get_mut().unwrap();
+            let camera: &DeltaCamera = &self.resources.get().unwrap();
+            let settings: &GraphicsSettings = &self.resources.get().unwrap();
+            let graphics: &mut RenderingContext = &mut self.resources.get_mut().unwrap();
+            let shader: &ShaderBind = &self.resources.get().unwrap();
+            minimap
+                .update(
+                    &mut self.world,
+                    camera,
+                    settings.screen_size,
+                    graphics,
+                    &shader["screen"],
+                )
+                .expect("updated minimap");
+        }
+        //moving the sun
+        {
+            let gl: &mut RenderingContext = &mut self.resources.get_mut().unwrap();
+            let shader: &mut ShaderBind = &mut self.resources.get_mut().unwrap();
+            let shadow_map: &mut ShadowMap = &mut self.resources.get_mut().unwrap();
+            let time_of_day: &mut TimeOfDay = &mut self.resources.get_mut().unwrap();
+            time_of_day.advance();
+            shadow_map.sun_direction = time_of_day.light_direction();
+            for name in ["world", "water"].iter() {
+                shader.bind(name);
+                gl.bind_shader(shader.get_bind()).ok().unwrap();
+                gl.send_vec3_uniform(
+                    shader.get_bind(),
+                    "sun_direction",
+                    time_of_day.light_direction(),
+                )
+                .expect("failed to send sun direction");
+                gl.send_vec4_uniform(shader.get_bind(), "sun_color", time_of_day.light_color())
+                    .expect("failed to send sun color");
+            }
+        }
+        //rendering the world from the sun into the shadow map
+        {
+            let gl: &mut RenderingContext = &mut self.resources.get_mut().unwrap();
+            let shader: &mut ShaderBind = &mut self.resources.get_mut().unwrap();
+            let shadow_map: &mut ShadowMap = &mut self.resources.get_mut().unwrap();
+            if let Some(terrain) = <&Terrain>::query().iter(&self.world).next() {
+                let (min, max) = terrain.bounds();
+                shadow_map.fit(min, max);
+            }
+            shader.bind("shadow");
+            gl.bind_shader(shader.get_bind()).ok().unwrap();
+            shadow_map.begin(gl);
+        }
+        let mut schedule = Schedule::builder()
+            .add_system(graphics_system::render_object_shadow_system())
+            .add_system(graphics_system::render_terrain_shadow_system())
+            .build();
+        schedule.execute(&mut self.world, &mut self.resources);
+        {
+            let gl: &mut RenderingContext = &mut self.resources.get_mut().unwrap();
+            let shader: &mut ShaderBind = &mut self.resources.get_mut().unwrap();
+            shader.bind("water_shadow");
+            gl.bind_shader(shader.get_bind()).ok().unwrap();
+        }
+        // all of the relief of a flat bed is in the water surface, so it casts shadows too
+        let mut schedule = Schedule::builder()
+            .add_system(graphics_system::render_water_shadow_system())
+            .build();
+        schedule.execute(&mut self.world, &mut self.resources);
+        {
+            //binding to world framebuffer and rendering to it
+
+            let gl: &mut RenderingContext = &mut self.resources.get_mut().unwrap();
+            gl.bind_framebuffer(&self.world_framebuffer);
+            gl.clear_screen(Vector4::new(0.0, 0.0, 0.0, 1.0));
+
+            //drawing the sky behind everything
+            let shader: &mut ShaderBind = &mut self.resources.get_mut().unwrap();
+            let camera: &DeltaCamera = &self.resources.get().unwrap();
+            let settings: &GraphicsSettings = &self.resources.get().unwrap();
+            let time_of_day: &TimeOfDay = &self.resources.get().unwrap();
+            shader.bind("screen");
+            gl.bind_shader(shader.get_bind()).ok().unwrap();
+            // the gradient is recomputed every frame but only uploaded once the time of
+            // day or the camera has changed it by at least one step of a color byte
+            let sky_texture = time_of_day.sky_texture(camera.get_matrix(settings.screen_size));
+            if sky_texture.pixels != self.sky_pixels {
+                self.sky_pixels = sky_texture.pixels.clone();
+                gl.update_texture(&mut self.sky.texture, sky_texture)
+                    .expect("failed to update sky texture");
+            }
+            gl.send_view_matrix(Matrix4::identity(), shader.get_bind());
+            gl.send_model_matrix(Matrix4::identity(), shader.get_bind());
+            gl.bind_texture(&self.sky.texture, shader.get_bind());
+            gl.draw_mesh(&self.sky.mesh);
+            gl.clear_depth();
+
+            shader.bind("world");
+            gl.bind_shader(shader.get_bind()).ok().unwrap();
+            let shadow_map: &ShadowMap = &self.resources.get().unwrap();
+            shadow_map
+                .bind(gl, shader.get_bind())
+                .expect("failed to bind shadow map");
         }
         //game logic
         //rendering susten
         let mut schedule = Schedule::builder()
             .add_system(graphics_system::render_object_system())
+            .add_system(graphics_system::render_terrain_system())
             .build();
         schedule.execute(&mut self.world, &mut self.resources);
         {
             let gl: &mut RenderingContext = &mut self.resources.get_mut().unwrap();
+            let shader: &mut ShaderBind = &mut self.resources.get_mut().unwrap();
+            shader.bind("water");
+            gl.bind_shader(shader.get_bind()).ok().unwrap();
+            let shadow_map: &ShadowMap = &self.resources.get().unwrap();
+            shadow_map
+                .bind(gl, shader.get_bind())
+                .expect("failed to bind shadow map");
+        }
+        // water is translucent so it is drawn after everything under it
+        let mut schedule = Schedule::builder()
+            .add_system(graphics_system::render_water_system())
+            .build();
+        schedule.execute(&mut self.world, &mut self.resources);
+        {
+            let gl: &mut RenderingContext = &mut self.resources.get_mut().unwrap();
+            let shader: &mut ShaderBind = &mut self.resources.get_mut().unwrap();
+            shader.bind("world");
+            gl.bind_shader(shader.get_bind()).ok().unwrap();
             gl.clear_depth();
         }
         let mut schedule = Schedule::builder()
@@ -312,6 +677,40 @@ impl Game {
 
         gui_schedule.execute(&mut self.world, &mut self.resources);
         info!("rendered gui");
+        {
+            let gl: &mut RenderingContext = &mut self.resources.get_mut().unwrap();
+            gl.finish_frame();
+        }
+        if self.screenshot_requested {
+            self.screenshot_requested = false;
+            self.save_screenshot();
+        }
+    }
+    /// Reads back the world view rendered during the last frame. Does not include the gui
+    pub fn screenshot(&mut self) -> RGBATexture {
+        let gl: &mut RenderingContext = &mut self.resources.get_mut().unwrap();
+        gl.read_texture(&self.world_render_surface.texture)
+            .expect("failed to read world framebuffer")
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    fn save_screenshot(&mut self) {
+        let time = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|t| t.as_millis())
+            .unwrap_or(0);
+        let path =
+            std::path::Path::new(SCREENSHOT_DIRECTORY).join(format!("screenshot_{}.png", time));
+        let result = std::fs::create_dir_all(SCREENSHOT_DIRECTORY)
+            .map_err(image::ImageError::IoError)
+            .and_then(|_| self.screenshot().save_png(&path));
+        match result {
+            Ok(()) => info!("saved screenshot to {}", path.display()),
+            Err(e) => error!("failed to save screenshot: {}", e),
+        }
+    }
+    #[cfg(target_arch = "wasm32")]
+    fn save_screenshot(&mut self) {
+        error!("screenshots can only be saved when running natively");
     }
 }
 #[cfg(target_arch = "wasm32")]
@@ -329,9 +728,19 @@ impl WebGame {
     pub fn render_frame(&mut self, events: JsArray) {
         let events: Vec<Event> = events
             .iter()
-            .map(|v| Event::from_map(v.into()))
-            .filter(|v| v.is_some())
-            .map(|v| v.unwrap())
+            .filter_map(|v| {
+                match v
+                    .dyn_into::<js_sys::Map>()
+                    .map_err(|_| events::EventDecodeError::NotAMap)
+                    .and_then(Event::from_map)
+                {
+                    Ok(event) => Some(event),
+                    Err(e) => {
+                        log::warn!("skipping event: {:?}", e);
+                        None
+                    }
+                }
+            })
             .collect();
         self.game.run_frame(events);
 
@@ -340,6 +749,48 @@ impl WebGame {
         //        .ok()
         //        .unwrap();
     }
+    /// Replaces the input bindings with the contents of a bindings file
+    #[wasm_bindgen]
+    pub fn load_bindings(&mut self, config: String) -> Result<(), JsValue> {
+        self.game
+            .load_bindings(&config)
+            .map_err(|e| JsValue::from_str(&format!("{:?}", e)))
+    }
+    #[wasm_bindgen]
+    pub fn start_recording(&mut self) {
+        self.game.start_recording();
+    }
+    /// Recording in text form, empty when nothing was recorded
+    #[wasm_bindgen]
+    pub fn stop_recording(&mut self) -> String {
+        self.game
+            .stop_recording()
+            .map(|recording| recording.to_text())
+            .unwrap_or_default()
+    }
+    /// Camera bookmarks and keyframes in the format of a camera paths file
+    #[wasm_bindgen]
+    pub fn camera_paths(&self) -> String {
+        self.game.camera_paths()
+    }
+    #[wasm_bindgen]
+    pub fn load_camera_paths(&mut self, text: String) -> Result<(), JsValue> {
+        self.game
+            .load_camera_paths(&text)
+            .map_err(|e| JsValue::from_str(&format!("{:?}", e)))
+    }
+    #[wasm_bindgen]
+    pub fn play_camera_path(&mut self) {
+        self.game.play_camera_path();
+    }
+    /// Plays back a recording returned by `stop_recording`
+    #[wasm_bindgen]
+    pub fn replay(&mut self, recording: String) -> Result<(), JsValue> {
+        let recording =
+            Recording::from_text(&recording).map_err(|e| JsValue::from_str(&format!("{:?}", e)))?;
+        self.game.replay(recording);
+        Ok(())
+    }
 }
 // When the `wee_alloc` feature is enabled, use `wee_alloc` as the global
 // allocator.
@@ -380,3 +831,61 @@ pub fn init_game(resolution: js_sys::Map) -> WebGame {
         panic!()
     }
 }
+#[cfg(all(test, feature = "software"))]
+mod test {
+    use super::*;
+    use prelude::{Modifiers, MouseButton};
+    #[test]
+    fn run_headless_frames() {
+        let screen_size = Vector2::new(64, 64);
+        let mut game = Game::new(screen_size, screen_size).expect("created game");
+        for _ in 0..3 {
+            game.run_frame(vec![]);
+        }
+        game.run_frame(vec![Event::ScreenSizeChange {
+            new_size: Vector2::new(32, 48),
+        }]);
+        let screenshot = game.screenshot();
+        assert_eq!(screenshot.dimensions, Vector2::new(32, 48));
+        assert_eq!(screenshot.pixels.len(), 32 * 48);
+    }
+    #[test]
+    fn replay_reproduces_session() {
+        let screen_size = Vector2::new(32, 32);
+        let frames = vec![
+            vec![Event::MouseDown {
+                x: 16.0,
+                y: 16.0,
+                button: MouseButton::LeftClick,
+                modifiers: Modifiers::default(),
+            }],
+            vec![Event::MouseMove {
+                delta_x: 5.0,
+                delta_y: -2.0,
+                x: 21.0,
+                y: 14.0,
+                delta_time_ms: 16.0,
+                buttons_pressed: vec![MouseButton::LeftClick],
+                modifiers: Modifiers::default(),
+            }],
+            vec![Event::Scroll {
+                delta_y: 50.0,
+                delta_time_ms: 16.0,
+                modifiers: Modifiers::default(),
+            }],
+        ];
+        let mut game = Game::new(screen_size, screen_size).expect("created game");
+        game.start_recording();
+        for events in frames.into_iter() {
+            game.run_frame(events);
+        }
+        let recording = game.stop_recording().expect("recording was started");
+        let recording = Recording::from_text(&recording.to_text()).expect("recording is valid");
+        let mut replayed = Game::new(screen_size, screen_size).expect("created game");
+        replayed.replay(recording);
+        for _ in 0..3 {
+            replayed.run_frame(vec![]);
+        }
+        assert_eq!(replayed.screenshot().pixels, game.screenshot().pixels);
+    }
+}
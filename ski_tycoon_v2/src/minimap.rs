@@ -0,0 +1,423 @@
+use super::prelude::{
+    CameraMode, Colormap, DeltaCamera, ErrorType, Event, GuiModel, GuiRuntimeModel, GuiTransform,
+    MouseButton, RenderingContext, ScalarField, Shader, Terrain, Texture, Transform,
+};
+use egui::CtxRef;
+use legion::*;
+use nalgebra::{Vector2, Vector3, Vector4};
+/// Longer side of the map on the screen in pixels
+const MAP_SIZE: f32 = 200.0;
+/// Gap between the map and the bottom right corner of the screen in pixels
+const MARGIN: f32 = 10.0;
+/// The map texture has at least this many pixels along its longer side so the
+/// frustum lines stay thin on small grids
+const MIN_RESOLUTION: usize = 128;
+const FRUSTUM_COLOR: [u8; 4] = [255, 255, 255, 255];
+const EYE_COLOR: [u8; 4] = [250, 60, 40, 255];
+/// Quantity shown on the map
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MinimapField {
+    /// height of the water surface, the bed where it is dry
+    Height,
+    Depth,
+}
+impl MinimapField {
+    pub const ALL: [Self; 2] = [Self::Height, Self::Depth];
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Height => "Height",
+            Self::Depth => "Water Depth",
+        }
+    }
+    /// Value at every grid point, laid out like the heights of the terrain
+    fn compute(&self, terrain: &Terrain) -> Vec<f32> {
+        match self {
+            Self::Height => terrain.surface_heights(),
+            Self::Depth => ScalarField::Depth.compute(terrain),
+        }
+    }
+    fn colormap(&self) -> Colormap {
+        match self {
+            Self::Height => Colormap::Viridis,
+            Self::Depth => Colormap::Turbo,
+        }
+    }
+}
+/// Top down map of the whole terrain in the bottom right corner of the screen,
+/// stored as a resource. The map is a gui quad with a texture drawn every frame
+/// and shows the ground the camera sees. Clicking or dragging on it moves the
+/// camera there
+pub struct Minimap {
+    pub visible: bool,
+    pub field: MinimapField,
+    /// the quad, inserted again when loading a scenario clears the world
+    entity: Option<Entity>,
+    /// corners of the map on the screen in pixels from the top left corner
+    rect: Option<(Vector2<f32>, Vector2<f32>)>,
+    /// the left mouse button was pressed over the map and is still held
+    dragging: bool,
+}
+impl Default for Minimap {
+    fn default() -> Self {
+        Self {
+            visible: true,
+            field: MinimapField::Height,
+            entity: None,
+            rect: None,
+            dragging: false,
+        }
+    }
+}
+impl Minimap {
+    /// Moves the camera to the point under the cursor while the left mouse button is
+    /// held after being pressed over the map. Returns whether the mouse is dragging
+    /// on the map, the camera should not also be rotated by it
+    pub fn handle_events(
+        &mut self,
+        events: &[Event],
+        camera: &mut DeltaCamera,
+        terrain: Option<&Terrain>,
+    ) -> bool {
+        let (rect, terrain) = match (self.rect, terrain, self.visible) {
+            (Some(rect), Some(terrain), true) => (rect, terrain),
+            _ => {
+                self.dragging = false;
+                return false;
+            }
+        };
+        for event in events.iter() {
+            let cursor = match event {
+                Event::MouseDown {
+                    x,
+                    y,
+                    button: MouseButton::LeftClick,
+                    ..
+                } => {
+                    let cursor = Vector2::new(*x, *y);
+                    self.dragging = map_position(rect, cursor).is_some();
+                    Some(cursor)
+                }
+                Event::MouseMove {
+                    x,
+                    y,
+                    buttons_pressed,
+                    ..
+                } => {
+                    self.dragging &= buttons_pressed.contains(&MouseButton::LeftClick);
+                    Some(Vector2::new(*x, *y))
+                }
+                Event::MouseUp { .. } => {
+                    self.dragging = false;
+                    None
+                }
+                _ => None,
+            };
+            if let (Some(cursor), true) = (cursor, self.dragging) {
+                let clamped = Vector2::new(
+                    cursor.x.clamp(rect.0.x, rect.1.x),
+                    cursor.y.clamp(rect.0.y, rect.1.y),
+                );
+                if let Some(point) = map_position(rect, clamped) {
+                    jump(camera, to_grid(terrain, point));
+                }
+            }
+        }
+        self.dragging
+    }
+    /// Draws the map for this frame, inserting the quad if it is missing and removing
+    /// it when the map is hidden
+    pub fn update(
+        &mut self,
+        world: &mut World,
+        camera: &DeltaCamera,
+        screen_size: Vector2<u32>,
+        graphics: &mut RenderingContext,
+        bound_shader: &Shader,
+    ) -> Result<(), ErrorType> {
+        let image = match <&Terrain>::query().iter(&*world).next() {
+            Some(terrain) if self.visible => {
+                let mut image = MapImage::new(terrain, self.field);
+                image.draw_view(terrain, camera, screen_size);
+                Some((image, terrain.dimensions()))
+            }
+            _ => None,
+        };
+        let (image, dimensions) = match image {
+            Some(image) => image,
+            None => {
+                self.rect = None;
+                if let Some(entity) = self.entity.take() {
+                    if let Some(mut entry) = world.entry(entity) {
+                        if let Ok(quad) = entry.get_component_mut::<GuiRuntimeModel>() {
+                            graphics.delete_mesh(&mut quad.model.mesh)?;
+                            graphics.delete_texture(&mut quad.model.texture);
+                        }
+                    }
+                    world.remove(entity);
+                }
+                return Ok(());
+            }
+        };
+        let rect = screen_rect(dimensions, screen_size);
+        self.rect = Some(rect);
+        let transform = quad_transform(rect, screen_size);
+        let texture = image.into_texture();
+        let quad = self.entity.and_then(|entity| world.entry(entity));
+        match quad {
+            Some(mut entry) => {
+                if let Ok(gui_transform) = entry.get_component_mut::<GuiTransform>() {
+                    gui_transform.transform = transform;
+                }
+                if let Ok(quad) = entry.get_component_mut::<GuiRuntimeModel>() {
+                    quad.set_texture(texture, graphics)?;
+                }
+            }
+            None => {
+                self.entity = Some(GuiModel::textured_quad(texture, transform).insert(
+                    world,
+                    graphics,
+                    bound_shader,
+                )?);
+            }
+        }
+        Ok(())
+    }
+    pub fn draw_gui(&mut self, context: &mut CtxRef) {
+        egui::Window::new("Minimap").show(context, |ui| {
+            ui.checkbox(&mut self.visible, "Show");
+            for field in MinimapField::ALL.iter() {
+                ui.radio_value(&mut self.field, *field, field.name());
+            }
+            ui.label("Click or drag on the map to move the camera");
+        });
+    }
+}
+/// Moves the camera over a point in grid coordinates, keeping its height while flying
+fn jump(camera: &mut DeltaCamera, point: Vector2<f32>) {
+    if let CameraMode::Follow(_) = camera.mode() {
+        camera.set_mode(CameraMode::Orbit);
+    }
+    let height = camera.pose().origin.y;
+    camera.set_translation(Vector3::new(point.x, height, point.y));
+}
+/// Corners of the map on the screen, keeping the aspect ratio of the grid
+fn screen_rect(
+    dimensions: Vector2<usize>,
+    screen_size: Vector2<u32>,
+) -> (Vector2<f32>, Vector2<f32>) {
+    let longest = dimensions.x.max(dimensions.y) as f32;
+    let size = Vector2::new(
+        MAP_SIZE * dimensions.x as f32 / longest,
+        MAP_SIZE * dimensions.y as f32 / longest,
+    );
+    let max = Vector2::new(screen_size.x as f32 - MARGIN, screen_size.y as f32 - MARGIN);
+    (max - size, max)
+}
+/// Places the plane from -1 to 1 over `rect` in screen space
+fn quad_transform(rect: (Vector2<f32>, Vector2<f32>), screen_size: Vector2<u32>) -> Transform {
+    let screen = Vector2::new(screen_size.x as f32, screen_size.y as f32);
+    let size = rect.1 - rect.0;
+    let center = (rect.0 + rect.1) / 2.0;
+    let mut transform = Transform::default();
+    transform.set_scale(Vector3::new(size.x / screen.x, size.y / screen.y, 1.0));
+    // in front of the world view, which is drawn at the same depth as an unmoved plane
+    transform.set_translation(Vector3::new(
+        2.0 * center.x / screen.x - 1.0,
+        1.0 - 2.0 * center.y / screen.y,
+        -0.2,
+    ));
+    transform
+}
+/// Position of a point on the screen across the map, from 0 at the top left corner
+/// to 1 at the bottom right. `None` outside the map
+fn map_position(rect: (Vector2<f32>, Vector2<f32>), cursor: Vector2<f32>) -> Option<Vector2<f32>> {
+    let size = rect.1 - rect.0;
+    let position = (cursor - rect.0).component_div(&size);
+    if (0.0..=1.0).contains(&position.x) && (0.0..=1.0).contains(&position.y) {
+        Some(position)
+    } else {
+        None
+    }
+}
+/// Grid coordinates of a position across the map, the top of the map is y = 0
+fn to_grid(terrain: &Terrain, position: Vector2<f32>) -> Vector2<f32> {
+    Vector2::new(
+        position.x * (terrain.dimensions().x - 1) as f32,
+        position.y * (terrain.dimensions().y - 1) as f32,
+    )
+}
+/// Pixels of the map, rows from the top of the map
+struct MapImage {
+    dimensions: Vector2<usize>,
+    /// pixels per grid point along each axis
+    scale: usize,
+    pixels: Vec<Vector4<u8>>,
+}
+impl MapImage {
+    /// The field colored over its range, each grid point covers `scale` by `scale`
+    /// pixels
+    fn new(terrain: &Terrain, field: MinimapField) -> Self {
+        let grid = terrain.dimensions();
+        let scale = ((MIN_RESOLUTION + grid.x.max(grid.y) - 1) / grid.x.max(grid.y)).max(1);
+        let dimensions = grid * scale;
+        let values = field.compute(terrain);
+        let (min, max) = values
+            .iter()
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), value| {
+                (min.min(*value), max.max(*value))
+            });
+        let range = (max - min).max(f32::EPSILON);
+        let colormap = field.colormap();
+        let mut pixels = Vec::with_capacity(dimensions.x * dimensions.y);
+        for row in 0..dimensions.y {
+            for column in 0..dimensions.x {
+                let value = values[(column / scale) * grid.y + row / scale];
+                pixels.push(colormap.color((value - min) / range));
+            }
+        }
+        Self {
+            dimensions,
+            scale,
+            pixels,
+        }
+    }
+    /// Pixel coordinates of a point in grid coordinates
+    fn to_pixel(&self, point: Vector2<f32>) -> Vector2<f32> {
+        (point + Vector2::new(0.5, 0.5)) * self.scale as f32
+    }
+    fn set(&mut self, pixel: Vector2<f32>, color: Vector4<u8>) {
+        if pixel.x >= 0.0
+            && pixel.y >= 0.0
+            && pixel.x < self.dimensions.x as f32
+            && pixel.y < self.dimensions.y as f32
+        {
+            self.pixels[pixel.y as usize * self.dimensions.x + pixel.x as usize] = color;
+        }
+    }
+    /// Line between two points in grid coordinates, the parts outside the map are
+    /// skipped
+    fn draw_line(&mut self, start: Vector2<f32>, end: Vector2<f32>, color: Vector4<u8>) {
+        let (start, end) = (self.to_pixel(start), self.to_pixel(end));
+        // two samples per pixel so rounding never leaves a gap
+        let steps = 2 * (end - start).abs().max().ceil().max(1.0) as usize;
+        for i in 0..=steps {
+            self.set(start + (end - start) * (i as f32 / steps as f32), color);
+        }
+    }
+    /// Outline of the ground the camera sees and a dot at the eye
+    fn draw_view(&mut self, terrain: &Terrain, camera: &DeltaCamera, screen_size: Vector2<u32>) {
+        let corners = view_footprint(terrain, camera, screen_size);
+        for i in 0..corners.len() {
+            self.draw_line(
+                corners[i],
+                corners[(i + 1) % corners.len()],
+                Vector4::from(FRUSTUM_COLOR),
+            );
+        }
+        let eye = camera.get_position();
+        let eye = self.to_pixel(Vector2::new(eye.x, eye.z));
+        for x in -1..=1 {
+            for y in -1..=1 {
+                self.set(
+                    eye + Vector2::new(x as f32, y as f32),
+                    Vector4::from(EYE_COLOR),
+                );
+            }
+        }
+    }
+    /// Texture with the top of the map at the top of the quad
+    fn into_texture(self) -> Texture {
+        let width = self.dimensions.x;
+        let mut pixels = Vec::with_capacity(self.pixels.len());
+        for row in self.pixels.chunks(width).rev() {
+            pixels.extend_from_slice(row);
+        }
+        Texture {
+            dimensions: Vector2::new(self.dimensions.x as u32, self.dimensions.y as u32),
+            pixels,
+        }
+    }
+}
+/// Points in grid coordinates where the rays through the corners of the screen meet
+/// a level plane halfway up the terrain. Rays above the horizon are cut off past the
+/// edge of the grid
+fn view_footprint(
+    terrain: &Terrain,
+    camera: &DeltaCamera,
+    screen_size: Vector2<u32>,
+) -> [Vector2<f32>; 4] {
+    let (min, max) = terrain.bounds();
+    let plane = (min.y + max.y) / 2.0;
+    let far = 2.0 * (max.x.max(max.z) + 1.0);
+    let screen = Vector2::new(screen_size.x as f32, screen_size.y as f32);
+    let corner = |x: f32, y: f32| {
+        let (origin, direction) = camera.screen_ray(Vector2::new(x, y), screen_size);
+        let flat = Vector2::new(direction.x, direction.z);
+        let distance = if direction.y < -f32::EPSILON {
+            ((plane - origin.y) / direction.y).max(0.0)
+        } else {
+            f32::INFINITY
+        };
+        let along = flat * distance.min(far / flat.norm().max(f32::EPSILON));
+        Vector2::new(origin.x, origin.z) + along
+    };
+    [
+        corner(0.0, 0.0),
+        corner(screen.x, 0.0),
+        corner(screen.x, screen.y),
+        corner(0.0, screen.y),
+    ]
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn map_sits_in_the_corner() {
+        let rect = screen_rect(Vector2::new(50, 25), Vector2::new(800, 600));
+        assert_eq!(
+            rect,
+            (Vector2::new(590.0, 490.0), Vector2::new(790.0, 590.0))
+        );
+        assert_eq!(
+            map_position(rect, Vector2::new(690.0, 490.0)),
+            Some(Vector2::new(0.5, 0.0))
+        );
+        assert_eq!(map_position(rect, Vector2::new(100.0, 500.0)), None);
+        let corner = quad_transform(rect, Vector2::new(800, 600)).build()
+            * Vector4::new(1.0, -1.0, -0.5, 1.0);
+        assert!((corner.x - (2.0 * 790.0 / 800.0 - 1.0)).abs() < 1e-5);
+        assert!((corner.y - (1.0 - 2.0 * 590.0 / 600.0)).abs() < 1e-5);
+    }
+    #[test]
+    fn image_is_flipped_into_texture() {
+        let mut depths = vec![1.0; 64 * 64];
+        depths[0] = 2.0;
+        let terrain = Terrain::from_tiles(depths, Vector2::new(64, 64));
+        let image = MapImage::new(&terrain, MinimapField::Depth);
+        assert_eq!(image.scale, 2);
+        let high = Colormap::Turbo.color(1.0);
+        assert_eq!(image.pixels[0], high);
+        assert_eq!(image.pixels[129], high);
+        assert_ne!(image.pixels[2], high);
+        let texture = image.into_texture();
+        assert_eq!(texture.dimensions, Vector2::new(128, 128));
+        assert_eq!(texture.pixels[127 * 128], high);
+        assert_ne!(texture.pixels[0], high);
+    }
+    #[test]
+    fn lines_stay_inside() {
+        let terrain = Terrain::flat(Vector2::new(8, 4), 1.0);
+        let mut image = MapImage::new(&terrain, MinimapField::Height);
+        let scale = image.scale;
+        image.draw_line(
+            Vector2::new(-100.0, 1.0),
+            Vector2::new(100.0, 1.0),
+            Vector4::from(FRUSTUM_COLOR),
+        );
+        let row = (1.5 * scale as f32) as usize;
+        let width = image.dimensions.x;
+        assert!(image.pixels[row * width..(row + 1) * width]
+            .iter()
+            .all(|pixel| *pixel == Vector4::from(FRUSTUM_COLOR)));
+    }
+}
@@ -1,4 +1,4 @@
-use super::prelude::{ItemDesc, Mesh, Texture, Transform, Vertex};
+use super::prelude::{Indices, ItemDesc, Mesh, Texture, Transform, Vertex};
 use nalgebra::{Vector2, Vector3, Vector4};
 #[derive(Clone)]
 pub struct Model {
@@ -25,87 +25,85 @@ impl Model {
         dimensions: Vector2<usize>,
         transform: Transform,
     ) -> Self {
-        let mut vertices = vec![];
-        for x in 0..dimensions.x - 1 {
-            for y in 0..dimensions.y - 1 {
-                let x0_y0 = Vector3::new(x as f32, heights[x * dimensions.y + y] as f32, y as f32);
-                let x0_y1 = Vector3::new(
-                    x as f32,
-                    heights[x * dimensions.y + y + 1] as f32,
-                    y as f32 + 1.0,
-                );
-                let x1_y0 = Vector3::new(
-                    x as f32 + 1.0,
-                    heights[(x + 1) * dimensions.y + y] as f32,
-                    y as f32,
-                );
-                let x1_y1 = Vector3::new(
-                    x as f32 + 1.0,
-                    heights[(x + 1) * dimensions.y + y + 1] as f32,
-                    y as f32 + 1.0,
-                );
-                let triangle0_normal = (x0_y1 - x0_y0).cross(&(x1_y0 - x0_y0)).normalize();
-                let triangle1_normal = (x1_y0 - x1_y1).cross(&(x0_y1 - x1_y1)).normalize();
-
-                //triangle 0
-                #[rustfmt::skip]
-                vertices.append(
-                    &mut vec![
-                        //position:
-                        x0_y0.x,x0_y0.y,x0_y0.z,
-                        //uv
-                        0.0,0.0,
-                        //normal
-                        triangle0_normal.x,triangle0_normal.y,triangle0_normal.z,
-                        //position:
-                        x0_y1.x,x0_y1.y,x0_y1.z,
-                        //uv
-                        0.0,1.0,
-                        //normal
-                        triangle0_normal.x,triangle0_normal.y,triangle0_normal.z,
-
-                        //position:
-                        x1_y0.x,x1_y0.y,x1_y0.z,
-                        //uv
-                        1.0,0.0,
-                        //normal
-                        triangle0_normal.x,triangle0_normal.y,triangle0_normal.z,
-            
-                //triangle 1
-    
-                        //position:
-                        x0_y1.x,x0_y1.y,x0_y1.z,
-                        //uv
-                        0.0,1.0,
-                        //normal
-                        triangle1_normal.x,triangle1_normal.y,triangle1_normal.z,
-      
-                        //position:
-                        x1_y1.x,x1_y1.y,x1_y1.z,
-                        //uv
-                        1.0,1.0,
-                        //normal
-                        triangle1_normal.x,triangle1_normal.y,triangle1_normal.z,
-         
-                        //position:
-                        x1_y0.x,x1_y0.y,x1_y0.z,
-                        //uv
-                        1.0,0.0,
-                        //normal
-                        triangle1_normal.x,triangle1_normal.y,triangle1_normal.z
-                    ],
-                );
-            }
-        }
         Model {
-            mesh: Mesh {
-                vertices: vertices,
-                description: ItemDesc::default_model(),
-            },
+            mesh: Self::mesh_from_heights(heights, dimensions),
             texture: Texture::constant_color(Vector4::new(32, 65, 147, 255), Vector2::new(2, 2)),
             transform,
         }
     }
+    /// Builds the mesh used by `from_heights`. There is one vertex per height and the
+    /// vertex count only depends on `dimensions`
+    pub fn mesh_from_heights(heights: &[f32], dimensions: Vector2<usize>) -> Mesh {
+        Mesh {
+            vertices: Self::vertices_from_heights(heights, dimensions),
+            description: ItemDesc::default_model(),
+            indices: Some(Self::indices_from_dimensions(dimensions)),
+        }
+    }
+    /// Vertices of `mesh_from_heights`. Vertex `x * dimensions.y + y` is at height
+    /// `heights[x * dimensions.y + y]`, normals are given by `normal_from_heights`
+    pub fn vertices_from_heights(heights: &[f32], dimensions: Vector2<usize>) -> Vec<f32> {
+        let mut vertices = Vec::with_capacity(dimensions.x * dimensions.y * 8);
+        for x in 0..dimensions.x {
+            for y in 0..dimensions.y {
+                let normal = Self::normal_from_heights(heights, dimensions, x, y);
+                #[rustfmt::skip]
+                vertices.extend_from_slice(&[
+                    //position
+                    x as f32, heights[x * dimensions.y + y], y as f32,
+                    //uv
+                    x as f32 / (dimensions.x - 1).max(1) as f32,
+                    y as f32 / (dimensions.y - 1).max(1) as f32,
+                    //normal
+                    normal.x, normal.y, normal.z,
+                ]);
+            }
+        }
+        vertices
+    }
+    /// Smooth normal at grid point (x, y) from central differences of the heights,
+    /// one sided differences are used along the edges of the grid
+    pub fn normal_from_heights(
+        heights: &[f32],
+        dimensions: Vector2<usize>,
+        x: usize,
+        y: usize,
+    ) -> Vector3<f32> {
+        let height = |x: usize, y: usize| heights[x * dimensions.y + y];
+        let x0 = x.saturating_sub(1);
+        let x1 = (x + 1).min(dimensions.x - 1);
+        let y0 = y.saturating_sub(1);
+        let y1 = (y + 1).min(dimensions.y - 1);
+        let dh_dx = if x1 > x0 {
+            (height(x1, y) - height(x0, y)) / (x1 - x0) as f32
+        } else {
+            0.0
+        };
+        let dh_dz = if y1 > y0 {
+            (height(x, y1) - height(x, y0)) / (y1 - y0) as f32
+        } else {
+            0.0
+        };
+        Vector3::new(-dh_dx, 1.0, -dh_dz).normalize()
+    }
+    /// Two triangles for every cell of a grid of vertices laid out like `vertices_from_heights`
+    pub fn indices_from_dimensions(dimensions: Vector2<usize>) -> Indices {
+        let index = |x: usize, y: usize| (x * dimensions.y + y) as u32;
+        let mut indices =
+            Vec::with_capacity(dimensions.x.saturating_sub(1) * dimensions.y.saturating_sub(1) * 6);
+        for x in 0..dimensions.x.saturating_sub(1) {
+            for y in 0..dimensions.y.saturating_sub(1) {
+                #[rustfmt::skip]
+                indices.extend_from_slice(&[
+                    //triangle 0
+                    index(x, y), index(x, y + 1), index(x + 1, y),
+                    //triangle 1
+                    index(x, y + 1), index(x + 1, y + 1), index(x + 1, y),
+                ]);
+            }
+        }
+        Indices::new(indices, dimensions.x * dimensions.y)
+    }
     pub fn cube(transform: Transform) -> Model {
         let vertices = vec![
             Vertex {
@@ -520,6 +518,7 @@ impl Model {
             mesh: Mesh {
                 vertices: vertices.iter().map(|v| v.data.clone()).flatten().collect(),
                 description: ItemDesc::default_model(),
+                indices: None,
             },
             texture: Texture::constant_color(Vector4::new(255, 0, 0, 255), Vector2::new(8, 8)),
             transform,
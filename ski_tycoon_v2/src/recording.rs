@@ -0,0 +1,427 @@
+use super::prelude::{Event, Modifiers, MouseButton};
+use nalgebra::Vector2;
+/// First line of a recording file
+const HEADER: &str = "# ski tycoon input recording";
+#[derive(PartialEq, Debug)]
+pub enum RecordingError {
+    MissingHeader,
+    /// the `frames` line after the header is missing or not a number
+    MissingLength,
+    InvalidEvent {
+        line: usize,
+        text: String,
+    },
+    /// events are not in frame order or come after the end of the recording
+    InvalidFrame {
+        line: usize,
+        frame: u64,
+    },
+}
+/// Events passed to `Game::run_frame`, with the frame they were passed on. Frames
+/// are counted from the start of the recording. A session is only reproduced
+/// exactly when recording starts with the game
+#[derive(Clone, Debug, Default)]
+pub struct Recording {
+    frames: u64,
+    events: Vec<(u64, Event)>,
+}
+impl Recording {
+    /// Starts with the screen size so playback renders at the recorded resolution
+    pub fn new(screen_size: Vector2<u32>) -> Self {
+        Self {
+            frames: 0,
+            events: vec![(
+                0,
+                Event::ScreenSizeChange {
+                    new_size: screen_size,
+                },
+            )],
+        }
+    }
+    /// Adds the events of the next frame
+    pub fn push(&mut self, events: &[Event]) {
+        for event in events.iter() {
+            // clicks are never sent by the frontends
+            if let Event::MouseClick(_) = event {
+                continue;
+            }
+            self.events.push((self.frames, event.clone()));
+        }
+        self.frames += 1;
+    }
+    /// Number of frames recorded, a recording stopped before any frame ran still
+    /// plays the frame with the screen size
+    pub fn frames(&self) -> u64 {
+        let last_event = self.events.last().map_or(0, |(frame, _)| frame + 1);
+        self.frames.max(last_event)
+    }
+    /// Writes the recording as text, one event per line
+    pub fn to_text(&self) -> String {
+        let mut text = format!("{}\nframes {}\n", HEADER, self.frames());
+        for (frame, event) in self.events.iter() {
+            if let Some(event) = encode(event) {
+                text += &format!("{} {}\n", frame, event);
+            }
+        }
+        text
+    }
+    /// Reads text written by `to_text`, lines are counted from 1
+    pub fn from_text(text: &str) -> Result<Self, RecordingError> {
+        let mut lines = text.lines().map(str::trim).enumerate();
+        if lines.next().map(|(_, line)| line) != Some(HEADER) {
+            return Err(RecordingError::MissingHeader);
+        }
+        let frames = lines
+            .next()
+            .and_then(|(_, line)| line.strip_prefix("frames "))
+            .and_then(|frames| frames.trim().parse().ok())
+            .ok_or(RecordingError::MissingLength)?;
+        let mut recording = Self {
+            frames,
+            events: vec![],
+        };
+        for (i, line) in lines {
+            let line_number = i + 1;
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || RecordingError::InvalidEvent {
+                line: line_number,
+                text: line.to_string(),
+            };
+            let mut parts = line.splitn(2, ' ');
+            let frame: u64 = parts
+                .next()
+                .and_then(|frame| frame.parse().ok())
+                .ok_or_else(invalid)?;
+            let event = parts.next().and_then(decode).ok_or_else(invalid)?;
+            let last_frame = recording.events.last().map(|(frame, _)| *frame);
+            if frame >= frames || last_frame.map(|last| frame < last).unwrap_or(false) {
+                return Err(RecordingError::InvalidFrame {
+                    line: line_number,
+                    frame,
+                });
+            }
+            recording.events.push((frame, event));
+        }
+        Ok(recording)
+    }
+}
+/// Plays back a recording one frame at a time
+pub struct Replay {
+    recording: Recording,
+    frame: u64,
+    next_event: usize,
+}
+impl Replay {
+    pub fn new(recording: Recording) -> Self {
+        Self {
+            recording,
+            frame: 0,
+            next_event: 0,
+        }
+    }
+    /// Events of the next frame, `None` once every recorded frame was played
+    pub fn next_frame(&mut self) -> Option<Vec<Event>> {
+        if self.frame >= self.recording.frames() {
+            return None;
+        }
+        let mut events = vec![];
+        while let Some((frame, event)) = self.recording.events.get(self.next_event) {
+            if *frame != self.frame {
+                break;
+            }
+            events.push(event.clone());
+            self.next_event += 1;
+        }
+        self.frame += 1;
+        Some(events)
+    }
+}
+/// Modifier keys as a word, `-` when none are held
+fn encode_modifiers(modifiers: &Modifiers) -> String {
+    let mut word: String = [
+        (modifiers.shift, 's'),
+        (modifiers.ctrl, 'c'),
+        (modifiers.alt, 'a'),
+    ]
+    .iter()
+    .filter(|(held, _)| *held)
+    .map(|(_, letter)| *letter)
+    .collect();
+    if word.is_empty() {
+        word.push('-');
+    }
+    word
+}
+fn decode_modifiers(word: &str) -> Option<Modifiers> {
+    if word == "-" {
+        return Some(Modifiers::default());
+    }
+    let mut modifiers = Modifiers::default();
+    for letter in word.chars() {
+        match letter {
+            's' => modifiers.shift = true,
+            'c' => modifiers.ctrl = true,
+            'a' => modifiers.alt = true,
+            _ => return None,
+        }
+    }
+    Some(modifiers)
+}
+/// Keys are written as hex encoded utf-8 so spaces and other separators survive
+fn encode_key(key: &str) -> String {
+    key.bytes().map(|byte| format!("{:02x}", byte)).collect()
+}
+fn decode_key(word: &str) -> Option<String> {
+    let bytes = word
+        .as_bytes()
+        .chunks(2)
+        .map(|pair| match pair {
+            [high, low] => u8::from_str_radix(std::str::from_utf8(&[*high, *low]).ok()?, 16).ok(),
+            _ => None,
+        })
+        .collect::<Option<Vec<u8>>>()?;
+    String::from_utf8(bytes).ok()
+}
+/// Event as a name followed by its fields, floats are written so they read back
+/// exactly
+fn encode(event: &Event) -> Option<String> {
+    Some(match event {
+        Event::MouseMove {
+            delta_x,
+            delta_y,
+            x,
+            y,
+            delta_time_ms,
+            buttons_pressed,
+            modifiers,
+        } => format!(
+            "mouse_move {} {} {} {} {} {} {}",
+            delta_x,
+            delta_y,
+            x,
+            y,
+            delta_time_ms,
+            MouseButton::to_bitmask(buttons_pressed),
+            encode_modifiers(modifiers)
+        ),
+        Event::ScreenSizeChange { new_size } => {
+            format!("screen_size_change {} {}", new_size.x, new_size.y)
+        }
+        Event::Scroll {
+            delta_y,
+            delta_time_ms,
+            modifiers,
+        } => format!(
+            "scroll {} {} {}",
+            delta_y,
+            delta_time_ms,
+            encode_modifiers(modifiers)
+        ),
+        Event::MouseDown {
+            x,
+            y,
+            button,
+            modifiers,
+        } => format!(
+            "mouse_down {} {} {} {}",
+            x,
+            y,
+            MouseButton::to_bitmask(std::slice::from_ref(button)),
+            encode_modifiers(modifiers)
+        ),
+        Event::MouseUp { x, y, modifiers } => {
+            format!("mouse_up {} {} {}", x, y, encode_modifiers(modifiers))
+        }
+        Event::KeyPress { key, modifiers } => format!(
+            "key_press {} {}",
+            encode_key(key),
+            encode_modifiers(modifiers)
+        ),
+        Event::Screenshot => "screenshot".to_string(),
+        Event::FrameTime { delta_time_ms } => format!("frame_time {}", delta_time_ms),
+        Event::ScaleFactorChange { scale_factor } => {
+            format!("scale_factor_change {}", scale_factor)
+        }
+        Event::MouseClick(_) => return None,
+    })
+}
+fn decode(text: &str) -> Option<Event> {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let (name, fields) = words.split_first()?;
+    let number = |i: usize| -> Option<f32> { fields.get(i)?.parse().ok() };
+    let unsigned = |i: usize| -> Option<u32> { fields.get(i)?.parse().ok() };
+    let modifiers = |i: usize| decode_modifiers(fields.get(i)?);
+    let field_count = match *name {
+        "mouse_move" => 7,
+        "screen_size_change" => 2,
+        "scroll" => 3,
+        "mouse_down" => 4,
+        "mouse_up" => 3,
+        "key_press" => 2,
+        "screenshot" => 0,
+        "frame_time" => 1,
+        "scale_factor_change" => 1,
+        _ => return None,
+    };
+    if fields.len() != field_count {
+        return None;
+    }
+    Some(match *name {
+        "mouse_move" => Event::MouseMove {
+            delta_x: number(0)?,
+            delta_y: number(1)?,
+            x: number(2)?,
+            y: number(3)?,
+            delta_time_ms: number(4)?,
+            buttons_pressed: MouseButton::from_bitmask(unsigned(5)?),
+            modifiers: modifiers(6)?,
+        },
+        "screen_size_change" => Event::ScreenSizeChange {
+            new_size: Vector2::new(unsigned(0)?, unsigned(1)?),
+        },
+        "scroll" => Event::Scroll {
+            delta_y: number(0)?,
+            delta_time_ms: number(1)?,
+            modifiers: modifiers(2)?,
+        },
+        "mouse_down" => Event::MouseDown {
+            x: number(0)?,
+            y: number(1)?,
+            button: MouseButton::from_bitmask(unsigned(2)?).into_iter().next()?,
+            modifiers: modifiers(3)?,
+        },
+        "mouse_up" => Event::MouseUp {
+            x: number(0)?,
+            y: number(1)?,
+            modifiers: modifiers(2)?,
+        },
+        "key_press" => Event::KeyPress {
+            key: decode_key(fields[0])?,
+            modifiers: modifiers(1)?,
+        },
+        "frame_time" => Event::FrameTime {
+            delta_time_ms: number(0)?,
+        },
+        "scale_factor_change" => Event::ScaleFactorChange {
+            scale_factor: number(0)?,
+        },
+        _ => Event::Screenshot,
+    })
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    fn events() -> Vec<Event> {
+        let modifiers = Modifiers {
+            shift: true,
+            ctrl: false,
+            alt: true,
+        };
+        vec![
+            Event::MouseMove {
+                delta_x: 0.1,
+                delta_y: -3.0,
+                x: 120.5,
+                y: 1.0 / 3.0,
+                delta_time_ms: 16.666,
+                buttons_pressed: vec![MouseButton::LeftClick, MouseButton::Back],
+                modifiers,
+            },
+            Event::Scroll {
+                delta_y: -100.0,
+                delta_time_ms: 8.0,
+                modifiers: Modifiers::default(),
+            },
+            Event::MouseDown {
+                x: 3.0,
+                y: 4.0,
+                button: MouseButton::RightClick,
+                modifiers,
+            },
+            Event::MouseUp {
+                x: 3.0,
+                y: 4.0,
+                modifiers,
+            },
+            Event::KeyPress {
+                key: " ".to_string(),
+                modifiers: Modifiers::default(),
+            },
+            Event::KeyPress {
+                key: "é".to_string(),
+                modifiers,
+            },
+            Event::Screenshot,
+            Event::FrameTime {
+                delta_time_ms: 16.7,
+            },
+            Event::ScaleFactorChange { scale_factor: 1.5 },
+        ]
+    }
+    #[test]
+    fn round_trip() {
+        let mut recording = Recording::new(Vector2::new(800, 600));
+        recording.push(&events()[..4]);
+        recording.push(&[]);
+        recording.push(&events()[4..]);
+        let text = recording.to_text();
+        let read = Recording::from_text(&text).expect("recording is valid");
+        assert_eq!(read.frames(), 3);
+        assert_eq!(format!("{:?}", read), format!("{:?}", recording));
+        assert_eq!(read.to_text(), text);
+    }
+    #[test]
+    fn empty_recording() {
+        let recording = Recording::new(Vector2::new(10, 20));
+        assert_eq!(recording.frames(), 1);
+        let text = recording.to_text();
+        let read = Recording::from_text(&text).expect("recording is valid");
+        assert_eq!(read.to_text(), text);
+        let mut replay = Replay::new(read);
+        assert_eq!(replay.next_frame().map(|events| events.len()), Some(1));
+        assert!(replay.next_frame().is_none());
+    }
+    #[test]
+    fn replay_frames() {
+        let mut recording = Recording::new(Vector2::new(10, 20));
+        recording.push(&events()[..1]);
+        recording.push(&[]);
+        recording.push(&events()[1..3]);
+        let mut replay = Replay::new(recording);
+        assert_eq!(replay.next_frame().map(|events| events.len()), Some(2));
+        assert_eq!(replay.next_frame().map(|events| events.len()), Some(0));
+        assert_eq!(replay.next_frame().map(|events| events.len()), Some(2));
+        assert!(replay.next_frame().is_none());
+    }
+    #[test]
+    fn invalid_recordings() {
+        assert_eq!(
+            Recording::from_text("frames 1").err(),
+            Some(RecordingError::MissingHeader)
+        );
+        assert_eq!(
+            Recording::from_text(HEADER).err(),
+            Some(RecordingError::MissingLength)
+        );
+        let text = format!("{}\nframes 2\n0 scroll 1 2 -\n0 scroll 1\n", HEADER);
+        assert_eq!(
+            Recording::from_text(&text).err(),
+            Some(RecordingError::InvalidEvent {
+                line: 4,
+                text: "0 scroll 1".to_string()
+            })
+        );
+        let text = format!("{}\nframes 2\n1 screenshot\n0 screenshot\n", HEADER);
+        assert_eq!(
+            Recording::from_text(&text).err(),
+            Some(RecordingError::InvalidFrame { line: 4, frame: 0 })
+        );
+        let text = format!("{}\nframes 2\n2 screenshot\n", HEADER);
+        assert_eq!(
+            Recording::from_text(&text).err(),
+            Some(RecordingError::InvalidFrame { line: 3, frame: 2 })
+        );
+    }
+}
@@ -0,0 +1,123 @@
+use super::graphics_engine::RuntimeDepthTexture;
+use super::prelude::{
+    ErrorType, Framebuffer, RenderBackend, RenderingContext, RuntimeTexture, Shader, Texture,
+};
+use nalgebra::{Matrix4, Point3, Vector2, Vector3, Vector4};
+/// Width and height of the shadow map in texels
+const SHADOW_MAP_SIZE: u32 = 1024;
+/// Depth map of the world as seen from the sun, stored as a resource. Rendered
+/// before the world each frame and sampled by the world and water shaders
+pub struct ShadowMap {
+    /// never read, framebuffers need a color attachment
+    #[allow(dead_code)]
+    color: RuntimeTexture,
+    depth: RuntimeDepthTexture,
+    framebuffer: Framebuffer,
+    /// direction the sun shines in
+    pub sun_direction: Vector3<f32>,
+    /// view and projection of the sun
+    light: Matrix4<f32>,
+}
+impl ShadowMap {
+    pub fn new(
+        sun_direction: Vector3<f32>,
+        graphics: &mut RenderingContext,
+        shader: &Shader,
+    ) -> Result<Self, ErrorType> {
+        let dimensions = Vector2::new(SHADOW_MAP_SIZE, SHADOW_MAP_SIZE);
+        let mut color = graphics.build_texture(
+            Texture::constant_color(Vector4::new(0, 0, 0, 0), dimensions),
+            shader,
+        )?;
+        let mut depth = graphics.build_depth_texture(dimensions, shader)?;
+        let framebuffer = graphics.build_framebuffer(&mut color, &mut depth)?;
+        Ok(Self {
+            color,
+            depth,
+            framebuffer,
+            sun_direction,
+            light: Matrix4::identity(),
+        })
+    }
+    /// View and projection of the sun used by the last call to `fit`
+    pub fn light(&self) -> Matrix4<f32> {
+        self.light
+    }
+    /// Fits the view of the sun around the box from `min` to `max`
+    pub fn fit(&mut self, min: Vector3<f32>, max: Vector3<f32>) {
+        self.light = light_matrix(self.sun_direction, min, max);
+    }
+    /// Binds and clears the shadow map so that casters can be drawn into it with
+    /// the shadow shader
+    pub fn begin(&self, graphics: &mut RenderingContext) {
+        graphics.bind_framebuffer(&self.framebuffer);
+        graphics.clear_screen(Vector4::new(0.0, 0.0, 0.0, 0.0));
+    }
+    /// Binds the shadow map to the world or water shader, that shader must be bound
+    /// and a framebuffer other than the shadow map must be bound
+    pub fn bind(&self, graphics: &mut RenderingContext, shader: &Shader) -> Result<(), ErrorType> {
+        graphics.bind_shadow_map(&self.depth, shader);
+        graphics.send_mat4_uniform(shader, "light", self.light)
+    }
+}
+/// Orthographic view looking along `sun_direction` that contains the bounding
+/// sphere of the box from `min` to `max`
+fn light_matrix(sun_direction: Vector3<f32>, min: Vector3<f32>, max: Vector3<f32>) -> Matrix4<f32> {
+    let center = (min + max) / 2.0;
+    let radius = ((max - min).norm() / 2.0).max(1.0);
+    let direction = sun_direction
+        .try_normalize(f32::EPSILON)
+        .unwrap_or_else(|| Vector3::new(0.0, -1.0, 0.0));
+    // the up vector can not be parallel to the view direction
+    let up = if direction.cross(&Vector3::y()).norm() < 1e-3 {
+        Vector3::z()
+    } else {
+        Vector3::y()
+    };
+    let eye = center - direction * 2.0 * radius;
+    let view = Matrix4::look_at_rh(&Point3::from(eye), &Point3::from(center), &up);
+    let projection =
+        Matrix4::new_orthographic(-radius, radius, -radius, radius, radius, 3.0 * radius);
+    projection * view
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn light_matrix_contains_box() {
+        let min = Vector3::new(0.0, -2.0, 0.0);
+        let max = Vector3::new(20.0, 5.0, 10.0);
+        for direction in [
+            Vector3::new(1.0, -1.0, 0.0),
+            Vector3::new(0.0, -1.0, 0.0),
+            Vector3::new(0.2, -0.3, 0.9),
+        ]
+        .iter()
+        {
+            let light = light_matrix(*direction, min, max);
+            for corner in 0..8 {
+                let point = Vector3::new(
+                    if corner & 1 == 0 { min.x } else { max.x },
+                    if corner & 2 == 0 { min.y } else { max.y },
+                    if corner & 4 == 0 { min.z } else { max.z },
+                );
+                let clip = light * point.push(1.0);
+                let ndc = clip.xyz() / clip.w;
+                assert!(ndc.iter().all(|c| c.abs() <= 1.0), "{:?}", ndc);
+            }
+        }
+    }
+    #[test]
+    fn closer_to_sun_is_shallower() {
+        let light = light_matrix(
+            Vector3::new(0.0, -1.0, 0.0),
+            Vector3::new(0.0, 0.0, 0.0),
+            Vector3::new(4.0, 4.0, 4.0),
+        );
+        let depth = |y: f32| {
+            let clip = light * Vector4::new(2.0, y, 2.0, 1.0);
+            clip.z / clip.w
+        };
+        assert!(depth(3.0) < depth(1.0));
+    }
+}
@@ -0,0 +1,245 @@
+use super::prelude::Terrain;
+use egui::CtxRef;
+/// What happens to water at an edge of the grid
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Boundary {
+    /// water is reflected back
+    Wall,
+    /// water flows out as if the terrain continued past the edge, nothing flows in
+    Open,
+}
+/// Boundary at each edge of the grid
+#[derive(Clone, Debug, PartialEq)]
+pub struct Boundaries {
+    pub x_min: Boundary,
+    pub x_max: Boundary,
+    pub y_min: Boundary,
+    pub y_max: Boundary,
+}
+impl Default for Boundaries {
+    fn default() -> Self {
+        Self {
+            x_min: Boundary::Wall,
+            x_max: Boundary::Wall,
+            y_min: Boundary::Wall,
+            y_max: Boundary::Wall,
+        }
+    }
+}
+/// Constants of the water simulation that can be changed while it runs
+#[derive(Clone, Debug, PartialEq)]
+pub struct SimulationParameters {
+    /// time of one velocity and water update
+    pub delta_t: f32,
+    /// velocity and water updates in one step
+    pub substeps: usize,
+    pub gravity: f32,
+    /// fraction of the velocity lost in every update
+    pub viscosity: f32,
+    pub boundaries: Boundaries,
+}
+impl Default for SimulationParameters {
+    fn default() -> Self {
+        Self {
+            delta_t: Terrain::DELTA_T,
+            substeps: Terrain::SUBSTEPS,
+            gravity: Terrain::G,
+            viscosity: Terrain::VISC,
+            boundaries: Boundaries::default(),
+        }
+    }
+}
+impl SimulationParameters {
+    /// Time simulated by one step
+    pub fn step_time(&self) -> f32 {
+        self.delta_t * self.substeps as f32
+    }
+}
+/// Whether the water simulation runs, how fast and with which parameters, stored as a
+/// resource
+pub struct SimulationControl {
+    pub paused: bool,
+    /// steps run every frame while not paused, fractions run a step every few frames
+    pub speed: f32,
+    pub parameters: SimulationParameters,
+    /// steps to run while paused
+    requested_steps: u32,
+    /// fraction of a step carried over to the next frame
+    partial_step: f32,
+    /// steps run since the scenario was built
+    step_count: u64,
+    /// time simulated since the scenario was built
+    time: f32,
+    reset_requested: bool,
+}
+impl Default for SimulationControl {
+    fn default() -> Self {
+        Self {
+            paused: false,
+            speed: 1.0,
+            parameters: SimulationParameters::default(),
+            requested_steps: 0,
+            partial_step: 0.0,
+            step_count: 0,
+            time: 0.0,
+            reset_requested: false,
+        }
+    }
+}
+impl SimulationControl {
+    const MAX_SPEED: f32 = 10.0;
+    /// CFL numbers above this are shown as a warning
+    const CFL_LIMIT: f32 = 1.0;
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+    }
+    /// Runs one step on the next frame, only used while paused
+    pub fn step(&mut self) {
+        if self.paused {
+            self.requested_steps += 1;
+        }
+    }
+    /// Number of simulation steps to run this frame
+    pub fn steps_this_frame(&mut self) -> u32 {
+        if self.paused {
+            std::mem::take(&mut self.requested_steps)
+        } else {
+            self.partial_step += self.speed.clamp(0.0, Self::MAX_SPEED);
+            let steps = self.partial_step.floor();
+            self.partial_step -= steps;
+            steps as u32
+        }
+    }
+    /// Advances the clock by steps that were run
+    pub fn finish_steps(&mut self, steps: u32) {
+        self.step_count += steps as u64;
+        self.time += steps as f32 * self.parameters.step_time();
+    }
+    /// Sets the clock back to zero, called when a scenario is built
+    pub fn reset_clock(&mut self) {
+        self.step_count = 0;
+        self.time = 0.0;
+        self.partial_step = 0.0;
+    }
+    pub fn step_count(&self) -> u64 {
+        self.step_count
+    }
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+    /// Whether the reset button was pressed since the last call
+    pub fn take_reset_request(&mut self) -> bool {
+        std::mem::take(&mut self.reset_requested)
+    }
+    pub fn draw_gui(&mut self, context: &mut CtxRef, terrain: Option<&Terrain>) {
+        egui::Window::new("Simulation").show(context, |ui| {
+            ui.horizontal(|ui| {
+                if ui
+                    .button(if self.paused { "Play" } else { "Pause" })
+                    .clicked
+                {
+                    self.toggle_pause();
+                }
+                if ui.button("Step").clicked {
+                    self.paused = true;
+                    self.step();
+                }
+                if ui.button("Reset").clicked {
+                    self.reset_requested = true;
+                }
+            });
+            ui.add(
+                egui::Slider::f32(&mut self.speed, 0.1..=Self::MAX_SPEED).text("Steps per Frame"),
+            );
+            ui.label(format!("Time: {:.2}", self.time));
+            ui.label(format!("Steps: {}", self.step_count));
+            if let Some(terrain) = terrain {
+                let cfl = terrain.cfl(&self.parameters);
+                ui.label(if cfl > Self::CFL_LIMIT {
+                    format!("CFL: {:.3} (unstable)", cfl)
+                } else {
+                    format!("CFL: {:.3}", cfl)
+                });
+            }
+            ui.separator();
+            let parameters = &mut self.parameters;
+            ui.add(egui::Slider::f32(&mut parameters.delta_t, 0.001..=0.1).text("Time Step"));
+            ui.add(egui::Slider::usize(&mut parameters.substeps, 1..=100).text("Substeps"));
+            ui.add(egui::Slider::f32(&mut parameters.gravity, 0.0..=1.0).text("Gravity"));
+            ui.add(egui::Slider::f32(&mut parameters.viscosity, 0.0..=0.01).text("Viscosity"));
+            let boundaries = &mut parameters.boundaries;
+            boundary_selector(ui, "x = 0", &mut boundaries.x_min);
+            boundary_selector(ui, "x = max", &mut boundaries.x_max);
+            boundary_selector(ui, "y = 0", &mut boundaries.y_min);
+            boundary_selector(ui, "y = max", &mut boundaries.y_max);
+            if ui.button("Default Parameters").clicked {
+                *parameters = SimulationParameters::default();
+            }
+        });
+    }
+}
+fn boundary_selector(ui: &mut egui::Ui, edge: &str, boundary: &mut Boundary) {
+    ui.horizontal(|ui| {
+        ui.label(edge.to_string());
+        ui.radio_value(boundary, Boundary::Wall, "Wall");
+        ui.radio_value(boundary, Boundary::Open, "Open");
+    });
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn step_while_paused() {
+        let mut control = SimulationControl::default();
+        control.step();
+        assert_eq!(control.steps_this_frame(), 1);
+        control.toggle_pause();
+        assert_eq!(control.steps_this_frame(), 0);
+        control.step();
+        control.step();
+        assert_eq!(control.steps_this_frame(), 2);
+        assert_eq!(control.steps_this_frame(), 0);
+    }
+    #[test]
+    fn fractional_speed() {
+        let mut control = SimulationControl {
+            speed: 0.25,
+            ..SimulationControl::default()
+        };
+        let steps: Vec<u32> = (0..8).map(|_| control.steps_this_frame()).collect();
+        assert_eq!(steps, vec![0, 0, 0, 1, 0, 0, 0, 1]);
+        control.speed = 2.5;
+        let steps: u32 = (0..4).map(|_| control.steps_this_frame()).sum();
+        assert_eq!(steps, 10);
+        control.finish_steps(10);
+        assert_eq!(control.step_count(), 10);
+        assert!((control.time() - 10.0 * control.parameters.step_time()).abs() < 1e-5);
+        control.reset_clock();
+        assert_eq!(control.step_count(), 0);
+    }
+    #[test]
+    fn open_boundaries_drain_water() {
+        use nalgebra::Vector2;
+        let volume = |terrain: &Terrain| terrain.surface_heights().iter().sum::<f32>();
+        let mound = || Terrain::new_cone(Vector2::new(12, 12), Vector2::new(6.0, 6.0), 3.0, -0.2);
+        let mut parameters = SimulationParameters::default();
+        let mut walled = mound();
+        let start = volume(&walled);
+        for _ in 0..20 {
+            walled.water_simulation(&parameters);
+        }
+        assert!((volume(&walled) - start).abs() < 1e-2 * start);
+        assert!(walled.cfl(&parameters) < 1.0);
+        parameters.boundaries = Boundaries {
+            x_min: Boundary::Open,
+            x_max: Boundary::Open,
+            y_min: Boundary::Open,
+            y_max: Boundary::Open,
+        };
+        let mut open = mound();
+        for _ in 0..20 {
+            open.water_simulation(&parameters);
+        }
+        assert!(volume(&open) < volume(&walled) - 1e-2 * start);
+    }
+}
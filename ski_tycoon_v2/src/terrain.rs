@@ -1,15 +1,32 @@
 use super::prelude::{
-    insert_terrain, AssetManager, DeltaCamera, Grid, Model, RenderingContext, RuntimeModel,
-    ShaderBind, Transform,
+    insert_terrain, Boundary, DeltaCamera, Diagnostics, Grid, GuiRuntimeModel, Model,
+    RenderingContext, RuntimeDebugMesh, ShaderBind, SimulationControl, SimulationParameters,
+    Transform,
 };
 use egui::CtxRef;
 
 use legion::*;
 use log::{error, info};
 use nalgebra::{Vector2, Vector3};
+mod flow_lines;
+mod lod;
+mod overlay;
 mod pgm_parser;
+mod picking;
+mod sculpt;
+mod tracer;
+mod transect;
+pub use flow_lines::FlowLines;
+pub use lod::TerrainChunks;
+pub use overlay::{Colormap, Overlay, ScalarField};
+pub use picking::{CursorPick, TerrainHit};
+pub use sculpt::{Brush, BrushTool, Falloff, Sculptor};
+pub use tracer::Tracer;
+pub use transect::{ProfilePoint, Transect};
 pub struct TerrainLibrary {
     pub entries: Vec<Scenario>,
+    /// index of the last scenario built, rebuilt by `reset`
+    current: Option<usize>,
 }
 impl Default for TerrainLibrary {
     fn default() -> Self {
@@ -80,6 +97,7 @@ impl Default for TerrainLibrary {
                     }),
                 },
             ],
+            current: None,
         }
     }
 }
@@ -93,9 +111,23 @@ impl Scenario {
         world: &mut World,
         camera: &mut DeltaCamera,
         graphics: &mut RenderingContext,
-        asset_manager: &mut AssetManager<RuntimeModel>,
         bound_shader: &ShaderBind,
     ) {
+        for (chunks, flow_lines) in
+            <(&mut TerrainChunks, &mut RuntimeDebugMesh)>::query().iter_mut(world)
+        {
+            chunks.delete(graphics).expect("failed to delete terrain");
+            flow_lines
+                .delete(graphics)
+                .expect("failed to delete flow lines");
+        }
+        // gui quads are cleared with the rest of the world, the minimap inserts its quad again
+        for quad in <&mut GuiRuntimeModel>::query().iter_mut(world) {
+            graphics
+                .delete_mesh(&mut quad.model.mesh)
+                .expect("failed to delete gui quad");
+            graphics.delete_texture(&mut quad.model.texture);
+        }
         world.clear();
         info!("building scene: {}", self.name);
         let terrain = (self.terrain_ctor)();
@@ -106,38 +138,65 @@ impl Scenario {
             terrain.dimensions.y as f32 / 2.0,
         ));
         camera.set_radius((terrain.dimensions.x as f32 * terrain.dimensions.y as f32).sqrt());
-        insert_terrain(
-            terrain,
-            world,
-            graphics,
-            asset_manager,
-            bound_shader.get_bind(),
-        )
-        .expect("failed to insert terrain");
+        camera.skip_smoothing();
+        insert_terrain(terrain, world, graphics, bound_shader).expect("failed to insert terrain");
     }
 }
 impl TerrainLibrary {
+    /// Builds the scenario at `index` and starts the simulation clock from zero
+    pub fn build(
+        &mut self,
+        index: usize,
+        world: &mut World,
+        camera: &mut DeltaCamera,
+        simulation: &mut SimulationControl,
+        graphics: &mut RenderingContext,
+        bound_shader: &ShaderBind,
+    ) {
+        if let Some(scenario) = self.entries.get(index) {
+            scenario.build_scenario(world, camera, graphics, bound_shader);
+            simulation.reset_clock();
+            self.current = Some(index);
+        }
+    }
+    /// Rebuilds the last scenario built, does nothing if none was
+    pub fn reset(
+        &mut self,
+        world: &mut World,
+        camera: &mut DeltaCamera,
+        simulation: &mut SimulationControl,
+        graphics: &mut RenderingContext,
+        bound_shader: &ShaderBind,
+    ) {
+        if let Some(index) = self.current {
+            self.build(index, world, camera, simulation, graphics, bound_shader);
+        }
+    }
     pub fn draw_gui(
-        &self,
+        &mut self,
         world: &mut World,
         camera: &mut DeltaCamera,
+        simulation: &mut SimulationControl,
         context: &mut CtxRef,
         graphics: &mut RenderingContext,
-        asset_manager: &mut AssetManager<RuntimeModel>,
         bound_shader: &ShaderBind,
     ) {
         egui::Window::new("How do I use this?").show(context, |ui| {
 
             ui.label("Click on a scenario to start simulation\nScroll to zoom\n Left click+drag to change view");
         });
+        let mut chosen = None;
         egui::Window::new("Scenarios").show(context, |ui| {
-            for t in self.entries.iter() {
+            for (index, t) in self.entries.iter().enumerate() {
                 ui.label(t.name.to_string());
                 if ui.button("Click Here").clicked {
-                    t.build_scenario(world, camera, graphics, asset_manager, bound_shader);
+                    chosen = Some(index);
                 }
             }
         });
+        if let Some(index) = chosen {
+            self.build(index, world, camera, simulation, graphics, bound_shader);
+        }
     }
 }
 
@@ -147,9 +206,19 @@ pub struct Tile {
 }
 
 pub struct Terrain {
+    /// depth of the water above the bed
     heights: Grid<f32>,
+    /// height of the ground under the water
+    bed: Grid<f32>,
+    /// cumulative change of the bed height made by the sculpt brushes, the
+    /// simulation itself never moves the bed
+    sculpted: Grid<f32>,
     velocity: Grid<Vector2<f32>>,
     dimensions: Vector2<usize>,
+    /// counts changes to the water or the bed, see `generation`
+    generation: u64,
+    /// counts changes to the bed only
+    bed_generation: u64,
 }
 pub struct Droplet {
     position: Vector2<usize>,
@@ -157,9 +226,11 @@ pub struct Droplet {
 }
 
 impl Terrain {
-    const DELTA_T: f32 = 0.01;
-    const G: f32 = 0.1;
-    const VISC: f32 = 0.0002;
+    /// default simulation parameters, see `SimulationParameters`
+    pub const DELTA_T: f32 = 0.01;
+    pub const SUBSTEPS: usize = 20;
+    pub const G: f32 = 0.1;
+    pub const VISC: f32 = 0.0002;
     /// Builds cone terrain with center at center and slope of `slope`
     pub fn new_cone(
         dimensions: Vector2<usize>,
@@ -182,7 +253,11 @@ impl Terrain {
                 vec![Vector2::new(0.0, 0.0); (dimensions.x + 1) * (dimensions.y + 1)],
                 Vector2::new(dimensions.x + 1, dimensions.y + 1),
             ),
+            bed: Grid::from_vec(vec![0.0; dimensions.x * dimensions.y], dimensions),
+            sculpted: Grid::from_vec(vec![0.0; dimensions.x * dimensions.y], dimensions),
             dimensions,
+            generation: 0,
+            bed_generation: 0,
         }
     }
     pub fn flat(dimensions: Vector2<usize>, height: f32) -> Self {
@@ -192,7 +267,11 @@ impl Terrain {
                 vec![Vector2::new(0.0, 0.0); (dimensions.x + 1) * (dimensions.y + 1)],
                 Vector2::new(dimensions.x + 1, dimensions.y + 1),
             ),
+            bed: Grid::from_vec(vec![0.0; dimensions.x * dimensions.y], dimensions),
+            sculpted: Grid::from_vec(vec![0.0; dimensions.x * dimensions.y], dimensions),
             dimensions,
+            generation: 0,
+            bed_generation: 0,
         }
     }
     pub fn cone_flat(
@@ -220,7 +299,11 @@ impl Terrain {
                 vec![Vector2::new(0.0, 0.0); (dimensions.x + 1) * (dimensions.y + 1)],
                 Vector2::new(dimensions.x + 1, dimensions.y + 1),
             ),
+            bed: Grid::from_vec(vec![0.0; dimensions.x * dimensions.y], dimensions),
+            sculpted: Grid::from_vec(vec![0.0; dimensions.x * dimensions.y], dimensions),
             dimensions,
+            generation: 0,
+            bed_generation: 0,
         }
     }
     pub fn droplet(dimensions: Vector2<usize>, height: f32, droplet: Vec<Droplet>) -> Self {
@@ -235,7 +318,11 @@ impl Terrain {
                 vec![Vector2::new(0.0, 0.0); (dimensions.x + 1) * (dimensions.y + 1)],
                 Vector2::new(dimensions.x + 1, dimensions.y + 1),
             ),
+            bed: Grid::from_vec(vec![0.0; dimensions.x * dimensions.y], dimensions),
+            sculpted: Grid::from_vec(vec![0.0; dimensions.x * dimensions.y], dimensions),
             dimensions,
+            generation: 0,
+            bed_generation: 0,
         }
     }
 
@@ -252,42 +339,70 @@ impl Terrain {
             None
         }
     }
+    /// Accelerates the water down the slope of the water surface, the surface is the bed plus the depth
     fn update_velocity(
         heights: &Grid<f32>,
+        bed: &Grid<f32>,
         velocity: &Grid<Vector2<f32>>,
         velocity_apply: &Grid<Vector2<f32>>,
         dimensions: &Vector2<usize>,
-        delta_t: f32,
+        parameters: &SimulationParameters,
     ) -> Grid<Vector2<f32>> {
+        let delta_t = parameters.delta_t;
         let mut new_velocities = velocity_apply.clone();
+        let surface = |x: i64, y: i64| {
+            heights.get_unchecked(Vector2::new(x, y)) + bed.get_unchecked(Vector2::new(x, y))
+        };
         //Update Velocities
         for x in 0..dimensions.x {
             for y in 0..dimensions.y {
                 let water_x_n1 = if x > 0 {
-                    heights.get_unchecked(Vector2::new(x as i64 - 1, y as i64))
+                    surface(x as i64 - 1, y as i64)
                 } else {
-                    heights.get_unchecked(Vector2::new(x as i64, y as i64))
+                    surface(x as i64, y as i64)
                 };
                 let water_y_n1 = if y > 0 {
-                    heights.get_unchecked(Vector2::new(x as i64, y as i64 - 1))
+                    surface(x as i64, y as i64 - 1)
                 } else {
-                    heights.get_unchecked(Vector2::new(x as i64, y as i64))
+                    surface(x as i64, y as i64)
                 };
 
                 let v = new_velocities.get_mut_unchecked(Vector2::new(x as i64, y as i64));
-                let center = heights.get_unchecked(Vector2::new(x as i64, y as i64));
-                v.x += (water_x_n1 - center) * delta_t * Self::G;
-                v.x -= v.x * Self::VISC;
-                if x == 0 {
-                    v.x = 0.0;
-                }
-                v.y += (water_y_n1 - center) * delta_t * Self::G;
-                v.y -= v.y * Self::VISC;
-                if y == 0 {
-                    v.y = 0.0;
-                }
+                let center = surface(x as i64, y as i64);
+                v.x += (water_x_n1 - center) * delta_t * parameters.gravity;
+                v.x -= v.x * parameters.viscosity;
+                v.y += (water_y_n1 - center) * delta_t * parameters.gravity;
+                v.y -= v.y * parameters.viscosity;
             }
         }
+        // faces on the edges of the grid are closed, or copy the flow next to them
+        // when it leaves the grid
+        let boundaries = &parameters.boundaries;
+        let edge = |boundary: Boundary, inner: f32, outflow: fn(f32, f32) -> f32| match boundary {
+            Boundary::Wall => 0.0,
+            Boundary::Open => outflow(inner, 0.0),
+        };
+        let (last_x, last_y) = (dimensions.x as i64, dimensions.y as i64);
+        for y in 0..last_y {
+            let inner = new_velocities
+                .get_unchecked(Vector2::new(1.min(last_x - 1), y))
+                .x;
+            new_velocities.get_mut_unchecked(Vector2::new(0, y)).x =
+                edge(boundaries.x_min, inner, f32::min);
+            let inner = new_velocities.get_unchecked(Vector2::new(last_x - 1, y)).x;
+            new_velocities.get_mut_unchecked(Vector2::new(last_x, y)).x =
+                edge(boundaries.x_max, inner, f32::max);
+        }
+        for x in 0..last_x {
+            let inner = new_velocities
+                .get_unchecked(Vector2::new(x, 1.min(last_y - 1)))
+                .y;
+            new_velocities.get_mut_unchecked(Vector2::new(x, 0)).y =
+                edge(boundaries.y_min, inner, f32::min);
+            let inner = new_velocities.get_unchecked(Vector2::new(x, last_y - 1)).y;
+            new_velocities.get_mut_unchecked(Vector2::new(x, last_y)).y =
+                edge(boundaries.y_max, inner, f32::max);
+        }
         return new_velocities;
     }
     fn update_water(
@@ -318,7 +433,13 @@ impl Terrain {
                             .y,
                     )
                 } else {
-                    (heights.get_unchecked(Vector2::new(x as i64, y as i64)), 0.0)
+                    // face on the edge of the grid, see `Boundary`
+                    (
+                        heights.get_unchecked(Vector2::new(x as i64, y as i64)),
+                        velocity
+                            .get_unchecked(Vector2::new(x as i64, y as i64 + 1))
+                            .y,
+                    )
                 };
                 let water_xn1 = if x > 0 {
                     heights.get_unchecked(Vector2::new(x as i64 - 1, y as i64))
@@ -333,7 +454,12 @@ impl Terrain {
                             .x,
                     )
                 } else {
-                    (heights.get_unchecked(Vector2::new(x as i64, y as i64)), 0.0)
+                    (
+                        heights.get_unchecked(Vector2::new(x as i64, y as i64)),
+                        velocity
+                            .get_unchecked(Vector2::new(x as i64 + 1, y as i64))
+                            .x,
+                    )
                 };
                 let water_xn1_avg = (water_xn1 + water_0) / 2.0;
                 let water_x1_avg = (water_x1 + water_0) / 2.0;
@@ -442,39 +568,73 @@ impl Terrain {
             }
         }
     }
-    pub fn water_simulation(&mut self) {
+    pub fn water_simulation(&mut self, parameters: &SimulationParameters) {
         //Update Velocities
-        for _ in 0..20 {
+        for _ in 0..parameters.substeps {
             let half_uv = Self::update_velocity(
                 &self.heights,
+                &self.bed,
                 &self.velocity,
                 &self.velocity,
                 &self.dimensions,
-                Self::DELTA_T,
+                parameters,
             );
             let half_h = Self::update_water(
                 &self.heights,
                 &self.velocity,
                 &self.heights,
                 &self.dimensions,
-                Self::DELTA_T,
+                parameters.delta_t,
             );
 
             self.velocity = Self::update_velocity(
                 &half_h,
+                &self.bed,
                 &half_uv,
                 &self.velocity,
                 &self.dimensions,
-                Self::DELTA_T,
+                parameters,
             );
             self.heights = Self::update_water(
                 &half_h,
                 &half_uv,
                 &self.heights,
                 &self.dimensions,
-                Self::DELTA_T,
+                parameters.delta_t,
             );
         }
+        self.generation += 1;
+    }
+    /// Largest CFL number of any cell for one update with `parameters`, the simulation
+    /// becomes unstable when it goes above one
+    pub fn cfl(&self, parameters: &SimulationParameters) -> f32 {
+        self.velocities()
+            .iter()
+            .zip(self.heights.data.iter())
+            .map(|(velocity, depth)| {
+                let wave_speed = (parameters.gravity * depth.max(0.0)).sqrt();
+                parameters.delta_t * (velocity.x.abs() + velocity.y.abs() + 2.0 * wave_speed)
+            })
+            .fold(0.0, f32::max)
+    }
+    /// Totals over the whole grid, recorded every step to watch for drift
+    pub fn diagnostics(&self, gravity: f32) -> Diagnostics {
+        let mut diagnostics = Diagnostics::default();
+        let cells = self
+            .heights
+            .data
+            .iter()
+            .zip(self.bed.data.iter())
+            .zip(self.velocities());
+        for ((depth, bed), velocity) in cells {
+            diagnostics.volume += depth;
+            // kinetic energy plus the potential energy of a column from the bed to the surface
+            diagnostics.energy +=
+                0.5 * depth * velocity.norm_squared() + gravity * depth * (bed + depth / 2.0);
+            diagnostics.max_speed = diagnostics.max_speed.max(velocity.norm());
+        }
+        diagnostics.sculpted_bed = self.sculpted.data.iter().map(|change| change.abs()).sum();
+        diagnostics
     }
     pub fn from_tiles(heights: Vec<f32>, dimensions: Vector2<usize>) -> Self {
         Self {
@@ -483,13 +643,88 @@ impl Terrain {
                 vec![Vector2::new(0.0, 0.0); (dimensions.x + 1) * (dimensions.y + 1)],
                 Vector2::new(dimensions.x + 1, dimensions.y + 1),
             ),
+            bed: Grid::from_vec(vec![0.0; dimensions.x * dimensions.y], dimensions),
+            sculpted: Grid::from_vec(vec![0.0; dimensions.x * dimensions.y], dimensions),
             dimensions,
+            generation: 0,
+            bed_generation: 0,
         }
     }
 
+    /// Number of grid points along each axis
+    pub fn dimensions(&self) -> Vector2<usize> {
+        self.dimensions
+    }
+    /// Raises the bed at grid index `index` by `delta`, every change to the bed goes
+    /// through here so `sculpted` keeps up with it
+    fn change_bed(&mut self, index: usize, delta: f32) {
+        if delta == 0.0 {
+            return;
+        }
+        self.bed.data[index] += delta;
+        self.sculpted.data[index] += delta;
+        self.generation += 1;
+        self.bed_generation += 1;
+    }
+    /// Changes whenever the water or the bed change, so that what is built from
+    /// them only needs to be rebuilt when it differs from the last generation seen
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+    /// Changes whenever the bed changes
+    pub fn bed_generation(&self) -> u64 {
+        self.bed_generation
+    }
     pub fn model(&self) -> Model {
         Model::from_heights(&self.heights.data, self.dimensions, Transform::default())
     }
+    /// Height of the water surface at every grid point
+    pub fn surface_heights(&self) -> Vec<f32> {
+        self.heights
+            .data
+            .iter()
+            .zip(self.bed.data.iter())
+            .map(|(depth, bed)| depth + bed)
+            .collect()
+    }
+    /// Velocity of the water at every grid point. Velocities are stored on the faces
+    /// of cells so the two faces on each axis are averaged
+    pub fn velocities(&self) -> Vec<Vector2<f32>> {
+        let mut velocities = Vec::with_capacity(self.dimensions.x * self.dimensions.y);
+        for x in 0..self.dimensions.x {
+            for y in 0..self.dimensions.y {
+                let u = (self.velocity[Vector2::new(x, y)].x
+                    + self.velocity[Vector2::new(x + 1, y)].x)
+                    / 2.0;
+                let v = (self.velocity[Vector2::new(x, y)].y
+                    + self.velocity[Vector2::new(x, y + 1)].y)
+                    / 2.0;
+                velocities.push(Vector2::new(u, v));
+            }
+        }
+        velocities
+    }
+    /// Speed of the water at every grid point
+    pub fn speeds(&self) -> Vec<f32> {
+        self.velocities().iter().map(|v| v.norm()).collect()
+    }
+    /// Corners of the box containing the bed and the water surface in model space
+    pub fn bounds(&self) -> (Vector3<f32>, Vector3<f32>) {
+        let min_height = self.bed.data.iter().copied().fold(f32::INFINITY, f32::min);
+        let max_height = self
+            .surface_heights()
+            .iter()
+            .copied()
+            .fold(f32::NEG_INFINITY, f32::max);
+        (
+            Vector3::new(0.0, min_height, 0.0),
+            Vector3::new(
+                (self.dimensions.x - 1) as f32,
+                max_height,
+                (self.dimensions.y - 1) as f32,
+            ),
+        )
+    }
     pub fn get_transform_rounded(&self, coordinate: &Vector2<f32>) -> Vector3<f32> {
         let x: i64 = unsafe { coordinate.x.to_int_unchecked() };
         let y: i64 = unsafe { coordinate.y.to_int_unchecked() };
@@ -509,10 +744,12 @@ impl Terrain {
         .unwrap()
     }
     pub fn get_transform(&self, coordinate: &Vector2<i64>) -> Option<Vector3<f32>> {
-        if let Some(height) = self.heights.get(*coordinate) {
+        if let (Some(height), Some(bed)) =
+            (self.heights.get(*coordinate), self.bed.get(*coordinate))
+        {
             Some(Vector3::new(
                 coordinate.x as f32,
-                *height,
+                height + bed,
                 coordinate.y as f32,
             ))
         } else {
@@ -0,0 +1,270 @@
+use super::Terrain;
+use crate::prelude::{ItemDesc, Mesh, Texture};
+use egui::CtxRef;
+use nalgebra::{Vector2, Vector3, Vector4};
+/// Water slower than this gets no glyph and stops streamlines
+const MIN_SPEED: f32 = 1e-3;
+/// Distance in cells a streamline moves each step
+const STREAMLINE_STEP: f32 = 0.5;
+/// Lines float this far above the water so they are not hidden by it
+const LINE_OFFSET: f32 = 0.05;
+/// Length of the arrow head relative to the arrow
+const HEAD_LENGTH: f32 = 0.3;
+/// Angle of the arrow head lines away from the shaft, in radians
+const HEAD_ANGLE: f32 = 2.6;
+/// uv of the glyph, streamline and transect colors in `FlowLines::texture`
+const GLYPH_UV: [f32; 2] = [1.0 / 6.0, 0.5];
+const STREAMLINE_UV: [f32; 2] = [0.5, 0.5];
+const TRANSECT_UV: [f32; 2] = [5.0 / 6.0, 0.5];
+/// Debug line rendering of the water velocity, stored as a resource. Arrow glyphs
+/// are placed on a subsampled grid and streamlines are traced from seeds on a
+/// coarser grid
+pub struct FlowLines {
+    pub show_glyphs: bool,
+    pub show_streamlines: bool,
+    /// cells between glyphs
+    pub glyph_spacing: usize,
+    /// length of a glyph for a speed of one cell per step
+    pub glyph_scale: f32,
+    /// cells between streamline seeds
+    pub streamline_spacing: usize,
+    /// maximum number of steps traced for each streamline
+    pub streamline_length: usize,
+}
+impl Default for FlowLines {
+    fn default() -> Self {
+        Self {
+            show_glyphs: false,
+            show_streamlines: false,
+            glyph_spacing: 4,
+            glyph_scale: 8.0,
+            streamline_spacing: 8,
+            streamline_length: 64,
+        }
+    }
+}
+impl FlowLines {
+    /// Colors of the lines, glyphs on the left, then streamlines and the transect
+    pub fn texture() -> Texture {
+        Texture {
+            dimensions: Vector2::new(3, 1),
+            pixels: vec![
+                Vector4::new(230, 60, 40, 255),
+                Vector4::new(250, 250, 250, 255),
+                Vector4::new(250, 210, 40, 255),
+            ],
+        }
+    }
+    /// Line list with every enabled kind of line and the `transect` polyline, `None`
+    /// if there is nothing to draw
+    pub fn mesh(&self, terrain: &Terrain, transect: &[Vector3<f32>]) -> Option<Mesh> {
+        let field = VelocityField::new(terrain);
+        let mut lines = vec![];
+        for segment in transect.windows(2) {
+            lines.push((segment[0], segment[1], TRANSECT_UV));
+        }
+        if self.show_glyphs {
+            for (start, end) in field.glyphs(self.glyph_spacing, self.glyph_scale) {
+                lines.push((start, end, GLYPH_UV));
+            }
+        }
+        if self.show_streamlines {
+            for line in field.streamlines(self.streamline_spacing, self.streamline_length) {
+                for segment in line.windows(2) {
+                    lines.push((segment[0], segment[1], STREAMLINE_UV));
+                }
+            }
+        }
+        if lines.is_empty() {
+            return None;
+        }
+        let mut vertices = Vec::with_capacity(lines.len() * 16);
+        for (start, end, uv) in lines {
+            for point in [start, end].iter() {
+                vertices
+                    .extend_from_slice(&[point.x, point.y, point.z, uv[0], uv[1], 0.0, 1.0, 0.0]);
+            }
+        }
+        Some(Mesh {
+            vertices,
+            description: ItemDesc::default_model(),
+            indices: None,
+        })
+    }
+    pub fn draw_gui(&mut self, context: &mut CtxRef) {
+        egui::Window::new("Flow Lines").show(context, |ui| {
+            ui.checkbox(&mut self.show_glyphs, "Velocity Arrows");
+            ui.add(egui::Slider::usize(&mut self.glyph_spacing, 1..=32).text("Arrow Spacing"));
+            ui.add(egui::Slider::f32(&mut self.glyph_scale, 0.1..=64.0).text("Arrow Scale"));
+            ui.separator();
+            ui.checkbox(&mut self.show_streamlines, "Streamlines");
+            ui.add(egui::Slider::usize(&mut self.streamline_spacing, 1..=64).text("Seed Spacing"));
+            ui.add(egui::Slider::usize(&mut self.streamline_length, 1..=512).text("Max Steps"));
+        });
+    }
+}
+/// Velocity and water surface at every grid point
+struct VelocityField {
+    velocities: Vec<Vector2<f32>>,
+    surface: Vec<f32>,
+    dimensions: Vector2<usize>,
+}
+impl VelocityField {
+    fn new(terrain: &Terrain) -> Self {
+        Self {
+            velocities: terrain.velocities(),
+            surface: terrain.surface_heights(),
+            dimensions: terrain.dimensions,
+        }
+    }
+    /// Bilinear interpolation of the velocity and surface, `None` outside of the grid
+    fn sample(&self, position: Vector2<f32>) -> Option<(Vector2<f32>, f32)> {
+        let max = Vector2::new(self.dimensions.x - 1, self.dimensions.y - 1);
+        if position.x < 0.0
+            || position.y < 0.0
+            || position.x > max.x as f32
+            || position.y > max.y as f32
+        {
+            return None;
+        }
+        let x0 = (position.x.floor() as usize).min(max.x.saturating_sub(1));
+        let y0 = (position.y.floor() as usize).min(max.y.saturating_sub(1));
+        let x1 = (x0 + 1).min(max.x);
+        let y1 = (y0 + 1).min(max.y);
+        let tx = position.x - x0 as f32;
+        let ty = position.y - y0 as f32;
+        let index = |x: usize, y: usize| x * self.dimensions.y + y;
+        let weights = [
+            (index(x0, y0), (1.0 - tx) * (1.0 - ty)),
+            (index(x1, y0), tx * (1.0 - ty)),
+            (index(x0, y1), (1.0 - tx) * ty),
+            (index(x1, y1), tx * ty),
+        ];
+        let mut velocity = Vector2::new(0.0, 0.0);
+        let mut surface = 0.0;
+        for (i, weight) in weights.iter() {
+            velocity += self.velocities[*i] * *weight;
+            surface += self.surface[*i] * *weight;
+        }
+        Some((velocity, surface))
+    }
+    /// Point in world space above the water
+    fn world_position(&self, position: Vector2<f32>, surface: f32) -> Vector3<f32> {
+        Vector3::new(position.x, surface + LINE_OFFSET, position.y)
+    }
+    /// Grid points `spacing` apart, offset so they are centered on the grid
+    fn seeds(&self, spacing: usize) -> Vec<Vector2<usize>> {
+        let spacing = spacing.max(1);
+        let offset = |size: usize| ((size - 1) % spacing) / 2;
+        let mut seeds = vec![];
+        for x in (offset(self.dimensions.x)..self.dimensions.x).step_by(spacing) {
+            for y in (offset(self.dimensions.y)..self.dimensions.y).step_by(spacing) {
+                seeds.push(Vector2::new(x, y));
+            }
+        }
+        seeds
+    }
+    /// Arrows drawn as line segments
+    fn glyphs(&self, spacing: usize, scale: f32) -> Vec<(Vector3<f32>, Vector3<f32>)> {
+        let mut lines = vec![];
+        for seed in self.seeds(spacing) {
+            let index = seed.x * self.dimensions.y + seed.y;
+            let velocity = self.velocities[index];
+            if velocity.norm() < MIN_SPEED {
+                continue;
+            }
+            let start = self.world_position(seed.cast(), self.surface[index]);
+            let shaft = Vector3::new(velocity.x, 0.0, velocity.y) * scale;
+            let end = start + shaft;
+            lines.push((start, end));
+            for angle in [HEAD_ANGLE, -HEAD_ANGLE].iter() {
+                let (sin, cos) = angle.sin_cos();
+                let head = Vector3::new(
+                    shaft.x * cos - shaft.z * sin,
+                    0.0,
+                    shaft.x * sin + shaft.z * cos,
+                ) * HEAD_LENGTH;
+                lines.push((end, end + head));
+            }
+        }
+        lines
+    }
+    /// Traces streamlines downstream with the midpoint method. Each streamline is a
+    /// list of connected points
+    fn streamlines(&self, spacing: usize, max_steps: usize) -> Vec<Vec<Vector3<f32>>> {
+        let mut streamlines = vec![];
+        for seed in self.seeds(spacing) {
+            let mut position: Vector2<f32> = seed.cast();
+            let mut line = vec![];
+            if let Some((_, surface)) = self.sample(position) {
+                line.push(self.world_position(position, surface));
+            }
+            for _ in 0..max_steps {
+                let direction = |velocity: Vector2<f32>| {
+                    if velocity.norm() < MIN_SPEED {
+                        None
+                    } else {
+                        Some(velocity.normalize() * STREAMLINE_STEP)
+                    }
+                };
+                let next = self
+                    .sample(position)
+                    .and_then(|(velocity, _)| direction(velocity))
+                    .and_then(|step| self.sample(position + step / 2.0))
+                    .and_then(|(velocity, _)| direction(velocity))
+                    .map(|step| position + step)
+                    .and_then(|next| self.sample(next).map(|(_, surface)| (next, surface)));
+                match next {
+                    Some((next, surface)) => {
+                        position = next;
+                        line.push(self.world_position(position, surface));
+                    }
+                    None => break,
+                }
+            }
+            if line.len() > 1 {
+                streamlines.push(line);
+            }
+        }
+        streamlines
+    }
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    fn uniform_flow(velocity: Vector2<f32>) -> VelocityField {
+        let dimensions = Vector2::new(9, 7);
+        VelocityField {
+            velocities: vec![velocity; dimensions.x * dimensions.y],
+            surface: vec![1.0; dimensions.x * dimensions.y],
+            dimensions,
+        }
+    }
+    #[test]
+    fn still_water_has_no_lines() {
+        let field = uniform_flow(Vector2::new(0.0, 0.0));
+        assert!(field.glyphs(1, 1.0).is_empty());
+        assert!(field.streamlines(1, 16).is_empty());
+    }
+    #[test]
+    fn glyphs_point_downstream() {
+        let field = uniform_flow(Vector2::new(0.5, 0.0));
+        let glyphs = field.glyphs(4, 2.0);
+        // three lines for each of the 3x2 seeds
+        assert_eq!(glyphs.len(), 3 * 6);
+        let (start, end) = glyphs[0];
+        assert_eq!(end - start, Vector3::new(1.0, 0.0, 0.0));
+    }
+    #[test]
+    fn streamlines_follow_flow() {
+        let field = uniform_flow(Vector2::new(0.0, 1.0));
+        for line in field.streamlines(4, 100) {
+            let start = line[0];
+            let end = line[line.len() - 1];
+            assert_eq!(start.x, end.x);
+            assert!(end.z > start.z);
+            // stops at the edge of the grid
+            assert!(end.z <= 6.0);
+        }
+    }
+}
@@ -0,0 +1,411 @@
+use super::Terrain;
+use crate::prelude::{
+    ErrorType, Indices, ItemDesc, Mesh, Model, RenderBackend, RenderingContext, RuntimeMesh,
+    RuntimeTexture, Shader, ShaderBind, Texture,
+};
+use nalgebra::{Vector2, Vector3, Vector4};
+/// Number of cells along each side of a chunk
+const CHUNK_SIZE: usize = 32;
+/// Coarsest level of detail, a chunk is then a single cell
+const MAX_LOD: u32 = 5;
+/// Chunks closer than twice this distance are drawn at full resolution. Every
+/// doubling of the distance after that halves the resolution
+const LOD_DISTANCE: f32 = 48.0;
+/// Terrain split into square chunks (geomipmapping). Each chunk is drawn at a
+/// resolution picked from its distance to the camera. Chunks hang skirts down
+/// from their borders to hide cracks between neighbors with different resolutions.
+/// The bed is drawn with the world shader and the water surface above it with the
+/// water shader
+pub struct TerrainChunks {
+    chunks: Vec<TerrainChunk>,
+    bed_texture: RuntimeTexture,
+    water_texture: RuntimeTexture,
+    /// false color texture drawn instead of the water when an overlay is shown
+    overlay_texture: Option<RuntimeTexture>,
+    /// `Terrain::generation` and `Terrain::bed_generation` the meshes were built from
+    generation: u64,
+    bed_generation: u64,
+}
+struct TerrainChunk {
+    /// first vertex of the chunk
+    origin: Vector2<usize>,
+    /// number of cells in the chunk
+    size: Vector2<usize>,
+    lod: u32,
+    bed: RuntimeMesh,
+    water: RuntimeMesh,
+}
+/// Heights of one surface for the whole terrain, gathered at most once per update
+struct Layer {
+    heights: Vec<f32>,
+    /// depth and speed of the water at every grid point, empty for the bed
+    water: Vec<Vector2<f32>>,
+}
+impl Layer {
+    fn bed(terrain: &Terrain) -> Self {
+        Self {
+            heights: terrain.bed.data.clone(),
+            water: vec![],
+        }
+    }
+    fn water(terrain: &Terrain) -> Self {
+        Self {
+            heights: terrain.surface_heights(),
+            water: terrain
+                .heights
+                .data
+                .iter()
+                .zip(terrain.speeds())
+                .map(|(depth, speed)| Vector2::new(*depth, speed))
+                .collect(),
+        }
+    }
+    fn description(&self) -> Vec<ItemDesc> {
+        if self.water.is_empty() {
+            ItemDesc::default_model()
+        } else {
+            ItemDesc::water_model()
+        }
+    }
+}
+impl TerrainChunks {
+    /// Builds every chunk at full resolution, `update` picks the level of detail
+    pub fn new(
+        terrain: &Terrain,
+        graphics: &mut RenderingContext,
+        shaders: &ShaderBind,
+    ) -> Result<Self, ErrorType> {
+        let cells = Vector2::new(
+            terrain.dimensions.x.saturating_sub(1),
+            terrain.dimensions.y.saturating_sub(1),
+        );
+        let bed = Layer::bed(terrain);
+        let water = Layer::water(terrain);
+        let mut chunks = vec![];
+        for x in (0..cells.x).step_by(CHUNK_SIZE) {
+            for y in (0..cells.y).step_by(CHUNK_SIZE) {
+                let origin = Vector2::new(x, y);
+                let size = Vector2::new(CHUNK_SIZE.min(cells.x - x), CHUNK_SIZE.min(cells.y - y));
+                chunks.push(TerrainChunk {
+                    origin,
+                    size,
+                    lod: 0,
+                    bed: graphics.build_mesh(
+                        chunk_mesh(&bed, terrain.dimensions, origin, size, 0),
+                        &shaders["world"],
+                    )?,
+                    water: graphics.build_mesh(
+                        chunk_mesh(&water, terrain.dimensions, origin, size, 0),
+                        &shaders["water"],
+                    )?,
+                });
+            }
+        }
+        let bed_texture = graphics.build_texture(
+            Texture::constant_color(Vector4::new(156, 134, 96, 255), Vector2::new(2, 2)),
+            &shaders["world"],
+        )?;
+        let water_texture = graphics.build_texture(
+            Texture::constant_color(Vector4::new(32, 65, 147, 255), Vector2::new(2, 2)),
+            &shaders["water"],
+        )?;
+        Ok(Self {
+            chunks,
+            bed_texture,
+            water_texture,
+            overlay_texture: None,
+            generation: terrain.generation(),
+            bed_generation: terrain.bed_generation(),
+        })
+    }
+    /// Uploads the heights of the terrain if they changed since the last update and
+    /// rebuilds chunks whose distance to the camera changed their level of detail.
+    /// The layers are only gathered from the terrain when a chunk needs them
+    pub fn update(
+        &mut self,
+        terrain: &Terrain,
+        camera_position: Vector3<f32>,
+        graphics: &mut RenderingContext,
+        shaders: &ShaderBind,
+    ) -> Result<(), ErrorType> {
+        let bed_changed = self.bed_generation != terrain.bed_generation();
+        let water_changed = self.generation != terrain.generation();
+        let mut bed = None;
+        let mut water = None;
+        let dimensions = terrain.dimensions;
+        for chunk in self.chunks.iter_mut() {
+            let lod = chunk.lod_for(terrain, camera_position);
+            if lod != chunk.lod {
+                let bed = bed.get_or_insert_with(|| Layer::bed(terrain));
+                graphics.delete_mesh(&mut chunk.bed)?;
+                chunk.bed = graphics.build_mesh(
+                    chunk_mesh(bed, dimensions, chunk.origin, chunk.size, lod),
+                    &shaders["world"],
+                )?;
+                let water = water.get_or_insert_with(|| Layer::water(terrain));
+                graphics.delete_mesh(&mut chunk.water)?;
+                chunk.water = graphics.build_mesh(
+                    chunk_mesh(water, dimensions, chunk.origin, chunk.size, lod),
+                    &shaders["water"],
+                )?;
+                chunk.lod = lod;
+                continue;
+            }
+            if bed_changed {
+                let bed = bed.get_or_insert_with(|| Layer::bed(terrain));
+                graphics.update_mesh(
+                    &mut chunk.bed,
+                    0,
+                    &chunk_vertices(bed, dimensions, chunk.origin, chunk.size, lod),
+                )?;
+            }
+            if water_changed {
+                let water = water.get_or_insert_with(|| Layer::water(terrain));
+                graphics.update_mesh(
+                    &mut chunk.water,
+                    0,
+                    &chunk_vertices(water, dimensions, chunk.origin, chunk.size, lod),
+                )?;
+            }
+        }
+        self.generation = terrain.generation();
+        self.bed_generation = terrain.bed_generation();
+        Ok(())
+    }
+    /// Replaces the pixels of the overlay texture, `None` turns the overlay off
+    pub fn set_overlay(
+        &mut self,
+        overlay: Option<Texture>,
+        graphics: &mut RenderingContext,
+        shader: &Shader,
+    ) -> Result<(), ErrorType> {
+        match (self.overlay_texture.as_mut(), overlay) {
+            (Some(texture), Some(overlay)) => graphics.update_texture(texture, overlay)?,
+            (None, Some(overlay)) => {
+                self.overlay_texture = Some(graphics.build_texture(overlay, shader)?)
+            }
+            (_, None) => {
+                if let Some(mut texture) = self.overlay_texture.take() {
+                    graphics.delete_texture(&mut texture);
+                }
+            }
+        }
+        Ok(())
+    }
+    /// Draws the bed of every chunk with the world shader, the model and view
+    /// matrices must already be sent
+    pub fn draw_bed(&self, graphics: &mut RenderingContext, shader: &Shader) {
+        graphics.bind_texture(&self.bed_texture, shader);
+        for chunk in self.chunks.iter() {
+            graphics.draw_mesh(&chunk.bed);
+        }
+    }
+    /// Draws the water surface of every chunk with the water shadow shader
+    pub fn draw_water_shadow(&self, graphics: &mut RenderingContext) {
+        for chunk in self.chunks.iter() {
+            graphics.draw_mesh(&chunk.water);
+        }
+    }
+    /// Draws the water surface of every chunk with the water shader. Must be drawn
+    /// after everything under the water
+    pub fn draw_water(
+        &self,
+        graphics: &mut RenderingContext,
+        shader: &Shader,
+    ) -> Result<(), ErrorType> {
+        if let Some(overlay) = self.overlay_texture.as_ref() {
+            graphics.bind_texture(overlay, shader);
+            graphics.send_float_uniform(shader, "overlay", 1.0)?;
+        } else {
+            graphics.bind_texture(&self.water_texture, shader);
+            graphics.send_float_uniform(shader, "overlay", 0.0)?;
+        }
+        for chunk in self.chunks.iter() {
+            graphics.draw_mesh(&chunk.water);
+        }
+        Ok(())
+    }
+    /// Frees the gpu resources of every chunk
+    pub fn delete(&mut self, graphics: &mut RenderingContext) -> Result<(), ErrorType> {
+        for mut chunk in self.chunks.drain(..) {
+            graphics.delete_mesh(&mut chunk.bed)?;
+            graphics.delete_mesh(&mut chunk.water)?;
+        }
+        graphics.delete_texture(&mut self.bed_texture);
+        graphics.delete_texture(&mut self.water_texture);
+        if let Some(mut texture) = self.overlay_texture.take() {
+            graphics.delete_texture(&mut texture);
+        }
+        Ok(())
+    }
+}
+impl TerrainChunk {
+    /// Level of detail picked from the distance between the camera and the water
+    /// surface at the center of the chunk
+    fn lod_for(&self, terrain: &Terrain, camera_position: Vector3<f32>) -> u32 {
+        let center = Vector2::new(
+            self.origin.x + self.size.x / 2,
+            self.origin.y + self.size.y / 2,
+        );
+        let height = terrain.heights[center] + terrain.bed[center];
+        let distance =
+            (Vector3::new(center.x as f32, height, center.y as f32) - camera_position).norm();
+        ((distance / LOD_DISTANCE).max(1.0).log2().floor() as u32).min(MAX_LOD)
+    }
+}
+/// Offsets of the vertices sampled along one side of a chunk, the last vertex is
+/// always included so that neighboring chunks share their borders
+fn samples(size: usize, lod: u32) -> Vec<usize> {
+    let mut samples: Vec<usize> = (0..=size).step_by(1 << lod).collect();
+    if samples.last() != Some(&size) {
+        samples.push(size);
+    }
+    samples
+}
+/// Vertices along the border of the chunk as indices into the grid of sampled
+/// vertices, in order around the chunk
+fn border(nx: usize, ny: usize) -> Vec<usize> {
+    let index = |i: usize, j: usize| i * ny + j;
+    let mut border = vec![];
+    border.extend((0..nx).map(|i| index(i, 0)));
+    border.extend((1..ny).map(|j| index(nx - 1, j)));
+    border.extend((0..nx - 1).rev().map(|i| index(i, ny - 1)));
+    border.extend((1..ny - 1).rev().map(|j| index(0, j)));
+    border
+}
+fn chunk_vertices(
+    layer: &Layer,
+    dimensions: Vector2<usize>,
+    origin: Vector2<usize>,
+    size: Vector2<usize>,
+    lod: u32,
+) -> Vec<f32> {
+    let xs = samples(size.x, lod);
+    let ys = samples(size.y, lod);
+    let vertex_size: usize = layer
+        .description()
+        .iter()
+        .map(|d| d.number_components)
+        .sum();
+    let mut min_height = f32::INFINITY;
+    let mut max_height = f32::NEG_INFINITY;
+    let mut vertices =
+        Vec::with_capacity((xs.len() * ys.len() + 2 * (xs.len() + ys.len())) * vertex_size);
+    for dx in xs.iter() {
+        for dy in ys.iter() {
+            let x = origin.x + dx;
+            let y = origin.y + dy;
+            let height = layer.heights[x * dimensions.y + y];
+            min_height = min_height.min(height);
+            max_height = max_height.max(height);
+            let normal = Model::normal_from_heights(&layer.heights, dimensions, x, y);
+            #[rustfmt::skip]
+            vertices.extend_from_slice(&[
+                //position
+                x as f32, height, y as f32,
+                //uv
+                x as f32 / (dimensions.x - 1).max(1) as f32,
+                y as f32 / (dimensions.y - 1).max(1) as f32,
+                //normal
+                normal.x, normal.y, normal.z,
+            ]);
+            if let Some(water) = layer.water.get(x * dimensions.y + y) {
+                vertices.extend_from_slice(&[water.x, water.y]);
+            }
+        }
+    }
+    // skirts are copies of the border vertices moved down far enough to cover any crack
+    let skirt_depth = max_height - min_height + 1.0;
+    for i in border(xs.len(), ys.len()) {
+        let mut skirt = vertices[i * vertex_size..(i + 1) * vertex_size].to_vec();
+        skirt[1] -= skirt_depth;
+        if !layer.water.is_empty() {
+            // zero depth makes the water skirts fade out instead of showing as curtains
+            skirt[8] = 0.0;
+        }
+        vertices.extend_from_slice(&skirt);
+    }
+    vertices
+}
+fn chunk_indices(size: Vector2<usize>, lod: u32) -> Indices {
+    let nx = samples(size.x, lod).len();
+    let ny = samples(size.y, lod).len();
+    let index = |i: usize, j: usize| (i * ny + j) as u32;
+    let mut indices = vec![];
+    for i in 0..nx - 1 {
+        for j in 0..ny - 1 {
+            #[rustfmt::skip]
+            indices.extend_from_slice(&[
+                index(i, j), index(i, j + 1), index(i + 1, j),
+                index(i, j + 1), index(i + 1, j + 1), index(i + 1, j),
+            ]);
+        }
+    }
+    let border = border(nx, ny);
+    let skirt_start = nx * ny;
+    for k in 0..border.len() {
+        let next = (k + 1) % border.len();
+        let top0 = border[k] as u32;
+        let top1 = border[next] as u32;
+        let bottom0 = (skirt_start + k) as u32;
+        let bottom1 = (skirt_start + next) as u32;
+        indices.extend_from_slice(&[top0, bottom0, top1, top1, bottom0, bottom1]);
+    }
+    Indices::new(indices, skirt_start + border.len())
+}
+fn chunk_mesh(
+    layer: &Layer,
+    dimensions: Vector2<usize>,
+    origin: Vector2<usize>,
+    size: Vector2<usize>,
+    lod: u32,
+) -> Mesh {
+    Mesh {
+        vertices: chunk_vertices(layer, dimensions, origin, size, lod),
+        description: layer.description(),
+        indices: Some(chunk_indices(size, lod)),
+    }
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn samples_include_end() {
+        assert_eq!(samples(4, 0), vec![0, 1, 2, 3, 4]);
+        assert_eq!(samples(4, 1), vec![0, 2, 4]);
+        assert_eq!(samples(5, 1), vec![0, 2, 4, 5]);
+        assert_eq!(samples(3, 5), vec![0, 3]);
+    }
+    #[test]
+    fn border_loops_once() {
+        let b = border(3, 4);
+        assert_eq!(b.len(), 2 * (3 + 4) - 4);
+        let mut sorted = b.clone();
+        sorted.sort_unstable();
+        sorted.dedup();
+        assert_eq!(sorted.len(), b.len());
+        // interior vertex (1,1) and (1,2) are not on the border
+        assert!(!b.contains(&(4 + 1)));
+        assert!(!b.contains(&(4 + 2)));
+    }
+    #[test]
+    fn chunk_layout_matches() {
+        let terrain = Terrain::flat(Vector2::new(40, 20), 1.0);
+        for layer in [Layer::bed(&terrain), Layer::water(&terrain)].iter() {
+            let vertex_size: usize = layer
+                .description()
+                .iter()
+                .map(|d| d.number_components)
+                .sum();
+            for lod in 0..=MAX_LOD {
+                let size = Vector2::new(CHUNK_SIZE, 19);
+                let vertices =
+                    chunk_vertices(layer, terrain.dimensions, Vector2::new(0, 0), size, lod);
+                let indices = chunk_indices(size, lod);
+                assert_eq!(vertices.len() % vertex_size, 0);
+                let num_vertices = vertices.len() / vertex_size;
+                assert!((0..indices.len()).all(|i| indices.get(i) < num_vertices));
+            }
+        }
+    }
+}
@@ -0,0 +1,321 @@
+use super::Terrain;
+use crate::prelude::Texture;
+use egui::{
+    math::{pos2, vec2, Rect},
+    CtxRef, Srgba,
+};
+use nalgebra::{Vector2, Vector4};
+/// Quantity of the simulation that can be drawn over the water surface
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ScalarField {
+    Depth,
+    Speed,
+    Divergence,
+    Vorticity,
+    Slope,
+    /// change of the bed made by sculpting
+    BedChange,
+}
+impl ScalarField {
+    pub const ALL: [ScalarField; 6] = [
+        Self::Depth,
+        Self::Speed,
+        Self::Divergence,
+        Self::Vorticity,
+        Self::Slope,
+        Self::BedChange,
+    ];
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Depth => "Water Depth",
+            Self::Speed => "Speed",
+            Self::Divergence => "Divergence",
+            Self::Vorticity => "Vorticity",
+            Self::Slope => "Surface Slope",
+            Self::BedChange => "Sculpted Bed Change",
+        }
+    }
+    /// Fields where the sign matters are centered on zero
+    fn is_signed(&self) -> bool {
+        matches!(self, Self::Divergence | Self::Vorticity | Self::BedChange)
+    }
+    pub fn default_colormap(&self) -> Colormap {
+        if self.is_signed() {
+            Colormap::Diverging
+        } else {
+            Colormap::Viridis
+        }
+    }
+    /// Value of the field at every grid point, laid out like the heights of the terrain
+    pub fn compute(&self, terrain: &Terrain) -> Vec<f32> {
+        let dimensions = terrain.dimensions;
+        let grid = |f: &dyn Fn(usize, usize) -> f32| {
+            let mut values = Vec::with_capacity(dimensions.x * dimensions.y);
+            for x in 0..dimensions.x {
+                for y in 0..dimensions.y {
+                    values.push(f(x, y));
+                }
+            }
+            values
+        };
+        match self {
+            Self::Depth => terrain.heights.data.clone(),
+            Self::Speed => terrain.speeds(),
+            Self::Divergence => {
+                let velocity = &terrain.velocity;
+                grid(&|x, y| {
+                    velocity[Vector2::new(x + 1, y)].x - velocity[Vector2::new(x, y)].x
+                        + velocity[Vector2::new(x, y + 1)].y
+                        - velocity[Vector2::new(x, y)].y
+                })
+            }
+            Self::Vorticity => {
+                let velocities = terrain.velocities();
+                let u: Vec<f32> = velocities.iter().map(|v| v.x).collect();
+                let v: Vec<f32> = velocities.iter().map(|v| v.y).collect();
+                grid(&|x, y| gradient(&v, dimensions, x, y).x - gradient(&u, dimensions, x, y).y)
+            }
+            Self::Slope => {
+                let surface = terrain.surface_heights();
+                grid(&|x, y| gradient(&surface, dimensions, x, y).norm())
+            }
+            Self::BedChange => terrain.sculpted.data.clone(),
+        }
+    }
+    /// Range of values mapped onto the colormap
+    fn range(&self, values: &[f32]) -> (f32, f32) {
+        let (min, max) = if self.is_signed() {
+            let max = values.iter().fold(0.0f32, |m, v| m.max(v.abs()));
+            (-max, max)
+        } else {
+            values
+                .iter()
+                .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), v| {
+                    (min.min(*v), max.max(*v))
+                })
+        };
+        if max - min < f32::EPSILON {
+            (min - f32::EPSILON, min + f32::EPSILON)
+        } else {
+            (min, max)
+        }
+    }
+}
+/// Gradient of a grid from central differences, one sided differences are used
+/// along the edges
+fn gradient(values: &[f32], dimensions: Vector2<usize>, x: usize, y: usize) -> Vector2<f32> {
+    let value = |x: usize, y: usize| values[x * dimensions.y + y];
+    let x0 = x.saturating_sub(1);
+    let x1 = (x + 1).min(dimensions.x - 1);
+    let y0 = y.saturating_sub(1);
+    let y1 = (y + 1).min(dimensions.y - 1);
+    Vector2::new(
+        if x1 > x0 {
+            (value(x1, y) - value(x0, y)) / (x1 - x0) as f32
+        } else {
+            0.0
+        },
+        if y1 > y0 {
+            (value(x, y1) - value(x, y0)) / (y1 - y0) as f32
+        } else {
+            0.0
+        },
+    )
+}
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Colormap {
+    Viridis,
+    Turbo,
+    Diverging,
+}
+impl Colormap {
+    pub const ALL: [Colormap; 3] = [Self::Viridis, Self::Turbo, Self::Diverging];
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Viridis => "Viridis",
+            Self::Turbo => "Turbo",
+            Self::Diverging => "Diverging",
+        }
+    }
+    /// Evenly spaced samples of the colormap
+    fn control_points(&self) -> &'static [[u8; 3]] {
+        match self {
+            Self::Viridis => &[
+                [68, 1, 84],
+                [71, 44, 122],
+                [59, 82, 139],
+                [44, 114, 142],
+                [33, 145, 140],
+                [40, 174, 128],
+                [94, 201, 98],
+                [173, 220, 48],
+                [253, 231, 37],
+            ],
+            Self::Turbo => &[
+                [48, 18, 59],
+                [67, 74, 190],
+                [69, 124, 249],
+                [44, 170, 243],
+                [24, 214, 203],
+                [70, 247, 132],
+                [141, 253, 74],
+                [206, 234, 52],
+                [251, 185, 56],
+                [237, 108, 30],
+                [122, 4, 3],
+            ],
+            // cool to warm through a light gray
+            Self::Diverging => &[
+                [59, 76, 192],
+                [141, 176, 254],
+                [221, 221, 221],
+                [244, 154, 123],
+                [180, 4, 38],
+            ],
+        }
+    }
+    /// Color at `t`, `t` is clamped to 0-1
+    pub fn color(&self, t: f32) -> Vector4<u8> {
+        let points = self.control_points();
+        let position = t.clamp(0.0, 1.0) * (points.len() - 1) as f32;
+        let i = (position.floor() as usize).min(points.len() - 2);
+        let fraction = position - i as f32;
+        let channel = |c: usize| {
+            (points[i][c] as f32 * (1.0 - fraction) + points[i + 1][c] as f32 * fraction).round()
+                as u8
+        };
+        Vector4::new(channel(0), channel(1), channel(2), 255)
+    }
+}
+/// Settings for coloring the water surface by a scalar field, stored as a resource
+pub struct Overlay {
+    pub field: Option<ScalarField>,
+    pub colormap: Colormap,
+    /// range of the values in the last texture, shown in the legend
+    range: (f32, f32),
+}
+impl Default for Overlay {
+    fn default() -> Self {
+        Self {
+            field: None,
+            colormap: Colormap::Viridis,
+            range: (0.0, 1.0),
+        }
+    }
+}
+impl Overlay {
+    /// Number of color steps drawn in the legend
+    const LEGEND_STEPS: usize = 32;
+    /// Colors every grid point of the terrain by the selected field. The texture
+    /// has one pixel per grid point so it lines up with the uvs of the terrain mesh.
+    /// Returns `None` when no field is selected
+    pub fn texture(&mut self, terrain: &Terrain) -> Option<Texture> {
+        let field = self.field?;
+        let values = field.compute(terrain);
+        self.range = field.range(&values);
+        let (min, max) = self.range;
+        let dimensions = terrain.dimensions;
+        let mut pixels = Vec::with_capacity(values.len());
+        for y in 0..dimensions.y {
+            for x in 0..dimensions.x {
+                let value = values[x * dimensions.y + y];
+                pixels.push(self.colormap.color((value - min) / (max - min)));
+            }
+        }
+        Some(Texture {
+            dimensions: Vector2::new(dimensions.x as u32, dimensions.y as u32),
+            pixels,
+        })
+    }
+    pub fn draw_gui(&mut self, context: &mut CtxRef) {
+        egui::Window::new("Overlay").show(context, |ui| {
+            let previous_field = self.field;
+            ui.radio_value(&mut self.field, None, "Off");
+            for field in ScalarField::ALL.iter() {
+                ui.radio_value(&mut self.field, Some(*field), field.name());
+            }
+            if self.field != previous_field {
+                if let Some(field) = self.field {
+                    self.colormap = field.default_colormap();
+                }
+            }
+            ui.separator();
+            ui.horizontal(|ui| {
+                for colormap in Colormap::ALL.iter() {
+                    ui.radio_value(&mut self.colormap, *colormap, colormap.name());
+                }
+            });
+            if self.field.is_some() {
+                ui.separator();
+                let rect = ui.allocate_space(vec2(200.0, 16.0));
+                let step_width = rect.width() / Self::LEGEND_STEPS as f32;
+                for i in 0..Self::LEGEND_STEPS {
+                    let color = self
+                        .colormap
+                        .color((i as f32 + 0.5) / Self::LEGEND_STEPS as f32);
+                    let left = rect.left() + i as f32 * step_width;
+                    ui.painter().rect_filled(
+                        Rect::from_min_max(
+                            pos2(left, rect.top()),
+                            pos2(left + step_width, rect.bottom()),
+                        ),
+                        0.0,
+                        Srgba::from_rgb(color.x, color.y, color.z),
+                    );
+                }
+                ui.horizontal(|ui| {
+                    ui.label(format!("{:.3}", self.range.0));
+                    ui.label(" to ");
+                    ui.label(format!("{:.3}", self.range.1));
+                });
+            }
+        });
+    }
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn colormap_endpoints() {
+        for colormap in Colormap::ALL.iter() {
+            let points = colormap.control_points();
+            let first = points[0];
+            let last = points[points.len() - 1];
+            assert_eq!(
+                colormap.color(-1.0),
+                Vector4::new(first[0], first[1], first[2], 255)
+            );
+            assert_eq!(
+                colormap.color(2.0),
+                Vector4::new(last[0], last[1], last[2], 255)
+            );
+        }
+        assert_eq!(
+            Colormap::Diverging.color(0.5),
+            Vector4::new(221, 221, 221, 255)
+        );
+    }
+    #[test]
+    fn signed_range_is_centered() {
+        assert_eq!(ScalarField::Vorticity.range(&[-1.0, 3.0]), (-3.0, 3.0));
+        assert_eq!(ScalarField::Depth.range(&[-1.0, 3.0]), (-1.0, 3.0));
+    }
+    #[test]
+    fn still_water_has_no_flow() {
+        let terrain = Terrain::flat(Vector2::new(5, 4), 1.0);
+        for field in [
+            ScalarField::Speed,
+            ScalarField::Divergence,
+            ScalarField::Vorticity,
+            ScalarField::Slope,
+        ]
+        .iter()
+        {
+            assert!(field.compute(&terrain).iter().all(|v| *v == 0.0));
+        }
+        assert!(ScalarField::Depth
+            .compute(&terrain)
+            .iter()
+            .all(|v| *v == 1.0));
+    }
+}
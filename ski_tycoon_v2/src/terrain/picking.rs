@@ -0,0 +1,220 @@
+use super::Terrain;
+use crate::prelude::{DeltaCamera, Event};
+use nalgebra::{Vector2, Vector3};
+/// Distance a ray moves between height checks, in cells
+const RAY_STEP: f32 = 0.25;
+/// Number of bisections used to refine a hit after it has been bracketed
+const REFINE_STEPS: usize = 16;
+/// Point where a ray meets the water surface, or the bed where it is dry
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TerrainHit {
+    /// grid point closest to the hit
+    pub cell: Vector2<usize>,
+    /// position of the hit in world space
+    pub position: Vector3<f32>,
+}
+/// Terrain under the mouse cursor, stored as a resource and updated every frame so
+/// that it follows the camera and the water even when the mouse does not move
+#[derive(Default)]
+pub struct CursorPick {
+    /// last known position of the cursor in pixels from the top left corner
+    cursor: Option<Vector2<f32>>,
+    hit: Option<TerrainHit>,
+}
+impl CursorPick {
+    pub fn update(
+        &mut self,
+        events: &[Event],
+        camera: &DeltaCamera,
+        screen_size: Vector2<u32>,
+        terrain: Option<&Terrain>,
+    ) {
+        for event in events.iter() {
+            if let Event::MouseMove { x, y, .. } = event {
+                self.cursor = Some(Vector2::new(*x, *y));
+            }
+        }
+        self.hit = match (self.cursor, terrain) {
+            (Some(cursor), Some(terrain)) => {
+                let (origin, direction) = camera.screen_ray(cursor, screen_size);
+                terrain.ray_cast(origin, direction)
+            }
+            _ => None,
+        };
+    }
+    pub fn cursor(&self) -> Option<Vector2<f32>> {
+        self.cursor
+    }
+    pub fn hit(&self) -> Option<TerrainHit> {
+        self.hit
+    }
+}
+impl Terrain {
+    /// Height of the water surface at a point in grid coordinates, bilinearly
+    /// interpolated between grid points. `None` outside of the grid
+    pub fn surface_height_at(&self, position: Vector2<f32>) -> Option<f32> {
+        self.interpolate(position, |index| self.heights[index] + self.bed[index])
+    }
+    /// Height of the bed at a point in grid coordinates, like `surface_height_at`
+    pub fn bed_height_at(&self, position: Vector2<f32>) -> Option<f32> {
+        self.interpolate(position, |index| self.bed[index])
+    }
+    /// Bilinear interpolation of a value defined at every grid point
+    fn interpolate(
+        &self,
+        position: Vector2<f32>,
+        value: impl Fn(Vector2<usize>) -> f32,
+    ) -> Option<f32> {
+        let max = Vector2::new(self.dimensions.x - 1, self.dimensions.y - 1);
+        if position.x < 0.0
+            || position.y < 0.0
+            || position.x > max.x as f32
+            || position.y > max.y as f32
+        {
+            return None;
+        }
+        let x0 = (position.x.floor() as usize).min(max.x.saturating_sub(1));
+        let y0 = (position.y.floor() as usize).min(max.y.saturating_sub(1));
+        let x1 = (x0 + 1).min(max.x);
+        let y1 = (y0 + 1).min(max.y);
+        let tx = position.x - x0 as f32;
+        let ty = position.y - y0 as f32;
+        Some(
+            value(Vector2::new(x0, y0)) * (1.0 - tx) * (1.0 - ty)
+                + value(Vector2::new(x1, y0)) * tx * (1.0 - ty)
+                + value(Vector2::new(x0, y1)) * (1.0 - tx) * ty
+                + value(Vector2::new(x1, y1)) * tx * ty,
+        )
+    }
+    /// Like `surface_height_at` with the point moved onto the edge of the grid when
+    /// it is outside
+    pub fn surface_height_clamped(&self, position: Vector2<f32>) -> f32 {
+        self.surface_height_at(self.clamp_to_grid(position))
+            .unwrap_or(0.0)
+    }
+    pub(super) fn clamp_to_grid(&self, position: Vector2<f32>) -> Vector2<f32> {
+        Vector2::new(
+            position.x.clamp(0.0, (self.dimensions.x - 1) as f32),
+            position.y.clamp(0.0, (self.dimensions.y - 1) as f32),
+        )
+    }
+    /// First point where a ray hits the water surface. `direction` must be normalized
+    pub fn ray_cast(&self, origin: Vector3<f32>, direction: Vector3<f32>) -> Option<TerrainHit> {
+        let (min, max) = self.bounds();
+        let (enter, exit) = ray_box(origin, direction, min, max)?;
+        let point = |t: f32| origin + direction * t;
+        // distance above the surface, negative under it
+        let above = |t: f32| {
+            let p = point(t);
+            self.surface_height_at(Vector2::new(p.x, p.z))
+                .map_or(f32::INFINITY, |height| p.y - height)
+        };
+        let hit = |t: f32| {
+            let position = point(t);
+            TerrainHit {
+                cell: Vector2::new(
+                    (position.x.round().max(0.0) as usize).min(self.dimensions.x - 1),
+                    (position.z.round().max(0.0) as usize).min(self.dimensions.y - 1),
+                ),
+                position,
+            }
+        };
+        if above(enter) <= 0.0 {
+            return Some(hit(enter));
+        }
+        let mut previous = enter;
+        while previous < exit {
+            let t = (previous + RAY_STEP).min(exit);
+            if above(t) <= 0.0 {
+                let (mut outside, mut inside) = (previous, t);
+                for _ in 0..REFINE_STEPS {
+                    let middle = (outside + inside) / 2.0;
+                    if above(middle) <= 0.0 {
+                        inside = middle;
+                    } else {
+                        outside = middle;
+                    }
+                }
+                return Some(hit(inside));
+            }
+            previous = t;
+        }
+        None
+    }
+}
+/// Distances along a ray where it enters and leaves a box, `None` if it misses.
+/// The ray starts at the origin so the entry distance is never negative
+fn ray_box(
+    origin: Vector3<f32>,
+    direction: Vector3<f32>,
+    min: Vector3<f32>,
+    max: Vector3<f32>,
+) -> Option<(f32, f32)> {
+    let mut enter = 0.0f32;
+    let mut exit = f32::INFINITY;
+    for axis in 0..3 {
+        if direction[axis].abs() < f32::EPSILON {
+            if origin[axis] < min[axis] || origin[axis] > max[axis] {
+                return None;
+            }
+            continue;
+        }
+        let t0 = (min[axis] - origin[axis]) / direction[axis];
+        let t1 = (max[axis] - origin[axis]) / direction[axis];
+        enter = enter.max(t0.min(t1));
+        exit = exit.min(t0.max(t1));
+    }
+    if enter <= exit {
+        Some((enter, exit))
+    } else {
+        None
+    }
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn straight_down() {
+        let terrain = Terrain::flat(Vector2::new(5, 4), 1.0);
+        let hit = terrain
+            .ray_cast(Vector3::new(2.2, 10.0, 1.9), Vector3::new(0.0, -1.0, 0.0))
+            .expect("ray hits the water");
+        assert_eq!(hit.cell, Vector2::new(2, 2));
+        assert!((hit.position.y - 1.0).abs() < 1e-3);
+    }
+    #[test]
+    fn oblique_ray_finds_surface() {
+        let terrain = Terrain::flat(Vector2::new(20, 4), 1.0);
+        let direction = Vector3::new(1.0, -0.25, 0.0).normalize();
+        let hit = terrain
+            .ray_cast(Vector3::new(0.0, 3.0, 1.0), direction)
+            .expect("ray hits the water");
+        assert!((hit.position.x - 8.0).abs() < 1e-2);
+        assert_eq!(hit.cell, Vector2::new(8, 1));
+    }
+    #[test]
+    fn clamped_height() {
+        let mut terrain = Terrain::flat(Vector2::new(3, 3), 1.0);
+        terrain.bed.data[2 * 3 + 1] = 2.0;
+        let height = terrain.surface_height_clamped(Vector2::new(1.5, 1.0));
+        assert!((height - 2.0).abs() < 1e-5);
+        let height = terrain.surface_height_clamped(Vector2::new(10.0, 1.0));
+        assert!((height - 3.0).abs() < 1e-5);
+        let height = terrain.surface_height_clamped(Vector2::new(-4.0, -1.0));
+        assert!((height - 1.0).abs() < 1e-5);
+    }
+    #[test]
+    fn misses() {
+        let terrain = Terrain::flat(Vector2::new(5, 4), 1.0);
+        // pointing away from the terrain
+        assert_eq!(
+            terrain.ray_cast(Vector3::new(2.0, 10.0, 2.0), Vector3::new(0.0, 1.0, 0.0)),
+            None
+        );
+        // passing beside it
+        assert_eq!(
+            terrain.ray_cast(Vector3::new(10.0, 10.0, 2.0), Vector3::new(0.0, -1.0, 0.0)),
+            None
+        );
+    }
+}
@@ -0,0 +1,445 @@
+use super::{Terrain, TerrainHit};
+use egui::CtxRef;
+use nalgebra::Vector2;
+use std::collections::HashMap;
+/// Strokes kept for undo, the oldest are dropped first
+const UNDO_LIMIT: usize = 64;
+/// Edit made by a brush under the cursor
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BrushTool {
+    Raise,
+    Lower,
+    Smooth,
+    /// levels the bed to its height where the stroke started
+    Flatten,
+    Noise,
+    AddWater,
+    RemoveWater,
+}
+impl BrushTool {
+    pub const ALL: [BrushTool; 7] = [
+        Self::Raise,
+        Self::Lower,
+        Self::Smooth,
+        Self::Flatten,
+        Self::Noise,
+        Self::AddWater,
+        Self::RemoveWater,
+    ];
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Raise => "Raise",
+            Self::Lower => "Lower",
+            Self::Smooth => "Smooth",
+            Self::Flatten => "Flatten",
+            Self::Noise => "Noise",
+            Self::AddWater => "Add Water",
+            Self::RemoveWater => "Remove Water",
+        }
+    }
+}
+/// How the strength of a brush fades towards its edge
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Falloff {
+    Constant,
+    Linear,
+    Smooth,
+}
+impl Falloff {
+    pub const ALL: [Falloff; 3] = [Self::Constant, Self::Linear, Self::Smooth];
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Constant => "Constant",
+            Self::Linear => "Linear",
+            Self::Smooth => "Smooth",
+        }
+    }
+    /// Weight at `distance` from the center as a fraction of the radius, 1 at the
+    /// center and 0 past the edge
+    pub fn weight(&self, distance: f32) -> f32 {
+        if distance > 1.0 {
+            return 0.0;
+        }
+        match self {
+            Self::Constant => 1.0,
+            Self::Linear => 1.0 - distance,
+            Self::Smooth => 1.0 - distance * distance * (3.0 - 2.0 * distance),
+        }
+    }
+}
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Brush {
+    /// radius in cells
+    pub radius: f32,
+    /// change in height at the center of the brush every frame, for smooth and
+    /// flatten the fraction of the way to the target height
+    pub strength: f32,
+    pub falloff: Falloff,
+}
+impl Default for Brush {
+    fn default() -> Self {
+        Self {
+            radius: 3.0,
+            strength: 0.05,
+            falloff: Falloff::Smooth,
+        }
+    }
+}
+/// Changes made to the bed and the water by one stroke, keyed by the index of the
+/// cell in the grids of the terrain so only the cells under the brush are kept.
+/// Stored as differences so undoing does not roll back the simulation
+#[derive(Clone, Debug)]
+struct Stroke {
+    dimensions: Vector2<usize>,
+    /// change of the bed and the water of every touched cell
+    cells: HashMap<usize, (f32, f32)>,
+}
+impl Stroke {
+    fn new(dimensions: Vector2<usize>) -> Self {
+        Self {
+            dimensions,
+            cells: HashMap::new(),
+        }
+    }
+    fn is_empty(&self) -> bool {
+        self.cells
+            .values()
+            .all(|(bed, water)| *bed == 0.0 && *water == 0.0)
+    }
+    fn add(&mut self, index: usize, bed: f32, water: f32) {
+        let cell = self.cells.entry(index).or_insert((0.0, 0.0));
+        cell.0 += bed;
+        cell.1 += water;
+    }
+    /// Adds the changes to the terrain, or takes them away when `sign` is negative
+    fn apply(&self, terrain: &mut Terrain, sign: f32) {
+        if self.dimensions != terrain.dimensions {
+            return;
+        }
+        for (index, (bed, water)) in self.cells.iter() {
+            terrain.change_bed(*index, sign * bed);
+            let height = &mut terrain.heights.data[*index];
+            *height = (*height + sign * water).max(0.0);
+        }
+        terrain.generation += 1;
+    }
+}
+/// Stroke being drawn while the sculpt button is held
+struct ActiveStroke {
+    edit: Stroke,
+    /// bed height under the cursor when the stroke started, used by flatten
+    target: Option<f32>,
+    /// seed of the noise brush, changes every stroke
+    seed: u32,
+}
+/// Brush tools for editing the terrain under the cursor, stored as a resource.
+/// Edits apply every frame the sculpt action is held while the simulation keeps
+/// running, and every stroke can be undone
+pub struct Sculptor {
+    /// selected tool, `None` leaves the mouse to the camera
+    pub tool: Option<BrushTool>,
+    pub brush: Brush,
+    stroke: Option<ActiveStroke>,
+    undo: Vec<Stroke>,
+    redo: Vec<Stroke>,
+    strokes: u32,
+}
+impl Default for Sculptor {
+    fn default() -> Self {
+        Self {
+            tool: None,
+            brush: Brush::default(),
+            stroke: None,
+            undo: vec![],
+            redo: vec![],
+            strokes: 0,
+        }
+    }
+}
+impl Sculptor {
+    /// Applies the brush at `hit` while `held`, the stroke ends when it is released
+    pub fn update(&mut self, held: bool, hit: Option<TerrainHit>, terrain: Option<&mut Terrain>) {
+        let terrain = match terrain {
+            Some(terrain) => terrain,
+            None => {
+                self.stroke = None;
+                return;
+            }
+        };
+        // a new scenario was loaded
+        if self
+            .undo
+            .iter()
+            .chain(self.redo.iter())
+            .chain(self.stroke.iter().map(|stroke| &stroke.edit))
+            .any(|stroke| stroke.dimensions != terrain.dimensions)
+        {
+            self.stroke = None;
+            self.clear_history();
+        }
+        match (held, self.tool, hit) {
+            (true, Some(tool), Some(hit)) => {
+                let center = Vector2::new(hit.position.x, hit.position.z);
+                if self.stroke.is_none() {
+                    self.strokes = self.strokes.wrapping_add(1);
+                    self.stroke = Some(ActiveStroke {
+                        edit: Stroke::new(terrain.dimensions),
+                        target: None,
+                        seed: self.strokes,
+                    });
+                }
+                if let Some(stroke) = self.stroke.as_mut() {
+                    if stroke.target.is_none() {
+                        stroke.target = Some(terrain.bed[hit.cell]);
+                    }
+                    dab(terrain, tool, &self.brush, center, stroke);
+                }
+            }
+            (true, _, _) => (),
+            (false, _, _) => self.finish_stroke(),
+        }
+    }
+    fn finish_stroke(&mut self) {
+        if let Some(stroke) = self.stroke.take() {
+            if !stroke.edit.is_empty() {
+                self.undo.push(stroke.edit);
+                if self.undo.len() > UNDO_LIMIT {
+                    self.undo.remove(0);
+                }
+                self.redo.clear();
+            }
+        }
+    }
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty() || self.stroke.is_some()
+    }
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+    /// Takes back the last stroke, including one that is still being drawn
+    pub fn undo(&mut self, terrain: &mut Terrain) {
+        self.finish_stroke();
+        if let Some(stroke) = self.undo.pop() {
+            stroke.apply(terrain, -1.0);
+            self.redo.push(stroke);
+        }
+    }
+    pub fn redo(&mut self, terrain: &mut Terrain) {
+        if let Some(stroke) = self.redo.pop() {
+            stroke.apply(terrain, 1.0);
+            self.undo.push(stroke);
+        }
+    }
+    pub fn clear_history(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+    pub fn draw_gui(&mut self, context: &mut CtxRef, terrain: Option<&mut Terrain>) {
+        egui::Window::new("Sculpt").show(context, |ui| {
+            ui.radio_value(&mut self.tool, None, "Off");
+            for tool in BrushTool::ALL.iter() {
+                ui.radio_value(&mut self.tool, Some(*tool), tool.name());
+            }
+            ui.separator();
+            ui.add(egui::Slider::f32(&mut self.brush.radius, 0.5..=20.0).text("Radius"));
+            ui.add(egui::Slider::f32(&mut self.brush.strength, 0.001..=0.5).text("Strength"));
+            ui.horizontal(|ui| {
+                for falloff in Falloff::ALL.iter() {
+                    ui.radio_value(&mut self.brush.falloff, *falloff, falloff.name());
+                }
+            });
+            ui.separator();
+            ui.label("Hold the sculpt binding, the right mouse button by default");
+            if let Some(terrain) = terrain {
+                ui.horizontal(|ui| {
+                    if ui.button("Undo").clicked && self.can_undo() {
+                        self.undo(terrain);
+                    }
+                    if ui.button("Redo").clicked && self.can_redo() {
+                        self.redo(terrain);
+                    }
+                });
+            }
+        });
+    }
+}
+/// Applies one frame of a brush centered at `center` in grid coordinates and records
+/// the change in the stroke
+fn dab(
+    terrain: &mut Terrain,
+    tool: BrushTool,
+    brush: &Brush,
+    center: Vector2<f32>,
+    stroke: &mut ActiveStroke,
+) {
+    let dimensions = terrain.dimensions;
+    let radius = brush.radius.max(f32::EPSILON);
+    let min_x = (center.x - radius).floor().max(0.0) as usize;
+    let min_y = (center.y - radius).floor().max(0.0) as usize;
+    let max_x = ((center.x + radius).ceil().max(0.0) as usize).min(dimensions.x - 1);
+    let max_y = ((center.y + radius).ceil().max(0.0) as usize).min(dimensions.y - 1);
+    // new heights are computed before any are written so smoothing reads the old bed
+    let mut changes = vec![];
+    for x in min_x..=max_x {
+        for y in min_y..=max_y {
+            let distance = (Vector2::new(x as f32, y as f32) - center).norm() / radius;
+            let weight = brush.falloff.weight(distance) * brush.strength;
+            if weight <= 0.0 {
+                continue;
+            }
+            let index = Vector2::new(x, y);
+            let bed = terrain.bed[index];
+            let water = terrain.heights[index];
+            let (new_bed, new_water) = match tool {
+                BrushTool::Raise => (bed + weight, water),
+                BrushTool::Lower => (bed - weight, water),
+                BrushTool::Smooth => {
+                    let average = neighbor_average(terrain, x, y);
+                    (bed + (average - bed) * weight.min(1.0), water)
+                }
+                BrushTool::Flatten => {
+                    let target = stroke.target.unwrap_or(bed);
+                    (bed + (target - bed) * weight.min(1.0), water)
+                }
+                BrushTool::Noise => (bed + weight * noise(x, y, stroke.seed), water),
+                BrushTool::AddWater => (bed, water + weight),
+                BrushTool::RemoveWater => (bed, (water - weight).max(0.0)),
+            };
+            changes.push((x * dimensions.y + y, new_bed - bed, new_water - water));
+        }
+    }
+    for (i, bed, water) in changes {
+        terrain.change_bed(i, bed);
+        terrain.heights.data[i] += water;
+        stroke.edit.add(i, bed, water);
+    }
+    terrain.generation += 1;
+}
+/// Average bed height of a grid point and its neighbors inside the grid
+fn neighbor_average(terrain: &Terrain, x: usize, y: usize) -> f32 {
+    let mut sum = 0.0;
+    let mut count = 0.0;
+    for nx in x.saturating_sub(1)..=(x + 1).min(terrain.dimensions.x - 1) {
+        for ny in y.saturating_sub(1)..=(y + 1).min(terrain.dimensions.y - 1) {
+            sum += terrain.bed[Vector2::new(nx, ny)];
+            count += 1.0;
+        }
+    }
+    sum / count
+}
+/// Hash of a grid point mapped to -1 to 1, the same for every frame of a stroke
+fn noise(x: usize, y: usize, seed: u32) -> f32 {
+    let mut hash = (x as u32).wrapping_mul(0x8da6_b343)
+        ^ (y as u32).wrapping_mul(0xd816_3841)
+        ^ seed.wrapping_mul(0xcb1a_b31f);
+    hash ^= hash >> 13;
+    hash = hash.wrapping_mul(0x5bd1_e995);
+    hash ^= hash >> 15;
+    hash as f32 / u32::MAX as f32 * 2.0 - 1.0
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::prelude::SimulationParameters;
+    use nalgebra::Vector3;
+    fn hit(x: usize, y: usize) -> Option<TerrainHit> {
+        Some(TerrainHit {
+            cell: Vector2::new(x, y),
+            position: Vector3::new(x as f32, 0.0, y as f32),
+        })
+    }
+    #[test]
+    fn raise_fades_to_edge() {
+        let mut terrain = Terrain::flat(Vector2::new(11, 11), 1.0);
+        let mut sculptor = Sculptor::default();
+        sculptor.tool = Some(BrushTool::Raise);
+        sculptor.brush.falloff = Falloff::Linear;
+        sculptor.brush.radius = 4.0;
+        sculptor.brush.strength = 1.0;
+        sculptor.update(true, hit(5, 5), Some(&mut terrain));
+        assert!((terrain.bed[Vector2::new(5, 5)] - 1.0).abs() < 1e-6);
+        assert!((terrain.bed[Vector2::new(7, 5)] - 0.5).abs() < 1e-6);
+        assert_eq!(terrain.bed[Vector2::new(10, 5)], 0.0);
+        // water depth is unchanged so the surface follows the bed
+        assert_eq!(terrain.heights[Vector2::new(5, 5)], 1.0);
+        // holding the button keeps raising
+        sculptor.update(true, hit(5, 5), Some(&mut terrain));
+        assert!((terrain.bed[Vector2::new(5, 5)] - 2.0).abs() < 1e-6);
+    }
+    #[test]
+    fn undo_and_redo_strokes() {
+        let mut terrain = Terrain::flat(Vector2::new(8, 8), 1.0);
+        let mut sculptor = Sculptor::default();
+        sculptor.tool = Some(BrushTool::Lower);
+        sculptor.update(true, hit(3, 3), Some(&mut terrain));
+        sculptor.update(false, hit(3, 3), Some(&mut terrain));
+        sculptor.tool = Some(BrushTool::AddWater);
+        sculptor.update(true, hit(4, 4), Some(&mut terrain));
+        sculptor.update(false, hit(4, 4), Some(&mut terrain));
+        // only the cells under the default brush are kept
+        assert!(sculptor.undo.iter().all(|stroke| stroke.cells.len() <= 49));
+        let bed = terrain.bed.data.clone();
+        let water = terrain.heights.data.clone();
+        assert!(bed[3 * 8 + 3] < 0.0);
+        assert!(water[4 * 8 + 4] > 1.0);
+
+        let close =
+            |a: &[f32], b: &[f32]| a.iter().zip(b.iter()).all(|(a, b)| (a - b).abs() < 1e-6);
+        sculptor.undo(&mut terrain);
+        assert!((terrain.heights[Vector2::new(4, 4)] - 1.0).abs() < 1e-6);
+        assert!(close(&terrain.bed.data, &bed));
+        assert!(close(&terrain.sculpted.data, &bed));
+        sculptor.undo(&mut terrain);
+        assert!(terrain.bed.data.iter().all(|b| b.abs() < 1e-6));
+        assert!(terrain.sculpted.data.iter().all(|d| d.abs() < 1e-6));
+        assert!(!sculptor.can_undo());
+
+        sculptor.redo(&mut terrain);
+        sculptor.redo(&mut terrain);
+        assert!(close(&terrain.heights.data, &water));
+        assert!(close(&terrain.bed.data, &bed));
+        assert!(!sculptor.can_redo());
+    }
+    #[test]
+    fn smooth_and_remove_water() {
+        let mut terrain = Terrain::flat(Vector2::new(5, 5), 0.01);
+        terrain.bed.data[2 * 5 + 2] = 9.0;
+        let mut sculptor = Sculptor::default();
+        sculptor.tool = Some(BrushTool::Smooth);
+        sculptor.brush.falloff = Falloff::Constant;
+        sculptor.brush.strength = 1.0;
+        sculptor.update(true, hit(2, 2), Some(&mut terrain));
+        assert!((terrain.bed[Vector2::new(2, 2)] - 1.0).abs() < 1e-6);
+        sculptor.tool = Some(BrushTool::RemoveWater);
+        sculptor.update(true, hit(2, 2), Some(&mut terrain));
+        assert!(terrain.heights.data.iter().all(|h| *h >= 0.0));
+        assert_eq!(terrain.heights[Vector2::new(2, 2)], 0.0);
+    }
+    #[test]
+    fn generations_follow_changes() {
+        let mut terrain = Terrain::flat(Vector2::new(8, 8), 1.0);
+        let mut sculptor = Sculptor::default();
+        let generations = |terrain: &Terrain| (terrain.generation(), terrain.bed_generation());
+        let (water, bed) = generations(&terrain);
+        // adding water leaves the bed meshes alone
+        sculptor.tool = Some(BrushTool::AddWater);
+        sculptor.update(true, hit(4, 4), Some(&mut terrain));
+        sculptor.update(false, hit(4, 4), Some(&mut terrain));
+        assert!(terrain.generation() > water);
+        assert_eq!(terrain.bed_generation(), bed);
+        let (water, bed) = generations(&terrain);
+        sculptor.undo(&mut terrain);
+        assert!(terrain.generation() > water);
+        assert_eq!(terrain.bed_generation(), bed);
+        let (water, bed) = generations(&terrain);
+        sculptor.tool = Some(BrushTool::Raise);
+        sculptor.update(true, hit(4, 4), Some(&mut terrain));
+        assert!(terrain.generation() > water);
+        assert!(terrain.bed_generation() > bed);
+        // a step of the simulation only moves the water
+        let (water, bed) = generations(&terrain);
+        terrain.water_simulation(&SimulationParameters::default());
+        assert!(terrain.generation() > water);
+        assert_eq!(terrain.bed_generation(), bed);
+    }
+}
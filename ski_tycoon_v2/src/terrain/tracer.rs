@@ -0,0 +1,98 @@
+use super::Terrain;
+use nalgebra::{Vector2, Vector3};
+/// Point floating on the water, carried along by the flow every simulation step.
+/// Stored as a component so the camera can follow it
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tracer {
+    /// position in grid coordinates
+    pub grid_position: Vector2<f32>,
+    /// position on the water surface in world space
+    pub position: Vector3<f32>,
+}
+impl Tracer {
+    /// Tracer on the water surface above a point in grid coordinates, clamped to the grid
+    pub fn new(grid_position: Vector2<f32>, terrain: &Terrain) -> Self {
+        let mut tracer = Self {
+            grid_position: terrain.clamp_to_grid(grid_position),
+            position: Vector3::new(0.0, 0.0, 0.0),
+        };
+        tracer.update_position(terrain);
+        tracer
+    }
+    /// Moves the tracer by the water velocity over one simulation step of `step_time`,
+    /// it stops at the edge of the grid
+    pub fn advect(&mut self, terrain: &Terrain, step_time: f32) {
+        if let Some(velocity) = terrain.velocity_at(self.grid_position) {
+            self.grid_position = terrain.clamp_to_grid(self.grid_position + velocity * step_time);
+        }
+        self.update_position(terrain);
+    }
+    fn update_position(&mut self, terrain: &Terrain) {
+        let height = terrain
+            .surface_height_at(self.grid_position)
+            .unwrap_or(self.position.y);
+        self.position = Vector3::new(self.grid_position.x, height, self.grid_position.y);
+    }
+}
+impl Terrain {
+    /// Velocity of the water at a point in grid coordinates, bilinearly interpolated
+    /// between grid points. `None` outside of the grid
+    pub fn velocity_at(&self, position: Vector2<f32>) -> Option<Vector2<f32>> {
+        let max = Vector2::new(self.dimensions.x - 1, self.dimensions.y - 1);
+        if position.x < 0.0
+            || position.y < 0.0
+            || position.x > max.x as f32
+            || position.y > max.y as f32
+        {
+            return None;
+        }
+        let x0 = (position.x.floor() as usize).min(max.x.saturating_sub(1));
+        let y0 = (position.y.floor() as usize).min(max.y.saturating_sub(1));
+        let x1 = (x0 + 1).min(max.x);
+        let y1 = (y0 + 1).min(max.y);
+        let tx = position.x - x0 as f32;
+        let ty = position.y - y0 as f32;
+        // velocities are stored on the faces of cells, see `Terrain::velocities`
+        let velocity = |x: usize, y: usize| {
+            Vector2::new(
+                (self.velocity[Vector2::new(x, y)].x + self.velocity[Vector2::new(x + 1, y)].x)
+                    / 2.0,
+                (self.velocity[Vector2::new(x, y)].y + self.velocity[Vector2::new(x, y + 1)].y)
+                    / 2.0,
+            )
+        };
+        Some(
+            velocity(x0, y0) * (1.0 - tx) * (1.0 - ty)
+                + velocity(x1, y0) * tx * (1.0 - ty)
+                + velocity(x0, y1) * (1.0 - tx) * ty
+                + velocity(x1, y1) * tx * ty,
+        )
+    }
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn floats_on_still_water() {
+        let terrain = Terrain::flat(Vector2::new(4, 6), 2.0);
+        let mut tracer = Tracer::new(Vector2::new(1.5, 10.0), &terrain);
+        assert_eq!(tracer.grid_position, Vector2::new(1.5, 5.0));
+        tracer.advect(&terrain, 0.2);
+        assert_eq!(tracer.position, Vector3::new(1.5, 2.0, 5.0));
+    }
+    #[test]
+    fn carried_by_flow() {
+        let mut terrain = Terrain::flat(Vector2::new(8, 8), 1.0);
+        for v in terrain.velocity.data.iter_mut() {
+            *v = Vector2::new(1.0, -0.5);
+        }
+        let mut tracer = Tracer::new(Vector2::new(4.0, 4.0), &terrain);
+        tracer.advect(&terrain, 0.2);
+        let expected = Vector2::new(4.0, 4.0) + Vector2::new(1.0, -0.5) * 0.2;
+        assert!((tracer.grid_position - expected).norm() < 1e-5);
+        for _ in 0..1000 {
+            tracer.advect(&terrain, 0.2);
+        }
+        assert_eq!(tracer.grid_position, Vector2::new(7.0, 0.0));
+    }
+}
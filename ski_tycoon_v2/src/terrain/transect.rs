@@ -0,0 +1,220 @@
+use super::{Terrain, TerrainHit};
+use crate::gui::line_plot;
+use egui::{CtxRef, Srgba};
+use nalgebra::{Vector2, Vector3};
+/// The line floats this far above the water so it is not hidden by it
+const LINE_OFFSET: f32 = 0.1;
+/// Bed, water and velocity at one point of a transect
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ProfilePoint {
+    /// position in grid coordinates
+    pub position: Vector2<f32>,
+    /// distance from the start of the transect in cells
+    pub distance: f32,
+    pub bed: f32,
+    pub surface: f32,
+    /// water velocity along the transect, positive from the start towards the end
+    pub along: f32,
+    pub speed: f32,
+}
+/// Line across the terrain drawn with the mouse, stored as a resource. The bed, the
+/// water surface and the velocity along it are plotted every frame
+pub struct Transect {
+    /// ends of the line in grid coordinates
+    pub line: Option<(Vector2<f32>, Vector2<f32>)>,
+    /// points sampled along the line
+    pub samples: usize,
+    /// whether the end of the line follows the cursor
+    drawing: bool,
+}
+impl Default for Transect {
+    fn default() -> Self {
+        Self {
+            line: None,
+            samples: 64,
+            drawing: false,
+        }
+    }
+}
+impl Transect {
+    const MAX_SAMPLES: usize = 512;
+    /// Starts a new line where the cursor is when `held` is pressed over the terrain,
+    /// its end follows the cursor until it is released
+    pub fn update(&mut self, held: bool, hit: Option<TerrainHit>) {
+        match (held, hit) {
+            (true, Some(hit)) => {
+                let point = Vector2::new(hit.position.x, hit.position.z);
+                match self.line.as_mut() {
+                    Some(line) if self.drawing => line.1 = point,
+                    _ => self.line = Some((point, point)),
+                }
+                self.drawing = true;
+            }
+            (true, None) => (),
+            (false, _) => self.drawing = false,
+        }
+    }
+    /// Evenly spaced points from the start to the end of the line, which is clamped
+    /// to the grid. Empty when no line was drawn
+    pub fn profile(&self, terrain: &Terrain) -> Vec<ProfilePoint> {
+        let (start, end) = match self.line {
+            Some((start, end)) => (terrain.clamp_to_grid(start), terrain.clamp_to_grid(end)),
+            None => return vec![],
+        };
+        let length = (end - start).norm();
+        let direction = if length > 0.0 {
+            (end - start) / length
+        } else {
+            Vector2::new(0.0, 0.0)
+        };
+        let samples = self.samples.max(2);
+        (0..samples)
+            .map(|i| {
+                let t = i as f32 / (samples - 1) as f32;
+                let position = start + (end - start) * t;
+                let velocity = terrain
+                    .velocity_at(position)
+                    .unwrap_or_else(|| Vector2::new(0.0, 0.0));
+                ProfilePoint {
+                    position,
+                    distance: length * t,
+                    bed: terrain.bed_height_at(position).unwrap_or(0.0),
+                    surface: terrain.surface_height_at(position).unwrap_or(0.0),
+                    along: velocity.dot(&direction),
+                    speed: velocity.norm(),
+                }
+            })
+            .collect()
+    }
+    /// The line draped over the water surface in world space, drawn with the flow
+    /// lines
+    pub fn line_points(&self, terrain: &Terrain) -> Vec<Vector3<f32>> {
+        self.profile(terrain)
+            .iter()
+            .map(|point| {
+                Vector3::new(
+                    point.position.x,
+                    point.surface + LINE_OFFSET,
+                    point.position.y,
+                )
+            })
+            .collect()
+    }
+    pub fn draw_gui(&mut self, context: &mut CtxRef, terrain: Option<&Terrain>) {
+        egui::Window::new("Cross Section").show(context, |ui| {
+            ui.label("Hold the transect binding, ctrl and the right mouse button by default, and drag across the terrain");
+            ui.add(egui::Slider::usize(&mut self.samples, 2..=Self::MAX_SAMPLES).text("Samples"));
+            if let Some((start, end)) = self.line {
+                ui.label(format!(
+                    "From ({:.1}, {:.1}) to ({:.1}, {:.1}), {:.1} cells",
+                    start.x,
+                    start.y,
+                    end.x,
+                    end.y,
+                    (end - start).norm()
+                ));
+                if ui.button("Clear").clicked {
+                    self.line = None;
+                }
+            }
+            let profile = match terrain {
+                Some(terrain) => self.profile(terrain),
+                None => vec![],
+            };
+            if profile.is_empty() {
+                return;
+            }
+            let series = |value: fn(&ProfilePoint) -> f32| -> Vec<f32> {
+                profile.iter().map(value).collect()
+            };
+            let (bed, surface) = (series(|p| p.bed), series(|p| p.surface));
+            let (along, speed) = (series(|p| p.along), series(|p| p.speed));
+            ui.separator();
+            ui.label(format!(
+                "Bed (brown) and water surface (blue), {:.3} to {:.3}",
+                bed.iter().copied().fold(f32::INFINITY, f32::min),
+                surface.iter().copied().fold(f32::NEG_INFINITY, f32::max)
+            ));
+            line_plot(
+                ui,
+                &[
+                    (&bed, Srgba::from_rgb(150, 110, 70)),
+                    (&surface, Srgba::from_rgb(80, 160, 255)),
+                ],
+            );
+            ui.separator();
+            ui.label(format!(
+                "Velocity along the line (red) and speed (white), largest speed {:.3}",
+                speed.iter().copied().fold(0.0, f32::max)
+            ));
+            line_plot(
+                ui,
+                &[
+                    (&along, Srgba::from_rgb(240, 80, 80)),
+                    (&speed, Srgba::from_rgb(230, 230, 230)),
+                ],
+            );
+        });
+    }
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    fn hit(x: f32, z: f32) -> Option<TerrainHit> {
+        Some(TerrainHit {
+            cell: Vector2::new(x.round() as usize, z.round() as usize),
+            position: Vector3::new(x, 1.0, z),
+        })
+    }
+    #[test]
+    fn line_follows_cursor_while_held() {
+        let mut transect = Transect::default();
+        transect.update(true, hit(1.0, 2.0));
+        transect.update(true, None);
+        transect.update(true, hit(5.0, 2.0));
+        assert_eq!(
+            transect.line,
+            Some((Vector2::new(1.0, 2.0), Vector2::new(5.0, 2.0)))
+        );
+        transect.update(false, hit(6.0, 6.0));
+        transect.update(false, hit(7.0, 6.0));
+        assert_eq!(
+            transect.line,
+            Some((Vector2::new(1.0, 2.0), Vector2::new(5.0, 2.0)))
+        );
+        // pressing again starts a new line
+        transect.update(true, hit(3.0, 3.0));
+        assert_eq!(
+            transect.line,
+            Some((Vector2::new(3.0, 3.0), Vector2::new(3.0, 3.0)))
+        );
+    }
+    #[test]
+    fn profile_along_the_flow() {
+        let mut terrain = Terrain::flat(Vector2::new(8, 8), 1.0);
+        for v in terrain.velocity.data.iter_mut() {
+            *v = Vector2::new(3.0, 4.0);
+        }
+        for bed in terrain.bed.data.iter_mut() {
+            *bed = 2.0;
+        }
+        let transect = Transect {
+            line: Some((Vector2::new(1.0, 1.0), Vector2::new(20.0, 1.0))),
+            samples: 4,
+            ..Transect::default()
+        };
+        let profile = transect.profile(&terrain);
+        assert_eq!(profile.len(), 4);
+        assert_eq!(profile[3].position, Vector2::new(7.0, 1.0));
+        assert_eq!(profile[3].distance, 6.0);
+        for point in profile.iter() {
+            assert_eq!(point.bed, 2.0);
+            assert_eq!(point.surface, 3.0);
+            assert_eq!(point.along, 3.0);
+            assert_eq!(point.speed, 5.0);
+        }
+        let line = transect.line_points(&terrain);
+        assert_eq!(line[0], Vector3::new(1.0, 3.0 + LINE_OFFSET, 1.0));
+        assert!(Transect::default().profile(&terrain).is_empty());
+    }
+}
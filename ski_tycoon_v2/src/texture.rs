@@ -26,4 +26,15 @@ impl RGBATexture {
     pub fn height(&self) -> u32 {
         self.dimensions.y
     }
+    /// Saves as a png, the first row is the top of the image
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save_png<P: AsRef<std::path::Path>>(&self, path: P) -> image::ImageResult<()> {
+        image::save_buffer(
+            path,
+            &self.get_raw_vector(),
+            self.width(),
+            self.height(),
+            image::ColorType::Rgba8,
+        )
+    }
 }
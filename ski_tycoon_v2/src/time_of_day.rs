@@ -0,0 +1,215 @@
+use super::prelude::Texture;
+use egui::CtxRef;
+use nalgebra::{Matrix4, Vector2, Vector3, Vector4};
+const MINUTES_PER_DAY: f32 = 24.0 * 60.0;
+/// Tilt of the path of the sun towards +z, keeps the sun from passing straight overhead
+const SUN_TILT: f32 = 0.4;
+/// Lifts run while the sun is higher than this, in the same units as `sun_elevation`
+const LIFT_SUN_ELEVATION: f32 = 0.1;
+/// Rows in the sky texture, each row is the sky at one height of the screen
+const SKY_TEXTURE_HEIGHT: u32 = 64;
+const NOON_LIGHT: [f32; 3] = [1.0, 1.0, 0.95];
+const SUNSET_LIGHT: [f32; 3] = [1.0, 0.6, 0.35];
+const MOON_LIGHT: [f32; 3] = [0.25, 0.3, 0.45];
+const DAY_ZENITH: [f32; 3] = [0.25, 0.5, 0.9];
+const DAY_HORIZON: [f32; 3] = [0.7, 0.85, 1.0];
+const SUNSET_ZENITH: [f32; 3] = [0.3, 0.35, 0.6];
+const SUNSET_HORIZON: [f32; 3] = [1.0, 0.55, 0.3];
+const NIGHT_ZENITH: [f32; 3] = [0.01, 0.02, 0.06];
+const NIGHT_HORIZON: [f32; 3] = [0.05, 0.07, 0.15];
+/// Game clock and position of the sun, stored as a resource. Advances by a fixed
+/// amount every frame so that the clock does not depend on the frame rate
+pub struct TimeOfDay {
+    /// days since the start of the game
+    day: u32,
+    /// minutes since midnight
+    minutes: f32,
+    /// game minutes that pass every frame
+    pub minutes_per_frame: f32,
+    pub paused: bool,
+}
+impl Default for TimeOfDay {
+    fn default() -> Self {
+        Self {
+            day: 0,
+            minutes: 10.0 * 60.0,
+            minutes_per_frame: 1.0,
+            paused: false,
+        }
+    }
+}
+impl TimeOfDay {
+    /// Moves the clock forward by one frame
+    pub fn advance(&mut self) {
+        if !self.paused {
+            let total = self.minutes + self.minutes_per_frame;
+            self.day += (total / MINUTES_PER_DAY).floor() as u32;
+            self.minutes = total.rem_euclid(MINUTES_PER_DAY);
+        }
+    }
+    pub fn day(&self) -> u32 {
+        self.day
+    }
+    /// Hours since midnight
+    pub fn hour(&self) -> f32 {
+        self.minutes / 60.0
+    }
+    pub fn set_hour(&mut self, hour: f32) {
+        self.minutes = (hour * 60.0).rem_euclid(MINUTES_PER_DAY);
+    }
+    /// Clock shown in the gui, days start at 1
+    pub fn clock(&self) -> String {
+        let minutes = self.minutes as u32;
+        format!(
+            "Day {} {:02}:{:02}",
+            self.day + 1,
+            minutes / 60,
+            minutes % 60
+        )
+    }
+    /// Unit vector pointing towards the sun. The sun rises in +x at 6:00 and sets
+    /// in -x at 18:00
+    pub fn sun_position(&self) -> Vector3<f32> {
+        let angle = (self.hour() - 6.0) / 12.0 * std::f32::consts::PI;
+        Vector3::new(angle.cos(), angle.sin(), SUN_TILT).normalize()
+    }
+    /// Height of the sun above the horizon, negative at night
+    pub fn sun_elevation(&self) -> f32 {
+        self.sun_position().y
+    }
+    pub fn is_daylight(&self) -> bool {
+        self.sun_elevation() > 0.0
+    }
+    /// Lifts open once the sun is up and close before it sets
+    pub fn lifts_open(&self) -> bool {
+        self.sun_elevation() > LIFT_SUN_ELEVATION
+    }
+    /// Direction the light travels in, sent as `sun_direction`. The moon opposite
+    /// the sun lights the world at night
+    pub fn light_direction(&self) -> Vector3<f32> {
+        let sun = self.sun_position();
+        if sun.y >= 0.0 {
+            -sun
+        } else {
+            sun
+        }
+    }
+    /// Color of the light, sent as `sun_color`. Alpha stays at 1 because the water
+    /// shader uses it for opacity
+    pub fn light_color(&self) -> Vector4<f32> {
+        let elevation = self.sun_elevation();
+        let sun = lerp(SUNSET_LIGHT, NOON_LIGHT, smoothstep(0.0, 0.4, elevation));
+        let color = Vector3::from(MOON_LIGHT).lerp(&sun, smoothstep(-0.1, 0.1, elevation));
+        Vector4::new(color.x, color.y, color.z, 1.0)
+    }
+    /// Colors of the sky straight up and at the horizon
+    fn sky_colors(&self) -> (Vector3<f32>, Vector3<f32>) {
+        let elevation = self.sun_elevation();
+        let day = smoothstep(-0.15, 0.25, elevation);
+        let sunset = 1.0 - smoothstep(0.0, 0.3, elevation.abs());
+        let zenith = lerp(NIGHT_ZENITH, DAY_ZENITH, day);
+        let horizon = lerp(NIGHT_HORIZON, DAY_HORIZON, day);
+        (
+            zenith.lerp(&Vector3::from(SUNSET_ZENITH), sunset * 0.5),
+            horizon.lerp(&Vector3::from(SUNSET_HORIZON), sunset),
+        )
+    }
+    /// Sky gradient drawn over the whole screen behind the world with the screen
+    /// shader. `camera` is the view and projection of the camera, each row is
+    /// colored by the height above the horizon of the ray through the center of
+    /// that row of the screen
+    pub fn sky_texture(&self, camera: Matrix4<f32>) -> Texture {
+        let (zenith, horizon) = self.sky_colors();
+        let inverse = camera.try_inverse().unwrap_or_else(Matrix4::identity);
+        let unproject = |y: f32, z: f32| {
+            let point = inverse * Vector4::new(0.0, y, z, 1.0);
+            point.xyz() / point.w
+        };
+        let pixels = (0..SKY_TEXTURE_HEIGHT)
+            .map(|row| {
+                let y = (row as f32 + 0.5) / SKY_TEXTURE_HEIGHT as f32 * 2.0 - 1.0;
+                let elevation = (unproject(y, 1.0) - unproject(y, -1.0))
+                    .try_normalize(f32::EPSILON)
+                    .map_or(0.0, |ray| ray.y.max(0.0));
+                let color = horizon.lerp(&zenith, elevation.sqrt());
+                Vector4::new(to_byte(color.x), to_byte(color.y), to_byte(color.z), 255)
+            })
+            .collect();
+        Texture {
+            dimensions: Vector2::new(1, SKY_TEXTURE_HEIGHT),
+            pixels,
+        }
+    }
+    pub fn draw_gui(&mut self, context: &mut CtxRef) {
+        egui::Window::new("Time of Day").show(context, |ui| {
+            ui.label(self.clock());
+            ui.label(if self.lifts_open() {
+                "Lifts open"
+            } else {
+                "Lifts closed"
+            });
+            ui.checkbox(&mut self.paused, "Paused");
+            let mut hour = self.hour();
+            ui.add(egui::Slider::f32(&mut hour, 0.0..=24.0).text("Hour"));
+            if (hour - self.hour()).abs() > f32::EPSILON {
+                self.set_hour(hour);
+            }
+            ui.add(
+                egui::Slider::f32(&mut self.minutes_per_frame, 0.0..=30.0)
+                    .text("Minutes per Frame"),
+            );
+        });
+    }
+}
+fn lerp(a: [f32; 3], b: [f32; 3], t: f32) -> Vector3<f32> {
+    Vector3::from(a).lerp(&Vector3::from(b), t)
+}
+/// Hermite interpolation between 0 and 1, same as glsl
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+fn to_byte(c: f32) -> u8 {
+    (c.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn clock_wraps_into_next_day() {
+        let mut time = TimeOfDay::default();
+        time.set_hour(23.5);
+        time.minutes_per_frame = 45.0;
+        time.advance();
+        assert_eq!(time.day(), 1);
+        assert_eq!(time.clock(), "Day 2 00:15");
+        time.paused = true;
+        time.advance();
+        assert_eq!(time.clock(), "Day 2 00:15");
+    }
+    #[test]
+    fn lifts_follow_daylight() {
+        let mut time = TimeOfDay::default();
+        time.set_hour(12.0);
+        assert!(time.lifts_open());
+        assert!(time.light_direction().y < 0.0);
+        time.set_hour(0.0);
+        assert!(!time.is_daylight());
+        assert!(!time.lifts_open());
+        // the moon still shines down
+        assert!(time.light_direction().y < 0.0);
+        assert!(time.light_color().x < 0.5);
+    }
+    #[test]
+    fn sky_is_darker_overhead() {
+        let mut time = TimeOfDay::default();
+        time.set_hour(12.0);
+        // looking along -z with the horizon in the middle of the screen
+        let camera = Matrix4::new_perspective(1.0, std::f32::consts::PI / 2.0, 0.1, 100.0);
+        let sky = time.sky_texture(camera);
+        let bottom = sky.pixels[0];
+        let top = sky.pixels[sky.pixels.len() - 1];
+        assert_eq!(bottom, sky.pixels[1]);
+        assert!(top.x < bottom.x);
+    }
+}
@@ -8,11 +8,22 @@ document.onreadystatechange = () => {
 let last_x = null;
 let last_y = null;
 let last_time = new Date();
+let frame_last_time = new Date();
 let wheel_last_time = new Date();
 let events = []
 let SCREEN_X_SIZE = 0;
 let SCREEN_Y_SIZE = 0;
+// size of the canvas on the page in css pixels, the game draws and gets mouse
+// positions in device pixels like the native build
+let CSS_X_SIZE = 0;
+let CSS_Y_SIZE = 0;
+let scale_factor = window.devicePixelRatio || 1;
 console.log(document);
+function set_modifiers(map, event) {
+    map.set("shift", event.shiftKey);
+    map.set("ctrl", event.ctrlKey);
+    map.set("alt", event.altKey);
+}
 function canvas_click() {
     console.log("clicked??")
     //document.getElementById("canvas").requestPointerLock();
@@ -30,15 +41,16 @@ function mouse_move(event) {
         last_y = event.clientY;
     }
     mouse_event.set("name", "mouse_move");
-    mouse_event.set("delta_x", event.offsetX - last_x);
-    mouse_event.set("x", event.offsetX);
-    mouse_event.set("delta_y", event.offsetY - last_y);
-    mouse_event.set("y", event.offsetY);
+    mouse_event.set("delta_x", (event.offsetX - last_x) * scale_factor);
+    mouse_event.set("x", event.offsetX * scale_factor);
+    mouse_event.set("delta_y", (event.offsetY - last_y) * scale_factor);
+    mouse_event.set("y", event.offsetY * scale_factor);
     console.log(event)
     console.log(event.target)
     console.log(event.offsetX)
     mouse_event.set("delta_time_ms", Number(now - last_time))
     mouse_event.set("buttons", event.buttons);
+    set_modifiers(mouse_event, event);
     events.push(mouse_event)
     last_x = event.offsetX;
     last_y = event.offsetY;
@@ -51,6 +63,7 @@ function onwheel(event) {
     wheel_event.set("delta_y", event.deltaY);
     let delta_time = Number(now - wheel_last_time);
     wheel_event.set("delta_time_ms", delta_time);
+    set_modifiers(wheel_event, event);
     events.push(wheel_event);
     last_time = now;
 }
@@ -59,14 +72,25 @@ function press_putton(event) {
     let button_event = new Map();
     button_event.set("name", "keypress");
     button_event.set("key", event.key)
+    set_modifiers(button_event, event);
     events.push(button_event)
 }
+// keys that do not type a character, like Backspace or the arrows, never fire
+// keypress so they are sent on keydown. Enter already fires keypress
+function on_key_down(event) {
+    if (event.key.length > 1 && event.key !== "Enter") {
+        press_putton(event);
+    }
+}
 function on_mouse_down(event) {
     let mouse_event = new Map();
     mouse_event.set("name", "mousedown");
+    mouse_event.set("button", event.button);
     mouse_event.set("buttons", event.buttons);
-    mouse_event.set("x", event.clientX);
-    mouse_event.set("y", event.clientY);
+    set_modifiers(mouse_event, event);
+    // relative to the canvas like mouse_move
+    mouse_event.set("x", event.offsetX * scale_factor);
+    mouse_event.set("y", event.offsetY * scale_factor);
     console.info("mouse down");
     events.push(mouse_event);
 }
@@ -75,8 +99,10 @@ function on_mouse_up(event) {
     console.info("mouse up");
     mouse_event.set("name", "mouseup");
     mouse_event.set("buttons", event.buttons);
-    mouse_event.set("x", event.clientX);
-    mouse_event.set("y", event.clientY);
+    set_modifiers(mouse_event, event);
+    // relative to the canvas like mouse_move
+    mouse_event.set("x", event.offsetX * scale_factor);
+    mouse_event.set("y", event.offsetY * scale_factor);
 
     events.push(mouse_event);
 }
@@ -89,48 +115,70 @@ document.getElementById("canvas").onmousemove = mouse_move
 document.getElementById("canvas").onwheel = onwheel;
 document.getElementById("canvas").onresize = resize;
 document.onkeypress = press_putton;
+document.onkeydown = on_key_down;
 document.getElementById("canvas").onmousedown = on_mouse_down;
 document.getElementById("canvas").onmouseup = on_mouse_up;
+// the right button sculpts the terrain
+document.getElementById("canvas").oncontextmenu = (event) => event.preventDefault();
 //let c = document.getElementById("canvas");
 
 //c.width = document.getElementById("game_body").innerWidth;
 //console.log(c.width);
-SCREEN_X_SIZE = document.getElementById("canvas").width;
-SCREEN_Y_SIZE = document.getElementById("canvas").height;
-document.getElementById("canvas").width = SCREEN_X_SIZE;
-document.getElementById("canvas").height = SCREEN_Y_SIZE;
+CSS_X_SIZE = document.getElementById("canvas").width;
+CSS_Y_SIZE = document.getElementById("canvas").height;
+document.getElementById("canvas").style.width = CSS_X_SIZE + "px";
+document.getElementById("canvas").style.height = CSS_Y_SIZE + "px";
+// the backing store has one pixel for every device pixel so the page stays sharp
+// on high density displays
+function size_canvas() {
+    SCREEN_X_SIZE = Math.round(CSS_X_SIZE * scale_factor);
+    SCREEN_Y_SIZE = Math.round(CSS_Y_SIZE * scale_factor);
+    document.getElementById("canvas").width = SCREEN_X_SIZE;
+    document.getElementById("canvas").height = SCREEN_Y_SIZE;
+}
+function send_scale_factor() {
+    let scale_map = new Map();
+    scale_map.set("name", "scale_factor_change");
+    scale_map.set("scale_factor", scale_factor);
+    events.push(scale_map);
+}
+// devicePixelRatio changes when the page is zoomed or moved to another display,
+// the media query only matches the current ratio so it is replaced every change
+function watch_scale_factor() {
+    window.matchMedia("(resolution: " + scale_factor + "dppx)")
+        .addEventListener("change", on_scale_factor_change, { once: true });
+}
+function on_scale_factor_change() {
+    scale_factor = window.devicePixelRatio || 1;
+    size_canvas();
+    let screen_update_map = new Map();
+    screen_update_map.set("name", "screen_size_change");
+    screen_update_map.set("x", SCREEN_X_SIZE);
+    screen_update_map.set("y", SCREEN_Y_SIZE);
+    events.push(screen_update_map);
+    send_scale_factor();
+    watch_scale_factor();
+}
+size_canvas();
 console.log("loading game")
 let resolution_map = new Map();
 resolution_map.set("x", SCREEN_X_SIZE);
 resolution_map.set("y", SCREEN_Y_SIZE);
 
 let game = rust.init_game(resolution_map);
+send_scale_factor();
+watch_scale_factor();
+// bindings.cfg next to the page replaces the default bindings
+fetch("bindings.cfg")
+    .then((response) => response.ok ? response.text() : Promise.reject(response.status))
+    .then((config) => game.load_bindings(config))
+    .catch((error) => console.log("using default bindings: " + error));
 console.log(window);
 console.log("loaded game")
 
 
 function render() {
 
-    let new_x_size = SCREEN_X_SIZE;
-    let new_y_size = SCREEN_Y_SIZE;
-    if (new_x_size != SCREEN_X_SIZE || new_y_size != SCREEN_Y_SIZE) {
-        console.log("screen updated");
-        console.log(new_x_size);
-        console.log(SCREEN_X_SIZE);
-        console.log(new_y_size);
-        console.log(SCREEN_Y_SIZE);
-        let screen_update_map = new Map();
-        screen_update_map.set("name", "screen_size_change");
-        screen_update_map.set("x", new_x_size);
-        screen_update_map.set("y", new_y_size);
-        SCREEN_X_SIZE = new_x_size;
-        SCREEN_Y_SIZE = new_y_size;
-        document.getElementById("canvas").width = SCREEN_X_SIZE;
-        document.getElementById("canvas").height = SCREEN_Y_SIZE;
-        events.push(screen_update_map);
-
-    }
-
     let event_state = new Map();
     if (last_x === null) {
         event_state.set("position_x", 0.0);
@@ -143,6 +191,13 @@ function render() {
         event_state.set("position_y", last_y);
     }
 
+    let now = new Date();
+    let frame_time = new Map();
+    frame_time.set("name", "frame_time");
+    frame_time.set("delta_time_ms", Number(now - frame_last_time));
+    frame_last_time = now;
+    events.push(frame_time);
+
     game.render_frame(events);
     events = []
     requestAnimationFrame(render)